@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use plotsirv::chunk_view::{wanted_chunks, within};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use valence::prelude::*;
+
+/// The width of a region file, in chunks.
+const REGION: i32 = 32;
+
+const PLAYERS: usize = 100;
+
+/// How far past their view players keep chunks loaded, as in the server.
+const UNLOAD_MARGIN: u8 = 2;
+
+/// How much of a 50 ms tick working out which chunks to keep may take. The
+/// rest is for everything else the server does.
+const BUDGET: Duration = Duration::from_millis(5);
+
+/// How many ticks the budget is checked over.
+const TICKS: u32 = 100;
+
+/// Where players are and how far they see, spread over a region.
+fn views() -> Vec<(ChunkPos, u8)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..PLAYERS)
+        .map(|_| {
+            let pos = ChunkPos::new(rng.gen_range(0..REGION), rng.gen_range(0..REGION));
+            (pos, rng.gen_range(2..=16))
+        })
+        .collect()
+}
+
+/// A tick's worth of chunk residency: the chunks players want, then which
+/// of the loaded ones nobody is near enough to keep.
+fn residency_tick(views: &[(ChunkPos, u8)], loaded: &HashSet<ChunkPos>) -> (usize, usize) {
+    let wanted = wanted_chunks(views);
+    let unused = loaded
+        .iter()
+        .filter(|&&pos| {
+            !views.iter().any(|&(center, distance)| {
+                within(center, pos, distance.saturating_add(UNLOAD_MARGIN))
+            })
+        })
+        .count();
+    (wanted.len(), unused)
+}
+
+fn region_views(c: &mut Criterion) {
+    let views = views();
+    let loaded: HashSet<_> = wanted_chunks(&views).into_iter().collect();
+
+    // Memory follows what's loaded, which is never more than every player's
+    // view added up.
+    let most: usize = views
+        .iter()
+        .map(|&(_, distance)| (2 * usize::from(distance) + 1).pow(2))
+        .sum();
+    assert!(
+        loaded.len() <= most,
+        "{} chunks loaded for views covering {most}",
+        loaded.len()
+    );
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        black_box(residency_tick(black_box(&views), &loaded));
+    }
+    let per_tick = start.elapsed() / TICKS;
+    assert!(
+        per_tick <= BUDGET,
+        "chunk residency for {PLAYERS} players took {per_tick:?} a tick, over {BUDGET:?}"
+    );
+
+    c.bench_function("chunk residency for 100 players in a region", |b| {
+        b.iter(|| black_box(residency_tick(black_box(&views), &loaded)))
+    });
+}
+
+criterion_group!(benches, region_views);
+criterion_main!(benches);
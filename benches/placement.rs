@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use plotsirv::placement::placed_state;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use valence::prelude::*;
+use valence_protocol::BlockFace;
+
+/// A block from each family placement treats differently: plain blocks,
+/// stairs, slabs, blocks that face the player, trapdoors, logs and pistons.
+const KINDS: [BlockKind; 7] = [
+    BlockKind::Stone,
+    BlockKind::OakStairs,
+    BlockKind::StoneSlab,
+    BlockKind::Furnace,
+    BlockKind::OakTrapdoor,
+    BlockKind::OakLog,
+    BlockKind::Piston,
+];
+
+const FACES: [BlockFace; 6] = [
+    BlockFace::Bottom,
+    BlockFace::Top,
+    BlockFace::North,
+    BlockFace::South,
+    BlockFace::West,
+    BlockFace::East,
+];
+
+fn placements(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let events: Vec<_> = (0..10_000)
+        .map(|_| {
+            (
+                KINDS[rng.gen_range(0..KINDS.len())],
+                rng.gen_range(-180.0..180.0),
+                rng.gen_range(-90.0..=90.0),
+                FACES[rng.gen_range(0..FACES.len())],
+                rng.gen::<f32>(),
+            )
+        })
+        .collect();
+
+    c.bench_function("10k placements", |b| {
+        b.iter(|| {
+            for &(kind, yaw, pitch, face, cursor_y) in &events {
+                black_box(placed_state(kind, yaw, pitch, face, cursor_y));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, placements);
+criterion_main!(benches);
@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use valence::client::event::{
+    ChatCommand, ChatMessage, ClickContainer, StartDigging, UseItemOnBlock,
+};
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::kick;
+use crate::lang::Lang;
+use crate::permissions::Permissions;
+
+const AFK: CommandInfo = CommandInfo {
+    name: "afk",
+    aliases: &[],
+    usage: "/afk",
+    description: "Mark yourself as away.",
+    permission: None,
+    console: false,
+};
+
+/// Present on clients who are away from their keyboard.
+#[derive(Component, Debug)]
+pub struct Afk;
+
+/// When a client last did anything, and where they were looking from then.
+#[derive(Component, Debug)]
+struct Activity {
+    last_active: Instant,
+    /// Set when something happened since the last check.
+    touched: bool,
+    position: DVec3,
+    yaw: f32,
+    pitch: f32,
+}
+
+pub struct AfkPlugin;
+
+impl Plugin for AfkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(AFK)
+            .add_system(init_activity)
+            .add_system_to_stage(EventLoop, record_activity)
+            .add_system_to_stage(EventLoop, afk_command.after(record_activity))
+            .add_system(update_afk);
+    }
+}
+
+fn init_activity(mut commands: Commands, clients: Query<(Entity, &Client), Added<Client>>) {
+    for (entity, client) in &clients {
+        commands.entity(entity).insert(Activity {
+            last_active: Instant::now(),
+            touched: false,
+            position: client.position(),
+            yaw: client.yaw(),
+            pitch: client.pitch(),
+        });
+    }
+}
+
+fn record_activity(
+    mut activity: Query<&mut Activity>,
+    mut chat: EventReader<ChatMessage>,
+    mut commands: EventReader<ChatCommand>,
+    mut digging: EventReader<StartDigging>,
+    mut placing: EventReader<UseItemOnBlock>,
+    mut clicks: EventReader<ClickContainer>,
+) {
+    let clients = chat
+        .iter()
+        .map(|e| e.client)
+        .chain(commands.iter().map(|e| e.client))
+        .chain(digging.iter().map(|e| e.client))
+        .chain(placing.iter().map(|e| e.client))
+        .chain(clicks.iter().map(|e| e.client));
+
+    for client in clients {
+        if let Ok(mut activity) = activity.get_mut(client) {
+            activity.touched = true;
+        }
+    }
+}
+
+fn afk_command(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &mut Activity, Option<&Afk>)>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(AFK.name)) {
+        let Ok((_, mut client, mut activity, afk)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        if !event.args.is_empty() {
+            client.send_message(usage(&lang, event.sender, &AFK));
+            continue;
+        }
+
+        let username = client.username().to_string();
+        let now_afk = afk.is_none();
+        // Running the command isn't activity that should end it again.
+        activity.touched = false;
+
+        if now_afk {
+            commands.entity(event.sender).insert(Afk);
+        } else {
+            activity.last_active = Instant::now();
+            commands.entity(event.sender).remove::<Afk>();
+        }
+
+        announce(&mut clients, &config, &lang, &username, now_afk);
+    }
+}
+
+fn announce(
+    clients: &mut Query<(Entity, &mut Client, &mut Activity, Option<&Afk>)>,
+    config: &Config,
+    lang: &Lang,
+    username: &str,
+    afk: bool,
+) {
+    if !config.afk.announce {
+        return;
+    }
+
+    let key = if afk { "afk.now" } else { "afk.back" };
+    for (entity, mut client, _, _) in clients.iter_mut() {
+        client.send_message(lang.tr(entity, key, &[("name", &username)]));
+    }
+}
+
+fn update_afk(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &mut Activity, Option<&Afk>)>,
+    config: Res<Config>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+) {
+    let idle_limit = Duration::from_secs(config.afk.idle_secs);
+    let kick_limit = config.afk.kick_secs.map(Duration::from_secs);
+    let mut changes = Vec::new();
+
+    for (entity, mut client, mut activity, afk) in &mut clients {
+        if client.is_disconnected() {
+            continue;
+        }
+
+        let moved = client.position() != activity.position
+            || client.yaw() != activity.yaw
+            || client.pitch() != activity.pitch;
+
+        if moved {
+            activity.position = client.position();
+            activity.yaw = client.yaw();
+            activity.pitch = client.pitch();
+            activity.touched = true;
+        }
+
+        if std::mem::take(&mut activity.touched) {
+            activity.last_active = Instant::now();
+            if afk.is_some() {
+                commands.entity(entity).remove::<Afk>();
+                changes.push((client.username().to_string(), false));
+            }
+            continue;
+        }
+
+        let idle = activity.last_active.elapsed();
+
+        if kick_limit.map_or(false, |limit| idle >= limit)
+            && !permissions.has_permission(client.uuid(), "plots.afk.kick_exempt")
+        {
+            kick(&mut client, lang.tr(entity, "afk.kicked", &[]));
+            continue;
+        }
+
+        if afk.is_none() && idle >= idle_limit {
+            commands.entity(entity).insert(Afk);
+            changes.push((client.username().to_string(), true));
+        }
+    }
+
+    for (username, afk) in changes {
+        announce(&mut clients, &config, &lang, &username, afk);
+    }
+}
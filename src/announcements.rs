@@ -0,0 +1,153 @@
+use rand::Rng;
+use tracing::warn;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{SetSubtitleText, SetTitleAnimationTimes, SetTitleText};
+
+use crate::boss_bar::{BossBar, BossBarId, BossBarTarget, BossBars};
+use crate::config::{AnnouncementDisplay, Config};
+use crate::hud::{Hud, SUPPRESS_FOR};
+use crate::lang::Lang;
+use crate::reload::ConfigReloaded;
+use crate::tps::Tps;
+
+/// How long a boss bar announcement stays up, in ticks.
+const BOSS_BAR_TICKS: u64 = 10 * 20;
+
+/// Title timings, in ticks.
+const TITLE_FADE_IN: i32 = 10;
+const TITLE_STAY: i32 = 70;
+const TITLE_FADE_OUT: i32 = 20;
+
+#[derive(Resource, Default)]
+struct Announcer {
+    /// Ticks until the next announcement, counted only while players are
+    /// online. Starts over when the config is reloaded.
+    remaining: Option<u64>,
+    /// The next message to send, when going in order.
+    next: usize,
+    /// The bar showing the last boss bar announcement, and the ticks until
+    /// it's taken down.
+    bar: Option<(BossBarId, u64)>,
+}
+
+pub struct AnnouncementsPlugin;
+
+impl Plugin for AnnouncementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Announcer>()
+            .add_system(restart_announcements)
+            .add_system(run_announcements.after(restart_announcements));
+    }
+}
+
+fn restart_announcements(
+    mut announcer: ResMut<Announcer>,
+    mut events: EventReader<ConfigReloaded>,
+) {
+    if events.iter().count() > 0 {
+        announcer.remaining = None;
+        announcer.next = 0;
+    }
+}
+
+fn run_announcements(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut huds: Query<&mut Hud>,
+    mut announcer: ResMut<Announcer>,
+    mut bars: ResMut<BossBars>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    tps: Res<Tps>,
+) {
+    if let Some((bar, remaining)) = announcer.bar {
+        if remaining <= 1 {
+            bars.remove(bar);
+            announcer.bar = None;
+        } else {
+            announcer.bar = Some((bar, remaining - 1));
+        }
+    }
+
+    let announcements = &config.announcements;
+    let online = clients.iter().filter(|(_, c)| !c.is_disconnected()).count();
+    if announcements.messages.is_empty() || online == 0 {
+        return;
+    }
+
+    let interval = announcements.interval_secs * 20;
+    let remaining = announcer.remaining.get_or_insert(interval);
+    *remaining = remaining.saturating_sub(1);
+    if *remaining > 0 {
+        return;
+    }
+    *remaining = interval;
+
+    let count = announcements.messages.len();
+    let index = if announcements.random {
+        rand::thread_rng().gen_range(0..count)
+    } else {
+        let index = announcer.next % count;
+        announcer.next = index + 1;
+        index
+    };
+
+    let announcement = &announcements.messages[index];
+    if announcement.text.trim().is_empty() {
+        warn!("Skipping announcement {} as it has no text", index + 1);
+        return;
+    }
+
+    let tps = format!("{:.1}", tps.get());
+    let render = |entity: Option<Entity>, player: &str| {
+        let values: [(&str, &dyn std::fmt::Display); 5] = [
+            ("online", &online),
+            ("maxplayers", &config.server.max_players),
+            // As the tab list footer spells it.
+            ("max_players", &config.server.max_players),
+            ("tps", &tps),
+            ("player", &player),
+        ];
+        match entity {
+            Some(entity) => lang.text(entity, &announcement.text, &values),
+            None => lang.text_default(&announcement.text, &values),
+        }
+    };
+
+    if announcement.display == AnnouncementDisplay::BossBar {
+        if let Some((bar, _)) = announcer.bar.take() {
+            bars.remove(bar);
+        }
+        let bar = bars.create(BossBar::new(render(None, ""), BossBarTarget::All));
+        announcer.bar = Some((bar, BOSS_BAR_TICKS));
+        return;
+    }
+
+    for (entity, mut client) in &mut clients {
+        let text = render(Some(entity), client.username().as_str());
+        show(&mut client, announcement.display, text);
+    }
+    if announcement.display == AnnouncementDisplay::ActionBar {
+        for mut hud in &mut huds {
+            hud.suppress(SUPPRESS_FOR);
+        }
+    }
+}
+
+fn show(client: &mut Client, display: AnnouncementDisplay, text: Text) {
+    match display {
+        AnnouncementDisplay::Chat => client.send_message(text),
+        AnnouncementDisplay::ActionBar => client.set_action_bar(text),
+        AnnouncementDisplay::Title => {
+            client.write_packet(&SetTitleAnimationTimes {
+                fade_in: TITLE_FADE_IN,
+                stay: TITLE_STAY,
+                fade_out: TITLE_FADE_OUT,
+            });
+            client.write_packet(&SetSubtitleText {
+                subtitle_text: Text::default(),
+            });
+            client.write_packet(&SetTitleText { title_text: text });
+        }
+        AnnouncementDisplay::BossBar => {}
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashSet;
+
+use plotsirv::chunk_view::within;
+use plotsirv::placement::{sane_rotation, use_up_one};
+use valence::client::event::{InteractWithEntity, UseItemOnBlock};
+use valence::entity::EulerAngle;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{EquipmentEntry, SetEquipment};
+use valence_protocol::types::{EntityInteraction, Hand};
+use valence_protocol::VarInt;
+
+use crate::border::{inside_border, WorldBorder};
+use crate::drops::drop_item;
+use crate::inspect::Inspecting;
+use crate::reject_placement;
+use crate::sound::{Feedback, FeedbackSound};
+use crate::UsedClicks;
+
+/// Equipment slots, numbered as the protocol numbers them.
+const MAIN_HAND: usize = 0;
+const FEET: usize = 2;
+const LEGS: usize = 3;
+const CHEST: usize = 4;
+const HEAD: usize = 5;
+
+/// Shows an armor stand's arms, so what it holds can be seen.
+const SHOW_ARMS: u8 = 0x04;
+
+/// Poses armor stands cycle through, as head, body, left arm, right arm,
+/// left leg and right leg rotations in degrees.
+const POSES: &[[[f32; 3]; 6]] = &[
+    // Standing, as they're placed.
+    [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0],
+        [-10.0, 0.0, -10.0],
+        [-15.0, 0.0, 10.0],
+        [-1.0, 0.0, -1.0],
+        [1.0, 0.0, 1.0],
+    ],
+    // Waving.
+    [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0],
+        [-10.0, 0.0, -10.0],
+        [-160.0, 0.0, 20.0],
+        [-1.0, 0.0, -1.0],
+        [1.0, 0.0, 1.0],
+    ],
+    // Holding something out.
+    [
+        [10.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0],
+        [-60.0, 20.0, 0.0],
+        [-60.0, -20.0, 0.0],
+        [-1.0, 0.0, -1.0],
+        [1.0, 0.0, 1.0],
+    ],
+    // Walking.
+    [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0],
+        [30.0, 0.0, 0.0],
+        [-30.0, 0.0, 0.0],
+        [-30.0, 0.0, 0.0],
+        [30.0, 0.0, 0.0],
+    ],
+];
+
+/// An armor stand placed by a player, and what it's wearing and holding.
+#[derive(Component, Default, Debug)]
+struct ArmorStand {
+    /// Indexed by equipment slot. Armor stands have no off hand.
+    equipment: [Option<ItemStack>; 6],
+    pose: usize,
+}
+
+/// The armor stands a client has been sent the equipment of, and the ones
+/// that came into view this tick, whose equipment is sent next tick once
+/// the stand itself has been.
+#[derive(Component, Default, Debug)]
+struct SeenStands {
+    sent: HashSet<Entity>,
+    pending: HashSet<Entity>,
+}
+
+pub struct ArmorStandsPlugin;
+
+impl Plugin for ArmorStandsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(init_seen_stands)
+            .add_system_to_stage(EventLoop, place_armor_stands)
+            .add_system_to_stage(EventLoop, use_armor_stands)
+            .add_system(show_equipment);
+    }
+}
+
+fn init_seen_stands(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(SeenStands::default());
+    }
+}
+
+/// Which slot an item goes in when it's put on an armor stand.
+fn slot_for(item: ItemKind) -> usize {
+    let name = item.to_str();
+    if name.ends_with("_helmet")
+        || name.ends_with("_head")
+        || name.ends_with("_skull")
+        || item == ItemKind::CarvedPumpkin
+    {
+        HEAD
+    } else if name.ends_with("_chestplate") || item == ItemKind::Elytra {
+        CHEST
+    } else if name.ends_with("_leggings") {
+        LEGS
+    } else if name.ends_with("_boots") {
+        FEET
+    } else {
+        MAIN_HAND
+    }
+}
+
+/// Which of a stand's slots a player clicked, from how high up the stand
+/// they clicked, going by the slots that have something in them as vanilla
+/// does.
+fn slot_at(stand: &ArmorStand, height: f32) -> Option<usize> {
+    let has = |slot: usize| stand.equipment[slot].is_some();
+    if (0.1..0.55).contains(&height) && has(FEET) {
+        Some(FEET)
+    } else if (0.9..1.6).contains(&height) && has(CHEST) {
+        Some(CHEST)
+    } else if (0.4..1.2).contains(&height) && has(LEGS) {
+        Some(LEGS)
+    } else if height >= 1.6 && has(HEAD) {
+        Some(HEAD)
+    } else if has(MAIN_HAND) {
+        Some(MAIN_HAND)
+    } else {
+        None
+    }
+}
+
+fn angle([pitch, yaw, roll]: [f32; 3]) -> EulerAngle {
+    EulerAngle::new(pitch, yaw, roll)
+}
+
+fn show_pose(entity: &mut McEntity, pose: usize) {
+    let [head, body, left_arm, right_arm, left_leg, right_leg] = POSES[pose];
+    if let TrackedData::ArmorStand(data) = entity.data_mut() {
+        data.set_armor_stand_flags(SHOW_ARMS);
+        data.set_head_rotation(angle(head));
+        data.set_body_rotation(angle(body));
+        data.set_left_arm_rotation(angle(left_arm));
+        data.set_right_arm_rotation(angle(right_arm));
+        data.set_left_leg_rotation(angle(left_leg));
+        data.set_right_leg_rotation(angle(right_leg));
+    }
+}
+
+/// Puts an armor stand where a player uses one on a block, turned to face
+/// them to the nearest eighth of a turn. It needs two blocks of room.
+fn place_armor_stands(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &mut Inventory), Without<Inspecting>>,
+    instances: Query<&Instance>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let slot = client.held_item_slot();
+        if inventory.slot(slot).map(|stack| stack.item) != Some(ItemKind::ArmorStand) {
+            continue;
+        }
+        used.mark(event.client);
+        let Ok(instance) = instances.get(client.instance()) else {
+            continue;
+        };
+
+        let clicked = instance.block(event.position).map(|b| b.state());
+        let pos = if clicked.map_or(false, BlockState::is_replaceable) {
+            event.position
+        } else {
+            event.position.get_in_direction(event.face)
+        };
+        let above = BlockPos::new(pos.x, pos.y + 1, pos.z);
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+        let room = [pos, above].into_iter().all(|pos| {
+            instance
+                .block(pos)
+                .map_or(false, |block| block.state().is_replaceable())
+        });
+        if !room {
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+
+        let (yaw, _) = sane_rotation(client.yaw(), client.pitch());
+        let mut entity = McEntity::new(EntityKind::ArmorStand, client.instance());
+        entity.set_position([pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5]);
+        entity.set_yaw(((yaw - 180.0 + 22.5) / 45.0).floor() * 45.0);
+        show_pose(&mut entity, 0);
+        commands.spawn((entity, ArmorStand::default()));
+
+        if client.game_mode() == GameMode::Survival {
+            use_up_one(&mut inventory, slot);
+        }
+    }
+}
+
+/// Punching an armor stand knocks it down, and in survival drops it along
+/// with everything it had on. Using an item on one puts the item on it, in
+/// exchange for whatever it had in that slot; using an empty hand takes
+/// back what was clicked, or changes its pose when sneaking.
+fn use_armor_stands(
+    mut clients: Query<(&mut Client, &mut Inventory)>,
+    mut stands: Query<(&mut McEntity, &mut ArmorStand)>,
+    entities: Res<McEntityManager>,
+    borders: Query<&WorldBorder>,
+    mut commands: Commands,
+    mut events: EventReader<InteractWithEntity>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
+    for event in events.iter() {
+        let Some(stand) = entities.get_with_protocol_id(event.entity_id) else {
+            continue;
+        };
+        let Ok((mut entity, mut stand)) = stands.get_mut(stand) else {
+            continue;
+        };
+        let Ok((client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if entity.is_despawned() || client.instance() != entity.instance() {
+            continue;
+        }
+
+        let position = entity.position();
+        let pos = BlockPos::new(
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        );
+        if !inside_border(&borders, entity.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            continue;
+        }
+
+        let (target, hand) = match event.interact {
+            EntityInteraction::Attack => {
+                entity.set_despawned(true);
+                if client.game_mode() == GameMode::Survival {
+                    let stand_item = ItemStack::new(ItemKind::ArmorStand, 1, None);
+                    let equipment = stand.equipment.iter_mut().filter_map(Option::take);
+                    for stack in std::iter::once(stand_item).chain(equipment) {
+                        drop_item(&mut commands, entity.instance(), position, stack);
+                    }
+                }
+                continue;
+            }
+            // Clients send where on the stand they clicked as well, which
+            // says which slot they meant, so that's the one acted on.
+            EntityInteraction::Interact(_) => continue,
+            EntityInteraction::InteractAt { target, hand } => (target, hand),
+        };
+        let slot = match hand {
+            Hand::Main => client.held_item_slot(),
+            Hand::Off => continue,
+        };
+
+        match inventory.slot(slot).cloned() {
+            Some(mut held) => {
+                let equip = slot_for(held.item);
+                let one = ItemStack::new(held.item, 1, held.nbt.clone());
+                let old = stand.equipment[equip].replace(one);
+                if client.game_mode() == GameMode::Creative {
+                    continue;
+                }
+                // The stand takes one of the held items, and whatever it had
+                // there goes back in their hand if that leaves it empty.
+                held.set_count(held.count() - 1);
+                let held = if held.count() == 0 { old } else { Some(held) };
+                inventory.replace_slot(slot, held);
+            }
+            None if event.sneaking => {
+                stand.pose = (stand.pose + 1) % POSES.len();
+                show_pose(&mut entity, stand.pose);
+            }
+            None => {
+                if let Some(equip) = slot_at(&stand, target.y) {
+                    let taken = stand.equipment[equip].take();
+                    inventory.replace_slot(slot, taken);
+                }
+            }
+        }
+    }
+}
+
+/// Sends clients what the armor stands they can see are wearing, when they
+/// come into view and whenever it changes.
+fn show_equipment(
+    mut clients: Query<(&mut Client, &mut SeenStands)>,
+    stands: Query<(Entity, &McEntity, &ArmorStand, ChangeTrackers<ArmorStand>)>,
+) {
+    for (mut client, mut seen) in &mut clients {
+        let center = ChunkPos::at(client.position().x, client.position().z);
+        let visible: HashSet<Entity> = stands
+            .iter()
+            .filter(|(_, entity, _, _)| {
+                let at = entity.position();
+                entity.instance() == client.instance()
+                    && !entity.is_despawned()
+                    && within(center, ChunkPos::at(at.x, at.z), client.view_distance())
+            })
+            .map(|(stand, _, _, _)| stand)
+            .collect();
+        seen.sent.retain(|stand| visible.contains(stand));
+        seen.pending.retain(|stand| visible.contains(stand));
+
+        let SeenStands { sent, pending } = &mut *seen;
+        for (stand, entity, armor, changes) in stands.iter_many(&visible) {
+            let send = if pending.remove(&stand) {
+                sent.insert(stand);
+                true
+            } else if sent.contains(&stand) {
+                changes.is_changed()
+            } else {
+                pending.insert(stand);
+                false
+            };
+            if send {
+                client.write_packet(&SetEquipment {
+                    entity_id: VarInt(entity.protocol_id()),
+                    equipment: armor
+                        .equipment
+                        .iter()
+                        .enumerate()
+                        .filter(|&(slot, _)| slot != 1)
+                        .map(|(slot, item)| EquipmentEntry {
+                            slot: slot as i8,
+                            item: item.clone(),
+                        })
+                        .collect(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,595 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::command::{
+    find_client, sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console,
+};
+use crate::format::{fill_placeholders, format_duration, legacy_text, parse_duration};
+use crate::kick;
+use crate::lang::Lang;
+use crate::persistence::{Persistence, WriteKind};
+use crate::profiles::{KnownPlayer, Profiles};
+use crate::reload::ConfigReloaded;
+
+const KICK: CommandInfo = CommandInfo {
+    name: "kick",
+    aliases: &[],
+    usage: "/kick <player> [reason]",
+    description: "Disconnect a player.",
+    permission: Some("plots.command.kick"),
+    console: true,
+};
+
+const BAN: CommandInfo = CommandInfo {
+    name: "ban",
+    aliases: &[],
+    usage: "/ban <player> [duration] [reason]",
+    description: "Ban a player, for good or for a while like 7d12h.",
+    permission: Some("plots.command.ban"),
+    console: true,
+};
+
+const BANIP: CommandInfo = CommandInfo {
+    name: "banip",
+    aliases: &["ban-ip"],
+    usage: "/banip <player|address> [duration] [reason]",
+    description: "Ban an IP address.",
+    permission: Some("plots.command.banip"),
+    console: true,
+};
+
+const UNBAN: CommandInfo = CommandInfo {
+    name: "unban",
+    aliases: &["pardon"],
+    usage: "/unban <player|address>",
+    description: "Lift a ban.",
+    permission: Some("plots.command.unban"),
+    console: true,
+};
+
+const BANINFO: CommandInfo = CommandInfo {
+    name: "baninfo",
+    aliases: &[],
+    usage: "/baninfo <player>",
+    description: "Show a player's ban history.",
+    permission: Some("plots.command.baninfo"),
+    console: true,
+};
+
+const DEFAULT_FILE: &str = "bans.toml";
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A ban on a player, an IP address, or both. Lifted and expired bans are
+/// kept as history.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ban {
+    pub uuid: Option<Uuid>,
+    pub ip: Option<IpAddr>,
+    /// The player's name when they were banned, or the address.
+    pub name: String,
+    pub reason: Option<String>,
+    pub banned_by: String,
+    /// Unix timestamps, in seconds.
+    pub created: u64,
+    pub expires: Option<u64>,
+    /// Who lifted the ban with `/unban`, if anyone.
+    pub lifted_by: Option<String>,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.lifted_by.is_none() && self.expires.map_or(true, |expires| now < expires)
+    }
+
+    fn applies_to(&self, uuid: Uuid, ip: IpAddr) -> bool {
+        self.uuid == Some(uuid) || self.ip == Some(ip)
+    }
+}
+
+/// Message templates for telling a player why they're banned.
+#[derive(Clone, Default, Debug)]
+struct BanMessages {
+    permanent: String,
+    temporary: String,
+    default_reason: String,
+}
+
+impl BanMessages {
+    fn in_language(lang: &Lang, client: Option<Entity>) -> Self {
+        let get = |key| match client {
+            Some(client) => lang.plain(client, key).to_owned(),
+            None => lang.plain_default(key).to_owned(),
+        };
+
+        Self {
+            permanent: get("ban.message"),
+            temporary: get("ban.message_temporary"),
+            default_reason: get("ban.default_reason"),
+        }
+    }
+
+    fn render(&self, ban: &Ban, now: u64) -> Text {
+        let reason = ban.reason.as_deref().unwrap_or(&self.default_reason);
+        let text = match ban.expires {
+            None => fill_placeholders(&self.permanent, &[("reason", &reason)]),
+            Some(expires) => {
+                let remaining = format_duration(Duration::from_secs(expires.saturating_sub(now)));
+                fill_placeholders(
+                    &self.temporary,
+                    &[("reason", &reason), ("remaining", &remaining)],
+                )
+            }
+        };
+        legacy_text(&text)
+    }
+}
+
+/// Every ban ever made, stored as TOML.
+#[derive(Default, Debug)]
+pub struct BanList {
+    bans: Vec<Ban>,
+    /// The ban message in the default language, for the login check.
+    messages: BanMessages,
+    path: PathBuf,
+}
+
+/// The on-disk form of the ban list.
+#[derive(Serialize, Deserialize, Default)]
+struct BanFile {
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    /// Reads the ban list, starting with none if the file doesn't exist yet.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = PathBuf::from(DEFAULT_FILE);
+
+        let file: BanFile = if path.exists() {
+            let contents =
+                fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+            toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))?
+        } else {
+            BanFile::default()
+        };
+
+        Ok(Self {
+            bans: file.bans,
+            messages: BanMessages::default(),
+            path,
+        })
+    }
+
+    /// The most recent ban currently keeping a player out.
+    pub fn active(&self, uuid: Uuid, ip: IpAddr) -> Option<&Ban> {
+        let now = now_secs();
+        self.bans
+            .iter()
+            .rev()
+            .find(|ban| ban.is_active(now) && ban.applies_to(uuid, ip))
+    }
+
+    /// Why a player can't join, if they're banned.
+    pub fn rejection(&self, uuid: Uuid, ip: IpAddr) -> Option<Text> {
+        self.active(uuid, ip)
+            .map(|ban| self.messages.render(ban, now_secs()))
+    }
+
+    /// Queues the list to be written. Failing to write it is reported
+    /// later, as a [`WriteFailed`](crate::persistence::WriteFailed).
+    fn save(&self, persistence: &Persistence) -> anyhow::Result<()> {
+        let file = BanFile {
+            bans: self.bans.clone(),
+        };
+        let contents = toml::to_string(&file)?;
+        persistence.write(WriteKind::Bans, self.path.clone(), contents.into_bytes());
+        Ok(())
+    }
+}
+
+/// A handle to the [`BanList`] shared with the login callback.
+#[derive(Resource, Clone, Default)]
+pub struct SharedBans(Arc<RwLock<BanList>>);
+
+impl SharedBans {
+    pub fn new(bans: BanList) -> Self {
+        Self(Arc::new(RwLock::new(bans)))
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, BanList> {
+        self.0.read().unwrap()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BanList> {
+        self.0.write().unwrap()
+    }
+}
+
+pub struct BanPlugin;
+
+impl Plugin for BanPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(KICK)
+            .add_command(BAN)
+            .add_command(BANIP)
+            .add_command(UNBAN)
+            .add_command(BANINFO)
+            .add_startup_system(render_messages)
+            .add_system(rerender_messages)
+            .add_system_to_stage(EventLoop, kick_command)
+            .add_system_to_stage(EventLoop, ban_commands)
+            .add_system_to_stage(EventLoop, unban_command)
+            .add_system_to_stage(EventLoop, baninfo_command);
+    }
+}
+
+fn render_messages(bans: Res<SharedBans>, lang: Res<Lang>) {
+    bans.write().messages = BanMessages::in_language(&lang, None);
+}
+
+fn rerender_messages(
+    bans: Res<SharedBans>,
+    lang: Res<Lang>,
+    mut events: EventReader<ConfigReloaded>,
+) {
+    if events.iter().count() > 0 {
+        render_messages(bans, lang);
+    }
+}
+
+/// Splits the arguments after a player into an optional duration and an
+/// optional reason.
+pub fn duration_and_reason(args: &[String]) -> (Option<Duration>, Option<String>) {
+    let (duration, rest) = match args.split_first() {
+        Some((first, rest)) => match parse_duration(first) {
+            Some(duration) => (Some(duration), rest),
+            None => (None, args),
+        },
+        None => (None, args),
+    };
+
+    let reason = (!rest.is_empty()).then(|| rest.join(" "));
+    (duration, reason)
+}
+
+/// Sends a command's reply to whoever ran it.
+fn reply(
+    clients: &mut Query<(Entity, &mut Client)>,
+    consoles: &mut Query<&mut Console>,
+    sender: Entity,
+    reply: Text,
+) {
+    if let Ok((_, mut client)) = clients.get_mut(sender) {
+        client.send_message(reply);
+    } else if let Ok(mut console) = consoles.get_mut(sender) {
+        console.send_message(reply);
+    }
+}
+
+fn kick_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(KICK.name)) {
+        let Some((name, rest)) = event.args.split_first() else {
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, &KICK),
+            );
+            continue;
+        };
+
+        let Some(target) = find_client(clients.iter(), name) else {
+            let text = lang.tr(event.sender, "command.not_online", &[("name", name)]);
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        };
+
+        let kicked_by = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+
+        let Ok((_, mut client)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        let reason = if rest.is_empty() {
+            lang.plain(target, "kick.default_reason").to_owned()
+        } else {
+            rest.join(" ")
+        };
+        let username = client.username().to_string();
+
+        info!("{kicked_by} kicked {username}: {reason}");
+        kick(
+            &mut client,
+            lang.tr(target, "kick.message", &[("reason", &reason)]),
+        );
+
+        let text = lang.tr(event.sender, "kick.done", &[("name", &username)]);
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+/// Kicks every online player a new ban applies to.
+fn kick_banned(clients: &mut Query<(Entity, &mut Client)>, ban: &Ban, lang: &Lang) {
+    let now = now_secs();
+
+    for (entity, mut client) in clients.iter_mut() {
+        if !client.is_disconnected() && ban.applies_to(client.uuid(), client.ip()) {
+            let message = BanMessages::in_language(lang, Some(entity)).render(ban, now);
+            kick(&mut client, message);
+        }
+    }
+}
+
+/// The reply to a change, or a warning if it couldn't be saved.
+fn saved_reply(saved: anyhow::Result<()>, reply: Text, sender: Entity, lang: &Lang) -> Text {
+    match saved {
+        Ok(()) => reply,
+        Err(e) => {
+            warn!("Failed to save bans: {e:#}");
+            lang.tr(sender, "ban.unsaved", &[])
+        }
+    }
+}
+
+fn ban_commands(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    bans: Res<SharedBans>,
+    mut profiles: ResMut<Profiles>,
+    persistence: Res<Persistence>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter() {
+        let by_ip = if event.is(BAN.name) {
+            false
+        } else if event.is(BANIP.name) {
+            true
+        } else {
+            continue;
+        };
+
+        let Some((target, rest)) = event.args.split_first() else {
+            let info = if by_ip { &BANIP } else { &BAN };
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, info),
+            );
+            continue;
+        };
+
+        let (uuid, ip, name) = if by_ip {
+            let ip = target.parse::<IpAddr>().ok().or_else(|| {
+                let entity = find_client(clients.iter(), target)?;
+                clients.get(entity).ok().map(|(_, c)| c.ip())
+            });
+            let Some(ip) = ip else {
+                let text = lang.tr(event.sender, "command.not_online", &[("name", target)]);
+                reply(&mut clients, &mut consoles, event.sender, text);
+                continue;
+            };
+            (None, Some(ip), ip.to_string())
+        } else {
+            let Some(KnownPlayer { uuid, name }) = profiles.resolve(target, event) else {
+                continue;
+            };
+            (Some(uuid), None, name)
+        };
+
+        let (duration, reason) = duration_and_reason(rest);
+        let banned_by = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+
+        let mut list = bans.write();
+        let now = now_secs();
+
+        let already = list
+            .bans
+            .iter()
+            .any(|ban| ban.is_active(now) && ban.uuid == uuid && ban.ip == ip);
+        if already {
+            let text = lang.tr(event.sender, "ban.already", &[("name", &name)]);
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        }
+
+        let ban = Ban {
+            uuid,
+            ip,
+            name: name.clone(),
+            reason,
+            banned_by: banned_by.clone(),
+            created: now,
+            expires: duration.map(|d| now.saturating_add(d.as_secs())),
+            lifted_by: None,
+        };
+
+        info!(
+            "{banned_by} banned {name} {}: {}",
+            duration.map_or("permanently".to_owned(), |d| format!(
+                "for {}",
+                format_duration(d)
+            )),
+            ban.reason.as_deref().unwrap_or("no reason given"),
+        );
+
+        kick_banned(&mut clients, &ban, &lang);
+        list.bans.push(ban);
+
+        let text = match duration {
+            None => lang.tr(event.sender, "ban.done", &[("name", &name)]),
+            Some(duration) => lang.tr(
+                event.sender,
+                "ban.done_temporary",
+                &[("name", &name), ("duration", &format_duration(duration))],
+            ),
+        };
+        let text = saved_reply(list.save(&persistence), text, event.sender, &lang);
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn unban_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    bans: Res<SharedBans>,
+    mut profiles: ResMut<Profiles>,
+    persistence: Res<Persistence>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(UNBAN.name)) {
+        let [target] = event.args.as_slice() else {
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, &UNBAN),
+            );
+            continue;
+        };
+
+        let matches: Box<dyn Fn(&Ban) -> bool> = match target.parse::<IpAddr>() {
+            Ok(ip) => Box::new(move |ban: &Ban| ban.ip == Some(ip)),
+            Err(_) => {
+                let Some(player) = profiles.resolve(target, event) else {
+                    continue;
+                };
+                Box::new(move |ban: &Ban| ban.uuid == Some(player.uuid))
+            }
+        };
+
+        let lifted_by = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+
+        let mut list = bans.write();
+        let now = now_secs();
+        let mut lifted = false;
+
+        for ban in list.bans.iter_mut() {
+            if ban.is_active(now) && matches(ban) {
+                ban.lifted_by = Some(lifted_by.clone());
+                lifted = true;
+            }
+        }
+
+        let text = if lifted {
+            info!("{lifted_by} unbanned {target}");
+            let text = lang.tr(event.sender, "ban.unbanned", &[("name", target)]);
+            saved_reply(list.save(&persistence), text, event.sender, &lang)
+        } else {
+            lang.tr(event.sender, "ban.not_banned", &[("name", target)])
+        };
+
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn baninfo_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    bans: Res<SharedBans>,
+    mut profiles: ResMut<Profiles>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(BANINFO.name)) {
+        let [target] = event.args.as_slice() else {
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, &BANINFO),
+            );
+            continue;
+        };
+
+        let Some(player) = profiles.resolve(target, event) else {
+            continue;
+        };
+
+        let text = ban_history(&bans.read(), &player, event.sender, &lang);
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn ban_history(list: &BanList, player: &KnownPlayer, sender: Entity, lang: &Lang) -> Text {
+    let history: Vec<_> = list
+        .bans
+        .iter()
+        .filter(|ban| ban.uuid == Some(player.uuid))
+        .collect();
+
+    if history.is_empty() {
+        return lang.tr(sender, "ban.none", &[("name", &player.name)]);
+    }
+
+    let now = now_secs();
+    let mut out = lang.tr(sender, "ban.history", &[("name", &player.name)]);
+
+    for ban in history {
+        let ago = format_duration(Duration::from_secs(now.saturating_sub(ban.created)));
+        let length = match ban.expires {
+            None => lang.plain(sender, "ban.permanent").to_owned(),
+            Some(expires) => fill_placeholders(
+                lang.plain(sender, "ban.for"),
+                &[(
+                    "duration",
+                    &format_duration(Duration::from_secs(expires.saturating_sub(ban.created))),
+                )],
+            ),
+        };
+        let state = match &ban.lifted_by {
+            Some(by) => fill_placeholders(lang.plain(sender, "ban.lifted"), &[("name", by)]),
+            None if ban.is_active(now) => lang.plain(sender, "ban.active").to_owned(),
+            None => lang.plain(sender, "ban.expired").to_owned(),
+        };
+        let reason = ban
+            .reason
+            .as_deref()
+            .unwrap_or_else(|| lang.plain(sender, "ban.default_reason"));
+
+        out = out
+            + "\n"
+            + lang.tr(
+                sender,
+                "ban.entry",
+                &[
+                    ("ago", &ago),
+                    ("by", &ban.banned_by),
+                    ("length", &length),
+                    ("reason", &reason),
+                    ("state", &state),
+                ],
+            );
+    }
+
+    out
+}
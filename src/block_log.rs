@@ -0,0 +1,438 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::ban::now_secs;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::format::{format_date, format_duration, parse_duration};
+use crate::lang::Lang;
+use crate::persistence::{Persistence, WriteKind};
+use crate::WorldName;
+
+const BLOCKLOG: CommandInfo = CommandInfo {
+    name: "blocklog",
+    aliases: &["bl"],
+    usage: "/blocklog lookup <radius> [time]",
+    description: "Show who changed blocks near you, by default over the last three days.",
+    permission: Some("plots.command.blocklog"),
+    console: false,
+};
+
+const MAX_RADIUS: i32 = 32;
+const DEFAULT_LOOKUP: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+/// How many changes a lookup shows, newest first.
+const LOOKUP_LIMIT: usize = 10;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Who a block change is logged as.
+#[derive(Clone, Debug)]
+pub struct Actor {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+impl Actor {
+    pub fn of(client: &Client) -> Self {
+        Self {
+            uuid: client.uuid(),
+            name: client.username().to_string(),
+        }
+    }
+}
+
+/// Sent by whatever changes a block on a player's behalf, to be logged.
+pub struct BlockChanged {
+    /// Who caused it. Changes can come a while after, like TNT going off,
+    /// so this is kept rather than looked up from a client who may have
+    /// left or gone to another world since.
+    pub actor: Actor,
+    /// The instance the block is in.
+    pub instance: Entity,
+    pub position: BlockPos,
+    pub old: BlockState,
+    pub new: BlockState,
+}
+
+/// One line of the log. Changes are written in the order they happened, so
+/// setting `old` back on each, newest first, undoes them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockRecord {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub actor: Uuid,
+    /// The actor's name at the time, so lookups don't need to resolve it.
+    pub name: String,
+    pub world: String,
+    pub pos: [i32; 3],
+    /// Raw block state IDs.
+    pub old: u16,
+    pub new: u16,
+}
+
+impl BlockRecord {
+    pub fn old_state(&self) -> BlockState {
+        BlockState::from_raw(self.old).unwrap_or(BlockState::AIR)
+    }
+
+    pub fn new_state(&self) -> BlockState {
+        BlockState::from_raw(self.new).unwrap_or(BlockState::AIR)
+    }
+}
+
+/// What a search of the log is for, so the right system answers it.
+#[derive(Clone, Copy, Debug)]
+pub enum SearchKind {
+    /// `/blocklog lookup`.
+    Area { radius: i32, since: Duration },
+    /// The history of one block, for the inspector.
+    Block(BlockPos),
+}
+
+/// A search of the log that has finished, for the player who asked.
+pub struct SearchFinished {
+    pub client: Entity,
+    pub kind: SearchKind,
+    pub result: anyhow::Result<Vec<BlockRecord>>,
+}
+
+/// An append-only log of block changes, one file of JSON lines per day.
+/// Each tick's changes are added to the day's file by [`Persistence`], and
+/// days older than the retention period are deleted as the log rolls over.
+#[derive(Resource)]
+pub struct BlockLog {
+    dir: PathBuf,
+    retention_days: u64,
+    /// The day last written to, to notice the log rolling over.
+    day: Option<String>,
+    runtime: Handle,
+    sender: flume::Sender<SearchFinished>,
+    receiver: flume::Receiver<SearchFinished>,
+}
+
+impl BlockLog {
+    fn new(dir: PathBuf, retention_days: u64, runtime: Handle) -> Self {
+        let (sender, receiver) = flume::unbounded();
+        let log = Self {
+            dir,
+            retention_days,
+            day: None,
+            runtime,
+            sender,
+            receiver,
+        };
+        log.prune();
+        log
+    }
+
+    /// Queues a tick's changes, all made at `time`, to be added to the log.
+    fn record(&mut self, persistence: &Persistence, time: u64, records: &[BlockRecord]) {
+        let mut lines = Vec::new();
+        for record in records {
+            match serde_json::to_writer(&mut lines, record) {
+                Ok(()) => lines.push(b'\n'),
+                Err(e) => warn!("Failed to write to the block log: {e}"),
+            }
+        }
+
+        let day = format_date(time);
+        let path = self.dir.join(format!("{day}.jsonl"));
+        persistence.append(WriteKind::BlockLog, path, lines);
+
+        let rolled_over = self.day.as_ref().map_or(false, |last| *last != day);
+        self.day = Some(day);
+        if rolled_over {
+            self.prune();
+        }
+    }
+
+    /// Reads the log since `since`, keeping the records that match. Reading
+    /// days of history shouldn't hold up the tick, so the results come back
+    /// later as a [`SearchFinished`].
+    pub fn search(
+        &self,
+        client: Entity,
+        kind: SearchKind,
+        since: u64,
+        filter: impl Fn(&BlockRecord) -> bool + Send + 'static,
+    ) {
+        let dir = self.dir.clone();
+        let results = self.sender.clone();
+
+        self.runtime.spawn_blocking(move || {
+            let result = read_since(&dir, since).map(|records| {
+                records
+                    .into_iter()
+                    .filter(|record| filter(record))
+                    .collect()
+            });
+            let _ = results.send(SearchFinished {
+                client,
+                kind,
+                result,
+            });
+        });
+    }
+
+    /// Deletes the days that are past the retention period, away from the
+    /// tick. Zero keeps everything.
+    fn prune(&self) {
+        if self.retention_days == 0 {
+            return;
+        }
+
+        let dir = self.dir.clone();
+        let oldest = format_date(now_secs().saturating_sub(self.retention_days * DAY_SECS));
+        self.runtime.spawn_blocking(move || {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                return;
+            };
+
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(day) = name.to_str().and_then(|n| n.strip_suffix(".jsonl")) else {
+                    continue;
+                };
+                if day < oldest.as_str() {
+                    match fs::remove_file(entry.path()) {
+                        Ok(()) => info!("Deleted the block log for {day}"),
+                        Err(e) => warn!("Failed to delete the block log for {day}: {e}"),
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Every change logged since `since`, oldest first. Lines that can't be
+/// read are skipped, as the last one may still be being written.
+pub fn read_since(dir: &Path, since: u64) -> anyhow::Result<Vec<BlockRecord>> {
+    let first_day = format_date(since);
+    let mut days: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let day = name.strip_suffix(".jsonl")?.to_owned();
+            (day >= first_day).then_some(day)
+        })
+        .collect();
+    days.sort();
+
+    let mut records = Vec::new();
+    for day in days {
+        let path = dir.join(format!("{day}.jsonl"));
+        let file = File::open(&path).with_context(|| format!("reading {}", path.display()))?;
+
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("reading {}", path.display()))?;
+            if let Ok(record) = serde_json::from_str::<BlockRecord>(&line) {
+                if record.time >= since {
+                    records.push(record);
+                }
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+pub struct BlockLogPlugin;
+
+impl Plugin for BlockLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BlockChanged>()
+            .add_command(BLOCKLOG)
+            .add_startup_system(init_block_log)
+            .add_system(log_block_changes)
+            .add_event::<SearchFinished>()
+            .add_system_to_stage(EventLoop, blocklog_command)
+            .add_system(finish_searches)
+            .add_system(show_lookups.after(finish_searches));
+    }
+}
+
+fn init_block_log(mut commands: Commands, server: Res<Server>, config: Res<Config>) {
+    let dir = config.block_log.directory.clone();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        warn!("Failed to create {}: {e}", dir.display());
+    }
+
+    commands.insert_resource(BlockLog::new(
+        dir,
+        config.block_log.retention_days,
+        server.tokio_handle().clone(),
+    ));
+}
+
+fn log_block_changes(
+    worlds: Query<&WorldName>,
+    mut log: ResMut<BlockLog>,
+    persistence: Res<Persistence>,
+    mut events: EventReader<BlockChanged>,
+) {
+    let time = now_secs();
+    let mut records = Vec::new();
+    for event in events.iter() {
+        if event.old == event.new {
+            continue;
+        }
+        let Ok(world) = worlds.get(event.instance) else {
+            continue;
+        };
+
+        let pos = event.position;
+        records.push(BlockRecord {
+            time,
+            actor: event.actor.uuid,
+            name: event.actor.name.clone(),
+            world: world.0.clone(),
+            pos: [pos.x, pos.y, pos.z],
+            old: event.old.to_raw(),
+            new: event.new.to_raw(),
+        });
+    }
+
+    if !records.is_empty() {
+        log.record(&persistence, time, &records);
+    }
+}
+
+fn blocklog_command(
+    mut clients: Query<&mut Client>,
+    worlds: Query<&WorldName>,
+    log: Res<BlockLog>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(BLOCKLOG.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let (radius, since) = match event.args.as_slice() {
+            [action, radius] if action == "lookup" => {
+                (radius.parse::<i32>().ok(), Some(DEFAULT_LOOKUP))
+            }
+            [action, radius, time] if action == "lookup" => {
+                (radius.parse::<i32>().ok(), parse_duration(time))
+            }
+            _ => (None, None),
+        };
+        let (Some(radius), Some(since)) = (radius.filter(|&r| r >= 0), since) else {
+            client.send_message(usage(&lang, event.sender, &BLOCKLOG));
+            continue;
+        };
+        if radius > MAX_RADIUS {
+            client.send_message(lang.tr(event.sender, "blocklog.too_far", &[("max", &MAX_RADIUS)]));
+            continue;
+        }
+        let Ok(world) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        let pos = client.position();
+        let center = [
+            pos.x.floor() as i32,
+            pos.y.floor() as i32,
+            pos.z.floor() as i32,
+        ];
+        let world = world.0.clone();
+        log.search(
+            event.sender,
+            SearchKind::Area { radius, since },
+            now_secs().saturating_sub(since.as_secs()),
+            move |record| {
+                record.world == world && (0..3).all(|i| (record.pos[i] - center[i]).abs() <= radius)
+            },
+        );
+    }
+}
+
+/// One change, like who broke what and how long ago.
+pub fn describe(lang: &Lang, client: Entity, record: &BlockRecord, now: u64) -> Text {
+    let (old, new) = (record.old_state(), record.new_state());
+    let (key, block) = if old.is_air() {
+        ("blocklog.placed", new)
+    } else if new.is_air() {
+        ("blocklog.broke", old)
+    } else {
+        ("blocklog.replaced", new)
+    };
+
+    let ago = format_duration(Duration::from_secs(now.saturating_sub(record.time)));
+    lang.tr(
+        client,
+        key,
+        &[
+            ("ago", &ago),
+            ("name", &record.name),
+            ("block", &block.to_kind().to_str()),
+            ("old", &old.to_kind().to_str()),
+            ("x", &record.pos[0]),
+            ("y", &record.pos[1]),
+            ("z", &record.pos[2]),
+        ],
+    )
+}
+
+pub fn finish_searches(log: Res<BlockLog>, mut finished: EventWriter<SearchFinished>) {
+    finished.send_batch(log.receiver.try_iter());
+}
+
+fn show_lookups(
+    mut clients: Query<&mut Client>,
+    lang: Res<Lang>,
+    mut events: EventReader<SearchFinished>,
+) {
+    for lookup in events.iter() {
+        let SearchKind::Area { radius, since } = lookup.kind else {
+            continue;
+        };
+        // They may have left while it was running.
+        let Ok(mut client) = clients.get_mut(lookup.client) else {
+            continue;
+        };
+
+        let records = match &lookup.result {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("Failed to look up block changes: {e:#}");
+                client.send_message(lang.tr(lookup.client, "blocklog.failed", &[]));
+                continue;
+            }
+        };
+        if records.is_empty() {
+            client.send_message(lang.tr(lookup.client, "blocklog.none", &[]));
+            continue;
+        }
+
+        let now = now_secs();
+        let mut out = lang.tr(
+            lookup.client,
+            "blocklog.header",
+            &[("radius", &radius), ("time", &format_duration(since))],
+        );
+        for record in records.iter().rev().take(LOOKUP_LIMIT) {
+            out = out + "\n" + describe(&lang, lookup.client, record, now);
+        }
+        if records.len() > LOOKUP_LIMIT {
+            out = out
+                + "\n"
+                + lang.tr(
+                    lookup.client,
+                    "blocklog.more",
+                    &[("count", &(records.len() - LOOKUP_LIMIT))],
+                );
+        }
+
+        client.send_message(out);
+    }
+}
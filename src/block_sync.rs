@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use valence::client::event::{FinishDigging, StartDigging, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{AcknowledgeBlockChange, BlockUpdate};
+use valence_protocol::VarInt;
+
+pub struct BlockSyncPlugin;
+
+impl Plugin for BlockSyncPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(acknowledge_edits);
+    }
+}
+
+/// Sends a client the block at `pos` as the server has it, undoing whatever
+/// it guessed an edit there would do.
+pub fn resend_block(client: &mut Client, instance: &Instance, pos: BlockPos) {
+    if let Some(block) = instance.block(pos) {
+        client.write_packet(&BlockUpdate {
+            position: pos,
+            block_id: VarInt(block.state().to_raw() as i32),
+        });
+    }
+}
+
+/// Tells each client which of its digs and placements have been dealt with.
+/// Clients show their own edits straight away, and keep them until they're
+/// acknowledged; then they take whatever the server sent for those blocks,
+/// so edits that were refused or changed don't leave ghost blocks behind.
+///
+/// Every edit is dealt with in the tick it arrives, whatever came of it, so
+/// acknowledging the newest sequence a client sent this tick covers them all.
+fn acknowledge_edits(
+    mut clients: Query<&mut Client>,
+    mut start_digging: EventReader<StartDigging>,
+    mut finish_digging: EventReader<FinishDigging>,
+    mut use_item: EventReader<UseItemOnBlock>,
+    mut newest: Local<HashMap<Entity, i32>>,
+) {
+    let sequences = start_digging
+        .iter()
+        .map(|e| (e.client, e.sequence))
+        .chain(finish_digging.iter().map(|e| (e.client, e.sequence)))
+        .chain(use_item.iter().map(|e| (e.client, e.sequence)));
+    for (client, sequence) in sequences {
+        let newest = newest.entry(client).or_insert(sequence);
+        *newest = (*newest).max(sequence);
+    }
+
+    for (entity, sequence) in newest.drain() {
+        if let Ok(mut client) = clients.get_mut(entity) {
+            client.write_packet(&AcknowledgeBlockChange {
+                sequence: VarInt(sequence),
+            });
+        }
+    }
+}
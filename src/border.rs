@@ -0,0 +1,306 @@
+use tracing::warn;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{InitializeWorldBorder, SetBorderLerpSize};
+use valence_protocol::{VarInt, VarLong};
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::{BorderConfig, Config, MAX_BORDER_RADIUS};
+use crate::hud::{Hud, SUPPRESS_FOR};
+use crate::lang::Lang;
+use crate::persistence::Persistence;
+use crate::WorldName;
+
+const WORLDBORDER: CommandInfo = CommandInfo {
+    name: "worldborder",
+    aliases: &[],
+    usage: "/worldborder set <radius> [seconds]",
+    description: "Resize the border of your world.",
+    permission: Some("plots.command.worldborder"),
+    console: false,
+};
+
+/// How long `/worldborder set` takes to move the border when no time is
+/// given.
+const DEFAULT_RESIZE_SECS: u64 = 5;
+
+/// The longest `/worldborder set` can take to move the border, a day.
+const MAX_RESIZE_SECS: u64 = 86_400;
+
+/// The diameter the client uses when there's no border at all.
+const NO_BORDER_DIAMETER: f64 = 59_999_968.0;
+
+/// How close to the border players are put back when they cross it.
+const PUSH_BACK: f64 = 0.5;
+
+const WARNING_BLOCKS: i32 = 5;
+const WARNING_SECS: i32 = 15;
+
+/// A square border around an instance. Resizing moves the border gradually
+/// over `lerp_total` ticks, both here and on clients.
+#[derive(Component, Debug)]
+pub struct WorldBorder {
+    center: [f64; 2],
+    old_radius: f64,
+    radius: f64,
+    lerp_total: u64,
+    lerp_elapsed: u64,
+    /// Bumped on every resize so viewers know to update.
+    revision: u64,
+}
+
+impl WorldBorder {
+    fn new(config: &BorderConfig) -> Self {
+        Self {
+            center: [config.center_x, config.center_z],
+            old_radius: config.radius,
+            radius: config.radius,
+            lerp_total: 0,
+            lerp_elapsed: 0,
+            revision: 0,
+        }
+    }
+
+    /// The radius right now, partway through any resize.
+    pub fn current_radius(&self) -> f64 {
+        if self.lerp_elapsed >= self.lerp_total {
+            return self.radius;
+        }
+
+        let progress = self.lerp_elapsed as f64 / self.lerp_total as f64;
+        self.old_radius + (self.radius - self.old_radius) * progress
+    }
+
+    pub fn resize(&mut self, radius: f64, ticks: u64) {
+        self.old_radius = self.current_radius();
+        self.radius = radius;
+        self.lerp_total = ticks;
+        self.lerp_elapsed = 0;
+        self.revision += 1;
+    }
+
+    fn remaining_millis(&self) -> i64 {
+        let ticks = self.lerp_total.saturating_sub(self.lerp_elapsed);
+        i64::try_from(ticks.saturating_mul(50)).unwrap_or(i64::MAX)
+    }
+
+    pub fn contains_block(&self, pos: BlockPos) -> bool {
+        let radius = self.current_radius();
+        let [cx, cz] = self.center;
+
+        pos.x as f64 >= cx - radius
+            && pos.x as f64 + 1.0 <= cx + radius
+            && pos.z as f64 >= cz - radius
+            && pos.z as f64 + 1.0 <= cz + radius
+    }
+}
+
+/// Whether `pos` may be edited in `instance`, i.e. it's inside the border or
+/// there is none.
+pub fn inside_border(borders: &Query<&WorldBorder>, instance: Entity, pos: BlockPos) -> bool {
+    borders
+        .get(instance)
+        .map_or(true, |border| border.contains_block(pos))
+}
+
+/// The border a client was last shown, so joining, changing worlds and
+/// resizes can be told apart.
+#[derive(Component, Default, Debug)]
+struct BorderViewer {
+    instance: Option<Entity>,
+    revision: Option<u64>,
+}
+
+pub struct BorderPlugin;
+
+impl Plugin for BorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(WORLDBORDER)
+            .add_system(init_borders)
+            .add_system(init_border_viewers)
+            .add_system(advance_borders)
+            .add_system_to_stage(EventLoop, worldborder_command)
+            .add_system(enforce_borders.after(advance_borders))
+            .add_system(
+                sync_borders
+                    .after(advance_borders)
+                    .after(init_border_viewers),
+            );
+    }
+}
+
+fn init_borders(
+    mut commands: Commands,
+    instances: Query<(Entity, &WorldName), Added<Instance>>,
+    config: Res<Config>,
+) {
+    for (entity, name) in &instances {
+        if let Some(border) = &config.world(&name.0).border {
+            commands.entity(entity).insert(WorldBorder::new(border));
+        }
+    }
+}
+
+fn init_border_viewers(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(BorderViewer::default());
+    }
+}
+
+fn advance_borders(mut borders: Query<&mut WorldBorder>) {
+    for mut border in &mut borders {
+        if border.lerp_elapsed < border.lerp_total {
+            border.lerp_elapsed += 1;
+        }
+    }
+}
+
+fn worldborder_command(
+    mut commands: Commands,
+    mut clients: Query<&mut Client>,
+    mut borders: Query<&mut WorldBorder>,
+    worlds: Query<&WorldName>,
+    (mut config, persistence): (ResMut<Config>, Res<Persistence>),
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(WORLDBORDER.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let Ok(name) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        let parsed = match event.args.as_slice() {
+            [action, radius] if action == "set" => radius
+                .parse::<f64>()
+                .ok()
+                .map(|radius| (radius, DEFAULT_RESIZE_SECS)),
+            [action, radius, secs] if action == "set" => {
+                radius.parse::<f64>().ok().zip(secs.parse::<u64>().ok())
+            }
+            _ => None,
+        };
+
+        let valid = |&(radius, secs): &(f64, u64)| {
+            (1.0..=MAX_BORDER_RADIUS).contains(&radius) && secs <= MAX_RESIZE_SECS
+        };
+        let Some((radius, secs)) = parsed.filter(valid) else {
+            client.send_message(usage(&lang, event.sender, &WORLDBORDER));
+            continue;
+        };
+
+        let world = config.worlds.entry(name.0.clone()).or_default();
+        let border_config = world.border.get_or_insert_with(Default::default);
+        border_config.radius = radius;
+
+        match borders.get_mut(client.instance()) {
+            Ok(mut border) => border.resize(radius, secs * 20),
+            Err(_) => {
+                let border = WorldBorder::new(border_config);
+                commands.entity(client.instance()).insert(border);
+            }
+        }
+
+        let reply = match config.save(&persistence) {
+            Ok(()) => lang.tr(
+                event.sender,
+                "border.set",
+                &[("world", &name.0), ("radius", &radius)],
+            ),
+            Err(e) => {
+                warn!("Failed to save config: {e:#}");
+                lang.tr(event.sender, "border.set_unsaved", &[])
+            }
+        };
+
+        client.send_message(reply);
+    }
+}
+
+/// Puts players who crossed the border back just inside it.
+fn enforce_borders(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut huds: Query<&mut Hud>,
+    borders: Query<&WorldBorder>,
+    lang: Res<Lang>,
+) {
+    for (entity, mut client) in &mut clients {
+        let Ok(border) = borders.get(client.instance()) else {
+            continue;
+        };
+
+        let radius = (border.current_radius() - PUSH_BACK).max(0.0);
+        let [cx, cz] = border.center;
+        let pos = client.position();
+
+        let x = pos.x.clamp(cx - radius, cx + radius);
+        let z = pos.z.clamp(cz - radius, cz + radius);
+
+        if x != pos.x || z != pos.z {
+            client.set_position([x, pos.y, z]);
+            client.set_action_bar(lang.tr(entity, "border.reached", &[]));
+            if let Ok(mut hud) = huds.get_mut(entity) {
+                hud.suppress(SUPPRESS_FOR);
+            }
+        }
+    }
+}
+
+fn init_packet(border: Option<&WorldBorder>) -> InitializeWorldBorder {
+    let (center, old_diameter, new_diameter, millis) = match border {
+        Some(border) => (
+            border.center,
+            border.current_radius() * 2.0,
+            border.radius * 2.0,
+            border.remaining_millis(),
+        ),
+        None => ([0.0, 0.0], NO_BORDER_DIAMETER, NO_BORDER_DIAMETER, 0),
+    };
+
+    InitializeWorldBorder {
+        x: center[0],
+        z: center[1],
+        old_diameter,
+        new_diameter,
+        speed: VarLong(millis),
+        portal_teleport_boundary: VarInt(29_999_984),
+        warning_blocks: VarInt(WARNING_BLOCKS),
+        warning_time: VarInt(WARNING_SECS),
+    }
+}
+
+/// Shows clients the border of the world they're in, including any resize
+/// that's still in progress.
+fn sync_borders(
+    mut clients: Query<(&mut Client, &mut BorderViewer)>,
+    borders: Query<&WorldBorder>,
+) {
+    for (mut client, mut viewer) in &mut clients {
+        let border = borders.get(client.instance()).ok();
+        let revision = border.map(|b| b.revision);
+
+        if viewer.instance != Some(client.instance()) {
+            // Clients start without a border, so only worlds with one need
+            // anything sent on arrival.
+            if border.is_some() || viewer.revision.is_some() {
+                client.write_packet(&init_packet(border));
+            }
+        } else if viewer.revision != revision {
+            match border {
+                Some(border) if viewer.revision.is_some() => {
+                    client.write_packet(&SetBorderLerpSize {
+                        old_diameter: border.old_radius * 2.0,
+                        new_diameter: border.radius * 2.0,
+                        speed: VarLong(border.remaining_millis()),
+                    });
+                }
+                _ => client.write_packet(&init_packet(border)),
+            }
+        }
+
+        viewer.instance = Some(client.instance());
+        viewer.revision = revision;
+    }
+}
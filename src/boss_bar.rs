@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use valence::prelude::*;
+use valence_protocol::packets::s2c::boss_bar::{BossBarAction, BossBarFlags};
+use valence_protocol::packets::s2c::play::BossBar as BossBarPacket;
+pub use valence_protocol::packets::s2c::boss_bar::{BossBarColor, BossBarDivision};
+
+use crate::config::Config;
+use crate::format::{fill_placeholders, legacy_text};
+use crate::tps::Tps;
+
+/// Identifies a bar created through [`BossBars`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BossBarId(Uuid);
+
+/// Who a boss bar is shown to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BossBarTarget {
+    All,
+}
+
+impl BossBarTarget {
+    fn includes(self) -> bool {
+        match self {
+            BossBarTarget::All => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BossBar {
+    title: Text,
+    color: BossBarColor,
+    division: BossBarDivision,
+    progress: f32,
+    target: BossBarTarget,
+    /// Bumped on every change so viewers know to resend the bar.
+    revision: u64,
+}
+
+impl BossBar {
+    pub fn new(title: impl Into<Text>, target: BossBarTarget) -> Self {
+        Self {
+            title: title.into(),
+            color: BossBarColor::Purple,
+            division: BossBarDivision::NoDivision,
+            progress: 1.0,
+            target,
+            revision: 0,
+        }
+    }
+
+    pub fn with_style(mut self, color: BossBarColor, division: BossBarDivision) -> Self {
+        self.color = color;
+        self.division = division;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: f32) -> Self {
+        self.progress = progress.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn set_title(&mut self, title: impl Into<Text>) {
+        self.title = title.into();
+        self.revision += 1;
+    }
+
+    pub fn set_style(&mut self, color: BossBarColor, division: BossBarDivision) {
+        self.color = color;
+        self.division = division;
+        self.revision += 1;
+    }
+
+    pub fn set_progress(&mut self, progress: f32) {
+        self.progress = progress.clamp(0.0, 1.0);
+        self.revision += 1;
+    }
+}
+
+/// All active boss bars. Clients are sent add/remove packets automatically as
+/// they move in and out of each bar's target.
+#[derive(Resource, Default)]
+pub struct BossBars {
+    next_id: u128,
+    bars: HashMap<BossBarId, BossBar>,
+}
+
+impl BossBars {
+    pub fn create(&mut self, bar: BossBar) -> BossBarId {
+        self.next_id += 1;
+        let id = BossBarId(Uuid::from_u128(self.next_id));
+        self.bars.insert(id, bar);
+        id
+    }
+
+    pub fn get_mut(&mut self, id: BossBarId) -> Option<&mut BossBar> {
+        self.bars.get_mut(&id)
+    }
+
+    pub fn remove(&mut self, id: BossBarId) -> Option<BossBar> {
+        self.bars.remove(&id)
+    }
+}
+
+/// The bars a client has been sent, and the revision each was sent at.
+#[derive(Component, Default, Debug)]
+pub struct BossBarViewer {
+    shown: HashMap<BossBarId, u64>,
+}
+
+/// The always-on status bar, if enabled in config.
+#[derive(Resource, Default)]
+struct StatusBar(Option<BossBarId>);
+
+pub struct BossBarPlugin;
+
+impl Plugin for BossBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BossBars>()
+            .init_resource::<StatusBar>()
+            .add_system(init_viewers)
+            .add_system(update_status_bar)
+            .add_system(sync_boss_bars.after(init_viewers).after(update_status_bar));
+    }
+}
+
+fn init_viewers(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(BossBarViewer::default());
+    }
+}
+
+fn update_status_bar(
+    mut bars: ResMut<BossBars>,
+    mut status: ResMut<StatusBar>,
+    config: Res<Config>,
+    tps: Res<Tps>,
+    mut last_text: Local<String>,
+) {
+    let settings = &config.boss_bar;
+
+    if !settings.status_enabled {
+        if let Some(id) = status.0.take() {
+            bars.remove(id);
+            last_text.clear();
+        }
+        return;
+    }
+
+    let text = fill_placeholders(&settings.status_text, &[("tps", &format!("{:.1}", tps.get()))]);
+    if status.0.is_some() && text == *last_text {
+        return;
+    }
+
+    let progress = (tps.get() / 20.0) as f32;
+    let color = status_color(tps.get());
+    match status.0.and_then(|id| bars.get_mut(id)) {
+        Some(bar) => {
+            bar.set_title(legacy_text(&text));
+            bar.set_progress(progress);
+            bar.set_style(color, BossBarDivision::NoDivision);
+        }
+        None => {
+            let bar = BossBar::new(legacy_text(&text), BossBarTarget::All)
+                .with_style(color, BossBarDivision::NoDivision)
+                .with_progress(progress);
+            status.0 = Some(bars.create(bar));
+        }
+    }
+
+    *last_text = text;
+}
+
+/// Green while the server keeps up, shading to red as it falls behind.
+fn status_color(tps: f64) -> BossBarColor {
+    if tps >= 18.0 {
+        BossBarColor::Green
+    } else if tps >= 15.0 {
+        BossBarColor::Yellow
+    } else {
+        BossBarColor::Red
+    }
+}
+
+fn sync_boss_bars(mut clients: Query<(&mut Client, &mut BossBarViewer)>, bars: Res<BossBars>) {
+    for (mut client, mut viewer) in &mut clients {
+        viewer.shown.retain(|id, _| {
+            let visible = bars.bars.get(id).map_or(false, |bar| bar.target.includes());
+
+            if !visible {
+                client.write_packet(&BossBarPacket {
+                    id: id.0,
+                    action: BossBarAction::Remove,
+                });
+            }
+            visible
+        });
+
+        for (&id, bar) in &bars.bars {
+            if !bar.target.includes() {
+                continue;
+            }
+
+            match viewer.shown.get(&id) {
+                Some(&revision) if revision == bar.revision => continue,
+                Some(_) => {
+                    client.write_packet(&BossBarPacket {
+                        id: id.0,
+                        action: BossBarAction::UpdateTitle(bar.title.clone()),
+                    });
+                    client.write_packet(&BossBarPacket {
+                        id: id.0,
+                        action: BossBarAction::UpdateHealth(bar.progress),
+                    });
+                    client.write_packet(&BossBarPacket {
+                        id: id.0,
+                        action: BossBarAction::UpdateStyle(bar.color, bar.division),
+                    });
+                }
+                None => client.write_packet(&BossBarPacket {
+                    id: id.0,
+                    action: BossBarAction::Add {
+                        title: bar.title.clone(),
+                        health: bar.progress,
+                        color: bar.color,
+                        division: bar.division,
+                        flags: BossBarFlags::new(),
+                    },
+                }),
+            }
+
+            viewer.shown.insert(id, bar.revision);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use tracing::info;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{SetSubtitleText, SetTitleAnimationTimes, SetTitleText};
+
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::Config;
+use crate::format::{legacy_text, strip_legacy};
+use crate::hud::{Hud, SUPPRESS_FOR};
+use crate::lang::Lang;
+use crate::logging::AUDIT;
+
+const BROADCAST: CommandInfo = CommandInfo {
+    name: "broadcast",
+    aliases: &["bc"],
+    usage: "/broadcast [-t] [-a] <message>",
+    description: "Announce something to everyone, also as a title (-t) or on the action bar (-a).",
+    permission: Some("plots.command.broadcast"),
+    console: true,
+};
+
+/// The longest chat message a client can send, so a broadcast from the
+/// console can't be longer than one typed in game.
+const MAX_LENGTH: usize = 256;
+
+/// Title timings, in ticks.
+const TITLE_FADE_IN: i32 = 10;
+const TITLE_STAY: i32 = 70;
+const TITLE_FADE_OUT: i32 = 20;
+
+pub struct BroadcastPlugin;
+
+impl Plugin for BroadcastPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(BROADCAST)
+            .add_system_to_stage(EventLoop, broadcast_command);
+    }
+}
+
+fn broadcast_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    mut huds: Query<&mut Hud>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(BROADCAST.name)) {
+        let mut title = false;
+        let mut action_bar = false;
+        let mut words = event.args.as_slice();
+
+        while let Some((flag, rest)) = words.split_first() {
+            match flag.as_str() {
+                "-t" => title = true,
+                "-a" => action_bar = true,
+                _ => break,
+            }
+            words = rest;
+        }
+
+        let message = words.join(" ");
+        let visible = strip_legacy(&message);
+
+        if visible.trim().is_empty() || message.chars().count() > MAX_LENGTH {
+            let reply = usage(&lang, event.sender, &BROADCAST);
+            if let Ok(mut client) = clients.get_mut(event.sender) {
+                client.send_message(reply);
+            } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+                console.send_message(reply);
+            }
+            continue;
+        }
+
+        let name = sender_name(
+            clients.get(event.sender).ok(),
+            consoles.get(event.sender).ok(),
+        );
+        info!(target: AUDIT, "[Broadcast] {name}: {visible}");
+
+        let text = legacy_text(&message);
+        let chat = legacy_text(&config.broadcast.prefix) + text.clone();
+
+        // Made once and written to everyone, rather than cloning the text
+        // into new packets for each player.
+        let times = SetTitleAnimationTimes {
+            fade_in: TITLE_FADE_IN,
+            stay: TITLE_STAY,
+            fade_out: TITLE_FADE_OUT,
+        };
+        let subtitle = SetSubtitleText {
+            subtitle_text: Text::default(),
+        };
+        let title_text = SetTitleText {
+            title_text: text.clone(),
+        };
+
+        for mut client in &mut clients {
+            client.send_message(chat.clone());
+
+            if title {
+                client.write_packet(&times);
+                client.write_packet(&subtitle);
+                client.write_packet(&title_text);
+            }
+            if action_bar {
+                client.set_action_bar(text.clone());
+            }
+        }
+        if action_bar {
+            for mut hud in &mut huds {
+                hud.suppress(SUPPRESS_FOR);
+            }
+        }
+
+        // Players see the broadcast itself; consoles only see it in the log
+        // otherwise.
+        if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(chat);
+        }
+    }
+}
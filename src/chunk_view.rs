@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+
+use valence::prelude::*;
+
+/// Whether a chunk is within `distance` chunks of `center`, by the same
+/// circle clients see.
+pub fn within(center: ChunkPos, pos: ChunkPos, distance: u8) -> bool {
+    let (dx, dz) = (i64::from(pos.x - center.x), i64::from(pos.z - center.z));
+    dx * dx + dz * dz <= i64::from(distance).pow(2)
+}
+
+/// The chunks players can see from each of `views`, given as a center and a
+/// view distance, without repeats. Chunks come in rings outwards from every
+/// player at once, so whoever's loading in sees what's around them first.
+pub fn wanted_chunks(views: &[(ChunkPos, u8)]) -> Vec<ChunkPos> {
+    let mut seen = HashSet::new();
+    let mut wanted = Vec::new();
+    let furthest = views.iter().map(|&(_, distance)| distance).max();
+
+    for ring in 0..=furthest.map_or(-1, i32::from) {
+        for &(center, distance) in views {
+            if ring > i32::from(distance) {
+                continue;
+            }
+
+            // The square of chunks `ring` away, clipped to the circle.
+            for dz in -ring..=ring {
+                for dx in -ring..=ring {
+                    if dx.abs() != ring && dz.abs() != ring {
+                        continue;
+                    }
+                    let pos = ChunkPos::new(center.x + dx, center.z + dz);
+                    if within(center, pos, distance) && seen.insert(pos) {
+                        wanted.push(pos);
+                    }
+                }
+            }
+        }
+    }
+
+    wanted
+}
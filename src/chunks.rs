@@ -0,0 +1,422 @@
+use std::collections::{HashMap, HashSet};
+
+use plotsirv::chunk_view::{wanted_chunks, within};
+use valence::prelude::*;
+
+use crate::block_log::BlockChanged;
+use crate::tps::{Deferrable, Tps};
+use crate::SPAWN_Y;
+
+/// Half the width of the grass platform around the origin, in blocks.
+const PLATFORM_RADIUS: i32 = 25;
+
+/// Chunks stay loaded this many chunks past where they'd be loaded, so
+/// walking back and forth over a chunk border doesn't load and unload the
+/// same chunks over and over.
+const UNLOAD_MARGIN: u8 = 2;
+
+/// At most this many chunks are made in one tick, nearest first, so a
+/// crowd joining at once spreads the work out instead of stalling a tick.
+const MAX_LOADS_PER_TICK: usize = 64;
+
+/// Unused chunks are looked for this often, in ticks.
+const UNLOAD_INTERVAL: u64 = 20;
+
+/// Chunks players have built in. There's nowhere to save chunks to, so these
+/// are never unloaded; the rest can be made again just as they were.
+#[derive(Resource, Default)]
+pub struct ModifiedChunks(HashMap<Entity, HashSet<ChunkPos>>);
+
+impl ModifiedChunks {
+    /// How many chunks have been built in in an instance.
+    pub fn count(&self, instance: Entity) -> usize {
+        self.0.get(&instance).map_or(0, HashSet::len)
+    }
+
+    pub fn contains(&self, instance: Entity, pos: ChunkPos) -> bool {
+        self.0
+            .get(&instance)
+            .map_or(false, |chunks| chunks.contains(&pos))
+    }
+}
+
+/// Where a client was last seen, and where it's being held until the chunk
+/// there reaches it.
+#[derive(Component, Default, Debug)]
+struct TerrainWait {
+    last_seen: Option<(Entity, ChunkPos)>,
+    hold: Option<Hold>,
+}
+
+#[derive(Debug)]
+struct Hold {
+    position: DVec3,
+    /// Whether the chunk was there when this tick's chunks were sent, so
+    /// the client has it next tick.
+    sent: bool,
+}
+
+pub struct ChunksPlugin;
+
+impl Plugin for ChunksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ModifiedChunks>()
+            .add_system(track_modified_chunks)
+            .add_system(load_chunks.after(track_modified_chunks))
+            .add_system(unload_chunks.after(track_modified_chunks))
+            .add_system(init_terrain_waits)
+            .add_system(hold_until_terrain::<Client, Instance>.after(load_chunks));
+    }
+}
+
+/// Makes a chunk of the flat world, which is a grass platform around the
+/// origin over the void.
+pub fn generate_chunk(instance: &mut Instance, pos: ChunkPos) {
+    instance.insert_chunk(pos, Chunk::default());
+
+    for z in pos.z * 16..pos.z * 16 + 16 {
+        for x in pos.x * 16..pos.x * 16 + 16 {
+            let platform = -PLATFORM_RADIUS..PLATFORM_RADIUS;
+            if platform.contains(&x) && platform.contains(&z) {
+                instance.set_block([x, SPAWN_Y, z], BlockState::GRASS_BLOCK);
+            }
+        }
+    }
+}
+
+fn chunk_of(pos: BlockPos) -> ChunkPos {
+    ChunkPos::new(pos.x.div_euclid(16), pos.z.div_euclid(16))
+}
+
+/// Fills `views` with where each instance's players are and how far they
+/// see. The map and its lists are reused from the last tick rather than
+/// allocated again.
+fn views_by_instance(clients: &Query<&Client>, views: &mut HashMap<Entity, Vec<(ChunkPos, u8)>>) {
+    for list in views.values_mut() {
+        list.clear();
+    }
+    for client in clients.iter().filter(|c| !c.is_disconnected()) {
+        let pos = client.position();
+        views
+            .entry(client.instance())
+            .or_default()
+            .push((ChunkPos::at(pos.x, pos.z), client.view_distance()));
+    }
+    views.retain(|_, list| !list.is_empty());
+}
+
+/// What [`hold_until_terrain`] needs of a client, so it can be tested with a
+/// stand-in.
+trait Body: Component {
+    fn instance(&self) -> Entity;
+    fn position(&self) -> DVec3;
+    fn set_position(&mut self, position: DVec3);
+    /// Stops the client moving, so it doesn't carry on falling.
+    fn stop(&mut self);
+}
+
+impl Body for Client {
+    fn instance(&self) -> Entity {
+        Client::instance(self)
+    }
+
+    fn position(&self) -> DVec3 {
+        Client::position(self)
+    }
+
+    fn set_position(&mut self, position: DVec3) {
+        Client::set_position(self, position);
+    }
+
+    fn stop(&mut self) {
+        self.set_velocity([0.0, 0.0, 0.0]);
+    }
+}
+
+/// What [`hold_until_terrain`] needs of an instance.
+trait Terrain: Component {
+    fn is_loaded(&self, pos: ChunkPos) -> bool;
+}
+
+impl Terrain for Instance {
+    fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunk(pos).is_some()
+    }
+}
+
+fn init_terrain_waits(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(TerrainWait::default());
+    }
+}
+
+/// Keeps players where they are until the chunk they're in has been sent to
+/// them. Otherwise a client that joins, teleports or changes world on a slow
+/// connection starts falling before there's ground to land on, and ends up
+/// under the floor.
+///
+/// Chunks made this tick are sent at the end of it, so players are held for
+/// as long as their chunk isn't loaded, then one tick more. Arriving in a
+/// chunk another way than walking into it, which joining, teleporting and
+/// changing world all do, also holds them for a tick, as the chunk can be
+/// loaded but not yet sent to them.
+fn hold_until_terrain<C: Body, I: Terrain>(
+    mut clients: Query<(&mut C, &mut TerrainWait)>,
+    instances: Query<&I>,
+) {
+    for (mut client, mut wait) in &mut clients {
+        let position = client.position();
+        let here = (client.instance(), ChunkPos::at(position.x, position.z));
+        let arrived = wait
+            .last_seen
+            .replace(here)
+            .map_or(true, |(instance, pos)| {
+                instance != here.0 || !within(pos, here.1, 1)
+            });
+        let loaded = instances
+            .get(here.0)
+            .map_or(false, |instance| instance.is_loaded(here.1));
+
+        // Arriving somewhere new while held, like a second teleport, moves
+        // the hold there.
+        if arrived || (wait.hold.is_none() && !loaded) {
+            wait.hold = Some(Hold {
+                position,
+                sent: false,
+            });
+        }
+        let Some(hold) = &mut wait.hold else {
+            continue;
+        };
+
+        if loaded && hold.sent {
+            wait.hold = None;
+            continue;
+        }
+        hold.sent = loaded;
+        let held = hold.position;
+        if position != held {
+            client.set_position(held);
+        }
+        client.stop();
+    }
+}
+
+fn track_modified_chunks(
+    mut modified: ResMut<ModifiedChunks>,
+    mut events: EventReader<BlockChanged>,
+) {
+    for event in events.iter() {
+        modified
+            .0
+            .entry(event.instance)
+            .or_default()
+            .insert(chunk_of(event.position));
+    }
+}
+
+/// Loads what players can see. Once everything an instance's players can
+/// see is loaded, it's only looked at again when one of them moves to
+/// another chunk or changes their view distance. A tick that's running
+/// late stops generating and leaves the rest to the next.
+fn load_chunks(
+    clients: Query<&Client>,
+    mut instances: Query<&mut Instance>,
+    mut settled: Local<HashMap<Entity, Vec<(ChunkPos, u8)>>>,
+    mut views: Local<HashMap<Entity, Vec<(ChunkPos, u8)>>>,
+    tps: Res<Tps>,
+) {
+    views_by_instance(&clients, &mut views);
+    settled.retain(|entity, _| views.contains_key(entity));
+
+    for (&entity, views) in views.iter() {
+        if settled.get(&entity) == Some(views) {
+            continue;
+        }
+        let Ok(mut instance) = instances.get_mut(entity) else {
+            continue;
+        };
+
+        let missing: Vec<_> = wanted_chunks(views)
+            .into_iter()
+            .filter(|&pos| instance.chunk(pos).is_none())
+            .take(MAX_LOADS_PER_TICK)
+            .collect();
+        let mut done = missing.len() < MAX_LOADS_PER_TICK;
+        for pos in missing {
+            if tps.defer(Deferrable::ChunkLoading) {
+                done = false;
+                break;
+            }
+            generate_chunk(&mut instance, pos);
+        }
+
+        if done {
+            settled.insert(entity, views.clone());
+        } else {
+            settled.remove(&entity);
+        }
+    }
+}
+
+fn unload_chunks(
+    clients: Query<&Client>,
+    mut instances: Query<(Entity, &mut Instance)>,
+    modified: Res<ModifiedChunks>,
+    server: Res<Server>,
+    tps: Res<Tps>,
+    mut views: Local<HashMap<Entity, Vec<(ChunkPos, u8)>>>,
+    mut overdue: Local<bool>,
+) {
+    if server.current_tick() % UNLOAD_INTERVAL != 0 && !*overdue {
+        return;
+    }
+    *overdue = tps.defer(Deferrable::ChunkUnloading);
+    if *overdue {
+        return;
+    }
+
+    views_by_instance(&clients, &mut views);
+    let no_views = Vec::new();
+    let unmodified = HashSet::new();
+
+    for (entity, mut instance) in &mut instances {
+        let views = views.get(&entity).unwrap_or(&no_views);
+        let modified = modified.0.get(&entity).unwrap_or(&unmodified);
+
+        let unused: Vec<_> = instance
+            .chunks()
+            .map(|(pos, _)| pos)
+            .filter(|pos| !modified.contains(pos))
+            .filter(|&pos| {
+                !views.iter().any(|&(center, distance)| {
+                    within(center, pos, distance.saturating_add(UNLOAD_MARGIN))
+                })
+            })
+            .collect();
+        for pos in unused {
+            instance.remove_chunk(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::{Stage, SystemStage};
+
+    use super::*;
+
+    /// The top of the ground under spawn.
+    const FLOOR: f64 = 65.0;
+    const SPAWN_HEIGHT: f64 = 70.0;
+
+    /// How many ticks the spawn chunk takes to load, as if sending it were
+    /// slow.
+    const DELAY: u32 = 20;
+
+    /// How a falling client speeds up each tick, as vanilla's do.
+    const GRAVITY: f64 = 0.08;
+    const DRAG: f64 = 0.98;
+
+    /// Stands in for a client, moving between ticks as a real one does.
+    #[derive(Component)]
+    struct Player {
+        instance: Entity,
+        position: DVec3,
+        velocity: f64,
+    }
+
+    impl Body for Player {
+        fn instance(&self) -> Entity {
+            self.instance
+        }
+
+        fn position(&self) -> DVec3 {
+            self.position
+        }
+
+        fn set_position(&mut self, position: DVec3) {
+            self.position = position;
+        }
+
+        fn stop(&mut self) {
+            self.velocity = 0.0;
+        }
+    }
+
+    /// Stands in for an instance, with the chunks it has loaded.
+    #[derive(Component, Default)]
+    struct Chunks(HashSet<ChunkPos>);
+
+    impl Terrain for Chunks {
+        fn is_loaded(&self, pos: ChunkPos) -> bool {
+            self.0.contains(&pos)
+        }
+    }
+
+    /// Joins a player over a chunk that takes [`DELAY`] ticks to arrive, and
+    /// returns the lowest they got and where they ended up.
+    fn join(hold: bool) -> (f64, f64) {
+        let mut world = World::new();
+        let instance = world.spawn(Chunks::default()).id();
+        let spawn = DVec3::new(8.5, SPAWN_HEIGHT, 8.5);
+        let player = world
+            .spawn((
+                Player {
+                    instance,
+                    position: spawn,
+                    velocity: 0.0,
+                },
+                TerrainWait::default(),
+            ))
+            .id();
+
+        let mut stage = SystemStage::single_threaded();
+        if hold {
+            stage.add_system(hold_until_terrain::<Player, Chunks>);
+        }
+
+        let chunk = ChunkPos::at(spawn.x, spawn.z);
+        let mut lowest = spawn.y;
+        for tick in 0..DELAY * 3 {
+            if tick == DELAY {
+                world.get_mut::<Chunks>(instance).unwrap().0.insert(chunk);
+            }
+            stage.run(&mut world);
+
+            // Chunks loaded in a tick are sent at its end, so the client
+            // has the ground from then on.
+            let ground = world.get::<Chunks>(instance).unwrap().is_loaded(chunk);
+            let mut player = world.get_mut::<Player>(player).unwrap();
+            let above = player.position.y >= FLOOR;
+            player.velocity = (player.velocity - GRAVITY) * DRAG;
+            player.position.y += player.velocity;
+            if ground && above && player.position.y < FLOOR {
+                player.position.y = FLOOR;
+                player.velocity = 0.0;
+            }
+            lowest = lowest.min(player.position.y);
+        }
+
+        let end = world.get::<Player>(player).unwrap().position.y;
+        (lowest, end)
+    }
+
+    #[test]
+    fn joining_players_wait_for_the_ground() {
+        let (lowest, end) = join(true);
+        assert!(
+            lowest >= FLOOR,
+            "fell to {lowest} before the ground arrived"
+        );
+        assert_eq!(end, FLOOR);
+    }
+
+    #[test]
+    fn without_holding_players_fall_through() {
+        // So the test above shows the hold working rather than the delay
+        // being too short to fall through.
+        let (lowest, _) = join(false);
+        assert!(lowest < FLOOR);
+    }
+}
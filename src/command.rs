@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashMap};
+
+use tracing::info;
+use valence::prelude::*;
+
+use crate::format::plain_text;
+use crate::lang::Lang;
+use crate::logging::{player_span, AUDIT};
+use crate::permissions::Permissions;
+use crate::rate_limit::{limit_commands, PlayerCommand};
+
+/// Static description of a chat command.
+#[derive(Clone, Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// The permission node needed to run the command, if it isn't open to
+    /// everyone.
+    pub permission: Option<&'static str>,
+    /// Whether a [`Console`] can run the command, or it only makes sense for
+    /// players.
+    pub console: bool,
+}
+
+/// Every command the server knows about, keyed by canonical name.
+#[derive(Resource, Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, CommandInfo>,
+    aliases: HashMap<&'static str, &'static str>,
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, info: CommandInfo) {
+        for alias in info.aliases {
+            self.aliases.insert(alias, info.name);
+        }
+        self.commands.insert(info.name, info);
+    }
+
+    /// Looks up a command by its name or one of its aliases.
+    pub fn get(&self, name: &str) -> Option<&CommandInfo> {
+        let name = self.aliases.get(name).copied().unwrap_or(name);
+        self.commands.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandInfo> {
+        self.commands.values()
+    }
+}
+
+/// A command sender that isn't a player, like an RCON connection. It passes
+/// every permission check, and replies to it are collected instead of going
+/// to chat.
+#[derive(Component, Debug)]
+pub struct Console {
+    /// Who to credit in logs and records, like `RCON`.
+    pub name: String,
+    output: Vec<Text>,
+}
+
+impl Console {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn send_message(&mut self, msg: impl Into<Text>) {
+        self.output.push(msg.into());
+    }
+
+    pub fn has_output(&self) -> bool {
+        !self.output.is_empty()
+    }
+
+    /// The replies so far, as plain text.
+    pub fn take_output(&mut self) -> String {
+        let lines: Vec<_> = self
+            .output
+            .drain(..)
+            .map(|text| plain_text(&text))
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// A command line from a [`Console`], with or without the leading slash.
+#[derive(Clone, Debug)]
+pub struct ConsoleCommand {
+    pub sender: Entity,
+    pub command: String,
+}
+
+/// A command sent by a client, resolved against the [`CommandRegistry`].
+#[derive(Clone, Debug)]
+pub struct CommandExecution {
+    pub sender: Entity,
+    /// The canonical name of the command, even if an alias was used.
+    pub name: &'static str,
+    pub args: Vec<String>,
+}
+
+impl CommandExecution {
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+}
+
+pub trait AddCommand {
+    fn add_command(&mut self, info: CommandInfo) -> &mut Self;
+}
+
+impl AddCommand for App {
+    fn add_command(&mut self, info: CommandInfo) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(CommandRegistry::default)
+            .register(info);
+        self
+    }
+}
+
+pub struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandRegistry>()
+            .add_event::<CommandExecution>()
+            .add_event::<ConsoleCommand>()
+            .add_system_to_stage(EventLoop, dispatch_commands.after(limit_commands))
+            .add_system_to_stage(EventLoop, dispatch_console_commands);
+    }
+}
+
+fn dispatch_commands(
+    mut clients: Query<&mut Client>,
+    registry: Res<CommandRegistry>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut events: EventReader<PlayerCommand>,
+    mut executions: EventWriter<CommandExecution>,
+) {
+    for event in events.iter() {
+        let Ok(mut client) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let _span = player_span(&client).entered();
+
+        let mut words = event.command.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+
+        let Some(info) = registry.get(&name.to_ascii_lowercase()) else {
+            client.send_message(lang.tr(event.client, "command.unknown", &[("name", &name)]));
+            continue;
+        };
+
+        if !permissions.may_run(client.uuid(), info) {
+            client.send_message(lang.tr(
+                event.client,
+                "command.no_permission",
+                &[("name", &info.name)],
+            ));
+            continue;
+        }
+
+        info!(target: AUDIT, "{} issued command: /{}", client.username(), event.command);
+
+        executions.send(CommandExecution {
+            sender: event.client,
+            name: info.name,
+            args: words.map(str::to_owned).collect(),
+        });
+    }
+}
+
+fn dispatch_console_commands(
+    mut consoles: Query<&mut Console>,
+    registry: Res<CommandRegistry>,
+    lang: Res<Lang>,
+    mut events: EventReader<ConsoleCommand>,
+    mut executions: EventWriter<CommandExecution>,
+) {
+    for event in events.iter() {
+        let Ok(mut console) = consoles.get_mut(event.sender) else {
+            continue;
+        };
+
+        let command = event.command.trim().trim_start_matches('/');
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+
+        let Some(info) = registry.get(&name.to_ascii_lowercase()) else {
+            console.send_message(
+                lang.tr(event.sender, "command.unknown", &[("name", &name)])
+                    + "\n"
+                    + lang.tr(event.sender, "console.hint", &[]),
+            );
+            continue;
+        };
+
+        if !info.console {
+            console.send_message(lang.tr(
+                event.sender,
+                "command.players_only",
+                &[("name", &info.name)],
+            ));
+            continue;
+        }
+
+        info!(target: AUDIT, "{} issued command: /{command}", console.name);
+
+        executions.send(CommandExecution {
+            sender: event.sender,
+            name: info.name,
+            args: words.map(str::to_owned).collect(),
+        });
+    }
+}
+
+/// Finds an online client by username, ignoring case.
+pub fn find_client<'a>(
+    clients: impl IntoIterator<Item = (Entity, &'a Client)>,
+    name: &str,
+) -> Option<Entity> {
+    clients
+        .into_iter()
+        .find(|(_, client)| client.username().as_str().eq_ignore_ascii_case(name))
+        .map(|(entity, _)| entity)
+}
+
+/// The name of whoever ran a command, for logs and records.
+pub fn sender_name(client: Option<&Client>, console: Option<&Console>) -> String {
+    match (client, console) {
+        (Some(client), _) => client.username().to_string(),
+        (None, Some(console)) => console.name.clone(),
+        (None, None) => "Server".to_owned(),
+    }
+}
+
+/// Tells a client how to use a command, in their language.
+pub fn usage(lang: &Lang, client: Entity, info: &CommandInfo) -> Text {
+    lang.tr(client, "command.usage", &[("usage", &info.usage)])
+}
@@ -0,0 +1,1605 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use valence::prelude::*;
+use valence_protocol::sound::Sound;
+
+use crate::persistence::{Persistence, WriteKind};
+
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// The view distances clients accept, in chunks.
+pub const MIN_VIEW_DISTANCE: u8 = 2;
+pub const MAX_VIEW_DISTANCE: u8 = 32;
+
+/// The build height of the dimension every world uses, which spawn has to
+/// be within.
+pub const MIN_SPAWN_Y: f64 = -64.0;
+pub const MAX_SPAWN_Y: f64 = 320.0;
+
+/// The largest world border radius, as in vanilla: the border can't go
+/// past the edge of the world.
+pub const MAX_BORDER_RADIUS: f64 = 29_999_984.0;
+
+/// The biggest packet the protocol allows, which is as high as a
+/// compression threshold can usefully go.
+const MAX_PACKET_SIZE: i32 = 2_097_152;
+
+/// Longest a title may fade or stay for, so a typo can't leave one stuck on
+/// screen.
+const MAX_TITLE_TICKS: u32 = 6000;
+
+/// Written at the top of a newly generated config file.
+const HEADER: &str = "\
+# Server configuration. Anything left out takes its default value.
+# Messages are either keys from the language files, like welcome.line, or
+# literal text with & color codes.
+# Any key can also be set in the environment, which beats this file, like
+# PLOTSIRV_SERVER__MAX_PLAYERS=100 for max_players under [server].
+";
+
+/// Written above each section of a newly generated config file.
+const SECTION_COMMENTS: &[(&str, &str)] = &[
+    ("server", "Where the server listens, how players log in, and how many can join."),
+    ("spawn", "Where players appear when they join or use /spawn."),
+    ("tab_list", "The header and footer of the player list."),
+    ("boss_bar", "Boss bars shown to everyone."),
+    ("motd", "What the server list shows."),
+    ("skins", "Where player skins are fetched from."),
+    ("resource_pack", "A resource pack offered to players as they join."),
+    ("void", "What happens to players who fall out of the world."),
+    ("messages", "Chat and join messages."),
+    ("welcome", "The title and messages shown to players as they join."),
+    ("afk", "When players are marked as away."),
+    ("fly", "Flying and flight speed."),
+    ("sounds", "Sounds played as feedback. Leave one empty to turn it off."),
+    ("lang", "Which languages are loaded, and the default."),
+    ("whitelist", "Who may join while the whitelist is on."),
+    ("rcon", "Remote console access."),
+    ("shutdown", "What players see when the server stops."),
+    ("runtime", "The threads used for networking and disk access."),
+    ("rate_limit", "How fast players may chat and run commands."),
+    ("connection_limit", "How fast one address may ping the server and log in."),
+    ("maintenance", "Maintenance mode, turned on and off with /maintenance."),
+    ("broadcast", "The look of /broadcast."),
+    ("announcements", "Messages broadcast on a timer."),
+    ("block_log", "The log of who changed which blocks."),
+    ("items", "Items dropped on the ground."),
+    ("tnt", "TNT, which is also allowed or not in each world."),
+    ("fluids", "Flowing water and lava."),
+    ("worlds", "Per-world settings, in tables like [worlds.world]."),
+];
+
+/// Settings given on the command line, which beat the file's.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub address: Option<SocketAddr>,
+    pub connection_mode: Option<ConfigConnectionMode>,
+    pub velocity_secret: Option<Secret>,
+    pub prevent_proxy_connections: bool,
+    pub compression_threshold: Option<i32>,
+    pub max_players: Option<usize>,
+    pub worker_threads: Option<usize>,
+    pub single_thread: bool,
+    /// Only let whitelisted players join.
+    pub whitelist: bool,
+    /// Start in maintenance mode.
+    pub maintenance: bool,
+}
+
+/// Environment variables starting with this set config keys, with `__`
+/// between the parts of the key, like `PLOTSIRV_SERVER__MAX_PLAYERS`.
+/// They beat the file but lose to the command line.
+const ENV_PREFIX: &str = "PLOTSIRV_";
+
+/// A config key set by an environment variable.
+#[derive(Clone)]
+struct EnvVar {
+    name: String,
+    path: Vec<String>,
+    value: toml::Value,
+}
+
+// Only the name, as the value may be a secret.
+impl fmt::Debug for EnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Reads the config keys set in the environment. Values are read as TOML,
+/// so lists and tables work too, except where the key holds a string,
+/// which is taken as it is.
+fn env_vars(file: &toml::Value, defaults: &toml::Value) -> Vec<EnvVar> {
+    let mut vars: Vec<_> = std::env::vars()
+        .filter_map(|(name, raw)| {
+            // Every setting is in a section, so keys without a separator
+            // are other variables, like `PLOTSIRV_RCON_PASSWORD`.
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            if !key.contains("__") {
+                return None;
+            }
+
+            let path: Vec<_> = key.split("__").map(str::to_lowercase).collect();
+            let value = match lookup(file, &path).or_else(|| lookup(defaults, &path)) {
+                Some(toml::Value::String(_)) => toml::Value::String(raw),
+                _ => toml::from_str::<toml::value::Table>(&format!("value = {raw}"))
+                    .ok()
+                    .and_then(|mut table| table.remove("value"))
+                    .unwrap_or(toml::Value::String(raw)),
+            };
+
+            Some(EnvVar { name, path, value })
+        })
+        .collect();
+
+    vars.sort_by(|a, b| a.name.cmp(&b.name));
+    vars
+}
+
+fn lookup<'a>(value: &'a toml::Value, path: &[String]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Sets the value at `path`, making tables along the way, or removes it.
+fn set(value: &mut toml::Value, path: &[String], new: Option<toml::Value>) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut value = value;
+    for key in parents {
+        value = as_table(value)
+            .entry(key.clone())
+            .or_insert(toml::Value::Table(toml::value::Table::new()));
+    }
+
+    let table = as_table(value);
+    match new {
+        Some(new) => table.insert(last.clone(), new),
+        None => table.remove(last),
+    };
+}
+
+/// The value as a table, replacing it with an empty one if it's something
+/// else.
+fn as_table(value: &mut toml::Value) -> &mut toml::value::Table {
+    if !value.is_table() {
+        *value = toml::Value::Table(toml::value::Table::new());
+    }
+    value.as_table_mut().expect("just made a table")
+}
+
+/// A setting that failed validation.
+#[derive(Debug)]
+struct Invalid {
+    /// The full key, like `rate_limit.chat_burst`.
+    key: String,
+    message: String,
+}
+
+fn check(
+    ok: bool,
+    key: impl Into<String>,
+    message: impl FnOnce() -> String,
+) -> Result<(), Invalid> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Invalid {
+            key: key.into(),
+            message: message(),
+        })
+    }
+}
+
+/// The line a key is set on, counting from 1, to point at mistakes. Only
+/// finds keys written out under their table's header.
+fn find_line(contents: &str, key: &str) -> Option<usize> {
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let mut current = "";
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = header.trim();
+        } else if let Some((key, _)) = line.split_once('=') {
+            if current == table && key.trim() == name {
+                return Some(i + 1);
+            }
+        }
+    }
+
+    None
+}
+
+/// Server configuration, read from a TOML file at startup.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Where the config was loaded from, so in-game changes can be saved.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Applied again whenever the file is re-read.
+    #[serde(skip)]
+    pub overrides: Overrides,
+    /// Keys set by environment variables.
+    #[serde(skip)]
+    env: Vec<EnvVar>,
+    /// The config as it was loaded or last saved, overrides and all, to
+    /// tell which keys in-game changes touched.
+    #[serde(skip)]
+    saved: toml::value::Table,
+    /// The file as it was read, which saving edits so its comments and
+    /// layout are kept.
+    #[serde(skip)]
+    document: toml_edit::Document,
+    pub server: ServerConfig,
+    pub spawn: SpawnConfig,
+    pub tab_list: TabListConfig,
+    pub boss_bar: BossBarConfig,
+    pub motd: MotdConfig,
+    pub skins: SkinsConfig,
+    pub resource_pack: ResourcePackConfig,
+    pub void: VoidConfig,
+    pub messages: MessagesConfig,
+    pub welcome: WelcomeConfig,
+    pub afk: AfkConfig,
+    pub fly: FlyConfig,
+    pub sounds: SoundsConfig,
+    pub lang: LangConfig,
+    pub whitelist: WhitelistConfig,
+    pub rcon: RconConfig,
+    pub shutdown: ShutdownConfig,
+    pub runtime: RuntimeConfig,
+    pub rate_limit: RateLimitConfig,
+    pub connection_limit: ConnectionLimitConfig,
+    pub maintenance: MaintenanceConfig,
+    pub broadcast: BroadcastConfig,
+    pub announcements: AnnouncementsConfig,
+    pub block_log: BlockLogConfig,
+    pub items: ItemsConfig,
+    pub tnt: TntConfig,
+    pub fluids: FluidsConfig,
+    /// Per-world settings, keyed by world name.
+    pub worlds: HashMap<String, WorldConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// The socket to listen for connections on.
+    pub address: SocketAddr,
+    /// Where to answer HTTP health checks, on `/healthz`, `/readyz` and
+    /// `/version`. Left out, there are none.
+    pub health_bind: Option<SocketAddr>,
+    /// How players are authenticated.
+    pub connection_mode: ConfigConnectionMode,
+    /// In online mode, whether to check that players connect from the same
+    /// IP address they authenticated from.
+    pub prevent_proxy_connections: bool,
+    /// Needed in velocity mode. Can also be given in the
+    /// `PLOTSIRV_VELOCITY_SECRET` environment variable or `--secret-file`.
+    pub velocity_secret: Option<Secret>,
+    /// Packets at least this many bytes long are compressed. -1 turns
+    /// compression off, which suits servers behind a proxy that compresses.
+    pub compression_threshold: i32,
+    /// How far players can see, in chunks. Players can lower their own with
+    /// `/viewdistance`.
+    pub view_distance: u8,
+    /// The most players allowed online at once.
+    pub max_players: usize,
+    /// How many more players past `max_players` can join if they have
+    /// `plots.join.full` or are in `priority_players`.
+    pub extra_slots: usize,
+    /// UUIDs of players who can join a full server, up to the extra slots.
+    pub priority_players: Vec<Uuid>,
+    /// Whether sneaking twice quickly on the ground switches between creative
+    /// and survival. Only read at startup.
+    pub sneak_toggles_game_mode: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([0, 0, 0, 0], 25565)),
+            health_bind: None,
+            connection_mode: ConfigConnectionMode::Online,
+            prevent_proxy_connections: false,
+            velocity_secret: None,
+            compression_threshold: 256,
+            view_distance: 10,
+            max_players: 20,
+            extra_slots: 5,
+            priority_players: Vec::new(),
+            sneak_toggles_game_mode: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The compression threshold as Valence takes it, with `None` for off.
+    pub fn compression_threshold(&self) -> Option<u32> {
+        u32::try_from(self.compression_threshold).ok()
+    }
+
+    pub fn connection_mode(&self) -> ConnectionMode {
+        match self.connection_mode {
+            ConfigConnectionMode::Online => ConnectionMode::Online {
+                prevent_proxy_connections: self.prevent_proxy_connections,
+            },
+            ConfigConnectionMode::Offline => ConnectionMode::Offline,
+            ConfigConnectionMode::Bungeecord => ConnectionMode::BungeeCord,
+            ConfigConnectionMode::Velocity => ConnectionMode::Velocity {
+                secret: self.velocity_secret.as_ref().map_or("", |s| &s.0).into(),
+            },
+        }
+    }
+}
+
+/// A password or key, which is left out when settings are logged.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// How players are authenticated, as written in the config or on the
+/// command line.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigConnectionMode {
+    #[default]
+    Online,
+    Offline,
+    Bungeecord,
+    Velocity,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SpawnConfig {
+    /// The name of the world players spawn in.
+    pub world: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// How long survival players must stand still before `/spawn` teleports
+    /// them. Zero disables the warmup.
+    pub warmup_secs: u64,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            world: "world".into(),
+            x: 0.0,
+            y: crate::SPAWN_Y as f64 + 1.0,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            warmup_secs: 0,
+        }
+    }
+}
+
+/// Templates for the tab list. Both support `&` color codes and the
+/// `{online}`, `{max_players}`, `{tps}`, `{mspt}`, `{player}` and `{ping}`
+/// placeholders. `{mspt}` is the average tick time in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TabListConfig {
+    pub header: String,
+    pub footer: String,
+    /// How often the header and footer are re-rendered.
+    pub refresh_secs: u64,
+}
+
+impl Default for TabListConfig {
+    fn default() -> Self {
+        Self {
+            header: "&6&lplotsirv".into(),
+            footer: "&7{online}/{max_players} online &8| &7TPS {tps} &8| &7Ping {ping}ms".into(),
+            refresh_secs: 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BossBarConfig {
+    /// Whether to show an always-on bar with the server status.
+    pub status_enabled: bool,
+    /// The status bar's text. Supports `&` color codes and `{tps}`.
+    pub status_text: String,
+}
+
+impl Default for BossBarConfig {
+    fn default() -> Self {
+        Self {
+            status_enabled: false,
+            status_text: "&6plotsirv &8| &7TPS {tps}".into(),
+        }
+    }
+}
+
+/// What the server list shows for this server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MotdConfig {
+    /// The first line of the MOTD. Supports `&` color codes.
+    pub line1: String,
+    pub line2: String,
+    /// A 64x64 PNG shown next to the server.
+    pub favicon: Option<PathBuf>,
+    /// Whether hovering the player count lists online players.
+    pub player_sample: bool,
+    /// Custom lines to show when hovering the player count instead of the
+    /// player sample.
+    pub hover: Vec<String>,
+}
+
+impl Default for MotdConfig {
+    fn default() -> Self {
+        Self {
+            line1: "&6&lplotsirv".into(),
+            line2: "&7Build something cool.".into(),
+            favicon: None,
+            player_sample: true,
+            hover: Vec::new(),
+        }
+    }
+}
+
+/// Skin lookups for players who join without textures, such as in offline
+/// mode or behind a proxy that doesn't forward them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SkinsConfig {
+    pub enabled: bool,
+    pub cache_dir: PathBuf,
+    /// How long a looked-up skin is reused before asking Mojang again.
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for SkinsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cache_dir: "skins".into(),
+            cache_ttl_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// A resource pack offered to players when they join.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ResourcePackConfig {
+    /// Where clients download the pack from. No pack is offered if unset.
+    pub url: Option<String>,
+    /// The hex-encoded SHA-1 of the pack, so clients can cache it.
+    pub sha1: String,
+    /// Whether players who decline the pack are kicked.
+    pub required: bool,
+    /// Shown in the prompt. Supports `&` color codes.
+    pub prompt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VoidConfig {
+    /// Players below this height are considered to be in the void.
+    pub y: i32,
+}
+
+impl Default for VoidConfig {
+    fn default() -> Self {
+        Self { y: -64 }
+    }
+}
+
+/// Broadcasts when players come and go. Each is a message key from the
+/// language files or a literal message with `&` color codes and the `{name}`
+/// placeholder; an empty message isn't sent.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MessagesConfig {
+    pub join: String,
+    /// Sent instead of `join` the first time a player joins.
+    pub first_join: String,
+    pub leave: String,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        Self {
+            join: "join.message".into(),
+            first_join: "join.first".into(),
+            leave: "join.leave".into(),
+        }
+    }
+}
+
+/// What players are greeted with when they join.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct WelcomeConfig {
+    pub join: JoinSequence,
+    /// Used instead of `join` the first time a player joins.
+    pub first_join: Option<JoinSequence>,
+}
+
+/// A title, chat lines and a sound sent to a joining player. Text is a
+/// message key or a literal message with `&` color codes and the `{name}`
+/// placeholder.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct JoinSequence {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    /// Title timings, in ticks.
+    pub fade_in: u32,
+    pub stay: u32,
+    pub fade_out: u32,
+    pub lines: Vec<String>,
+    /// A sound ID like `entity.player.levelup`.
+    pub sound: Option<String>,
+}
+
+impl Default for JoinSequence {
+    fn default() -> Self {
+        Self {
+            title: None,
+            subtitle: None,
+            fade_in: 10,
+            stay: 70,
+            fade_out: 20,
+            lines: vec!["welcome.line".into()],
+            sound: None,
+        }
+    }
+}
+
+impl JoinSequence {
+    fn validate(&self, section: &str) -> Result<(), Invalid> {
+        if self.title.is_some() || self.subtitle.is_some() {
+            check(self.stay > 0, format!("{section}.stay"), || {
+                "must be more than zero for the title to show".into()
+            })?;
+        }
+
+        for (key, ticks) in [
+            ("fade_in", self.fade_in),
+            ("stay", self.stay),
+            ("fade_out", self.fade_out),
+        ] {
+            check(ticks <= MAX_TITLE_TICKS, format!("{section}.{key}"), || {
+                format!("must be at most {MAX_TITLE_TICKS} ticks, got {ticks}")
+            })?;
+        }
+
+        if let Some(sound) = &self.sound {
+            check(
+                Sound::from_str(sound).is_some(),
+                format!("{section}.sound"),
+                || format!("{sound:?} is not a known sound"),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AfkConfig {
+    /// How long a player must be idle before they're marked AFK.
+    pub idle_secs: u64,
+    /// Whether everyone is told when a player goes AFK or comes back.
+    pub announce: bool,
+    /// How long a player may be idle before they're kicked. Never, if unset.
+    pub kick_secs: Option<u64>,
+}
+
+impl Default for AfkConfig {
+    fn default() -> Self {
+        Self {
+            idle_secs: 300,
+            announce: true,
+            kick_secs: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FlyConfig {
+    /// Whether survival players can start flying by jumping twice in quick
+    /// succession, without using `/fly` first.
+    pub double_tap: bool,
+    /// The most ticks between the two jumps.
+    pub tap_window_ticks: u64,
+}
+
+impl Default for FlyConfig {
+    fn default() -> Self {
+        Self {
+            double_tap: false,
+            tap_window_ticks: 20,
+        }
+    }
+}
+
+/// Sound IDs played alongside feedback in chat. An empty ID plays nothing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SoundsConfig {
+    pub teleport: String,
+    pub denied: String,
+    /// Played when someone says your name in chat.
+    pub mention: String,
+}
+
+impl Default for SoundsConfig {
+    fn default() -> Self {
+        Self {
+            teleport: "entity.enderman.teleport".into(),
+            denied: "entity.villager.no".into(),
+            mention: "entity.experience_orb.pickup".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WhitelistConfig {
+    /// Whether only whitelisted players may join.
+    pub enabled: bool,
+    /// Whether online players who aren't whitelisted are kicked when the
+    /// whitelist is turned on or they're removed from it.
+    pub enforce: bool,
+    /// Shown to players who aren't whitelisted. A message key or a literal
+    /// message with `&` color codes.
+    pub message: String,
+    /// Where the whitelisted players are stored.
+    pub file: PathBuf,
+}
+
+impl Default for WhitelistConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            enforce: true,
+            message: "whitelist.rejected".into(),
+            file: "whitelist.toml".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct RconConfig {
+    pub enabled: bool,
+    pub address: SocketAddr,
+    /// Overridden by the `PLOTSIRV_RCON_PASSWORD` environment variable. RCON
+    /// stays off without a password.
+    pub password: String,
+    /// Failed logins allowed from one address before it's locked out.
+    pub max_failures: u32,
+    pub lockout_secs: u64,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: ([0, 0, 0, 0], 25575).into(),
+            password: String::new(),
+            max_failures: 5,
+            lockout_secs: 300,
+        }
+    }
+}
+
+/// The async runtime used for networking and disk access. Only read at
+/// startup.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Zero means one per CPU core.
+    pub worker_threads: usize,
+    /// Runs every async task on one thread, which helps when tracking down
+    /// races.
+    pub single_thread: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Shown to players when the server stops. A message key or a literal
+    /// message with `&` color codes.
+    pub message: String,
+    /// How long saving may take before the server gives up and exits
+    /// anyway.
+    pub watchdog_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            message: "server.stopping".into(),
+            watchdog_secs: 30,
+        }
+    }
+}
+
+/// Limits on how fast players can chat and run commands. Each limit is a
+/// burst that refills at a steady rate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub chat_burst: f32,
+    pub chat_per_second: f32,
+    pub command_burst: f32,
+    pub command_per_second: f32,
+    /// How many times a player can hit a limit before they're muted, and
+    /// before they're kicked. Earlier times just warn them.
+    pub mute_after: u32,
+    pub kick_after: u32,
+    /// How long the automatic mute lasts.
+    pub mute_secs: u64,
+    /// How long a player has to stay under the limits for their warnings to
+    /// be forgotten.
+    pub forgive_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            chat_burst: 5.0,
+            chat_per_second: 1.0,
+            command_burst: 10.0,
+            command_per_second: 2.0,
+            mute_after: 3,
+            kick_after: 5,
+            mute_secs: 300,
+            forgive_secs: 120,
+        }
+    }
+}
+
+/// Limits on how fast one IP address can ping the server and start logging
+/// in, so bots can't crowd players out. Each limit is a burst that refills
+/// at a steady rate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ConnectionLimitConfig {
+    pub ping_burst: f32,
+    pub ping_per_second: f32,
+    pub login_burst: f32,
+    pub login_per_second: f32,
+    /// How many attempts from one address can be refused within a minute
+    /// before it's blocked outright. 0 never blocks.
+    pub block_after: u32,
+    /// How long a block lasts.
+    pub block_secs: u64,
+    /// Addresses that are never limited. Behind a Velocity or BungeeCord
+    /// proxy, put the proxy's address here: every ping comes from it, while
+    /// logins are limited by the player's own address from the proxy.
+    pub exempt: Vec<IpAddr>,
+}
+
+impl Default for ConnectionLimitConfig {
+    fn default() -> Self {
+        Self {
+            ping_burst: 10.0,
+            ping_per_second: 1.0,
+            login_burst: 5.0,
+            login_per_second: 0.5,
+            block_after: 50,
+            block_secs: 600,
+            exempt: Vec::new(),
+        }
+    }
+}
+
+/// Closes the server to everyone without `plots.maintenance.bypass`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Turned on and off with `/maintenance`.
+    pub enabled: bool,
+    /// Shown to players who are kicked or can't join. A message key or a
+    /// literal message with `&` color codes.
+    pub message: String,
+    /// Shown in the server list instead of the usual MOTD. Supports `&`
+    /// color codes, with a newline between the two lines.
+    pub motd: String,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "maintenance.message".into(),
+            motd: "&c&lDown for maintenance\n&7Back soon.".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BroadcastConfig {
+    /// Put before every `/broadcast` in chat. Supports `&` color codes.
+    pub prefix: String,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "&8[&6Broadcast&8] &r".into(),
+        }
+    }
+}
+
+/// Messages broadcast on a timer. The timer only runs while someone is
+/// online.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AnnouncementsConfig {
+    pub interval_secs: u64,
+    /// Whether to pick messages at random rather than in order.
+    pub random: bool,
+    pub messages: Vec<Announcement>,
+}
+
+impl Default for AnnouncementsConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 300,
+            random: false,
+            messages: Vec::new(),
+        }
+    }
+}
+
+/// A message key or a literal message with `&` color codes and the
+/// `{online}`, `{maxplayers}`, `{tps}` and `{player}` placeholders.
+/// `{max_players}` works too, as in the tab list.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Announcement {
+    pub text: String,
+    pub display: AnnouncementDisplay,
+}
+
+/// Where an announcement is shown.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementDisplay {
+    #[default]
+    Chat,
+    ActionBar,
+    Title,
+    /// Shown to everyone in the default language, for a few seconds.
+    BossBar,
+}
+
+/// Where block changes are logged and how long they're kept.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct BlockLogConfig {
+    /// Holds one file per day.
+    pub directory: PathBuf,
+    /// Days older than this are deleted. Zero keeps everything.
+    pub retention_days: u64,
+}
+
+impl Default for BlockLogConfig {
+    fn default() -> Self {
+        Self {
+            directory: "block_log".into(),
+            retention_days: 90,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ItemsConfig {
+    /// Whether players in creative mode pick items up, as they do in
+    /// vanilla.
+    pub creative_pickup: bool,
+    /// Whether items in a chunk that unloads are still there when it loads
+    /// again. Otherwise they're thrown away.
+    pub keep_in_unloaded_chunks: bool,
+}
+
+impl Default for ItemsConfig {
+    fn default() -> Self {
+        Self {
+            creative_pickup: true,
+            keep_in_unloaded_chunks: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TntConfig {
+    /// Turns TNT off everywhere, whatever each world allows.
+    pub enabled: bool,
+}
+
+impl Default for TntConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FluidsConfig {
+    /// How many blocks of water and lava can flow each tick. Any more wait
+    /// for the next tick, so a big flood flows slowly instead of slowing the
+    /// server down.
+    pub updates_per_tick: usize,
+}
+
+impl Default for FluidsConfig {
+    fn default() -> Self {
+        Self {
+            updates_per_tick: 1000,
+        }
+    }
+}
+
+/// Settings for a single world. Damage is off by default, since builders
+/// shouldn't die jumping off their own towers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub fall_damage: bool,
+    pub drowning: bool,
+    pub fire_damage: bool,
+    /// Whether survival players take damage in the void instead of being
+    /// sent back to spawn.
+    pub void_damage: bool,
+    /// Whether time passes. When off, the time stays wherever `/time` set
+    /// it.
+    pub daylight_cycle: bool,
+    /// Keeps the weather clear, refusing `/weather`.
+    pub lock_weather: bool,
+    /// A world border to keep players near the built area. No border if
+    /// unset.
+    pub border: Option<BorderConfig>,
+    /// The game mode players are put in when they arrive, unless they chose
+    /// one with `/gamemode`.
+    pub game_mode: ConfigGameMode,
+    /// Whether TNT can be lit, and blow things up inside the border.
+    pub tnt: bool,
+    /// Whether fire burns the blocks around it and spreads, inside the
+    /// border. Fire still burns out and can be put out when this is off.
+    pub fire_spread: bool,
+}
+
+/// A game mode as written in the config.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigGameMode {
+    Survival,
+    #[default]
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl From<ConfigGameMode> for GameMode {
+    fn from(mode: ConfigGameMode) -> Self {
+        match mode {
+            ConfigGameMode::Survival => GameMode::Survival,
+            ConfigGameMode::Creative => GameMode::Creative,
+            ConfigGameMode::Adventure => GameMode::Adventure,
+            ConfigGameMode::Spectator => GameMode::Spectator,
+        }
+    }
+}
+
+impl ConfigGameMode {
+    /// Parses a game mode by name or vanilla number.
+    pub fn parse(arg: &str) -> Option<Self> {
+        match arg.to_ascii_lowercase().as_str() {
+            "survival" | "s" | "0" => Some(Self::Survival),
+            "creative" | "c" | "1" => Some(Self::Creative),
+            "adventure" | "a" | "2" => Some(Self::Adventure),
+            "spectator" | "sp" | "3" => Some(Self::Spectator),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Survival => "survival",
+            Self::Creative => "creative",
+            Self::Adventure => "adventure",
+            Self::Spectator => "spectator",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct BorderConfig {
+    pub center_x: f64,
+    pub center_z: f64,
+    /// Distance from the center to each side of the border, in blocks.
+    pub radius: f64,
+}
+
+impl Default for BorderConfig {
+    fn default() -> Self {
+        Self {
+            center_x: 0.0,
+            center_z: 0.0,
+            radius: 1000.0,
+        }
+    }
+}
+
+/// Where message translations are read from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LangConfig {
+    /// The language used for players who haven't picked one, and whose
+    /// client language has no translation.
+    pub default: String,
+    /// A directory of `<code>.toml` files, like `de_de.toml`.
+    pub dir: PathBuf,
+}
+
+impl Default for LangConfig {
+    fn default() -> Self {
+        Self {
+            default: "en_us".into(),
+            dir: "lang".into(),
+        }
+    }
+}
+
+impl WorldConfig {
+    pub fn any_damage(&self) -> bool {
+        self.fall_damage || self.drowning || self.fire_damage || self.void_damage
+    }
+}
+
+impl Config {
+    /// Settings for the named world, or the defaults if it has none.
+    pub fn world(&self, name: &str) -> WorldConfig {
+        self.worlds.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Reads the config at `path`, writing out the defaults if it doesn't
+    /// exist, and applies the environment and then the command line
+    /// overrides.
+    pub fn load(path: impl AsRef<Path>, overrides: Overrides) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = if path.exists() {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            Some(contents)
+        } else {
+            None
+        };
+        Self::from_contents(path, contents, overrides)
+    }
+
+    /// Reads the config again while the server runs, with the overrides it
+    /// was loaded with. A save that's still queued is read from memory
+    /// rather than waited for.
+    pub fn reload(&self, persistence: &Persistence) -> anyhow::Result<Self> {
+        let contents = persistence
+            .read(&self.path)?
+            .map(String::from_utf8)
+            .transpose()
+            .with_context(|| format!("reading {}", self.path.display()))?;
+        Self::from_contents(&self.path, contents, self.overrides.clone())
+    }
+
+    /// Builds the config from the contents of its file, or the defaults if
+    /// there isn't one.
+    fn from_contents(
+        path: &Path,
+        contents: Option<String>,
+        overrides: Overrides,
+    ) -> anyhow::Result<Self> {
+        let file = match &contents {
+            Some(contents) => {
+                // Parsed as a config first for errors with line numbers.
+                toml::from_str::<Config>(contents)
+                    .with_context(|| format!("parsing {}", path.display()))?;
+                toml::from_str(contents)?
+            }
+            None => toml::Value::Table(toml::value::Table::new()),
+        };
+        let document = match &contents {
+            Some(contents) => contents
+                .parse()
+                .with_context(|| format!("parsing {}", path.display()))?,
+            None => match write_default(path, &Config::default()) {
+                Ok(written) => {
+                    info!("Wrote the default config to {}", path.display());
+                    written.parse()?
+                }
+                Err(e) => {
+                    warn!("Failed to write the default config: {e:#}");
+                    toml_edit::Document::new()
+                }
+            },
+        };
+
+        let env = env_vars(&file, &toml::Value::try_from(Config::default())?);
+        let mut merged = file.clone();
+        for var in &env {
+            info!("{} sets {}", var.name, var.path.join("."));
+            set(&mut merged, &var.path, Some(var.value.clone()));
+        }
+
+        let mut config: Config = match merged.try_into() {
+            Ok(config) => config,
+            Err(e) => {
+                // The file parsed on its own, so one of the variables is to
+                // blame.
+                for var in &env {
+                    let mut alone = file.clone();
+                    set(&mut alone, &var.path, Some(var.value.clone()));
+                    if let Err(e) = alone.try_into::<Config>() {
+                        bail!("{}: {e}", var.name);
+                    }
+                }
+                bail!("the environment doesn't fit the config: {e}");
+            }
+        };
+
+        config.path = path.to_owned();
+        config.env = env;
+        config.document = document;
+        config.apply(overrides);
+        config.clamp();
+
+        if let Err(Invalid { key, message }) = config.validate() {
+            if let Some(var) = config.env.iter().find(|v| v.path.join(".") == key) {
+                bail!("{key} {message} (set by {})", var.name);
+            }
+            match contents.as_deref().and_then(|c| find_line(c, &key)) {
+                Some(line) => bail!("{key} {message} (line {line} of {})", path.display()),
+                None => bail!("{key} {message}"),
+            }
+        }
+        config.mark_saved()?;
+        Ok(config)
+    }
+
+    fn apply(&mut self, overrides: Overrides) {
+        let server = &mut self.server;
+        if let Some(address) = overrides.address {
+            server.address = address;
+        }
+        if let Some(mode) = overrides.connection_mode {
+            server.connection_mode = mode;
+        }
+        if let Some(secret) = &overrides.velocity_secret {
+            server.velocity_secret = Some(secret.clone());
+        }
+        if overrides.prevent_proxy_connections {
+            server.prevent_proxy_connections = true;
+        }
+        if let Some(threshold) = overrides.compression_threshold {
+            server.compression_threshold = threshold;
+        }
+        if let Some(max_players) = overrides.max_players {
+            server.max_players = max_players;
+        }
+        if let Some(threads) = overrides.worker_threads {
+            self.runtime.worker_threads = threads;
+        }
+        if overrides.single_thread {
+            self.runtime.single_thread = true;
+        }
+        if overrides.whitelist {
+            self.whitelist.enabled = true;
+        }
+        if overrides.maintenance {
+            self.maintenance.enabled = true;
+        }
+
+        self.overrides = overrides;
+    }
+
+    /// Brings settings with hard limits back within them, with a warning.
+    fn clamp(&mut self) {
+        let distance = self.server.view_distance;
+        let clamped = distance.clamp(MIN_VIEW_DISTANCE, MAX_VIEW_DISTANCE);
+        if clamped != distance {
+            warn!(
+                "server.view_distance must be between {MIN_VIEW_DISTANCE} and \
+                 {MAX_VIEW_DISTANCE}; using {clamped} instead of {distance}"
+            );
+            self.server.view_distance = clamped;
+        }
+    }
+
+    /// Catches mistakes that would otherwise only show up once a player
+    /// joins.
+    fn validate(&self) -> Result<(), Invalid> {
+        if self.server.connection_mode == ConfigConnectionMode::Velocity {
+            check(
+                self.server.velocity_secret.is_some(),
+                "server.velocity_secret",
+                || "is needed in velocity mode".into(),
+            )?;
+        }
+
+        let y = self.spawn.y;
+        check((MIN_SPAWN_Y..MAX_SPAWN_Y).contains(&y), "spawn.y", || {
+            format!("must be from {MIN_SPAWN_Y} up to {MAX_SPAWN_Y}, got {y}")
+        })?;
+
+        let threshold = self.server.compression_threshold;
+        check(
+            (-1..=MAX_PACKET_SIZE).contains(&threshold),
+            "server.compression_threshold",
+            || format!("must be -1 (off) or from 0 to {MAX_PACKET_SIZE}, got {threshold}"),
+        )?;
+
+        if self.resource_pack.url.is_some() {
+            let sha1 = &self.resource_pack.sha1;
+            check(
+                sha1.len() == 40 && sha1.chars().all(|c| c.is_ascii_hexdigit()),
+                "resource_pack.sha1",
+                || format!("must be 40 hexadecimal characters, got {sha1:?}"),
+            )?;
+        }
+
+        self.welcome.join.validate("welcome.join")?;
+        if let Some(first_join) = &self.welcome.first_join {
+            first_join.validate("welcome.first_join")?;
+        }
+
+        let sounds = &self.sounds;
+        for (key, sound) in [
+            ("teleport", &sounds.teleport),
+            ("denied", &sounds.denied),
+            ("mention", &sounds.mention),
+        ] {
+            check(
+                sound.is_empty() || Sound::from_str(sound).is_some(),
+                format!("sounds.{key}"),
+                || format!("{sound:?} is not a known sound"),
+            )?;
+        }
+
+        check(
+            self.announcements.interval_secs > 0,
+            "announcements.interval_secs",
+            || "must be more than zero".into(),
+        )?;
+
+        let limits = &self.rate_limit;
+        for (key, burst, rate) in [
+            ("chat", limits.chat_burst, limits.chat_per_second),
+            ("command", limits.command_burst, limits.command_per_second),
+        ] {
+            check(burst >= 1.0, format!("rate_limit.{key}_burst"), || {
+                format!("must be at least 1, got {burst}")
+            })?;
+            check(rate > 0.0, format!("rate_limit.{key}_per_second"), || {
+                format!("must be more than zero, got {rate}")
+            })?;
+        }
+
+        check(
+            self.fluids.updates_per_tick > 0,
+            "fluids.updates_per_tick",
+            || "must be more than zero".into(),
+        )?;
+
+        for (name, world) in &self.worlds {
+            if let Some(border) = &world.border {
+                let radius = border.radius;
+                check(
+                    (1.0..=MAX_BORDER_RADIUS).contains(&radius),
+                    format!("worlds.{name}.border.radius"),
+                    || format!("must be from 1 to {MAX_BORDER_RADIUS}, got {radius}"),
+                )?;
+            }
+        }
+
+        let limits = &self.connection_limit;
+        for (key, burst, rate) in [
+            ("ping", limits.ping_burst, limits.ping_per_second),
+            ("login", limits.login_burst, limits.login_per_second),
+        ] {
+            check(burst >= 1.0, format!("connection_limit.{key}_burst"), || {
+                format!("must be at least 1, got {burst}")
+            })?;
+            check(rate > 0.0, format!("connection_limit.{key}_per_second"), || {
+                format!("must be more than zero, got {rate}")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// The config as TOML, with passwords and secrets hidden.
+    pub fn to_redacted_toml(&self) -> anyhow::Result<String> {
+        let mut value = toml::Value::try_from(self)?;
+        for path in [["server", "velocity_secret"], ["rcon", "password"]] {
+            let path = path.map(str::to_owned);
+            if lookup(&value, &path).map_or(false, |v| v.as_str() != Some("")) {
+                set(&mut value, &path, Some("<redacted>".into()));
+            }
+        }
+        Ok(toml::to_string(&value)?)
+    }
+
+    /// Takes the config as it is now to be what's in the file, so the next
+    /// [`Self::save`] leaves the changes so far out.
+    pub fn mark_saved(&mut self) -> anyhow::Result<()> {
+        self.saved = self.to_table()?;
+        Ok(())
+    }
+
+    fn to_table(&self) -> anyhow::Result<toml::value::Table> {
+        match toml::Value::try_from(self)? {
+            toml::Value::Table(table) => Ok(table),
+            _ => bail!("the config isn't a table"),
+        }
+    }
+
+    /// Queues the keys changed since the config was loaded to be written
+    /// back to its file. Only those keys are touched, so the file keeps its
+    /// comments and layout, and the command line and environment stay out
+    /// of it. Failing to write it is reported later, as a
+    /// [`WriteFailed`](crate::persistence::WriteFailed).
+    pub fn save(&mut self, persistence: &Persistence) -> anyhow::Result<()> {
+        let current = self.to_table()?;
+        let mut changes = Vec::new();
+        changed_keys(&self.saved, &current, &mut Vec::new(), &mut changes);
+
+        for (path, value) in changes {
+            set_in_document(&mut self.document, &path, value.as_ref().map(to_item));
+        }
+        self.saved = current;
+
+        let contents = self.document.to_string();
+        persistence.write(WriteKind::Config, self.path.clone(), contents.into_bytes());
+        Ok(())
+    }
+}
+
+/// The keys whose values differ between `old` and `new`, with the new
+/// value or `None` if it's gone. Tables are compared key by key, so one
+/// change doesn't rewrite its whole section.
+fn changed_keys(
+    old: &toml::value::Table,
+    new: &toml::value::Table,
+    path: &mut Vec<String>,
+    changes: &mut Vec<(Vec<String>, Option<toml::Value>)>,
+) {
+    let added = new.keys().filter(|key| !old.contains_key(*key));
+    for key in old.keys().chain(added) {
+        path.push(key.clone());
+        match (old.get(key), new.get(key)) {
+            (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+                changed_keys(old, new, path, changes);
+            }
+            (old, new) if old != new => changes.push((path.clone(), new.cloned())),
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+/// Sets the key at `path` in a TOML document, making tables along the way,
+/// or removes it.
+fn set_in_document(
+    document: &mut toml_edit::Document,
+    path: &[String],
+    item: Option<toml_edit::Item>,
+) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for key in parents {
+        let parent = table.entry(key).or_insert_with(implicit_table);
+        if !parent.is_table_like() {
+            *parent = implicit_table();
+        }
+        table = parent.as_table_like_mut().expect("just made a table");
+    }
+
+    match item {
+        Some(item) => table.insert(last, item),
+        None => table.remove(last),
+    };
+}
+
+/// A table that only gets a header of its own if it has keys that aren't
+/// tables.
+fn implicit_table() -> toml_edit::Item {
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+    toml_edit::Item::Table(table)
+}
+
+fn to_item(value: &toml::Value) -> toml_edit::Item {
+    match value {
+        toml::Value::Table(table) => {
+            let mut out = toml_edit::Table::new();
+            for (key, value) in table {
+                out.insert(key, to_item(value));
+            }
+            toml_edit::Item::Table(out)
+        }
+        value => toml_edit::Item::Value(to_value(value)),
+    }
+}
+
+fn to_value(value: &toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::String(s) => s.as_str().into(),
+        toml::Value::Integer(i) => (*i).into(),
+        toml::Value::Float(f) => (*f).into(),
+        toml::Value::Boolean(b) => (*b).into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(to_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        toml::Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.as_str(), to_value(value)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
+
+/// Writes a config with a comment above each section, returning what was
+/// written.
+fn write_default(path: &Path, config: &Config) -> anyhow::Result<String> {
+    let mut out = HEADER.to_owned();
+
+    for line in toml::to_string(config)?.lines() {
+        let table = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'));
+        if let Some((_, comment)) = SECTION_COMMENTS.iter().find(|(name, _)| Some(*name) == table) {
+            out.push_str(&format!("\n# {comment}\n"));
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    fs::write(path, &out).with_context(|| format!("writing {}", path.display()))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use valence_protocol::packets::s2c::login::SetCompression;
+    use valence_protocol::packets::s2c::play::SetTabListHeaderAndFooter;
+    use valence_protocol::{PacketDecoder, PacketEncoder, VarInt};
+
+    use super::*;
+
+    /// The default config with `threshold` set, validated.
+    fn with_threshold(threshold: i32) -> Result<Config, Invalid> {
+        let mut config = Config::default();
+        config.server.compression_threshold = threshold;
+        config.validate().map(|()| config)
+    }
+
+    #[test]
+    fn compression_thresholds_in_range() {
+        for (threshold, effective) in [(0, Some(0)), (256, Some(256)), (-1, None)] {
+            let config = with_threshold(threshold).unwrap();
+            assert_eq!(config.server.compression_threshold(), effective);
+        }
+        let largest = with_threshold(MAX_PACKET_SIZE).unwrap();
+        assert_eq!(
+            largest.server.compression_threshold(),
+            Some(MAX_PACKET_SIZE as u32)
+        );
+    }
+
+    #[test]
+    fn compression_thresholds_out_of_range() {
+        for threshold in [-2, i32::MIN, MAX_PACKET_SIZE + 1, i32::MAX] {
+            let invalid = with_threshold(threshold).unwrap_err();
+            assert_eq!(invalid.key, "server.compression_threshold", "{threshold}");
+        }
+    }
+
+    #[test]
+    fn compression_threshold_from_the_command_line() {
+        let mut config = Config::default();
+        config.apply(Overrides {
+            compression_threshold: Some(-1),
+            ..Default::default()
+        });
+        assert_eq!(config.server.compression_threshold(), None);
+    }
+
+    /// Sends a short and then a long packet the way a connection with
+    /// `threshold` set in the config does, and reads them back as a client
+    /// would. The threshold is told to the client in the login, as Valence
+    /// does, before either end compresses. Returns how many bytes the long
+    /// packet took to send.
+    fn round_trip(threshold: i32) -> usize {
+        let threshold = with_threshold(threshold)
+            .unwrap()
+            .server
+            .compression_threshold();
+        let mut server = PacketEncoder::new();
+        let mut client = PacketDecoder::new();
+
+        if let Some(threshold) = threshold {
+            server
+                .append_packet(&SetCompression {
+                    threshold: VarInt(threshold as i32),
+                })
+                .unwrap();
+            server.set_compression(Some(threshold));
+            client.queue_bytes(server.take());
+            let told = client.try_next_packet::<SetCompression>().unwrap().unwrap();
+            client.set_compression(told.threshold.0 >= 0);
+        }
+
+        let mut sent = 0;
+        for text in ["short".to_owned(), "long".repeat(500)] {
+            let packet = SetTabListHeaderAndFooter {
+                header: text.clone().into(),
+                footer: Text::default(),
+            };
+            server.append_packet(&packet).unwrap();
+            let bytes = server.take();
+            sent = bytes.len();
+            client.queue_bytes(bytes);
+
+            let received = client
+                .try_next_packet::<SetTabListHeaderAndFooter>()
+                .unwrap()
+                .unwrap();
+            assert!(received.header == packet.header, "{threshold:?}");
+            assert!(client
+                .try_next_packet::<SetTabListHeaderAndFooter>()
+                .unwrap()
+                .is_none());
+        }
+        sent
+    }
+
+    #[test]
+    fn packets_round_trip_at_each_threshold() {
+        // The long packet is 2000 bytes of text, which compresses to a
+        // fraction of that whenever compression is on.
+        assert!(round_trip(0) < 2000);
+        assert!(round_trip(256) < 2000);
+        assert!(round_trip(-1) > 2000);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+use valence::prelude::*;
+
+use crate::config::{Config, ConnectionLimitConfig};
+use crate::lang::Lang;
+use crate::rate_limit::Bucket;
+use crate::reload::ConfigReloaded;
+
+/// Refused attempts older than this no longer count towards a block.
+const REFUSAL_WINDOW: Duration = Duration::from_secs(60);
+
+/// Refused attempts are logged as one summary this often, rather than a line
+/// each, so flooding the log isn't part of the attack.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Addresses not heard from in this long are forgotten, unless blocked.
+const IDLE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Attempt {
+    Ping,
+    Login,
+}
+
+struct Address {
+    pings: Bucket,
+    logins: Bucket,
+    /// Attempts refused since `window_start`.
+    refused: u32,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct Limits {
+    settings: ConnectionLimitConfig,
+    /// Why a login was refused, in the default language.
+    message: Text,
+    addresses: HashMap<IpAddr, Address>,
+    /// Attempts refused since the last report, by address.
+    refusals: HashMap<IpAddr, u64>,
+}
+
+/// How fast each address has been pinging and logging in, shared with the
+/// login callbacks.
+#[derive(Resource, Clone, Default)]
+pub struct SharedConnectionLimits(Arc<Mutex<Limits>>);
+
+impl SharedConnectionLimits {
+    pub fn new(settings: &ConnectionLimitConfig) -> Self {
+        Self(Arc::new(Mutex::new(Limits {
+            settings: settings.clone(),
+            ..Default::default()
+        })))
+    }
+
+    /// Whether an address may go ahead with a ping or login, which counts
+    /// against its limit.
+    pub fn allow(&self, ip: IpAddr, attempt: Attempt) -> bool {
+        let mut limits = self.0.lock().unwrap();
+        let Limits {
+            settings,
+            addresses,
+            refusals,
+            ..
+        } = &mut *limits;
+        if settings.exempt.contains(&ip) {
+            return true;
+        }
+
+        let now = Instant::now();
+        let address = addresses.entry(ip).or_insert_with(|| Address {
+            pings: Bucket::new(settings.ping_burst),
+            logins: Bucket::new(settings.login_burst),
+            refused: 0,
+            window_start: now,
+            blocked_until: None,
+            last_seen: now,
+        });
+        address.last_seen = now;
+
+        let blocked = address.blocked_until.map_or(false, |until| now < until);
+        let allowed = !blocked
+            && match attempt {
+                Attempt::Ping => {
+                    address
+                        .pings
+                        .take(settings.ping_burst, settings.ping_per_second, now)
+                }
+                Attempt::Login => {
+                    address
+                        .logins
+                        .take(settings.login_burst, settings.login_per_second, now)
+                }
+            };
+        if allowed {
+            return true;
+        }
+
+        *refusals.entry(ip).or_default() += 1;
+        if blocked {
+            return false;
+        }
+
+        if now - address.window_start >= REFUSAL_WINDOW {
+            address.window_start = now;
+            address.refused = 0;
+        }
+        address.refused += 1;
+        if settings.block_after > 0 && address.refused >= settings.block_after {
+            address.blocked_until = Some(now + Duration::from_secs(settings.block_secs));
+            warn!(
+                "Blocked {ip} for {}s after {} refused connection attempts",
+                settings.block_secs, address.refused
+            );
+        }
+        false
+    }
+
+    /// What a refused login is told.
+    pub fn rejection(&self) -> Text {
+        self.0.lock().unwrap().message.clone()
+    }
+}
+
+pub struct ConnectionLimitPlugin;
+
+impl Plugin for ConnectionLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(update_settings)
+            .add_system(reload_settings)
+            .add_system(report_refusals);
+    }
+}
+
+fn update_settings(limits: Res<SharedConnectionLimits>, config: Res<Config>, lang: Res<Lang>) {
+    let mut limits = limits.0.lock().unwrap();
+    limits.settings = config.connection_limit.clone();
+    limits.message = lang.tr_default("server.throttled", &[]);
+}
+
+fn reload_settings(
+    limits: Res<SharedConnectionLimits>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<ConfigReloaded>,
+) {
+    if events.iter().count() > 0 {
+        update_settings(limits, config, lang);
+    }
+}
+
+/// Logs what was refused since the last report, and forgets addresses that
+/// have gone quiet.
+fn report_refusals(limits: Res<SharedConnectionLimits>, mut last_report: Local<Option<Instant>>) {
+    let now = Instant::now();
+    let last = *last_report.get_or_insert(now);
+    if now - last < REPORT_INTERVAL {
+        return;
+    }
+    *last_report = Some(now);
+
+    let mut limits = limits.0.lock().unwrap();
+    limits.addresses.retain(|_, address| {
+        now - address.last_seen < IDLE || address.blocked_until.map_or(false, |until| now < until)
+    });
+
+    let refusals = std::mem::take(&mut limits.refusals);
+    let Some((worst, worst_count)) = refusals.iter().max_by_key(|(_, count)| **count) else {
+        return;
+    };
+    let total: u64 = refusals.values().sum();
+    warn!(
+        "Refused {total} connection attempts from {} addresses in the last {}s, {worst_count} \
+         of them from {worst}",
+        refusals.len(),
+        (now - last).as_secs()
+    );
+}
@@ -0,0 +1,87 @@
+use std::io::BufRead;
+
+use tracing::warn;
+use valence::prelude::*;
+
+use crate::command::{Console, ConsoleCommand};
+use crate::lang::Lang;
+
+/// Lines typed into the server's standard input.
+#[derive(Resource)]
+struct ConsoleInput(flume::Receiver<String>);
+
+/// Marks the [`Console`] that reads from standard input, as opposed to the
+/// ones RCON makes for each command.
+#[derive(Component)]
+struct StdinConsole;
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(start_console)
+            .add_system_to_stage(CoreStage::First, read_console)
+            .add_system_to_stage(CoreStage::Last, print_console);
+    }
+}
+
+fn start_console(mut commands: Commands) {
+    let (sender, receiver) = flume::unbounded();
+
+    // Reading stdin blocks, so it gets a thread of its own. It stops quietly
+    // when stdin closes, like when running as a service.
+    let spawned = std::thread::Builder::new()
+        .name("console".into())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        warn!("Failed to start the console: {e}");
+        return;
+    }
+
+    commands.insert_resource(ConsoleInput(receiver));
+    commands.spawn((Console::new("Console"), StdinConsole));
+}
+
+fn read_console(
+    mut consoles: Query<(Entity, &mut Console), With<StdinConsole>>,
+    input: Option<Res<ConsoleInput>>,
+    lang: Res<Lang>,
+    mut commands: EventWriter<ConsoleCommand>,
+) {
+    let (Some(input), Ok((sender, mut console))) = (input, consoles.get_single_mut()) else {
+        return;
+    };
+
+    for line in input.0.try_iter() {
+        if line.trim().is_empty() {
+            console.send_message(lang.tr(sender, "console.hint", &[]));
+            continue;
+        }
+
+        commands.send(ConsoleCommand {
+            sender,
+            command: line,
+        });
+    }
+}
+
+/// Prints replies to console commands. Log lines are written whole, so as
+/// long as replies are too the two don't garble each other; there's no
+/// prompt for the log to write over.
+fn print_console(mut consoles: Query<&mut Console, With<StdinConsole>>) {
+    for mut console in &mut consoles {
+        if console.has_output() {
+            println!("{}", console.take_output());
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy_ecs::system::SystemParam;
+use plotsirv::chunk_view::within;
+use valence::prelude::*;
+
+use crate::chunks::ModifiedChunks;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::{MAX_SPAWN_Y, MIN_SPAWN_Y};
+use crate::lang::Lang;
+use crate::persistence::Persistence;
+use crate::WorldName;
+
+const DEBUG: CommandInfo = CommandInfo {
+    name: "debug",
+    aliases: &[],
+    usage: "/debug [chunk]",
+    description: "Show what the server has loaded, or the chunk you're in.",
+    permission: Some("plots.command.debug"),
+    console: true,
+};
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(DEBUG)
+            .add_system_to_stage(EventLoop, debug_command);
+    }
+}
+
+/// What `/debug` reports on. Everything here is either counted already by
+/// whatever owns it or cheap to walk, so asking on a struggling server
+/// doesn't make it worse.
+#[derive(SystemParam)]
+struct DebugStats<'w, 's> {
+    instances: Query<'w, 's, (Entity, &'static Instance, &'static WorldName)>,
+    entities: Query<'w, 's, &'static McEntity>,
+    modified: Res<'w, ModifiedChunks>,
+    persistence: Res<'w, Persistence>,
+}
+
+fn debug_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    stats: DebugStats,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(DEBUG.name)) {
+        let reply = match event.args.as_slice() {
+            [] => overview(&lang, event.sender, &stats, &clients),
+            [action] if action == "chunk" => match clients.get(event.sender) {
+                Ok(client) => chunk_info(&lang, event.sender, &stats, &clients, client),
+                Err(_) => lang.tr(
+                    event.sender,
+                    "command.players_only",
+                    &[("name", &"debug chunk")],
+                ),
+            },
+            _ => usage(&lang, event.sender, &DEBUG),
+        };
+
+        if let Ok(mut client) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+fn overview(lang: &Lang, sender: Entity, stats: &DebugStats, clients: &Query<&mut Client>) -> Text {
+    let mut out = lang.tr(sender, "debug.header", &[]);
+
+    let mut worlds: Vec<_> = stats.instances.iter().collect();
+    worlds.sort_unstable_by(|(_, _, a), (_, _, b)| a.0.cmp(&b.0));
+    for (entity, instance, name) in worlds {
+        out = out
+            + "\n"
+            + lang.tr(
+                sender,
+                "debug.world",
+                &[
+                    ("world", &name.0),
+                    ("chunks", &instance.chunks().count()),
+                    ("modified", &stats.modified.count(entity)),
+                ],
+            );
+    }
+
+    let mut kinds = BTreeMap::new();
+    for entity in &stats.entities {
+        *kinds.entry(format!("{:?}", entity.kind())).or_insert(0) += 1;
+    }
+    let entities = if kinds.is_empty() {
+        lang.plain(sender, "debug.none").to_owned()
+    } else {
+        kinds
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let online = clients.iter().filter(|c| !c.is_disconnected()).count();
+    let memory = resident_memory().map_or_else(
+        || lang.plain(sender, "debug.unknown").to_owned(),
+        |bytes| format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    );
+
+    out + "\n"
+        + lang.tr(sender, "debug.entities", &[("entities", &entities)])
+        + "\n"
+        + lang.tr(sender, "debug.clients", &[("clients", &online)])
+        + "\n"
+        + lang.tr(
+            sender,
+            "debug.pending",
+            &[("writes", &stats.persistence.pending_writes())],
+        )
+        + "\n"
+        + lang.tr(sender, "debug.memory", &[("memory", &memory)])
+}
+
+/// The chunk a player is standing in, section by section.
+fn chunk_info(
+    lang: &Lang,
+    sender: Entity,
+    stats: &DebugStats,
+    clients: &Query<&mut Client>,
+    client: &Client,
+) -> Text {
+    let position = client.position();
+    let pos = ChunkPos::at(position.x, position.z);
+    let Ok((entity, instance, world)) = stats.instances.get(client.instance()) else {
+        return lang.tr(sender, "debug.not_loaded", &[]);
+    };
+    if instance.chunk(pos).is_none() {
+        return lang.tr(sender, "debug.not_loaded", &[]);
+    }
+
+    let viewers = clients
+        .iter()
+        .filter(|c| !c.is_disconnected() && c.instance() == entity)
+        .filter(|c| {
+            let at = c.position();
+            within(ChunkPos::at(at.x, at.z), pos, c.view_distance())
+        })
+        .count();
+
+    let mut out = lang.tr(
+        sender,
+        "debug.chunk",
+        &[("x", &pos.x), ("z", &pos.z), ("world", &world.0)],
+    ) + "\n"
+        + lang.tr(
+            sender,
+            "debug.chunk_state",
+            &[
+                ("modified", &stats.modified.contains(entity, pos)),
+                ("viewers", &viewers),
+            ],
+        );
+
+    let mut empty = true;
+    for section in (MIN_SPAWN_Y as i32).div_euclid(16)..(MAX_SPAWN_Y as i32).div_euclid(16) {
+        let mut states = HashSet::new();
+        let mut blocks = 0;
+        for y in section * 16..section * 16 + 16 {
+            for z in pos.z * 16..pos.z * 16 + 16 {
+                for x in pos.x * 16..pos.x * 16 + 16 {
+                    let Some(block) = instance.block([x, y, z]) else {
+                        continue;
+                    };
+                    let state = block.state();
+                    if !state.is_air() {
+                        blocks += 1;
+                    }
+                    states.insert(state);
+                }
+            }
+        }
+
+        if blocks > 0 {
+            empty = false;
+            out = out
+                + "\n"
+                + lang.tr(
+                    sender,
+                    "debug.section",
+                    &[
+                        ("y", &(section * 16)),
+                        ("blocks", &blocks),
+                        ("states", &states.len()),
+                    ],
+                );
+        }
+    }
+    if empty {
+        out = out + "\n" + lang.tr(sender, "debug.empty", &[]);
+    }
+
+    out
+}
+
+/// The process's resident memory in bytes, where the OS says.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
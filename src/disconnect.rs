@@ -0,0 +1,122 @@
+use std::marker::PhantomData;
+
+use valence::prelude::*;
+
+/// Sent once for each client that disconnects, just before it's despawned,
+/// for anything keeping state about it to let go. Read it from a system added
+/// with [`AddDisconnectSystem::add_disconnect_system`].
+#[derive(Clone, Debug)]
+pub struct ClientDisconnected {
+    pub client: Entity,
+    pub uuid: Uuid,
+}
+
+/// Where disconnects are handled in `PostUpdate`. Whatever despawns
+/// disconnected clients is labelled [`DisconnectLabel::Despawn`].
+#[derive(SystemLabel, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DisconnectLabel {
+    Announce,
+    Despawn,
+}
+
+/// What announcing a disconnect needs of a client, so it can be tested with
+/// a stand-in.
+pub trait Connection: Component {
+    fn uuid(&self) -> Uuid;
+    fn is_disconnected(&self) -> bool;
+}
+
+impl Connection for Client {
+    fn uuid(&self) -> Uuid {
+        Client::uuid(self)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        Client::is_disconnected(self)
+    }
+}
+
+/// State kept about each client in a resource, dropped when they disconnect
+/// by a system added with [`AddDisconnectSystem::add_client_registry`].
+pub trait ClientRegistry: Resource {
+    /// Drops everything kept about a disconnecting client.
+    fn forget(&mut self, client: Entity, uuid: Uuid);
+
+    /// How many entries are kept about a client.
+    fn entries(&self, client: Entity, uuid: Uuid) -> usize;
+}
+
+pub trait AddDisconnectSystem {
+    /// Adds a system that runs after disconnects are announced and before
+    /// the clients are despawned, while they're still there to look at and
+    /// before their entities can be reused.
+    fn add_disconnect_system<Params>(
+        &mut self,
+        system: impl ParallelSystemDescriptorCoercion<Params>,
+    ) -> &mut Self;
+
+    /// Drops what `R` keeps about each client that disconnects.
+    fn add_client_registry<R: ClientRegistry>(&mut self) -> &mut Self {
+        self.add_disconnect_system(forget_disconnected::<R>)
+    }
+}
+
+impl AddDisconnectSystem for App {
+    fn add_disconnect_system<Params>(
+        &mut self,
+        system: impl ParallelSystemDescriptorCoercion<Params>,
+    ) -> &mut Self {
+        self.add_system_to_stage(
+            CoreStage::PostUpdate,
+            system
+                .after(DisconnectLabel::Announce)
+                .before(DisconnectLabel::Despawn),
+        )
+    }
+}
+
+/// Announces disconnects of clients of type `C`, which is [`Client`] in the
+/// server.
+pub struct DisconnectPlugin<C>(PhantomData<C>);
+
+impl<C> Default for DisconnectPlugin<C> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<C: Connection> Plugin for DisconnectPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ClientDisconnected>().add_system_to_stage(
+            CoreStage::PostUpdate,
+            announce_disconnects::<C>
+                .label(DisconnectLabel::Announce)
+                .before(DisconnectLabel::Despawn),
+        );
+    }
+}
+
+/// Clients are despawned in the same stage, after everything that could
+/// disconnect them has run, so each is announced exactly once.
+fn announce_disconnects<C: Connection>(
+    clients: Query<(Entity, &C)>,
+    mut events: EventWriter<ClientDisconnected>,
+) {
+    for (entity, client) in &clients {
+        if client.is_disconnected() {
+            events.send(ClientDisconnected {
+                client: entity,
+                uuid: client.uuid(),
+            });
+        }
+    }
+}
+
+fn forget_disconnected<R: ClientRegistry>(
+    mut registry: ResMut<R>,
+    mut events: EventReader<ClientDisconnected>,
+) {
+    for event in events.iter() {
+        registry.forget(event.client, event.uuid);
+    }
+}
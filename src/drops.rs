@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::PickupItem;
+use valence_protocol::sound::{Sound, SoundCategory};
+use valence_protocol::VarInt;
+
+use crate::config::{Config, ItemsConfig};
+use crate::items::insert_stack;
+use crate::sound::play_sound_at;
+
+/// How long a dropped item lies before it can be picked up, in ticks.
+const PICKUP_DELAY: u64 = 10;
+
+/// Dropped items disappear after this many ticks, which is five minutes.
+const LIFETIME: u64 = 6000;
+
+/// How far past a player's hitbox items are picked up from, sideways and up
+/// or down, as in vanilla.
+const PICKUP_REACH: DVec3 = DVec3::new(1.0, 0.5, 1.0);
+
+/// How close two dropped items have to be to merge, sideways. Up and down,
+/// they have to overlap.
+const MERGE_DISTANCE: f64 = 0.5;
+
+/// How tall a dropped item's hitbox is.
+const ITEM_HEIGHT: f64 = 0.25;
+
+/// Dropped items are looked at for merging this often, in ticks.
+const MERGE_INTERVAL: u64 = 10;
+
+/// The pickup sound is heard this far away.
+const SOUND_DISTANCE: f64 = 16.0;
+
+/// What each client calls its own entity, which is what the pickup
+/// animation flies towards.
+const OWN_ENTITY_ID: i32 = 0;
+
+/// A stack of items lying in the world.
+#[derive(Component, Debug)]
+pub struct DroppedItem {
+    stack: ItemStack,
+    /// In ticks.
+    age: u64,
+}
+
+/// Dropped items in chunks that have unloaded, kept to be put back when the
+/// chunks load again.
+#[derive(Resource, Default)]
+struct UnloadedItems(HashMap<(Entity, ChunkPos), Vec<(DVec3, DroppedItem)>>);
+
+pub struct DropsPlugin;
+
+impl Plugin for DropsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UnloadedItems>()
+            .add_system(age_items)
+            .add_system(restore_items.after(age_items))
+            .add_system(merge_items.after(age_items))
+            .add_system(pick_up_items.after(merge_items));
+    }
+}
+
+/// Drops `stack` at `position`.
+pub fn drop_item(commands: &mut Commands, instance: Entity, position: DVec3, stack: ItemStack) {
+    spawn(commands, instance, position, DroppedItem { stack, age: 0 });
+}
+
+fn spawn(commands: &mut Commands, instance: Entity, position: DVec3, item: DroppedItem) {
+    let mut entity = McEntity::new(EntityKind::Item, instance);
+    entity.set_position(position);
+    show_stack(&mut entity, &item.stack);
+    commands.spawn((entity, item));
+}
+
+/// Updates what a dropped item looks like to players.
+fn show_stack(entity: &mut McEntity, stack: &ItemStack) {
+    if let TrackedData::Item(data) = entity.data_mut() {
+        data.set_stack(Some(stack.clone()));
+    }
+}
+
+/// Whether items at `item` are in reach of a player whose feet are at
+/// `feet`.
+fn in_reach(feet: DVec3, item: DVec3) -> bool {
+    let min = feet - DVec3::new(0.3, 0.0, 0.3) - PICKUP_REACH;
+    let max = feet + DVec3::new(0.3, 1.8, 0.3) + PICKUP_REACH;
+    item.cmpge(min).all() && item.cmple(max).all()
+}
+
+fn can_pick_up(game_mode: GameMode, config: &ItemsConfig) -> bool {
+    match game_mode {
+        GameMode::Survival | GameMode::Adventure => true,
+        GameMode::Creative => config.creative_pickup,
+        GameMode::Spectator => false,
+    }
+}
+
+/// Gets rid of items that have been lying around too long, and takes items
+/// out of chunks that have unloaded, keeping them if the config says to.
+fn age_items(
+    mut items: Query<(&mut McEntity, &mut DroppedItem)>,
+    instances: Query<&Instance>,
+    mut unloaded: ResMut<UnloadedItems>,
+    config: Res<Config>,
+) {
+    for (mut entity, mut item) in &mut items {
+        if entity.is_despawned() {
+            continue;
+        }
+
+        item.age += 1;
+        if item.age >= LIFETIME {
+            entity.set_despawned(true);
+            continue;
+        }
+
+        let position = entity.position();
+        let chunk = ChunkPos::at(position.x, position.z);
+        let Ok(instance) = instances.get(entity.instance()) else {
+            entity.set_despawned(true);
+            continue;
+        };
+        if instance.chunk(chunk).is_none() {
+            entity.set_despawned(true);
+            if config.items.keep_in_unloaded_chunks {
+                let stack = item.stack.clone();
+                unloaded
+                    .0
+                    .entry((entity.instance(), chunk))
+                    .or_default()
+                    .push((
+                        position,
+                        DroppedItem {
+                            stack,
+                            age: item.age,
+                        },
+                    ));
+            }
+        }
+    }
+}
+
+/// Puts kept items back once their chunk has loaded again, and forgets the
+/// ones in worlds that are gone.
+fn restore_items(
+    mut commands: Commands,
+    instances: Query<&Instance>,
+    mut unloaded: ResMut<UnloadedItems>,
+) {
+    unloaded.0.retain(|&(instance, chunk), items| {
+        let Ok(loaded) = instances.get(instance) else {
+            return false;
+        };
+        if loaded.chunk(chunk).is_none() {
+            return true;
+        }
+
+        for (position, item) in items.drain(..) {
+            spawn(&mut commands, instance, position, item);
+        }
+        false
+    });
+}
+
+/// Merges dropped items lying next to each other into one stack, so a pile
+/// of drops doesn't mean a pile of entities.
+fn merge_items(mut items: Query<(Entity, &mut McEntity, &mut DroppedItem)>, server: Res<Server>) {
+    if server.current_tick() % MERGE_INTERVAL != 0 {
+        return;
+    }
+
+    let mut piles: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (item, entity, _) in &items {
+        if !entity.is_despawned() {
+            piles.entry(entity.instance()).or_default().push(item);
+        }
+    }
+
+    for pile in piles.values() {
+        for (i, &first) in pile.iter().enumerate() {
+            for &second in &pile[i + 1..] {
+                let Ok([(_, a, a_item), (_, b, b_item)]) = items.get_many_mut([first, second])
+                else {
+                    continue;
+                };
+                if a.is_despawned() || b.is_despawned() {
+                    continue;
+                }
+
+                let (a_pos, b_pos) = (a.position(), b.position());
+                let close = (a_pos.x - b_pos.x).abs() <= MERGE_DISTANCE
+                    && (a_pos.z - b_pos.z).abs() <= MERGE_DISTANCE
+                    && (a_pos.y - b_pos.y).abs() < ITEM_HEIGHT;
+                let same =
+                    a_item.stack.item == b_item.stack.item && a_item.stack.nbt == b_item.stack.nbt;
+                let total = u16::from(a_item.stack.count()) + u16::from(b_item.stack.count());
+                if !close || !same || total > u16::from(a_item.stack.item.max_stack()) {
+                    continue;
+                }
+
+                // The bigger stack takes in the smaller, and the merged one
+                // lasts as long as the newer of the two did.
+                let ((mut into, mut into_item), (mut from, from_item)) =
+                    if a_item.stack.count() >= b_item.stack.count() {
+                        ((a, a_item), (b, b_item))
+                    } else {
+                        ((b, b_item), (a, a_item))
+                    };
+                into_item.stack.set_count(total as u8);
+                into_item.age = into_item.age.min(from_item.age);
+                show_stack(&mut into, &into_item.stack);
+                from.set_despawned(true);
+            }
+        }
+    }
+}
+
+/// Puts dropped items into the inventories of players standing over them,
+/// as much as fits, leaving the rest on the ground.
+fn pick_up_items(
+    mut items: Query<(&mut McEntity, &mut DroppedItem)>,
+    mut clients: Query<(&mut Client, &mut Inventory)>,
+    config: Res<Config>,
+) {
+    let mut picked_up = Vec::new();
+
+    for (mut entity, mut item) in &mut items {
+        if entity.is_despawned() || item.age < PICKUP_DELAY {
+            continue;
+        }
+        let position = entity.position();
+
+        for (mut client, mut inventory) in &mut clients {
+            if client.is_disconnected()
+                || client.instance() != entity.instance()
+                || !can_pick_up(client.game_mode(), &config.items)
+                || !in_reach(client.position(), position)
+            {
+                continue;
+            }
+
+            let taken = insert_stack(&mut inventory, &item.stack);
+            if taken == 0 {
+                continue;
+            }
+
+            // Nobody else can see this player, so only they see the items
+            // fly to them; everyone else just sees the items go.
+            client.write_packet(&PickupItem {
+                collected_entity_id: VarInt(entity.protocol_id()),
+                collector_entity_id: VarInt(OWN_ENTITY_ID),
+                pickup_item_count: VarInt(i32::from(taken)),
+            });
+            picked_up.push((entity.instance(), position));
+
+            let left = item.stack.count() - taken;
+            if left == 0 {
+                entity.set_despawned(true);
+                break;
+            }
+            item.stack.set_count(left);
+            show_stack(&mut entity, &item.stack);
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for (instance, position) in picked_up {
+        let pitch = ((rng.gen::<f32>() - rng.gen::<f32>()) * 0.7 + 1.0) * 2.0;
+        for (mut client, _) in &mut clients {
+            if client.instance() == instance
+                && client.position().distance(position) <= SOUND_DISTANCE
+            {
+                play_sound_at(
+                    &mut client,
+                    Sound::EntityItemPickup,
+                    SoundCategory::Player,
+                    position,
+                    pitch,
+                    0.2,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use tracing::warn;
+use valence::prelude::*;
+
+/// The instance a client's block edit goes to: `instance`, the one the
+/// client is in, looked up per edit so each lands in its own world. If it
+/// doesn't exist, say because it went since the client sent the edit, it's
+/// warned about only the first time so a client can't flood the log.
+pub fn edited_instance<'a, I: Component>(
+    instances: &'a mut Query<&mut I>,
+    instance: Entity,
+    username: &str,
+    warned: &mut bool,
+) -> Option<Mut<'a, I>> {
+    let found = instances.get_mut(instance).ok();
+    if found.is_none() && !std::mem::replace(warned, true) {
+        warn!(
+            "Ignoring block edits from {} as their instance {:?} doesn't exist",
+            username, instance
+        );
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::SystemState;
+
+    use super::*;
+
+    /// Stands in for an instance, counting the edits it gets.
+    #[derive(Component, Default)]
+    struct Edits(u32);
+
+    fn edit(world: &mut World, instance: Entity, warned: &mut bool) -> bool {
+        let mut state = SystemState::<Query<&mut Edits>>::new(world);
+        let mut instances = state.get_mut(world);
+        match edited_instance(&mut instances, instance, "someone", warned) {
+            Some(mut edits) => {
+                edits.0 += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn edits(world: &World, instance: Entity) -> u32 {
+        world.get::<Edits>(instance).unwrap().0
+    }
+
+    #[test]
+    fn edits_go_to_the_clients_instance() {
+        let mut world = World::new();
+        let first = world.spawn(Edits::default()).id();
+        let second = world.spawn(Edits::default()).id();
+        let mut warned = false;
+
+        assert!(edit(&mut world, second, &mut warned));
+        assert!(edit(&mut world, second, &mut warned));
+        assert!(edit(&mut world, first, &mut warned));
+
+        assert_eq!(edits(&world, first), 1);
+        assert_eq!(edits(&world, second), 2);
+        assert!(!warned);
+    }
+
+    #[test]
+    fn edits_to_a_missing_instance_are_skipped() {
+        let mut world = World::new();
+        let first = world.spawn(Edits::default()).id();
+        let gone = world.spawn(Edits::default()).id();
+        world.despawn(gone);
+        let mut warned = false;
+
+        assert!(!edit(&mut world, gone, &mut warned));
+        assert!(warned);
+        // Still warned, so it isn't logged again.
+        assert!(!edit(&mut world, gone, &mut warned));
+        assert!(warned);
+
+        assert!(edit(&mut world, first, &mut warned));
+        assert_eq!(edits(&world, first), 1);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use valence::prelude::*;
+
+use crate::block_log::BlockChanged;
+use crate::border::{inside_border, WorldBorder};
+use crate::drops::drop_item;
+
+/// How long after the block under it goes a block starts to fall, in ticks.
+/// A column falls one block after another this far apart, as in vanilla.
+const FALL_DELAY: u64 = 2;
+
+/// How much faster falling blocks fall each tick, and how much of their
+/// speed they keep, in blocks per tick.
+const GRAVITY: f64 = 0.04;
+const DRAG: f64 = 0.98;
+
+/// A block falling through the air.
+#[derive(Component, Debug)]
+struct FallingBlock {
+    state: BlockState,
+    /// Where it fell from.
+    origin: BlockPos,
+    /// Downwards is negative.
+    velocity: f64,
+}
+
+/// Blocks to check for falling, and the tick to check each at.
+#[derive(Resource, Default)]
+struct FallChecks(HashMap<(Entity, BlockPos), u64>);
+
+impl FallChecks {
+    fn schedule(&mut self, instance: Entity, pos: BlockPos, now: u64) {
+        let due = now + FALL_DELAY;
+        let at = self.0.entry((instance, pos)).or_insert(due);
+        *at = (*at).min(due);
+    }
+}
+
+pub struct FallingBlocksPlugin;
+
+impl Plugin for FallingBlocksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FallChecks>()
+            .add_system(check_changed_blocks)
+            .add_system(start_falling.after(check_changed_blocks))
+            .add_system(move_falling_blocks.after(start_falling));
+    }
+}
+
+/// Whether blocks of `kind` fall when there's nothing under them.
+fn has_gravity(kind: BlockKind) -> bool {
+    matches!(
+        kind,
+        BlockKind::Sand | BlockKind::RedSand | BlockKind::Gravel
+    ) || kind.to_str().ends_with("_concrete_powder")
+}
+
+/// Whether a falling block falls through `state`. It falls through
+/// anything it could be placed over, and anything there's nothing to bump
+/// into, like torches.
+fn falls_through(state: BlockState) -> bool {
+    state.is_replaceable() || state.collision_shapes().next().is_none()
+}
+
+/// Whether a falling block resting on `state` ends up in the block above
+/// it, rather than sinking into it like it would into half a slab.
+fn holds_up(state: BlockState) -> bool {
+    state.collision_shapes().any(|shape| shape.max.y >= 1.0)
+}
+
+fn is_water(instance: &Instance, pos: BlockPos) -> bool {
+    instance
+        .block(pos)
+        .map_or(false, |block| block.state().to_kind() == BlockKind::Water)
+}
+
+/// The block a falling block becomes when it lands at `pos`. Concrete
+/// powder hardens into concrete in or next to water.
+fn landed_state(instance: &Instance, state: BlockState, pos: BlockPos) -> BlockState {
+    let name = state.to_kind().to_str();
+    let Some(concrete) = name.strip_suffix("_powder") else {
+        return state;
+    };
+    let wet = [
+        (0, 0, 0),
+        (0, 1, 0),
+        (1, 0, 0),
+        (-1, 0, 0),
+        (0, 0, 1),
+        (0, 0, -1),
+    ]
+    .into_iter()
+    .any(|(x, y, z)| is_water(instance, BlockPos::new(pos.x + x, pos.y + y, pos.z + z)));
+    match BlockKind::from_str(concrete) {
+        Some(kind) if wet => kind.to_state(),
+        _ => state,
+    }
+}
+
+/// A block placed in the air might fall, and a block taken away might leave
+/// one above it with nothing to stand on.
+fn check_changed_blocks(
+    mut checks: ResMut<FallChecks>,
+    server: Res<Server>,
+    mut events: EventReader<BlockChanged>,
+) {
+    let now = server.current_tick();
+    for event in events.iter() {
+        let pos = event.position;
+        checks.schedule(event.instance, pos, now);
+        checks.schedule(event.instance, BlockPos::new(pos.x, pos.y + 1, pos.z), now);
+    }
+}
+
+/// Turns blocks with nothing under them into falling blocks. Each one that
+/// falls has the block above it checked a little later, so a column falls
+/// from the bottom up a block at a time.
+fn start_falling(
+    mut commands: Commands,
+    mut instances: Query<&mut Instance>,
+    mut checks: ResMut<FallChecks>,
+    server: Res<Server>,
+) {
+    let now = server.current_tick();
+    let due: Vec<_> = checks
+        .0
+        .iter()
+        .filter(|&(_, &at)| at <= now)
+        .map(|(&key, _)| key)
+        .collect();
+
+    for (instance_entity, pos) in due {
+        checks.0.remove(&(instance_entity, pos));
+        let Ok(mut instance) = instances.get_mut(instance_entity) else {
+            continue;
+        };
+        let Some(state) = instance.block(pos).map(|block| block.state()) else {
+            continue;
+        };
+        let below = BlockPos::new(pos.x, pos.y - 1, pos.z);
+        let unsupported = instance
+            .block(below)
+            .map_or(false, |b| falls_through(b.state()));
+        if !has_gravity(state.to_kind()) || !unsupported {
+            continue;
+        }
+
+        instance.set_block(pos, BlockState::AIR);
+        let mut entity = McEntity::new(EntityKind::FallingBlock, instance_entity);
+        entity.set_position([pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5]);
+        // Clients draw falling blocks as the block their spawn data names.
+        entity.set_object_data(state.to_raw() as i32);
+        commands.spawn((
+            entity,
+            FallingBlock {
+                state,
+                origin: pos,
+                velocity: 0.0,
+            },
+        ));
+        checks.schedule(instance_entity, BlockPos::new(pos.x, pos.y + 1, pos.z), now);
+    }
+}
+
+/// Moves falling blocks down, and turns them back into blocks where they
+/// land. One landing somewhere it can't be placed, like on a torch or half
+/// a slab, drops as an item instead. One that falls out of the world or
+/// across the world border is gone.
+fn move_falling_blocks(
+    mut commands: Commands,
+    mut falling: Query<(&mut McEntity, &mut FallingBlock)>,
+    mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
+) {
+    for (mut entity, mut block) in &mut falling {
+        if entity.is_despawned() {
+            continue;
+        }
+        let Ok(mut instance) = instances.get_mut(entity.instance()) else {
+            entity.set_despawned(true);
+            continue;
+        };
+
+        block.velocity -= GRAVITY;
+        let position = entity.position();
+        let target = position.y + block.velocity;
+        block.velocity *= DRAG;
+
+        let (x, z) = (position.x.floor() as i32, position.z.floor() as i32);
+        let powder = block.state.to_kind().to_str().ends_with("_concrete_powder");
+        let mut landed = None;
+        let mut lost = false;
+        for y in (target.floor() as i32..position.y.floor() as i32).rev() {
+            let pos = BlockPos::new(x, y, z);
+            let Some(state) = instance.block(pos).map(|b| b.state()) else {
+                lost = true;
+                break;
+            };
+            // Concrete powder stops in water, to harden there.
+            if powder && state.to_kind() == BlockKind::Water {
+                landed = Some((pos, true));
+                break;
+            }
+            if !falls_through(state) {
+                landed = Some((BlockPos::new(x, y + 1, z), holds_up(state)));
+                break;
+            }
+        }
+
+        if lost {
+            entity.set_despawned(true);
+            continue;
+        }
+        let Some((pos, supported)) = landed else {
+            entity.set_position([position.x, target, position.z]);
+            continue;
+        };
+
+        entity.set_despawned(true);
+        if !inside_border(&borders, entity.instance(), pos)
+            && inside_border(&borders, entity.instance(), block.origin)
+        {
+            continue;
+        }
+
+        let here = instance.block(pos).map(|b| b.state());
+        if supported && here.map_or(false, BlockState::is_replaceable) {
+            let state = landed_state(&instance, block.state, pos);
+            instance.set_block(pos, state);
+        } else if let Some(item) = ItemKind::from_str(block.state.to_kind().to_str()) {
+            let at = DVec3::new(position.x, pos.y as f64, position.z);
+            drop_item(
+                &mut commands,
+                entity.instance(),
+                at,
+                ItemStack::new(item, 1, None),
+            );
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use valence::client::event::{StartDigging, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::sound::{Sound, SoundCategory};
+use valence_protocol::types::Hand;
+
+use crate::block_log::{Actor, BlockChanged};
+use crate::block_sync::resend_block;
+use crate::border::{inside_border, WorldBorder};
+use crate::config::Config;
+use crate::inspect::Inspecting;
+use crate::sound::{play_sound_at, Feedback, FeedbackSound};
+use crate::tnt;
+use crate::weather::{Weather, WeatherKind};
+use crate::{UsedClicks, WorldName};
+
+/// How long fire waits between ticks: this many ticks, plus up to
+/// [`TICK_JITTER`] more, as in vanilla.
+const TICK_DELAY: u64 = 30;
+const TICK_JITTER: u64 = 10;
+
+/// The oldest fire gets. Fire this old with nothing flammable under it
+/// may go out on its own.
+const MAX_AGE: u16 = 15;
+
+/// How hard fire spreads, on vanilla's scale from 0 on peaceful to 3 on
+/// hard. There's no difficulty setting here, so it's vanilla's default.
+const DIFFICULTY: u32 = 2;
+
+const NEIGHBOURS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// The woods whose blocks burn. Crimson and warped don't.
+const WOODS: &[&str] = &[
+    "oak", "spruce", "birch", "jungle", "acacia", "dark_oak", "mangrove",
+];
+
+/// A fire burning somewhere, as far as the next time it ticks.
+#[derive(Clone, Debug)]
+struct Fire {
+    due: u64,
+    /// Who lit it, or the fire it spread from. What it burns is logged as
+    /// theirs, even once they've left.
+    lit_by: Actor,
+}
+
+/// The fires burning in each instance, by where they are.
+#[derive(Resource, Default)]
+struct Fires(HashMap<(Entity, BlockPos), Fire>);
+
+impl Fires {
+    fn schedule(&mut self, instance: Entity, pos: BlockPos, lit_by: Actor, now: u64) {
+        let due = now + TICK_DELAY + rand::thread_rng().gen_range(0..TICK_JITTER);
+        self.0.insert((instance, pos), Fire { due, lit_by });
+    }
+}
+
+pub struct FirePlugin;
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Fires>()
+            .add_system_to_stage(EventLoop, light_fires)
+            .add_system_to_stage(EventLoop, punch_fires)
+            .add_system(track_fires)
+            .add_system(tick_fires.after(track_fires));
+    }
+}
+
+fn offset(pos: BlockPos, (x, y, z): (i32, i32, i32)) -> BlockPos {
+    BlockPos::new(pos.x + x, pos.y + y, pos.z + z)
+}
+
+fn is_fire(state: BlockState) -> bool {
+    matches!(state.to_kind(), BlockKind::Fire | BlockKind::SoulFire)
+}
+
+fn is_wooden(name: &str) -> bool {
+    let name = name.strip_prefix("stripped_").unwrap_or(name);
+    WOODS.iter().any(|wood| {
+        name.strip_prefix(wood)
+            .map_or(false, |rest| rest.starts_with('_'))
+    })
+}
+
+/// How readily fire next to blocks of `kind` spreads into the air around
+/// them, and how readily they catch and burn away, as vanilla has them.
+/// Blocks that don't burn have neither.
+fn flammability(kind: BlockKind) -> Option<(u32, u32)> {
+    let name = kind.to_str();
+    let odds = match kind {
+        BlockKind::Bookshelf | BlockKind::Lectern => (30, 20),
+        BlockKind::Tnt | BlockKind::Vine => (15, 100),
+        BlockKind::HayBlock => (60, 20),
+        BlockKind::CoalBlock => (5, 5),
+        BlockKind::DriedKelpBlock => (30, 60),
+        BlockKind::Scaffolding => (60, 60),
+        BlockKind::Composter | BlockKind::Beehive | BlockKind::BeeNest => (5, 20),
+        BlockKind::Target => (15, 20),
+        BlockKind::Grass | BlockKind::TallGrass | BlockKind::Fern | BlockKind::LargeFern => {
+            (60, 100)
+        }
+        BlockKind::Dandelion
+        | BlockKind::Poppy
+        | BlockKind::BlueOrchid
+        | BlockKind::Allium
+        | BlockKind::AzureBluet
+        | BlockKind::OxeyeDaisy
+        | BlockKind::Cornflower
+        | BlockKind::LilyOfTheValley
+        | BlockKind::WitherRose
+        | BlockKind::Sunflower
+        | BlockKind::Lilac
+        | BlockKind::RoseBush
+        | BlockKind::Peony => (60, 100),
+        _ if name.ends_with("_tulip") => (60, 100),
+        _ if name.ends_with("_leaves") || name.ends_with("_wool") => (30, 60),
+        _ if name.ends_with("_carpet") && name != "moss_carpet" => (60, 20),
+        _ if is_wooden(name) && (name.ends_with("_log") || name.ends_with("_wood")) => (5, 5),
+        _ if is_wooden(name)
+            && (name.ends_with("_planks")
+                || name.ends_with("_slab")
+                || name.ends_with("_stairs")
+                || name.ends_with("_fence")
+                || name.ends_with("_fence_gate")) =>
+        {
+            (5, 20)
+        }
+        _ => return None,
+    };
+    Some(odds)
+}
+
+fn flammable(instance: &Instance, pos: BlockPos) -> bool {
+    instance.block(pos).map_or(false, |block| {
+        flammability(block.state().to_kind()).is_some()
+    })
+}
+
+/// Whether `pos` has a block with a solid top to burn on under it.
+fn on_solid_ground(instance: &Instance, pos: BlockPos) -> bool {
+    instance
+        .block(offset(pos, (0, -1, 0)))
+        .map_or(false, |block| {
+            block
+                .state()
+                .collision_shapes()
+                .any(|shape| shape.max.y >= 1.0)
+        })
+}
+
+/// Whether fire can burn at `pos`: on solid ground, or next to something
+/// that burns.
+fn can_burn_at(instance: &Instance, pos: BlockPos) -> bool {
+    on_solid_ground(instance, pos)
+        || NEIGHBOURS
+            .into_iter()
+            .any(|direction| flammable(instance, offset(pos, direction)))
+}
+
+/// Whether fire at `pos` burns forever, on netherrack or magma.
+fn burns_forever(instance: &Instance, pos: BlockPos) -> bool {
+    instance
+        .block(offset(pos, (0, -1, 0)))
+        .map_or(false, |block| {
+            matches!(
+                block.state().to_kind(),
+                BlockKind::Netherrack | BlockKind::MagmaBlock
+            )
+        })
+}
+
+/// Whether rain falls on `pos`, with nothing above it up to the top of the
+/// world.
+fn open_to_sky(instance: &Instance, pos: BlockPos) -> bool {
+    (pos.y + 1..)
+        .map(|y| instance.block(BlockPos::new(pos.x, y, pos.z)))
+        .take_while(Option::is_some)
+        .flatten()
+        .all(|block| block.state().is_air())
+}
+
+/// The fire block for `pos`. Soul fire burns on soul sand and soul soil.
+/// Fire off the ground clings to the sides of the flammable blocks around
+/// it instead of sitting on the floor.
+fn fire_state(instance: &Instance, pos: BlockPos, age: u16) -> BlockState {
+    let below = instance
+        .block(offset(pos, (0, -1, 0)))
+        .map(|block| block.state().to_kind());
+    if matches!(below, Some(BlockKind::SoulSand | BlockKind::SoulSoil)) {
+        return BlockState::SOUL_FIRE;
+    }
+
+    let age = PropValue::from_u16(age.min(MAX_AGE)).unwrap_or(PropValue::_0);
+    let state = BlockState::FIRE.set(PropName::Age, age);
+    if on_solid_ground(instance, pos) {
+        return state;
+    }
+    [
+        (PropName::North, (0, 0, -1)),
+        (PropName::East, (1, 0, 0)),
+        (PropName::South, (0, 0, 1)),
+        (PropName::West, (-1, 0, 0)),
+        (PropName::Up, (0, 1, 0)),
+    ]
+    .into_iter()
+    .fold(state, |state, (side, direction)| {
+        let clings = flammable(instance, offset(pos, direction));
+        state.set(side, PropValue::from_bool(clings))
+    })
+}
+
+fn age(state: BlockState) -> u16 {
+    state
+        .get(PropName::Age)
+        .and_then(PropValue::to_u16)
+        .unwrap_or(0)
+}
+
+/// Sets fire to the block a player uses flint and steel on, inside the
+/// world border. TNT is left to be lit instead.
+fn light_fires(
+    mut clients: Query<(&mut Client, &Inventory), Without<Inspecting>>,
+    mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let held = inventory
+            .slot(client.held_item_slot())
+            .map(|stack| stack.item);
+        if held != Some(ItemKind::FlintAndSteel) {
+            continue;
+        }
+        used.mark(event.client);
+        let Ok(mut instance) = instances.get_mut(client.instance()) else {
+            continue;
+        };
+        let clicked = instance.block(event.position).map(|block| block.state());
+        if clicked.map_or(true, |state| state.to_kind() == BlockKind::Tnt) {
+            continue;
+        }
+
+        let pos = event.position.get_in_direction(event.face);
+        let Some(old) = instance.block(pos).map(|block| block.state()) else {
+            continue;
+        };
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            resend_block(&mut client, &instance, pos);
+            continue;
+        }
+        if !old.is_air() || !can_burn_at(&instance, pos) {
+            resend_block(&mut client, &instance, pos);
+            continue;
+        }
+
+        let new = fire_state(&instance, pos, 0);
+        changes.send(BlockChanged {
+            actor: Actor::of(&client),
+            instance: client.instance(),
+            position: pos,
+            old,
+            new,
+        });
+        instance.set_block(pos, new);
+
+        let center = DVec3::new(pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5);
+        let pitch = rand::thread_rng().gen_range(0.8..1.2);
+        play_sound_at(
+            &mut client,
+            Sound::ItemFlintandsteelUse,
+            SoundCategory::Block,
+            center,
+            pitch,
+            1.0,
+        );
+    }
+}
+
+/// Punching fire puts it out. Creative players already break whatever
+/// they punch; this is for survival, where fire goes at the first punch
+/// rather than being dug.
+fn punch_fires(
+    clients: Query<&Client, Without<Inspecting>>,
+    mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<StartDigging>,
+    mut changes: EventWriter<BlockChanged>,
+) {
+    for event in events.iter() {
+        let Ok(client) = clients.get(event.client) else {
+            continue;
+        };
+        if client.game_mode() != GameMode::Survival
+            || !inside_border(&borders, client.instance(), event.position)
+        {
+            continue;
+        }
+        let Ok(mut instance) = instances.get_mut(client.instance()) else {
+            continue;
+        };
+        let Some(old) = instance.block(event.position).map(|block| block.state()) else {
+            continue;
+        };
+        if !is_fire(old) {
+            continue;
+        }
+        changes.send(BlockChanged {
+            actor: Actor::of(client),
+            instance: client.instance(),
+            position: event.position,
+            old,
+            new: BlockState::AIR,
+        });
+        instance.set_block(event.position, BlockState::AIR);
+    }
+}
+
+/// Starts ticking fire that players light.
+fn track_fires(
+    mut fires: ResMut<Fires>,
+    server: Res<Server>,
+    mut events: EventReader<BlockChanged>,
+) {
+    let now = server.current_tick();
+    for event in events.iter().filter(|e| is_fire(e.new)) {
+        let key = (event.instance, event.position);
+        if !fires.0.contains_key(&key) {
+            fires.schedule(key.0, key.1, event.actor.clone(), now);
+        }
+    }
+}
+
+/// What the block at `pos` becomes if it catches fire from fire `age`
+/// old, one time in `chance` for the most flammable blocks, as in vanilla.
+/// It either burns away or is replaced by more fire, unless rained on.
+fn catch_fire(
+    instance: &Instance,
+    pos: BlockPos,
+    chance: u32,
+    age: u16,
+    rained_on: bool,
+) -> Option<BlockState> {
+    let mut rng = rand::thread_rng();
+    let old = instance.block(pos)?.state();
+    let (_, burns) = flammability(old.to_kind())?;
+    if rng.gen_range(0..chance) >= burns {
+        return None;
+    }
+    if rng.gen_range(0..u32::from(age) + 10) >= 5 || rained_on {
+        return Some(BlockState::AIR);
+    }
+    let age = (age + rng.gen_range(0..5) / 4).min(MAX_AGE);
+    Some(fire_state(instance, pos, age))
+}
+
+/// Ticks each fire that's due. Fire ages, goes out with nothing to burn
+/// on, and is put out by rain it's open to. Where the world allows it, it
+/// also burns the blocks around it and spreads into the air next to
+/// flammable blocks, but never across the world border.
+fn tick_fires(
+    mut commands: Commands,
+    mut fires: ResMut<Fires>,
+    mut instances: Query<(&mut Instance, &WorldName, Option<&Weather>)>,
+    borders: Query<&WorldBorder>,
+    config: Res<Config>,
+    server: Res<Server>,
+    mut changes: EventWriter<BlockChanged>,
+) {
+    let now = server.current_tick();
+    let due: Vec<_> = fires
+        .0
+        .iter()
+        .filter(|(_, fire)| fire.due <= now)
+        .map(|(&key, fire)| (key, fire.clone()))
+        .collect();
+    let mut rng = rand::thread_rng();
+
+    for ((instance_entity, pos), fire) in due {
+        fires.0.remove(&(instance_entity, pos));
+        let Ok((mut instance, world, weather)) = instances.get_mut(instance_entity) else {
+            continue;
+        };
+        let Some(state) = instance.block(pos).map(|block| block.state()) else {
+            continue;
+        };
+        if !is_fire(state) {
+            continue;
+        }
+        let mut go_out = |instance: &mut Instance| {
+            changes.send(BlockChanged {
+                actor: fire.lit_by.clone(),
+                instance: instance_entity,
+                position: pos,
+                old: state,
+                new: BlockState::AIR,
+            });
+            instance.set_block(pos, BlockState::AIR);
+        };
+
+        // Soul fire only lasts on soul sand and soul soil, and doesn't
+        // spread.
+        if state.to_kind() == BlockKind::SoulFire {
+            if fire_state(&instance, pos, 0) == BlockState::SOUL_FIRE {
+                fires.schedule(instance_entity, pos, fire.lit_by.clone(), now);
+            } else {
+                go_out(&mut instance);
+            }
+            continue;
+        }
+        if !can_burn_at(&instance, pos) {
+            go_out(&mut instance);
+            continue;
+        }
+
+        let old_age = age(state);
+        let forever = burns_forever(&instance, pos);
+        let raining = weather.map_or(false, |weather| weather.kind != WeatherKind::Clear);
+        let rained_on = raining && open_to_sky(&instance, pos);
+        if !forever && rained_on && rng.gen::<f32>() < 0.2 + f32::from(old_age) * 0.03 {
+            go_out(&mut instance);
+            continue;
+        }
+
+        let age = (old_age + rng.gen_range(0..3) / 2).min(MAX_AGE);
+        if !forever {
+            let fuel = NEIGHBOURS
+                .into_iter()
+                .any(|direction| flammable(&instance, offset(pos, direction)));
+            let below = offset(pos, (0, -1, 0));
+            let burnt_out = if fuel {
+                age == MAX_AGE && rng.gen_range(0..4) == 0 && !flammable(&instance, below)
+            } else {
+                !on_solid_ground(&instance, pos) || age > 3
+            };
+            if burnt_out {
+                go_out(&mut instance);
+                continue;
+            }
+        }
+        if age != old_age {
+            let new = fire_state(&instance, pos, age);
+            instance.set_block(pos, new);
+        }
+        fires.schedule(instance_entity, pos, fire.lit_by.clone(), now);
+
+        if !config.world(&world.0).fire_spread {
+            continue;
+        }
+        let tnt_allowed = tnt::allowed_in(&config, world);
+
+        for direction in NEIGHBOURS {
+            let next = offset(pos, direction);
+            if !inside_border(&borders, instance_entity, next) {
+                continue;
+            }
+            // Straight up and down, blocks catch a little more easily.
+            let chance = if direction.1 == 0 { 300 } else { 250 };
+            let rained_on = raining && open_to_sky(&instance, next);
+            let Some(old) = instance.block(next).map(|block| block.state()) else {
+                continue;
+            };
+            let Some(mut new) = catch_fire(&instance, next, chance, age, rained_on) else {
+                continue;
+            };
+            if old.to_kind() == BlockKind::Tnt && tnt_allowed {
+                new = BlockState::AIR;
+                tnt::prime(&mut commands, instance_entity, next, tnt::FUSE, fire.lit_by.clone());
+            }
+            changes.send(BlockChanged {
+                actor: fire.lit_by.clone(),
+                instance: instance_entity,
+                position: next,
+                old,
+                new,
+            });
+            instance.set_block(next, new);
+            if is_fire(new) {
+                fires.schedule(instance_entity, next, fire.lit_by.clone(), now);
+            }
+        }
+
+        // Into the air near anything flammable, less readily the higher
+        // above the fire it is.
+        for x in -1..=1 {
+            for z in -1..=1 {
+                for y in -1..=4 {
+                    let next = BlockPos::new(pos.x + x, pos.y + y, pos.z + z);
+                    if next == pos || !inside_border(&borders, instance_entity, next) {
+                        continue;
+                    }
+                    let empty = instance
+                        .block(next)
+                        .map_or(false, |block| block.state().is_air());
+                    if !empty {
+                        continue;
+                    }
+                    let encouragement = NEIGHBOURS
+                        .into_iter()
+                        .filter_map(|direction| instance.block(offset(next, direction)))
+                        .filter_map(|block| flammability(block.state().to_kind()))
+                        .map(|(encouragement, _)| encouragement)
+                        .max()
+                        .unwrap_or(0);
+                    if encouragement == 0 {
+                        continue;
+                    }
+                    let height = (y - 1).max(0) as u32;
+                    let odds = (encouragement + 40 + DIFFICULTY * 7) / (u32::from(age) + 30);
+                    if odds == 0
+                        || rng.gen_range(0..100 + height * 100) > odds
+                        || raining && open_to_sky(&instance, next)
+                    {
+                        continue;
+                    }
+
+                    let spread_age = (age + rng.gen_range(0..5) / 4).min(MAX_AGE);
+                    let new = fire_state(&instance, next, spread_age);
+                    changes.send(BlockChanged {
+                        actor: fire.lit_by.clone(),
+                        instance: instance_entity,
+                        position: next,
+                        old: BlockState::AIR,
+                        new,
+                    });
+                    instance.set_block(next, new);
+                    fires.schedule(instance_entity, next, fire.lit_by.clone(), now);
+                }
+            }
+        }
+    }
+}
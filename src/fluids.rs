@@ -0,0 +1,746 @@
+use std::collections::{BTreeMap, HashSet};
+
+use plotsirv::placement::{sane_rotation, use_up_one};
+use valence::client::event::{UseItem, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::sound::{Sound, SoundCategory};
+use valence_protocol::types::Hand;
+
+use crate::block_log::{Actor, BlockChanged};
+use crate::block_sync::resend_block;
+use crate::border::{inside_border, WorldBorder};
+use crate::config::Config;
+use crate::drops::drop_item;
+use crate::inspect::Inspecting;
+use crate::items::insert_stack;
+use crate::sound::{play_sound_at, Feedback, FeedbackSound};
+use crate::UsedClicks;
+
+const HORIZONTAL: [(i32, i32, i32); 4] = [(1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+
+const NEIGHBOURS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// How far a player can reach with a bucket, in blocks.
+const BUCKET_REACH: f64 = 5.0;
+
+/// How far above a player's feet their eyes are, which buckets are aimed
+/// from.
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Water or lava.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FluidKind {
+    Water,
+    Lava,
+}
+
+impl FluidKind {
+    /// How much less fluid there is with each block it flows sideways. A
+    /// source holds 8, so water flows 7 blocks and lava 3.
+    fn drop_off(self) -> u8 {
+        match self {
+            Self::Water => 1,
+            Self::Lava => 2,
+        }
+    }
+
+    /// How long it waits before flowing on, in ticks.
+    fn delay(self) -> u64 {
+        match self {
+            Self::Water => 5,
+            Self::Lava => 30,
+        }
+    }
+
+    /// How far it looks for a way down, to flow towards.
+    fn search_depth(self) -> u32 {
+        match self {
+            Self::Water => 4,
+            Self::Lava => 2,
+        }
+    }
+
+    fn source(self) -> BlockState {
+        match self {
+            Self::Water => BlockState::WATER,
+            Self::Lava => BlockState::LAVA,
+        }
+    }
+
+    fn bucket(self) -> ItemKind {
+        match self {
+            Self::Water => ItemKind::WaterBucket,
+            Self::Lava => ItemKind::LavaBucket,
+        }
+    }
+}
+
+/// The fluid in a block.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Fluid {
+    kind: FluidKind,
+    /// From 1 to 8, which is a full block.
+    amount: u8,
+    source: bool,
+    falling: bool,
+}
+
+impl Fluid {
+    fn source(kind: FluidKind) -> Self {
+        Self {
+            kind,
+            amount: 8,
+            source: true,
+            falling: false,
+        }
+    }
+
+    fn flowing(kind: FluidKind, amount: u8) -> Self {
+        Self {
+            kind,
+            amount,
+            source: false,
+            falling: false,
+        }
+    }
+
+    fn falling(kind: FluidKind) -> Self {
+        Self {
+            kind,
+            amount: 8,
+            source: false,
+            falling: true,
+        }
+    }
+
+    /// The fluid `state` holds. Waterlogged blocks hold a water source.
+    fn of(state: BlockState) -> Option<Self> {
+        let kind = match state.to_kind() {
+            BlockKind::Water | BlockKind::BubbleColumn => FluidKind::Water,
+            BlockKind::Lava => FluidKind::Lava,
+            _ if state.get(PropName::Waterlogged) == Some(PropValue::True) => {
+                return Some(Self::source(FluidKind::Water));
+            }
+            _ => return None,
+        };
+        let level = state
+            .get(PropName::Level)
+            .and_then(PropValue::to_u16)
+            .unwrap_or(0);
+        Some(match level {
+            0 => Self::source(kind),
+            1..=7 => Self::flowing(kind, 8 - level as u8),
+            _ => Self::falling(kind),
+        })
+    }
+
+    fn state(self) -> BlockState {
+        let level = if self.source {
+            0
+        } else if self.falling {
+            8
+        } else {
+            8 - u16::from(self.amount)
+        };
+        let level = PropValue::from_u16(level).unwrap_or(PropValue::_0);
+        self.kind.source().set(PropName::Level, level)
+    }
+}
+
+/// Blocks waiting for their fluid to flow, by the tick they're due. Only so
+/// many are flowed each tick; the rest wait their turn, so letting out a
+/// whole ocean slows the water down rather than the server.
+#[derive(Resource, Default)]
+struct FluidUpdates {
+    due: BTreeMap<u64, Vec<(Entity, BlockPos)>>,
+    waiting: HashSet<(Entity, BlockPos)>,
+}
+
+impl FluidUpdates {
+    fn schedule(&mut self, instance: Entity, pos: BlockPos, at: u64) {
+        if self.waiting.insert((instance, pos)) {
+            self.due.entry(at).or_default().push((instance, pos));
+        }
+    }
+
+    /// Schedules the fluid at `pos` and around it, each after its own delay.
+    fn schedule_around(
+        &mut self,
+        instance_entity: Entity,
+        instance: &Instance,
+        pos: BlockPos,
+        now: u64,
+    ) {
+        let around = NEIGHBOURS
+            .into_iter()
+            .map(|direction| offset(pos, direction));
+        for pos in std::iter::once(pos).chain(around) {
+            if let Some(fluid) = fluid_at(instance, pos) {
+                self.schedule(instance_entity, pos, now + fluid.kind.delay());
+            }
+        }
+    }
+
+    /// Takes up to `budget` of the updates due by `now`, earliest first.
+    fn take_due(&mut self, now: u64, budget: usize) -> Vec<(Entity, BlockPos)> {
+        let mut taken = Vec::new();
+        while taken.len() < budget {
+            let Some(mut entry) = self.due.first_entry() else {
+                break;
+            };
+            if *entry.key() > now {
+                break;
+            }
+            let updates = entry.get_mut();
+            let split = updates.len().saturating_sub(budget - taken.len());
+            taken.extend(updates.drain(split..));
+            if updates.is_empty() {
+                entry.remove();
+            }
+        }
+        for key in &taken {
+            self.waiting.remove(key);
+        }
+        taken
+    }
+}
+
+pub struct FluidsPlugin;
+
+impl Plugin for FluidsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FluidUpdates>()
+            .add_system_to_stage(EventLoop, empty_buckets)
+            .add_system_to_stage(EventLoop, fill_buckets)
+            .add_system(check_changed_blocks)
+            .add_system(flow_fluids.after(check_changed_blocks));
+    }
+}
+
+fn offset(pos: BlockPos, (x, y, z): (i32, i32, i32)) -> BlockPos {
+    BlockPos::new(pos.x + x, pos.y + y, pos.z + z)
+}
+
+fn below(pos: BlockPos) -> BlockPos {
+    offset(pos, (0, -1, 0))
+}
+
+fn fluid_at(instance: &Instance, pos: BlockPos) -> Option<Fluid> {
+    Fluid::of(instance.block(pos)?.state())
+}
+
+/// Whether fluid washes `state` away, dropping it, like torches and
+/// flowers. Blocks that can be waterlogged keep fluid out instead.
+fn washes_away(state: BlockState) -> bool {
+    !state.is_air()
+        && Fluid::of(state).is_none()
+        && state.get(PropName::Waterlogged).is_none()
+        && state.collision_shapes().next().is_none()
+        && !matches!(
+            state.to_kind(),
+            BlockKind::NetherPortal | BlockKind::EndPortal | BlockKind::EndGateway
+        )
+}
+
+/// Whether fluid of `kind` can get into `pos`: it's empty, holds something
+/// fluid washes away, or holds the same fluid short of a source.
+fn open(instance: &Instance, pos: BlockPos, kind: FluidKind) -> bool {
+    let Some(state) = instance.block(pos).map(|block| block.state()) else {
+        return false;
+    };
+    match Fluid::of(state) {
+        Some(fluid) => fluid.kind == kind && !fluid.source,
+        None => state.is_air() || washes_away(state),
+    }
+}
+
+/// Whether fluid of `kind` `amount` deep can flow into `pos`, inside the
+/// border. Lava also flows down into water, which it turns to stone.
+fn can_flow_into(
+    instance: &Instance,
+    inside: &dyn Fn(BlockPos) -> bool,
+    pos: BlockPos,
+    kind: FluidKind,
+    amount: u8,
+    down: bool,
+) -> bool {
+    if !inside(pos) {
+        return false;
+    }
+    let Some(state) = instance.block(pos).map(|block| block.state()) else {
+        return false;
+    };
+    match Fluid::of(state) {
+        Some(fluid) if fluid.kind == kind => {
+            !fluid.source && !fluid.falling && amount > fluid.amount
+        }
+        Some(fluid) => {
+            down && kind == FluidKind::Lava
+                && fluid.kind == FluidKind::Water
+                && state.to_kind() == BlockKind::Water
+        }
+        None => state.is_air() || washes_away(state),
+    }
+}
+
+/// What fluid belongs at `pos`, going by the fluid around it: as much as
+/// the fullest block next to it, less a little; a full falling block under
+/// fluid; or, for water, a new source between two others.
+fn fed_fluid(instance: &Instance, pos: BlockPos, kind: FluidKind) -> Option<Fluid> {
+    let mut fullest = 0;
+    let mut sources = 0;
+    for direction in HORIZONTAL {
+        match fluid_at(instance, offset(pos, direction)) {
+            Some(fluid) if fluid.kind == kind => {
+                sources += u32::from(fluid.source);
+                fullest = fullest.max(fluid.amount);
+            }
+            _ => {}
+        }
+    }
+
+    if kind == FluidKind::Water && sources >= 2 {
+        let under = instance.block(below(pos)).map(|block| block.state());
+        let solid = under.map_or(false, |state| state.collision_shapes().next().is_some());
+        let on_source = under
+            .and_then(Fluid::of)
+            .map_or(false, |fluid| fluid.kind == kind && fluid.source);
+        if solid || on_source {
+            return Some(Fluid::source(kind));
+        }
+    }
+    let above = fluid_at(instance, offset(pos, (0, 1, 0)));
+    if above.map_or(false, |fluid| fluid.kind == kind) {
+        return Some(Fluid::falling(kind));
+    }
+    let amount = fullest.saturating_sub(kind.drop_off());
+    (amount > 0).then(|| Fluid::flowing(kind, amount))
+}
+
+/// How many blocks sideways from `pos` the nearest way down is, looking no
+/// further than fluid of `kind` does, or `None` if there's none that near.
+fn distance_to_hole(
+    instance: &Instance,
+    pos: BlockPos,
+    kind: FluidKind,
+    depth: u32,
+    came_from: (i32, i32, i32),
+) -> Option<u32> {
+    let mut nearest = None;
+    for direction in HORIZONTAL.into_iter().filter(|&d| d != came_from) {
+        let next = offset(pos, direction);
+        if !open(instance, next, kind) {
+            continue;
+        }
+        if open(instance, below(next), kind) {
+            return Some(depth);
+        }
+        if depth < kind.search_depth() {
+            let back = (-direction.0, 0, -direction.2);
+            if let Some(distance) = distance_to_hole(instance, next, kind, depth + 1, back) {
+                nearest = Some(nearest.map_or(distance, |n: u32| n.min(distance)));
+            }
+        }
+    }
+    nearest
+}
+
+/// The sides fluid at `pos` flows to. Like vanilla, it only flows towards
+/// the nearest ways down, if there are any near enough, so it runs downhill
+/// instead of spreading out evenly.
+fn flow_directions(instance: &Instance, pos: BlockPos, kind: FluidKind) -> Vec<(i32, i32, i32)> {
+    let distances: Vec<_> = HORIZONTAL
+        .into_iter()
+        .filter(|&direction| open(instance, offset(pos, direction), kind))
+        .map(|direction| {
+            let next = offset(pos, direction);
+            let distance = if open(instance, below(next), kind) {
+                Some(0)
+            } else {
+                let back = (-direction.0, 0, -direction.2);
+                distance_to_hole(instance, next, kind, 1, back)
+            };
+            (direction, distance.unwrap_or(u32::MAX))
+        })
+        .collect();
+    let nearest = distances.iter().map(|&(_, distance)| distance).min();
+    distances
+        .into_iter()
+        .filter(|&(_, distance)| Some(distance) == nearest)
+        .map(|(direction, _)| direction)
+        .collect()
+}
+
+/// Puts `fluid` in `pos`, dropping anything it washes away. Lava flowing
+/// into water turns it to stone.
+fn flow_into(
+    commands: &mut Commands,
+    instance_entity: Entity,
+    instance: &mut Instance,
+    pos: BlockPos,
+    fluid: Fluid,
+) {
+    let Some(old) = instance.block(pos).map(|block| block.state()) else {
+        return;
+    };
+    if fluid.kind == FluidKind::Lava && Fluid::of(old).map_or(false, |f| f.kind == FluidKind::Water)
+    {
+        instance.set_block(pos, BlockState::STONE);
+        return;
+    }
+    if washes_away(old) {
+        if let Some(item) = ItemKind::from_str(old.to_kind().to_str()) {
+            let at = DVec3::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5);
+            drop_item(commands, instance_entity, at, ItemStack::new(item, 1, None));
+        }
+    }
+    instance.set_block(pos, fluid.state());
+}
+
+/// What lava at `pos` hardens into, if water touches it anywhere but from
+/// below: obsidian for a source, cobblestone otherwise.
+fn hardened(instance: &Instance, pos: BlockPos, fluid: Fluid) -> Option<BlockState> {
+    if fluid.kind != FluidKind::Lava {
+        return None;
+    }
+    let wet = NEIGHBOURS
+        .into_iter()
+        .filter(|&direction| direction != (0, -1, 0))
+        .any(|direction| {
+            fluid_at(instance, offset(pos, direction)).map_or(false, |f| f.kind == FluidKind::Water)
+        });
+    wet.then_some(if fluid.source {
+        BlockState::OBSIDIAN
+    } else {
+        BlockState::COBBLESTONE
+    })
+}
+
+/// The block whose fluid a player is aiming a bucket at: the first source
+/// along their line of sight, or `None` if something solid is in the way.
+fn aimed_source(instance: &Instance, client: &Client) -> Option<(BlockPos, BlockState, Fluid)> {
+    let (yaw, pitch) = sane_rotation(client.yaw(), client.pitch());
+    let (yaw, pitch) = (f64::from(yaw).to_radians(), f64::from(pitch).to_radians());
+    let look = DVec3::new(
+        -yaw.sin() * pitch.cos(),
+        -pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    );
+    let eyes = client.position() + DVec3::new(0.0, EYE_HEIGHT, 0.0);
+    let mut last = None;
+    for step in 0..(BUCKET_REACH * 10.0) as i32 {
+        let at = eyes + look * (f64::from(step) / 10.0);
+        let pos = BlockPos::new(
+            at.x.floor() as i32,
+            at.y.floor() as i32,
+            at.z.floor() as i32,
+        );
+        if last == Some(pos) {
+            continue;
+        }
+        last = Some(pos);
+        let state = instance.block(pos)?.state();
+        match Fluid::of(state) {
+            Some(fluid) if fluid.source => return Some((pos, state, fluid)),
+            Some(_) => continue,
+            None if state.collision_shapes().next().is_some() => return None,
+            None => continue,
+        }
+    }
+    None
+}
+
+/// Swaps one of the bucket a survival player used for `to`, like vanilla
+/// does: in place if it was the last, and otherwise into their inventory,
+/// or dropped if that's full.
+fn swap_bucket(
+    commands: &mut Commands,
+    client: &Client,
+    inventory: &mut Inventory,
+    slot: u16,
+    to: ItemKind,
+) {
+    if client.game_mode() != GameMode::Survival {
+        return;
+    }
+    let filled = ItemStack::new(to, 1, None);
+    if inventory.slot(slot).map_or(0, |stack| stack.count()) <= 1 {
+        inventory.replace_slot(slot, Some(filled));
+        return;
+    }
+    use_up_one(inventory, slot);
+    if insert_stack(inventory, &filled) == 0 {
+        drop_item(commands, client.instance(), client.position(), filled);
+    }
+}
+
+/// Empties a water or lava bucket a player uses on a block into the space
+/// next to it, or into the block itself if fluid can go there. Water fills
+/// blocks that can be waterlogged.
+fn empty_buckets(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &mut Inventory), Without<Inspecting>>,
+    mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let slot = client.held_item_slot();
+        let kind = match inventory.slot(slot).map(|stack| stack.item) {
+            Some(ItemKind::WaterBucket) => FluidKind::Water,
+            Some(ItemKind::LavaBucket) => FluidKind::Lava,
+            _ => continue,
+        };
+        used.mark(event.client);
+        let Ok(mut instance) = instances.get_mut(client.instance()) else {
+            continue;
+        };
+        let Some(clicked) = instance.block(event.position).map(|block| block.state()) else {
+            continue;
+        };
+
+        let waterlogs = kind == FluidKind::Water
+            && clicked.get(PropName::Waterlogged) == Some(PropValue::False);
+        let fills_clicked = waterlogs || clicked.is_replaceable() || washes_away(clicked);
+        let pos = if fills_clicked {
+            event.position
+        } else {
+            event.position.get_in_direction(event.face)
+        };
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            resend_block(&mut client, &instance, pos);
+            continue;
+        }
+        let Some(old) = instance.block(pos).map(|block| block.state()) else {
+            continue;
+        };
+        let new = if waterlogs {
+            clicked.set(PropName::Waterlogged, PropValue::True)
+        } else if old.get(PropName::Waterlogged) == Some(PropValue::False)
+            && kind == FluidKind::Water
+        {
+            old.set(PropName::Waterlogged, PropValue::True)
+        } else if old.is_air() || old.is_replaceable() || washes_away(old) {
+            kind.source()
+        } else {
+            resend_block(&mut client, &instance, pos);
+            continue;
+        };
+
+        changes.send(BlockChanged {
+            actor: Actor::of(&client),
+            instance: client.instance(),
+            position: pos,
+            old,
+            new,
+        });
+        instance.set_block(pos, new);
+        swap_bucket(
+            &mut commands,
+            &client,
+            &mut inventory,
+            slot,
+            ItemKind::Bucket,
+        );
+
+        let sound = match kind {
+            FluidKind::Water => Sound::ItemBucketEmpty,
+            FluidKind::Lava => Sound::ItemBucketEmptyLava,
+        };
+        let center = DVec3::new(pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5);
+        play_sound_at(&mut client, sound, SoundCategory::Block, center, 1.0, 1.0);
+    }
+}
+
+/// Scoops up the water or lava source a player aims an empty bucket at,
+/// draining waterlogged blocks.
+fn fill_buckets(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &mut Inventory), Without<Inspecting>>,
+    mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItem>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let slot = client.held_item_slot();
+        if inventory.slot(slot).map(|stack| stack.item) != Some(ItemKind::Bucket) {
+            continue;
+        }
+        used.mark(event.client);
+        let Ok(mut instance) = instances.get_mut(client.instance()) else {
+            continue;
+        };
+        let Some((pos, old, fluid)) = aimed_source(&instance, &client) else {
+            continue;
+        };
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            resend_block(&mut client, &instance, pos);
+            continue;
+        }
+
+        let new = if old.get(PropName::Waterlogged) == Some(PropValue::True) {
+            old.set(PropName::Waterlogged, PropValue::False)
+        } else {
+            BlockState::AIR
+        };
+        changes.send(BlockChanged {
+            actor: Actor::of(&client),
+            instance: client.instance(),
+            position: pos,
+            old,
+            new,
+        });
+        instance.set_block(pos, new);
+        swap_bucket(
+            &mut commands,
+            &client,
+            &mut inventory,
+            slot,
+            fluid.kind.bucket(),
+        );
+
+        let sound = match fluid.kind {
+            FluidKind::Water => Sound::ItemBucketFill,
+            FluidKind::Lava => Sound::ItemBucketFillLava,
+        };
+        let center = DVec3::new(pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5);
+        play_sound_at(&mut client, sound, SoundCategory::Block, center, 1.0, 1.0);
+    }
+}
+
+/// Fluid next to a changed block may have somewhere new to flow, or have
+/// lost what fed it. Breaking a waterlogged block leaves its water behind.
+fn check_changed_blocks(
+    mut instances: Query<&mut Instance>,
+    mut updates: ResMut<FluidUpdates>,
+    server: Res<Server>,
+    mut events: EventReader<BlockChanged>,
+) {
+    let now = server.current_tick();
+    for event in events.iter() {
+        let Ok(mut instance) = instances.get_mut(event.instance) else {
+            continue;
+        };
+        let pos = event.position;
+        let waterlogged = event.old.get(PropName::Waterlogged) == Some(PropValue::True);
+        let current = instance.block(pos).map(|block| block.state());
+        if waterlogged && event.new.is_air() && current == Some(BlockState::AIR) {
+            instance.set_block(pos, BlockState::WATER);
+        }
+        updates.schedule_around(event.instance, &instance, pos, now);
+    }
+}
+
+/// Flows the fluid that's due to, up to the configured number of blocks a
+/// tick. Each block first settles to what the fluid around it feeds it,
+/// then flows down if it can, and otherwise out to the sides, never across
+/// the world border. Lava touched by water hardens.
+fn flow_fluids(
+    mut commands: Commands,
+    mut instances: Query<&mut Instance>,
+    mut updates: ResMut<FluidUpdates>,
+    borders: Query<&WorldBorder>,
+    config: Res<Config>,
+    server: Res<Server>,
+) {
+    let now = server.current_tick();
+    for (instance_entity, pos) in updates.take_due(now, config.fluids.updates_per_tick) {
+        let Ok(mut instance) = instances.get_mut(instance_entity) else {
+            continue;
+        };
+        let Some(mut fluid) = fluid_at(&instance, pos) else {
+            continue;
+        };
+        let inside = |pos| inside_border(&borders, instance_entity, pos);
+
+        if let Some(hard) = hardened(&instance, pos, fluid) {
+            instance.set_block(pos, hard);
+            continue;
+        }
+        if !fluid.source {
+            let fed = fed_fluid(&instance, pos, fluid.kind);
+            if fed != Some(fluid) {
+                instance.set_block(pos, fed.map_or(BlockState::AIR, Fluid::state));
+                updates.schedule_around(instance_entity, &instance, pos, now);
+                let Some(fed) = fed else {
+                    continue;
+                };
+                fluid = fed;
+            }
+        }
+
+        let down = below(pos);
+        let flows_down = can_flow_into(&instance, &inside, down, fluid.kind, 8, true);
+        if flows_down {
+            flow_into(
+                &mut commands,
+                instance_entity,
+                &mut instance,
+                down,
+                Fluid::falling(fluid.kind),
+            );
+            updates.schedule_around(instance_entity, &instance, down, now);
+        }
+        // Fluid spreads out over what's under it, but only pours down a
+        // hole, unless it's a pool of sources emptying.
+        let on_itself = fluid_at(&instance, down).map_or(false, |f| f.kind == fluid.kind);
+        let pool = HORIZONTAL
+            .into_iter()
+            .filter(|&direction| {
+                fluid_at(&instance, offset(pos, direction))
+                    .map_or(false, |f| f.kind == fluid.kind && f.source)
+            })
+            .count()
+            >= 3;
+        let spreads = if flows_down {
+            fluid.source && pool
+        } else {
+            fluid.source || !on_itself
+        };
+        let amount = fluid.amount.saturating_sub(fluid.kind.drop_off());
+        if !spreads || amount == 0 {
+            continue;
+        }
+
+        for direction in flow_directions(&instance, pos, fluid.kind) {
+            let next = offset(pos, direction);
+            if can_flow_into(&instance, &inside, next, fluid.kind, amount, false) {
+                flow_into(
+                    &mut commands,
+                    instance_entity,
+                    &mut instance,
+                    next,
+                    Fluid::flowing(fluid.kind, amount),
+                );
+                updates.schedule_around(instance_entity, &instance, next, now);
+            }
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use valence::client::event::{StartFlying, StopFlying};
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::PlayerAbilitiesS2c;
+use valence_protocol::types::PlayerAbilitiesFlags;
+
+use crate::command::{find_client, usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::format::fill_placeholders;
+use crate::lang::Lang;
+use crate::permissions::{Permissions, PermissionsChanged};
+use crate::player_data::PlayerDataStore;
+
+/// Needed to fly outside of creative mode, whether through `/fly` or by
+/// double-tapping jump.
+pub const FLY_PERMISSION: &str = "plots.fly";
+
+const FLY: CommandInfo = CommandInfo {
+    name: "fly",
+    aliases: &[],
+    usage: "/fly [player]",
+    description: "Toggle flight outside of creative mode.",
+    permission: Some(FLY_PERMISSION),
+    console: false,
+};
+
+const SPEED: CommandInfo = CommandInfo {
+    name: "speed",
+    aliases: &[],
+    usage: "/speed <1-10|reset> [fly|walk] [player]",
+    description: "Change how fast you fly or walk.",
+    permission: Some("plots.command.speed"),
+    console: false,
+};
+
+/// Vanilla defaults for the ability packet, which `/speed` multiplies.
+const FLYING_SPEED: f32 = 0.05;
+const FOV_MODIFIER: f32 = 0.1;
+
+pub const DEFAULT_SPEED: u8 = 1;
+const MAX_SPEED: u8 = 10;
+
+/// The minimum Y of the default dimension.
+const WORLD_BOTTOM: i32 = -64;
+/// The highest Y a block can be at in the default dimension.
+const WORLD_TOP: i32 = 319;
+
+/// Per-client flight state, kept in sync with the client's abilities.
+#[derive(Component, Debug)]
+pub struct Flight {
+    /// Whether `/fly` is toggled on for this player.
+    pub allowed: bool,
+    /// Whether the client last reported that it is flying.
+    flying: bool,
+    /// The game mode the abilities were last computed for.
+    game_mode: Option<GameMode>,
+    /// Multipliers set with `/speed`, from 1 to 10.
+    fly_speed: u8,
+    walk_speed: u8,
+    dirty: bool,
+}
+
+impl Flight {
+    pub fn new(allowed: bool) -> Self {
+        Self {
+            allowed,
+            flying: false,
+            game_mode: None,
+            fly_speed: DEFAULT_SPEED,
+            walk_speed: DEFAULT_SPEED,
+            dirty: true,
+        }
+    }
+
+    pub fn with_speeds(mut self, fly_speed: u8, walk_speed: u8) -> Self {
+        self.fly_speed = fly_speed.clamp(1, MAX_SPEED);
+        self.walk_speed = walk_speed.clamp(1, MAX_SPEED);
+        self
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.flying
+    }
+
+    pub fn set_allowed(&mut self, allowed: bool) {
+        self.allowed = allowed;
+        self.dirty = true;
+    }
+}
+
+/// Movement seen by the double-jump detector.
+#[derive(Component, Default, Debug)]
+struct JumpTracker {
+    on_ground: bool,
+    y: f64,
+    /// The tick the client last left the ground going up.
+    last_jump: Option<u64>,
+}
+
+pub struct FlyPlugin;
+
+impl Plugin for FlyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(FLY)
+            .add_command(SPEED)
+            .add_system_to_stage(EventLoop, track_flying)
+            .add_system_to_stage(EventLoop, fly_command)
+            .add_system_to_stage(EventLoop, speed_command)
+            .add_system(init_jump_trackers)
+            .add_system(detect_double_jumps.before(sync_abilities))
+            .add_system(apply_permission_changes.before(sync_abilities))
+            .add_system(sync_abilities);
+    }
+}
+
+fn track_flying(
+    mut clients: Query<&mut Flight>,
+    mut start: EventReader<StartFlying>,
+    mut stop: EventReader<StopFlying>,
+) {
+    for event in start.iter() {
+        if let Ok(mut flight) = clients.get_mut(event.client) {
+            flight.bypass_change_detection().flying = true;
+        }
+    }
+
+    for event in stop.iter() {
+        if let Ok(mut flight) = clients.get_mut(event.client) {
+            flight.bypass_change_detection().flying = false;
+        }
+    }
+}
+
+fn fly_command(
+    mut clients: Query<(Entity, &mut Client, &mut Flight)>,
+    instances: Query<&Instance>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut commands: EventReader<CommandExecution>,
+) {
+    for command in commands.iter().filter(|c| c.is(FLY.name)) {
+        let target = match command.args.as_slice() {
+            [] => command.sender,
+            [name] => {
+                let Some(target) = find_client(clients.iter().map(|(e, c, _)| (e, c)), name) else {
+                    if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                        sender.send_message(lang.tr(
+                            command.sender,
+                            "command.not_online",
+                            &[("name", name)],
+                        ));
+                    }
+                    continue;
+                };
+                target
+            }
+            _ => {
+                if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                    sender.send_message(usage(&lang, command.sender, &FLY));
+                }
+                continue;
+            }
+        };
+
+        if target != command.sender {
+            let allowed = clients.get(command.sender).map_or(false, |(_, sender, _)| {
+                permissions.has_permission(sender.uuid(), "plots.command.fly.others")
+            });
+            if !allowed {
+                if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                    sender.send_message(lang.tr(
+                        command.sender,
+                        "command.no_permission_others",
+                        &[],
+                    ));
+                }
+                continue;
+            }
+        }
+
+        let Ok((_, mut client, mut flight)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        let allowed = !flight.allowed;
+        flight.set_allowed(allowed);
+        store.get(client.uuid()).fly = allowed;
+        store.save(client.uuid());
+
+        if !allowed {
+            land(&mut client, &mut flight, &instances);
+        }
+
+        let state = if allowed { "enabled" } else { "disabled" };
+        client.send_message(lang.tr(target, &format!("fly.{state}"), &[]));
+
+        if target != command.sender {
+            let username = client.username().to_string();
+            if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                sender.send_message(lang.tr(
+                    command.sender,
+                    &format!("fly.{state}_for"),
+                    &[("name", &username)],
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SpeedKind {
+    Fly,
+    Walk,
+}
+
+fn speed_command(
+    mut clients: Query<(Entity, &mut Client, &mut Flight)>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut commands: EventReader<CommandExecution>,
+) {
+    for command in commands.iter().filter(|c| c.is(SPEED.name)) {
+        let (speed, rest) = match command.args.as_slice() {
+            [speed, rest @ ..] if rest.len() <= 2 => (speed, rest),
+            _ => {
+                if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                    sender.send_message(usage(&lang, command.sender, &SPEED));
+                }
+                continue;
+            }
+        };
+
+        let speed = if speed.eq_ignore_ascii_case("reset") {
+            None
+        } else {
+            match speed.parse::<u8>() {
+                Ok(speed) if (1..=MAX_SPEED).contains(&speed) => Some(speed),
+                _ => {
+                    if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                        sender.send_message(usage(&lang, command.sender, &SPEED));
+                    }
+                    continue;
+                }
+            }
+        };
+
+        let (kind, name) = match rest {
+            [] => (None, None),
+            [kind] if kind == "fly" => (Some(SpeedKind::Fly), None),
+            [kind] if kind == "walk" => (Some(SpeedKind::Walk), None),
+            [name] => (None, Some(name)),
+            [kind, name] if kind == "fly" => (Some(SpeedKind::Fly), Some(name)),
+            [kind, name] if kind == "walk" => (Some(SpeedKind::Walk), Some(name)),
+            _ => {
+                if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                    sender.send_message(usage(&lang, command.sender, &SPEED));
+                }
+                continue;
+            }
+        };
+
+        let target = match name {
+            None => command.sender,
+            Some(name) => {
+                let Some(target) = find_client(clients.iter().map(|(e, c, _)| (e, c)), name) else {
+                    if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                        sender.send_message(lang.tr(
+                            command.sender,
+                            "command.not_online",
+                            &[("name", name)],
+                        ));
+                    }
+                    continue;
+                };
+                target
+            }
+        };
+
+        if target != command.sender {
+            let allowed = clients.get(command.sender).map_or(false, |(_, sender, _)| {
+                permissions.has_permission(sender.uuid(), "plots.command.speed.others")
+            });
+            if !allowed {
+                if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                    sender.send_message(lang.tr(
+                        command.sender,
+                        "command.no_permission_others",
+                        &[],
+                    ));
+                }
+                continue;
+            }
+        }
+
+        let Ok((_, mut client, mut flight)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        // Asking for more than the group allows gets the most it allows.
+        let speed = speed.map(|speed| speed.min(max_speed(&permissions, client.uuid())));
+        let data = store.get(client.uuid());
+        let (key, speed) = match (speed, kind) {
+            (None, _) => {
+                flight.fly_speed = DEFAULT_SPEED;
+                flight.walk_speed = DEFAULT_SPEED;
+                ("speed.reset", DEFAULT_SPEED)
+            }
+            // Like Essentials, without a kind the speed applies to whatever
+            // the player is doing right now.
+            (Some(speed), kind) => {
+                let kind = kind.unwrap_or(if flight.flying {
+                    SpeedKind::Fly
+                } else {
+                    SpeedKind::Walk
+                });
+                match kind {
+                    SpeedKind::Fly => flight.fly_speed = speed,
+                    SpeedKind::Walk => flight.walk_speed = speed,
+                }
+                let key = if kind == SpeedKind::Fly {
+                    "speed.fly"
+                } else {
+                    "speed.walk"
+                };
+                (key, speed)
+            }
+        };
+
+        data.fly_speed = flight.fly_speed;
+        data.walk_speed = flight.walk_speed;
+        store.save(client.uuid());
+        flight.dirty = true;
+
+        client.send_message(lang.tr(target, key, &[("speed", &speed)]));
+
+        if target != command.sender {
+            let username = client.username().to_string();
+            if let Ok((_, mut sender, _)) = clients.get_mut(command.sender) {
+                let message =
+                    fill_placeholders(lang.plain(command.sender, key), &[("speed", &speed)]);
+                sender.send_message(lang.tr(
+                    command.sender,
+                    "speed.for",
+                    &[("message", &message), ("name", &username)],
+                ));
+            }
+        }
+    }
+}
+
+fn init_jump_trackers(mut commands: Commands, clients: Query<Entity, Added<Flight>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(JumpTracker::default());
+    }
+}
+
+/// Starts flight when a survival player with [`FLY_PERMISSION`] jumps twice
+/// in quick succession. Clients only report double-tapping jump while
+/// they're allowed to fly, so until then it's recognised from movement
+/// instead: two jumps off the ground within the tap window. Once flight is
+/// allowed the client handles double-tapping itself, including to stop
+/// flying.
+fn detect_double_jumps(
+    mut clients: Query<(&Client, &mut Flight, &mut JumpTracker)>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    config: Res<Config>,
+    server: Res<Server>,
+) {
+    if !config.fly.double_tap {
+        return;
+    }
+
+    let tick = server.current_tick();
+
+    for (client, mut flight, mut tracker) in &mut clients {
+        let y = client.position().y;
+        let jumped = tracker.on_ground && !client.on_ground() && y > tracker.y;
+        tracker.on_ground = client.on_ground();
+        tracker.y = y;
+
+        if !jumped || flight.allowed || flies_anyway(client.game_mode()) {
+            continue;
+        }
+        if !permissions.has_permission(client.uuid(), FLY_PERMISSION) {
+            continue;
+        }
+
+        match tracker.last_jump {
+            Some(last) if tick - last <= config.fly.tap_window_ticks => {
+                tracker.last_jump = None;
+                flight.set_allowed(true);
+                flight.flying = true;
+                store.get(client.uuid()).fly = true;
+                store.save(client.uuid());
+            }
+            _ => tracker.last_jump = Some(tick),
+        }
+    }
+}
+
+/// Essentials-style graceful disable: rather than dropping a survival player
+/// who can no longer fly out of the sky, put them down on the ground beneath
+/// them.
+fn land(client: &mut Client, flight: &mut Flight, instances: &Query<&Instance>) {
+    if !flight.flying || flies_anyway(client.game_mode()) {
+        return;
+    }
+
+    if let Ok(instance) = instances.get(client.instance()) {
+        if let Some(y) = ground_below(instance, client.position()) {
+            let pos = client.position();
+            client.set_position([pos.x, y, pos.z]);
+        }
+    }
+    flight.flying = false;
+}
+
+/// Takes flight and speeds away from players whose permissions no longer
+/// allow them, and gives back flight to those allowed it again. What they
+/// chose is kept in their player data either way.
+fn apply_permission_changes(
+    mut clients: Query<(&mut Client, &mut Flight)>,
+    instances: Query<&Instance>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    mut changed: EventReader<PermissionsChanged>,
+) {
+    if changed.iter().count() == 0 {
+        return;
+    }
+
+    for (mut client, mut flight) in &mut clients {
+        let uuid = client.uuid();
+        let data = store.get(uuid);
+
+        let allowed = data.fly && permissions.has_permission(uuid, FLY_PERMISSION);
+        if allowed != flight.allowed {
+            flight.set_allowed(allowed);
+            if !allowed {
+                land(&mut client, &mut flight, &instances);
+            }
+        }
+
+        let max = max_speed(&permissions, uuid);
+        let (fly_speed, walk_speed) = (data.fly_speed.clamp(1, max), data.walk_speed.clamp(1, max));
+        if (flight.fly_speed, flight.walk_speed) != (fly_speed, walk_speed) {
+            flight.fly_speed = fly_speed;
+            flight.walk_speed = walk_speed;
+            flight.dirty = true;
+        }
+    }
+}
+
+/// The fastest a player may set `/speed`, given their group.
+pub fn max_speed(permissions: &Permissions, uuid: Uuid) -> u8 {
+    permissions
+        .max_speed(uuid)
+        .map_or(MAX_SPEED, |max| max.clamp(1, MAX_SPEED))
+}
+
+/// Game modes in which the client can always fly, regardless of `/fly`.
+fn flies_anyway(game_mode: GameMode) -> bool {
+    matches!(game_mode, GameMode::Creative | GameMode::Spectator)
+}
+
+/// Finds the height of the first solid surface at or below `pos`.
+fn ground_below(instance: &Instance, pos: DVec3) -> Option<f64> {
+    let (x, z) = (pos.x.floor() as i32, pos.z.floor() as i32);
+    // The position is the client's, so a player far above the world would
+    // otherwise have every empty block down to it checked.
+    let top = (pos.y.floor() as i32).min(WORLD_TOP);
+
+    (WORLD_BOTTOM..=top)
+        .rev()
+        .find(|&y| {
+            instance
+                .block([x, y, z])
+                .map_or(false, |block| !block.state().is_air())
+        })
+        .map(|y| y as f64 + 1.0)
+}
+
+/// Resends the ability packet whenever `/fly` changes or the game mode
+/// changes, since the client resets its abilities on every game mode switch.
+fn sync_abilities(mut clients: Query<(&mut Client, &mut Flight)>) {
+    for (mut client, mut flight) in &mut clients {
+        let game_mode = client.game_mode();
+
+        if flight.game_mode != Some(game_mode) {
+            // Valence sends the game mode change after this system runs, so
+            // wait for the next tick to send our abilities on top of it.
+            flight.game_mode = Some(game_mode);
+            flight.dirty = true;
+            continue;
+        }
+
+        if !flight.dirty {
+            continue;
+        }
+
+        let allow_flying = flight.allowed || flies_anyway(game_mode);
+        if !allow_flying {
+            flight.flying = false;
+        }
+
+        client.write_packet(&PlayerAbilitiesS2c {
+            flags: PlayerAbilitiesFlags::new()
+                .with_invulnerable(flies_anyway(game_mode))
+                .with_flying(flight.flying && allow_flying)
+                .with_allow_flying(allow_flying)
+                .with_instant_break(game_mode == GameMode::Creative),
+            flying_speed: FLYING_SPEED * flight.fly_speed as f32,
+            fov_modifier: FOV_MODIFIER * flight.walk_speed as f32,
+        });
+
+        flight.dirty = false;
+    }
+}
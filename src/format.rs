@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use valence::prelude::*;
+
+/// Converts a string with `&`-prefixed legacy formatting codes (`&e`, `&l`,
+/// `&r`, ...) into styled text. Unknown codes are kept as-is.
+pub fn legacy_text(input: &str) -> Text {
+    let mut out = Text::default();
+    let mut style = Style::default();
+    let mut segment = String::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '&' {
+            if let Some(next) = chars.peek().and_then(|&code| style.apply(code)) {
+                chars.next();
+                if !segment.is_empty() {
+                    out = out + style.render(std::mem::take(&mut segment));
+                }
+                style = next;
+                continue;
+            }
+        }
+        segment.push(c);
+    }
+
+    if !segment.is_empty() {
+        out = out + style.render(segment);
+    }
+
+    out
+}
+
+/// The visible characters of styled text, for places without formatting like
+/// the console.
+pub fn plain_text(text: &Text) -> String {
+    fn collect(value: &serde_json::Value, out: &mut String) {
+        match value {
+            serde_json::Value::String(s) => out.push_str(s),
+            serde_json::Value::Array(parts) => parts.iter().for_each(|part| collect(part, out)),
+            serde_json::Value::Object(fields) => {
+                if let Some(serde_json::Value::String(s)) = fields.get("text") {
+                    out.push_str(s);
+                }
+                if let Some(extra) = fields.get("extra") {
+                    collect(extra, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = String::new();
+    if let Ok(value) = serde_json::to_value(text) {
+        collect(&value, &mut out);
+    }
+    out
+}
+
+/// Replaces every `{key}` in `template` with its value.
+pub fn fill_placeholders(template: &str, values: &[(&str, &dyn std::fmt::Display)]) -> String {
+    let mut out = template.to_owned();
+    for (key, value) in values {
+        out = out.replace(&format!("{{{key}}}"), &value.to_string());
+    }
+    out
+}
+
+#[derive(Clone, Copy, Default)]
+struct Style {
+    color: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool,
+}
+
+impl Style {
+    /// Returns the style after applying a formatting code, or `None` if the
+    /// code isn't recognised.
+    fn apply(self, code: char) -> Option<Self> {
+        let color = match code.to_ascii_lowercase() {
+            '0' => Color::BLACK,
+            '1' => Color::DARK_BLUE,
+            '2' => Color::DARK_GREEN,
+            '3' => Color::DARK_AQUA,
+            '4' => Color::DARK_RED,
+            '5' => Color::DARK_PURPLE,
+            '6' => Color::GOLD,
+            '7' => Color::GRAY,
+            '8' => Color::DARK_GRAY,
+            '9' => Color::BLUE,
+            'a' => Color::GREEN,
+            'b' => Color::AQUA,
+            'c' => Color::RED,
+            'd' => Color::LIGHT_PURPLE,
+            'e' => Color::YELLOW,
+            'f' => Color::WHITE,
+            'k' => return Some(Self { obfuscated: true, ..self }),
+            'l' => return Some(Self { bold: true, ..self }),
+            'm' => return Some(Self { strikethrough: true, ..self }),
+            'n' => return Some(Self { underlined: true, ..self }),
+            'o' => return Some(Self { italic: true, ..self }),
+            'r' => return Some(Self::default()),
+            _ => return None,
+        };
+
+        // Like vanilla, a color code resets any formatting before it.
+        Some(Self {
+            color: Some(color),
+            ..Self::default()
+        })
+    }
+
+    fn render(self, segment: String) -> Text {
+        let mut text = segment.into_text();
+        if let Some(color) = self.color {
+            text = text.color(color);
+        }
+        if self.bold {
+            text = text.bold();
+        }
+        if self.italic {
+            text = text.italic();
+        }
+        if self.underlined {
+            text = text.underlined();
+        }
+        if self.strikethrough {
+            text = text.strikethrough();
+        }
+        if self.obfuscated {
+            text = text.obfuscated();
+        }
+        text
+    }
+}
+
+/// Truncates a string with legacy formatting codes to at most `max` visible
+/// characters, never splitting a code from its `&`.
+pub fn truncate_legacy(input: &str, max: usize) -> &str {
+    let mut visible = 0;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        if c == '&' {
+            if let Some(&(_, code)) = chars.peek() {
+                if Style::default().apply(code).is_some() {
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+
+        if visible == max {
+            return &input[..idx];
+        }
+        visible += 1;
+    }
+
+    input
+}
+
+/// Removes legacy formatting codes, leaving only the visible text.
+pub fn strip_legacy(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '&' {
+            if let Some(&code) = chars.peek() {
+                if Style::default().apply(code).is_some() {
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// The last color code in a string with legacy formatting codes, like `c`
+/// for `&c`, or `r` if it ends with a reset.
+pub fn last_color_code(input: &str) -> Option<char> {
+    let mut last = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '&' {
+            if let Some(&code) = chars.peek() {
+                let code = code.to_ascii_lowercase();
+                if code.is_ascii_hexdigit() || code == 'r' {
+                    last = Some(code);
+                    chars.next();
+                }
+            }
+        }
+    }
+
+    last
+}
+
+/// Parses a duration like `30m`, `7d` or `1w2d12h`. Units are `s`, `m`, `h`,
+/// `d` and `w`, and every number needs one.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total: u64 = 0;
+    let mut number = String::new();
+
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let value: u64 = std::mem::take(&mut number).parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+    }
+
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+/// Formats a duration in the same units [`parse_duration`] accepts, like
+/// `7d12h`, leaving out anything smaller than the two largest units.
+pub fn format_duration(duration: Duration) -> String {
+    const UNITS: [(u64, char); 5] = [
+        (7 * 24 * 60 * 60, 'w'),
+        (24 * 60 * 60, 'd'),
+        (60 * 60, 'h'),
+        (60, 'm'),
+        (1, 's'),
+    ];
+
+    let mut secs = duration.as_secs();
+    let mut out = String::new();
+    let mut shown = 0;
+
+    for (size, unit) in UNITS {
+        if secs >= size && shown < 2 {
+            out.push_str(&format!("{}{unit}", secs / size));
+            secs %= size;
+            shown += 1;
+        }
+    }
+
+    if out.is_empty() {
+        out.push_str("0s");
+    }
+    out
+}
+
+/// Formats a Unix timestamp as a UTC date, like `2023-02-18`.
+pub fn format_date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm, with eras starting on
+    // 0000-03-01 so leap days fall at the end of each year.
+    let days = (secs / (24 * 60 * 60)) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
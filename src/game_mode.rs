@@ -0,0 +1,284 @@
+use std::time::{Duration, Instant};
+
+use valence::client::event::StartSneaking;
+use valence::prelude::*;
+
+use crate::command::{find_client, usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::{Config, ConfigGameMode};
+use crate::format::fill_placeholders;
+use crate::lang::Lang;
+use crate::permissions::{Permissions, PermissionsChanged};
+use crate::player_data::PlayerDataStore;
+use crate::WorldName;
+
+const GAMEMODE: CommandInfo = CommandInfo {
+    name: "gamemode",
+    aliases: &["gm"],
+    usage: "/gamemode <survival|creative|adventure|spectator|reset> [player]",
+    description: "Change your game mode, or go back to the world's default.",
+    permission: Some("plots.command.gamemode"),
+    console: false,
+};
+
+/// How soon after one sneak a second has to come to toggle the game mode.
+const DOUBLE_SNEAK: Duration = Duration::from_millis(500);
+
+/// How long after a toggle sneaking does nothing, so crouching repeatedly
+/// along an edge doesn't flip back and forth.
+const TOGGLE_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// When a client last sneaked and last toggled its game mode by sneaking.
+#[derive(Component, Default, Debug)]
+struct SneakToggle {
+    last_sneak: Option<Instant>,
+    last_toggle: Option<Instant>,
+}
+
+/// The instance a client's game mode was last chosen for, so arriving in a
+/// world applies its default.
+#[derive(Component, Default, Debug)]
+struct GameModeWorld(Option<Entity>);
+
+pub struct GameModePlugin {
+    /// Whether sneaking toggles between creative and survival.
+    pub sneak_toggle: bool,
+}
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(GAMEMODE)
+            .add_system(init_game_mode_worlds)
+            .add_system(apply_world_game_modes.after(init_game_mode_worlds))
+            .add_system_to_stage(EventLoop, gamemode_command);
+
+        if self.sneak_toggle {
+            app.add_system(init_sneak_toggles)
+                .add_system_to_stage(EventLoop, toggle_game_mode_on_sneak);
+        }
+    }
+}
+
+/// The game mode a player gets in a world without choosing one: their
+/// group's for that world, or else the world's.
+fn default_game_mode(
+    permissions: &Permissions,
+    config: &Config,
+    uuid: Uuid,
+    world: &str,
+) -> ConfigGameMode {
+    permissions
+        .game_mode(uuid, world)
+        .unwrap_or(config.world(world).game_mode)
+}
+
+/// The game mode a player should have in a world. Their own `/gamemode`
+/// choice only counts while they're still allowed to make it.
+fn game_mode_for(
+    store: &mut PlayerDataStore,
+    permissions: &Permissions,
+    config: &Config,
+    uuid: Uuid,
+    world: &str,
+) -> GameMode {
+    let choice = store
+        .get(uuid)
+        .game_mode
+        .filter(|_| permissions.may_run(uuid, &GAMEMODE));
+    choice
+        .unwrap_or_else(|| default_game_mode(permissions, config, uuid, world))
+        .into()
+}
+
+fn init_game_mode_worlds(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(GameModeWorld::default());
+    }
+}
+
+/// Puts clients in the right game mode when they arrive in a world, and
+/// everyone again when the permissions change.
+fn apply_world_game_modes(
+    mut clients: Query<(&mut Client, &mut GameModeWorld)>,
+    worlds: Query<&WorldName>,
+    config: Res<Config>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    mut changed: EventReader<PermissionsChanged>,
+) {
+    let changed = changed.iter().count() > 0;
+
+    for (mut client, mut applied) in &mut clients {
+        if applied.0 == Some(client.instance()) && !changed {
+            continue;
+        }
+
+        let Ok(world) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        applied.0 = Some(client.instance());
+        let mode = game_mode_for(&mut store, &permissions, &config, client.uuid(), &world.0);
+        if client.game_mode() != mode {
+            client.set_game_mode(mode);
+        }
+    }
+}
+
+fn gamemode_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    worlds: Query<&WorldName>,
+    config: Res<Config>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(GAMEMODE.name)) {
+        let (mode, target) = match event.args.as_slice() {
+            [mode] => (mode, Some(event.sender)),
+            [mode, name] => (mode, find_client(clients.iter(), name)),
+            _ => {
+                if let Ok((_, mut sender)) = clients.get_mut(event.sender) {
+                    sender.send_message(usage(&lang, event.sender, &GAMEMODE));
+                }
+                continue;
+            }
+        };
+
+        let choice = if mode.eq_ignore_ascii_case("reset") {
+            None
+        } else if let Some(mode) = ConfigGameMode::parse(mode) {
+            Some(mode)
+        } else {
+            if let Ok((_, mut sender)) = clients.get_mut(event.sender) {
+                sender.send_message(usage(&lang, event.sender, &GAMEMODE));
+            }
+            continue;
+        };
+
+        let Some(target) = target else {
+            if let Ok((_, mut sender)) = clients.get_mut(event.sender) {
+                let name = event.args.last().map_or("", String::as_str);
+                sender.send_message(lang.tr(
+                    event.sender,
+                    "command.not_online",
+                    &[("name", &name)],
+                ));
+            }
+            continue;
+        };
+
+        if target != event.sender {
+            let allowed = clients.get(event.sender).map_or(false, |(_, sender)| {
+                permissions.has_permission(sender.uuid(), "plots.command.gamemode.others")
+            });
+            if !allowed {
+                if let Ok((_, mut sender)) = clients.get_mut(event.sender) {
+                    sender.send_message(lang.tr(event.sender, "command.no_permission_others", &[]));
+                }
+                continue;
+            }
+        }
+
+        let Ok((_, mut client)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        store.get(client.uuid()).game_mode = choice;
+        store.save(client.uuid());
+
+        let world = worlds.get(client.instance()).map_or("", |w| w.0.as_str());
+        let mode = game_mode_for(&mut store, &permissions, &config, client.uuid(), world);
+        client.set_game_mode(mode);
+
+        let default = default_game_mode(&permissions, &config, client.uuid(), world);
+        let username = client.username().to_string();
+        let mode = mode_name(&lang, target, choice, default);
+        client.send_message(lang.tr(target, "game_mode.changed", &[("mode", &mode)]));
+
+        if target != event.sender {
+            if let Ok((_, mut sender)) = clients.get_mut(event.sender) {
+                let mode = mode_name(&lang, event.sender, choice, default);
+                sender.send_message(lang.tr(
+                    event.sender,
+                    "game_mode.changed_for",
+                    &[("name", &username), ("mode", &mode)],
+                ));
+            }
+        }
+    }
+}
+
+/// Describes a `/gamemode` choice in a client's language.
+fn mode_name(
+    lang: &Lang,
+    client: Entity,
+    choice: Option<ConfigGameMode>,
+    default: ConfigGameMode,
+) -> String {
+    let name = |mode: ConfigGameMode| {
+        lang.plain(client, &format!("game_mode.{}", mode.name()))
+            .to_owned()
+    };
+
+    match choice {
+        Some(choice) => name(choice),
+        None => fill_placeholders(
+            lang.plain(client, "game_mode.default"),
+            &[("mode", &name(default))],
+        ),
+    }
+}
+
+fn init_sneak_toggles(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(SneakToggle::default());
+    }
+}
+
+/// Switches between creative and survival when a player sneaks twice in
+/// quick succession on the ground. A single crouch, like one to place against
+/// a chest, does nothing, and neither does sneaking in the air, which is how
+/// a flying player goes down. Like `/gamemode`, it needs
+/// `plots.command.gamemode`.
+fn toggle_game_mode_on_sneak(
+    mut clients: Query<(&mut Client, &mut SneakToggle)>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    mut events: EventReader<StartSneaking>,
+) {
+    for event in events.iter() {
+        let Ok((mut client, mut toggle)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if !client.on_ground() || !permissions.may_run(client.uuid(), &GAMEMODE) {
+            continue;
+        }
+
+        let now = Instant::now();
+        let double = toggle
+            .last_sneak
+            .replace(now)
+            .map_or(false, |last| now - last <= DOUBLE_SNEAK);
+        let cooling_down = toggle
+            .last_toggle
+            .map_or(false, |last| now - last < TOGGLE_COOLDOWN);
+        if !double || cooling_down {
+            continue;
+        }
+
+        let mode = match client.game_mode() {
+            GameMode::Survival => ConfigGameMode::Creative,
+            GameMode::Creative => ConfigGameMode::Survival,
+            _ => continue,
+        };
+        toggle.last_sneak = None;
+        toggle.last_toggle = Some(now);
+
+        // Kept like a /gamemode choice, so it's still in effect after
+        // relogging.
+        store.get(client.uuid()).game_mode = Some(mode);
+        store.save(client.uuid());
+        client.set_game_mode(mode.into());
+    }
+}
@@ -0,0 +1,194 @@
+use valence::prelude::*;
+
+use crate::fly::Flight;
+use crate::health::{DamageCause, DamageEvent, Health};
+
+/// How far a player can fall without getting hurt, matching vanilla.
+const SAFE_FALL: f64 = 3.0;
+
+/// Anything moving further than this in a tick was teleported rather than
+/// fell. Terminal velocity is just under 4 blocks a tick.
+const TELEPORT_DISTANCE: f64 = 8.0;
+
+/// How high a standing player's eyes are.
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Ticks of air a player has underwater, matching vanilla.
+const MAX_AIR: u32 = 300;
+
+/// Air regained each tick out of the water.
+const AIR_REFILL: u32 = 4;
+
+/// Damage and ticks between hits for drowning, fire and lava.
+const DROWNING_DAMAGE: f32 = 2.0;
+const DROWNING_INTERVAL: u64 = 20;
+const FIRE_DAMAGE: f32 = 1.0;
+const LAVA_DAMAGE: f32 = 4.0;
+const BURN_INTERVAL: u64 = 10;
+
+/// What a client's surroundings have been doing to them.
+#[derive(Component, Debug)]
+struct Exposure {
+    last_position: Option<DVec3>,
+    /// How far the client has dropped since last standing on something.
+    fallen: f64,
+    air: u32,
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self {
+            last_position: None,
+            fallen: 0.0,
+            air: MAX_AIR,
+        }
+    }
+}
+
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(init_exposure).add_system(expose_to_hazards);
+    }
+}
+
+fn init_exposure(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(Exposure::default());
+    }
+}
+
+/// The block something at `position` is in.
+fn block_at(position: DVec3) -> BlockPos {
+    BlockPos::new(
+        position.x.floor() as i32,
+        position.y.floor() as i32,
+        position.z.floor() as i32,
+    )
+}
+
+/// The damage for landing after falling `fallen` blocks, if any.
+fn fall_damage(fallen: f64) -> Option<f32> {
+    (fallen > SAFE_FALL).then(|| (fallen - SAFE_FALL).ceil() as f32)
+}
+
+/// Sends fall, drowning and fire damage. Whether any of it lands is up to
+/// the world's damage settings, which [`crate::health`] checks.
+fn expose_to_hazards(
+    mut clients: Query<(Entity, &Client, &Health, &Flight, &mut Exposure)>,
+    instances: Query<&Instance>,
+    server: Res<Server>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    let tick = server.current_tick();
+
+    for (entity, client, health, flight, mut exposure) in &mut clients {
+        let position = client.position();
+        let last = exposure.last_position.replace(position);
+
+        let vulnerable = matches!(client.game_mode(), GameMode::Survival | GameMode::Adventure);
+        if !vulnerable || health.is_dead() {
+            exposure.fallen = 0.0;
+            exposure.air = MAX_AIR;
+            continue;
+        }
+
+        let Ok(instance) = instances.get(client.instance()) else {
+            continue;
+        };
+        let kind_at = |position: DVec3| {
+            instance
+                .block(block_at(position))
+                .map(|block| block.state().to_kind())
+        };
+        let feet = kind_at(position);
+        let eyes = kind_at(position + DVec3::new(0.0, EYE_HEIGHT, 0.0));
+
+        let teleported = last.map_or(true, |last| last.distance(position) > TELEPORT_DISTANCE);
+        if teleported || flight.is_flying() || feet == Some(BlockKind::Water) {
+            exposure.fallen = 0.0;
+        } else if client.on_ground() {
+            if let Some(amount) = fall_damage(exposure.fallen) {
+                damage.send(DamageEvent {
+                    client: entity,
+                    amount,
+                    cause: DamageCause::Fall,
+                });
+            }
+            exposure.fallen = 0.0;
+        } else if let Some(last) = last {
+            exposure.fallen += (last.y - position.y).max(0.0);
+        }
+
+        if eyes == Some(BlockKind::Water) {
+            exposure.air = exposure.air.saturating_sub(1);
+            if exposure.air == 0 && tick % DROWNING_INTERVAL == 0 {
+                damage.send(DamageEvent {
+                    client: entity,
+                    amount: DROWNING_DAMAGE,
+                    cause: DamageCause::Drowning,
+                });
+            }
+        } else {
+            exposure.air = (exposure.air + AIR_REFILL).min(MAX_AIR);
+        }
+
+        let burn = match feet {
+            Some(BlockKind::Fire | BlockKind::SoulFire) => Some(FIRE_DAMAGE),
+            Some(BlockKind::Lava) => Some(LAVA_DAMAGE),
+            _ => None,
+        };
+        if let Some(amount) = burn {
+            if tick % BURN_INTERVAL == 0 {
+                damage.send(DamageEvent {
+                    client: entity,
+                    amount,
+                    cause: DamageCause::Fire,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::MAX_HEALTH;
+
+    #[test]
+    fn short_falls_are_safe() {
+        for fallen in [0.0, 1.0, 2.5, SAFE_FALL] {
+            assert_eq!(fall_damage(fallen), None, "{fallen}");
+        }
+        assert_eq!(fall_damage(3.2), Some(1.0));
+        assert_eq!(fall_damage(4.0), Some(1.0));
+        assert_eq!(fall_damage(10.0), Some(7.0));
+    }
+
+    #[test]
+    fn a_long_enough_fall_kills() {
+        let mut health = Health::default();
+        assert!(!health.take(fall_damage(22.0).unwrap()));
+        assert_eq!(health.get(), 1.0);
+
+        let mut health = Health::default();
+        assert!(health.take(fall_damage(SAFE_FALL + MAX_HEALTH as f64).unwrap()));
+        assert!(health.is_dead());
+    }
+
+    #[test]
+    fn drowning_and_fire_kill_in_time() {
+        // Out of air, hits come until there's no health left.
+        let hits = (MAX_HEALTH / DROWNING_DAMAGE).ceil() as u32;
+        let mut health = Health::default();
+        for _ in 1..hits {
+            assert!(!health.take(DROWNING_DAMAGE));
+        }
+        assert!(health.take(DROWNING_DAMAGE));
+
+        let mut health = Health::default();
+        let burns = (1..).find(|_| health.take(LAVA_DAMAGE)).unwrap();
+        assert_eq!(burns, 5);
+    }
+}
@@ -0,0 +1,213 @@
+use tracing::info;
+use valence::client::event::PerformRespawn;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::SetHealth;
+use valence_protocol::VarInt;
+
+use crate::config::{Config, WorldConfig};
+use crate::format::{fill_placeholders, strip_legacy};
+use crate::lang::Lang;
+use crate::spawn::send_to_spawn;
+use crate::worlds::Worlds;
+use crate::WorldName;
+
+pub const MAX_HEALTH: f32 = 20.0;
+const MAX_FOOD: i32 = 20;
+const MAX_SATURATION: f32 = 5.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageCause {
+    Void,
+    Fall,
+    Drowning,
+    Fire,
+}
+
+impl DamageCause {
+    /// Whether a world's settings allow this kind of damage.
+    fn enabled_in(self, world: &WorldConfig) -> bool {
+        match self {
+            DamageCause::Void => world.void_damage,
+            DamageCause::Fall => world.fall_damage,
+            DamageCause::Drowning => world.drowning,
+            DamageCause::Fire => world.fire_damage,
+        }
+    }
+
+    /// The message key for dying of this cause.
+    fn death_message(self) -> &'static str {
+        match self {
+            DamageCause::Void => "death.void",
+            DamageCause::Fall => "death.fall",
+            DamageCause::Drowning => "death.drowning",
+            DamageCause::Fire => "death.fire",
+        }
+    }
+}
+
+/// Asks for a client to be hurt. Whether it actually is depends on their game
+/// mode and the world's damage settings.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub client: Entity,
+    pub amount: f32,
+    pub cause: DamageCause,
+}
+
+#[derive(Component, Debug)]
+pub struct Health {
+    current: f32,
+    dead: bool,
+    dirty: bool,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: MAX_HEALTH,
+            dead: false,
+            dirty: true,
+        }
+    }
+}
+
+impl Health {
+    pub fn get(&self) -> f32 {
+        self.current
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    /// Takes `amount` off, returning whether that killed them.
+    pub fn take(&mut self, amount: f32) -> bool {
+        self.current = (self.current - amount).max(0.0);
+        self.dirty = true;
+        self.dead = self.current <= 0.0;
+        self.dead
+    }
+
+    pub fn heal_fully(&mut self) {
+        self.current = MAX_HEALTH;
+        self.dead = false;
+        self.dirty = true;
+    }
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_system(init_health)
+            .add_system(apply_damage.after(init_health))
+            .add_system(restore_health.after(apply_damage))
+            .add_system(sync_health.after(restore_health))
+            .add_system_to_stage(EventLoop, respawn);
+    }
+}
+
+fn init_health(mut commands: Commands, mut clients: Query<(Entity, &mut Client), Added<Client>>) {
+    for (entity, mut client) in &mut clients {
+        client.set_respawn_screen(true);
+        commands.entity(entity).insert(Health::default());
+    }
+}
+
+/// The settings for the world a client is in.
+fn client_world(client: &Client, worlds: &Query<&WorldName>, config: &Config) -> WorldConfig {
+    worlds
+        .get(client.instance())
+        .map(|name| config.world(&name.0))
+        .unwrap_or_default()
+}
+
+fn apply_damage(
+    mut clients: Query<(&mut Client, &mut Health)>,
+    worlds: Query<&WorldName>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<DamageEvent>,
+) {
+    for event in events.iter() {
+        let Ok((mut client, mut health)) = clients.get_mut(event.client) else {
+            continue;
+        };
+
+        if health.dead || !matches!(client.game_mode(), GameMode::Survival | GameMode::Adventure) {
+            continue;
+        }
+
+        if !event
+            .cause
+            .enabled_in(&client_world(&client, &worlds, &config))
+        {
+            continue;
+        }
+
+        if health.take(event.amount) {
+            let key = event.cause.death_message();
+            let username = client.username().to_string();
+            let logged = fill_placeholders(lang.plain_default(key), &[("name", &username)]);
+            info!("{}", strip_legacy(&logged));
+            client.kill(None, lang.tr(event.client, key, &[("name", &username)]));
+        }
+    }
+}
+
+/// Keeps health full in worlds without damage, so it doesn't stay wherever
+/// it was when the player came from a world with damage enabled.
+fn restore_health(
+    mut clients: Query<(&Client, &mut Health)>,
+    worlds: Query<&WorldName>,
+    config: Res<Config>,
+) {
+    for (client, mut health) in &mut clients {
+        if health.dead || health.current >= MAX_HEALTH {
+            continue;
+        }
+
+        if !client_world(client, &worlds, &config).any_damage() {
+            health.heal_fully();
+        }
+    }
+}
+
+fn sync_health(mut clients: Query<(&mut Client, &mut Health)>) {
+    for (mut client, mut health) in &mut clients {
+        if !health.dirty {
+            continue;
+        }
+
+        client.write_packet(&SetHealth {
+            health: health.current,
+            food: VarInt(MAX_FOOD),
+            food_saturation: MAX_SATURATION,
+        });
+        health.dirty = false;
+    }
+}
+
+fn respawn(
+    mut clients: Query<(&mut Client, &mut Health)>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    mut events: EventReader<PerformRespawn>,
+) {
+    for event in events.iter() {
+        let Ok((mut client, mut health)) = clients.get_mut(event.client) else {
+            continue;
+        };
+
+        if !health.dead {
+            continue;
+        }
+
+        health.heal_fully();
+        client.set_velocity([0.0, 0.0, 0.0]);
+        client.respawn();
+        send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances);
+    }
+}
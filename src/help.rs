@@ -0,0 +1,121 @@
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, CommandRegistry, Console};
+use crate::lang::Lang;
+use crate::permissions::Permissions;
+
+const HELP: CommandInfo = CommandInfo {
+    name: "help",
+    aliases: &["?"],
+    usage: "/help [page|command]",
+    description: "List commands, or show how to use one.",
+    permission: None,
+    console: true,
+};
+
+const PAGE_SIZE: usize = 8;
+
+pub struct HelpPlugin;
+
+impl Plugin for HelpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(HELP)
+            .add_system_to_stage(EventLoop, help_command);
+    }
+}
+
+/// The name of a command, which puts it in the chat box when clicked.
+fn clickable(lang: &Lang, client: Entity, name: &str) -> Text {
+    format!("/{name}")
+        .color(Color::GOLD)
+        .on_click_suggest_command(format!("/{name} "))
+        .on_hover_show_text(lang.tr(client, "help.click", &[]))
+}
+
+fn command_list(
+    lang: &Lang,
+    client: Entity,
+    commands: &[&CommandInfo],
+    page: usize,
+) -> Result<Text, Text> {
+    let pages = ((commands.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    if page == 0 || page > pages {
+        return Err(lang.tr(client, "help.no_page", &[("pages", &pages)]));
+    }
+
+    let mut out = lang.tr(client, "help.header", &[("page", &page), ("pages", &pages)]);
+    for info in commands.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+        out = out
+            + "\n"
+            + clickable(lang, client, info.name)
+            + lang.tr(client, "help.entry", &[("description", &info.description)]);
+    }
+
+    if page < pages {
+        let next = format!("/help {}", page + 1);
+        out = out
+            + "\n"
+            + lang
+                .tr(client, "help.more", &[("command", &next)])
+                .on_click_run_command(next);
+    }
+
+    Ok(out)
+}
+
+fn command_details(lang: &Lang, client: Entity, info: &CommandInfo) -> Text {
+    let mut out = clickable(lang, client, info.name)
+        + lang.tr(client, "help.entry", &[("description", &info.description)]);
+    out = out + "\n" + lang.tr(client, "help.usage", &[("usage", &info.usage)]);
+
+    if !info.aliases.is_empty() {
+        let aliases: Vec<_> = info.aliases.iter().map(|a| format!("/{a}")).collect();
+        out = out + "\n" + lang.tr(client, "help.aliases", &[("aliases", &aliases.join(", "))]);
+    }
+
+    out
+}
+
+fn help_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    registry: Res<CommandRegistry>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(HELP.name)) {
+        // Commands the sender isn't allowed to run aren't worth listing.
+        let uuid = clients.get(event.sender).ok().map(|client| client.uuid());
+        let commands: Vec<_> = registry
+            .iter()
+            .filter(|info| match uuid {
+                Some(uuid) => permissions.may_run(uuid, info),
+                None => info.console,
+            })
+            .collect();
+
+        let reply = match event.args.as_slice() {
+            [] => command_list(&lang, event.sender, &commands, 1),
+            [arg] => match arg.parse::<usize>() {
+                Ok(page) => command_list(&lang, event.sender, &commands, page),
+                Err(_) => {
+                    let name = arg.trim_start_matches('/').to_ascii_lowercase();
+                    registry
+                        .get(&name)
+                        .map(|info| command_details(&lang, event.sender, info))
+                        .ok_or_else(|| lang.tr(event.sender, "command.unknown", &[("name", &name)]))
+                }
+            },
+            _ => Err(usage(&lang, event.sender, &HELP)),
+        };
+
+        let reply = reply.unwrap_or_else(|e| e);
+        if let Ok(mut client) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
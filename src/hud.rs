@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+use plotsirv::placement::sane_rotation;
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::format::{fill_placeholders, legacy_text};
+use crate::lang::Lang;
+use crate::player_data::PlayerDataStore;
+use crate::tps::{Deferrable, Tps};
+
+const HUD: CommandInfo = CommandInfo {
+    name: "hud",
+    aliases: &[],
+    usage: "/hud",
+    description: "Toggle the coordinate display above your hotbar.",
+    permission: None,
+    console: false,
+};
+
+/// How many ticks between HUD refreshes.
+const REFRESH_TICKS: u64 = 5;
+
+/// The client fades the action bar out after a few seconds, so even an
+/// unchanged HUD has to be resent this often.
+const RESEND_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// How long another action bar message keeps the HUD away, about as long as
+/// the client shows a message for.
+pub const SUPPRESS_FOR: Duration = Duration::from_secs(3);
+
+/// Per-client state for the action bar coordinate display.
+#[derive(Component, Debug)]
+pub struct Hud {
+    enabled: bool,
+    last_sent: String,
+    last_sent_at: Option<Instant>,
+    suppressed_until: Option<Instant>,
+}
+
+impl Hud {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last_sent: String::new(),
+            last_sent_at: None,
+            suppressed_until: None,
+        }
+    }
+
+    /// Keeps the HUD off the action bar for a while so another message can
+    /// be shown there without the two flickering back and forth.
+    pub fn suppress(&mut self, duration: Duration) {
+        self.suppressed_until = Some(Instant::now() + duration);
+        // Whatever the other message was, the HUD needs to be redrawn after.
+        self.last_sent.clear();
+    }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(HUD)
+            .add_system_to_stage(EventLoop, hud_command)
+            .add_system(update_huds);
+    }
+}
+
+fn hud_command(
+    mut clients: Query<(&mut Client, &mut Hud)>,
+    mut store: ResMut<PlayerDataStore>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(HUD.name)) {
+        let Ok((mut client, mut hud)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        if !event.args.is_empty() {
+            client.send_message(usage(&lang, event.sender, &HUD));
+            continue;
+        }
+
+        hud.enabled = !hud.enabled;
+        store.get(client.uuid()).hud = hud.enabled;
+        store.save(client.uuid());
+
+        if !hud.enabled {
+            client.set_action_bar("");
+            hud.last_sent.clear();
+        }
+
+        let key = if hud.enabled {
+            "hud.enabled"
+        } else {
+            "hud.disabled"
+        };
+        client.send_message(lang.tr(event.sender, key, &[]));
+    }
+}
+
+/// The message key for the cardinal direction a yaw faces. Yaw 0 looks
+/// towards +Z (south).
+fn cardinal(yaw: f32) -> &'static str {
+    match yaw.rem_euclid(360.0) {
+        yaw if (45.0..135.0).contains(&yaw) => "hud.west",
+        yaw if (135.0..225.0).contains(&yaw) => "hud.north",
+        yaw if (225.0..315.0).contains(&yaw) => "hud.east",
+        _ => "hud.south",
+    }
+}
+
+fn update_huds(
+    mut clients: Query<(Entity, &mut Client, &mut Hud)>,
+    server: Res<Server>,
+    lang: Res<Lang>,
+    tps: Res<Tps>,
+    mut overdue: Local<bool>,
+) {
+    if server.current_tick() % REFRESH_TICKS != 0 && !*overdue {
+        return;
+    }
+    *overdue = tps.defer(Deferrable::Hud);
+    if *overdue {
+        return;
+    }
+
+    let now = Instant::now();
+
+    for (entity, mut client, mut hud) in &mut clients {
+        if !hud.enabled {
+            continue;
+        }
+
+        if let Some(until) = hud.suppressed_until {
+            if now < until {
+                continue;
+            }
+            hud.suppressed_until = None;
+        }
+
+        let pos = client.position();
+        let (yaw, _) = sane_rotation(client.yaw(), client.pitch());
+        let text = fill_placeholders(
+            lang.plain(entity, "hud.text"),
+            &[
+                ("x", &pos.x.floor()),
+                ("y", &pos.y.floor()),
+                ("z", &pos.z.floor()),
+                ("facing", &lang.plain(entity, cardinal(yaw))),
+            ],
+        );
+
+        let fresh = hud
+            .last_sent_at
+            .map_or(false, |at| now - at < RESEND_INTERVAL);
+        if text == hud.last_sent && fresh {
+            continue;
+        }
+
+        client.set_action_bar(legacy_text(&text));
+        hud.last_sent = text;
+        hud.last_sent_at = Some(now);
+    }
+}
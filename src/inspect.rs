@@ -0,0 +1,293 @@
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+use valence::client::event::{FinishDigging, StartDigging, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::types::Hand;
+
+use crate::ban::now_secs;
+use crate::block_log::{
+    describe, finish_searches, BlockLog, BlockRecord, SearchFinished, SearchKind,
+};
+use crate::block_sync;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::lang::Lang;
+use crate::WorldName;
+
+const INSPECT: CommandInfo = CommandInfo {
+    name: "inspect",
+    aliases: &["i"],
+    usage: "/inspect [page]",
+    description: "Toggle the inspector, where clicking a block shows who changed it.",
+    permission: Some("plots.command.inspect"),
+    console: false,
+};
+
+/// The inspector turns itself off after going this long unused.
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const PAGE_SIZE: usize = 7;
+
+/// Marks a client whose clicks look blocks up in the block log instead of
+/// breaking or placing them.
+#[derive(Component)]
+pub struct Inspecting {
+    last_used: Instant,
+    /// The last block looked up and its history, newest first, to page
+    /// through.
+    history: Option<(BlockPos, Vec<BlockRecord>)>,
+}
+
+pub struct InspectPlugin;
+
+impl Plugin for InspectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(INSPECT)
+            .add_system_to_stage(EventLoop, inspect_command)
+            .add_system_to_stage(EventLoop, inspect_blocks)
+            .add_system(show_history.after(finish_searches))
+            .add_system(expire_inspectors);
+    }
+}
+
+fn history_page(
+    lang: &Lang,
+    client: Entity,
+    pos: BlockPos,
+    history: &[BlockRecord],
+    page: usize,
+) -> Text {
+    let pages = ((history.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    if page == 0 || page > pages {
+        return lang.tr(client, "inspect.no_page", &[("pages", &pages)]);
+    }
+
+    let now = now_secs();
+    let mut out = lang.tr(
+        client,
+        "inspect.header",
+        &[
+            ("x", &pos.x),
+            ("y", &pos.y),
+            ("z", &pos.z),
+            ("page", &page),
+            ("pages", &pages),
+        ],
+    );
+    for record in history.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+        out = out + "\n" + describe(lang, client, record, now);
+    }
+
+    if page < pages {
+        let next = format!("/inspect {}", page + 1);
+        out = out
+            + "\n"
+            + lang
+                .tr(client, "inspect.more", &[("command", &next)])
+                .on_click_run_command(next);
+    }
+
+    out
+}
+
+fn inspect_command(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, Option<&mut Inspecting>)>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(INSPECT.name)) {
+        let Ok((mut client, inspecting)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let reply = match (event.args.as_slice(), inspecting) {
+            ([], Some(_)) => {
+                commands.entity(event.sender).remove::<Inspecting>();
+                lang.tr(event.sender, "inspect.disabled", &[])
+            }
+            ([], None) => {
+                commands.entity(event.sender).insert(Inspecting {
+                    last_used: Instant::now(),
+                    history: None,
+                });
+                lang.tr(event.sender, "inspect.enabled", &[])
+            }
+            ([page], inspecting) => match (page.parse::<usize>(), inspecting) {
+                (Ok(page), Some(mut inspecting)) => {
+                    inspecting.last_used = Instant::now();
+                    match &inspecting.history {
+                        Some((pos, history)) => {
+                            history_page(&lang, event.sender, *pos, history, page)
+                        }
+                        None => lang.tr(event.sender, "inspect.nothing", &[]),
+                    }
+                }
+                (Ok(_), None) => lang.tr(event.sender, "inspect.not_enabled", &[]),
+                (Err(_), _) => usage(&lang, event.sender, &INSPECT),
+            },
+            _ => usage(&lang, event.sender, &INSPECT),
+        };
+
+        client.send_message(reply);
+    }
+}
+
+/// Undoes what the client guessed a click would do, as the server ignores
+/// it while inspecting.
+fn resend_block(client: &mut Client, instances: &Query<&Instance>, pos: BlockPos) {
+    if let Ok(instance) = instances.get(client.instance()) {
+        block_sync::resend_block(client, instance, pos);
+    }
+}
+
+/// Looks up a block when an inspecting player clicks it. Left clicks show
+/// the block itself; right clicks with an empty hand show the space next
+/// to it, where a block may have been removed.
+fn inspect_blocks(
+    mut clients: Query<(&mut Client, &Inventory, &mut Inspecting)>,
+    instances: Query<&Instance>,
+    worlds: Query<&WorldName>,
+    log: Res<BlockLog>,
+    mut start_digging: EventReader<StartDigging>,
+    mut finish_digging: EventReader<FinishDigging>,
+    mut use_item: EventReader<UseItemOnBlock>,
+) {
+    let mut lookups = Vec::new();
+
+    for event in start_digging.iter() {
+        if let Ok((mut client, _, _)) = clients.get_mut(event.client) {
+            resend_block(&mut client, &instances, event.position);
+            lookups.push((event.client, event.position));
+        }
+    }
+    for event in finish_digging.iter() {
+        if let Ok((mut client, _, _)) = clients.get_mut(event.client) {
+            resend_block(&mut client, &instances, event.position);
+        }
+    }
+    for event in use_item.iter() {
+        let Ok((mut client, inventory, _)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let pos = event.position.get_in_direction(event.face);
+        resend_block(&mut client, &instances, pos);
+
+        let empty_hand = inventory.slot(client.held_item_slot()).is_none();
+        if event.hand == Hand::Main && empty_hand {
+            lookups.push((event.client, pos));
+        }
+    }
+
+    for (entity, pos) in lookups {
+        let Ok((client, _, mut inspecting)) = clients.get_mut(entity) else {
+            continue;
+        };
+        let Ok(world) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        inspecting.last_used = Instant::now();
+        let world = world.0.clone();
+        log.search(entity, SearchKind::Block(pos), 0, move |record| {
+            record.world == world && record.pos == [pos.x, pos.y, pos.z]
+        });
+    }
+}
+
+fn show_history(
+    mut clients: Query<(&mut Client, &mut Inspecting)>,
+    lang: Res<Lang>,
+    mut events: EventReader<SearchFinished>,
+) {
+    for search in events.iter() {
+        let SearchKind::Block(pos) = search.kind else {
+            continue;
+        };
+        // They may have turned the inspector off while it was running.
+        let Ok((mut client, mut inspecting)) = clients.get_mut(search.client) else {
+            continue;
+        };
+
+        let reply = match &search.result {
+            Ok(records) if records.is_empty() => {
+                inspecting.history = None;
+                lang.tr(
+                    search.client,
+                    "inspect.none",
+                    &[("x", &pos.x), ("y", &pos.y), ("z", &pos.z)],
+                )
+            }
+            Ok(records) => {
+                let history: Vec<_> = records.iter().rev().cloned().collect();
+                let reply = history_page(&lang, search.client, pos, &history, 1);
+                inspecting.history = Some((pos, history));
+                reply
+            }
+            Err(e) => {
+                warn!("Failed to look up block history: {e:#}");
+                lang.tr(search.client, "blocklog.failed", &[])
+            }
+        };
+
+        client.send_message(reply);
+    }
+}
+
+fn expire_inspectors(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &Inspecting)>,
+    lang: Res<Lang>,
+) {
+    for (entity, mut client, inspecting) in &mut clients {
+        if inspecting.last_used.elapsed() >= TIMEOUT {
+            commands.entity(entity).remove::<Inspecting>();
+            client.send_message(lang.tr(entity, "inspect.timed_out", &[]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plotsirv::placement::use_up_one;
+
+    use super::*;
+
+    /// The first hotbar slot of a player inventory.
+    const HOTBAR: u16 = 36;
+
+    fn holding(item: ItemKind) -> Inventory {
+        let mut inventory = Inventory::new(InventoryKind::Player);
+        inventory.replace_slot(HOTBAR, Some(ItemStack::new(item, 1, None)));
+        inventory
+    }
+
+    #[test]
+    fn inspecting_clicks_change_nothing() {
+        let mut world = World::new();
+        let inspecting = world
+            .spawn((
+                holding(ItemKind::FlintAndSteel),
+                Inspecting {
+                    last_used: Instant::now(),
+                    history: None,
+                },
+            ))
+            .id();
+        let playing = world.spawn(holding(ItemKind::FlintAndSteel)).id();
+
+        // What the click handlers see of the clicking clients, each using
+        // up what they hold as lighting a fire or placing a frame does.
+        let mut clicking = world.query_filtered::<&mut Inventory, Without<Inspecting>>();
+        for mut inventory in clicking.iter_mut(&mut world) {
+            use_up_one(&mut inventory, HOTBAR);
+        }
+
+        let held = |entity| {
+            let inventory = world.get::<Inventory>(entity).unwrap();
+            inventory.slot(HOTBAR).map(|stack| stack.item)
+        };
+        assert_eq!(held(inspecting), Some(ItemKind::FlintAndSteel));
+        assert_eq!(held(playing), None);
+    }
+}
@@ -0,0 +1,215 @@
+use plotsirv::placement::use_up_one;
+use valence::client::event::{InteractWithEntity, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::types::{EntityInteraction, Hand};
+use valence_protocol::BlockFace;
+
+use crate::border::{inside_border, WorldBorder};
+use crate::drops::drop_item;
+use crate::inspect::Inspecting;
+use crate::reject_placement;
+use crate::sound::{Feedback, FeedbackSound};
+use crate::UsedClicks;
+
+/// How many ways round an item in a frame can be turned.
+const ROTATIONS: u8 = 8;
+
+/// An item frame placed by a player, and what's in it.
+#[derive(Component, Debug)]
+struct ItemFrame {
+    /// Which frame item it drops as.
+    kind: ItemKind,
+    item: Option<ItemStack>,
+    rotation: u8,
+}
+
+pub struct ItemFramesPlugin;
+
+impl Plugin for ItemFramesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(EventLoop, place_item_frames)
+            .add_system_to_stage(EventLoop, use_item_frames);
+    }
+}
+
+/// Which way a frame hung on `face` of a block faces, as its spawn data
+/// gives it.
+fn facing_data(face: BlockFace) -> i32 {
+    match face {
+        BlockFace::Bottom => 0,
+        BlockFace::Top => 1,
+        BlockFace::North => 2,
+        BlockFace::South => 3,
+        BlockFace::West => 4,
+        BlockFace::East => 5,
+    }
+}
+
+/// Updates what players see in a frame. Valence keeps this in the frame's
+/// metadata, so players who come into range later see it too.
+fn show_contents(entity: &mut McEntity, frame: &ItemFrame) {
+    let (item, rotation) = (frame.item.clone(), i32::from(frame.rotation));
+    match entity.data_mut() {
+        TrackedData::ItemFrame(data) => {
+            data.set_item_stack(item);
+            data.set_rotation(rotation);
+        }
+        TrackedData::GlowItemFrame(data) => {
+            data.set_item_stack(item);
+            data.set_rotation(rotation);
+        }
+        _ => {}
+    }
+}
+
+/// Hangs an item frame on the side of a block a player uses one on. It
+/// needs a solid block to hang on, and nothing in the way.
+fn place_item_frames(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &mut Inventory), Without<Inspecting>>,
+    instances: Query<&Instance>,
+    frames: Query<(&McEntity, &ItemFrame)>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let slot = client.held_item_slot();
+        let (kind, entity_kind) = match inventory.slot(slot).map(|stack| stack.item) {
+            Some(ItemKind::ItemFrame) => (ItemKind::ItemFrame, EntityKind::ItemFrame),
+            Some(ItemKind::GlowItemFrame) => (ItemKind::GlowItemFrame, EntityKind::GlowItemFrame),
+            _ => continue,
+        };
+        used.mark(event.client);
+        let Ok(instance) = instances.get(client.instance()) else {
+            continue;
+        };
+
+        let pos = event.position.get_in_direction(event.face);
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+
+        let solid = instance.block(event.position).map_or(false, |block| {
+            !block.state().is_replaceable() && block.state().collision_shapes().next().is_some()
+        });
+        let clear = instance.block(pos).map_or(false, |block| {
+            block.state().collision_shapes().next().is_none()
+        });
+        let center = DVec3::new(pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5);
+        let taken = frames.iter().any(|(entity, _)| {
+            !entity.is_despawned()
+                && entity.instance() == client.instance()
+                && entity.position() == center
+                && entity.object_data() == facing_data(event.face)
+        });
+        if !solid || !clear || taken {
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+
+        let frame = ItemFrame {
+            kind,
+            item: None,
+            rotation: 0,
+        };
+        let mut entity = McEntity::new(entity_kind, client.instance());
+        entity.set_position(center);
+        entity.set_object_data(facing_data(event.face));
+        show_contents(&mut entity, &frame);
+        commands.spawn((entity, frame));
+
+        if client.game_mode() == GameMode::Survival {
+            use_up_one(&mut inventory, slot);
+        }
+    }
+}
+
+/// Using an item on an empty frame puts one of it in; using anything on a
+/// full frame turns what's in it. Punching a full frame pops its item out,
+/// and punching an empty one takes it down. In creative, nothing is used up
+/// or dropped.
+fn use_item_frames(
+    mut clients: Query<(&Client, &mut Inventory)>,
+    mut frames: Query<(&mut McEntity, &mut ItemFrame)>,
+    entities: Res<McEntityManager>,
+    borders: Query<&WorldBorder>,
+    mut commands: Commands,
+    mut events: EventReader<InteractWithEntity>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
+    for event in events.iter() {
+        let Some(frame) = entities.get_with_protocol_id(event.entity_id) else {
+            continue;
+        };
+        let Ok((mut entity, mut frame)) = frames.get_mut(frame) else {
+            continue;
+        };
+        let Ok((client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if entity.is_despawned() || client.instance() != entity.instance() {
+            continue;
+        }
+
+        let position = entity.position();
+        let pos = BlockPos::new(
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        );
+        if !inside_border(&borders, entity.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            continue;
+        }
+        let survival = client.game_mode() == GameMode::Survival;
+
+        match event.interact {
+            EntityInteraction::Attack => {
+                let dropped = match frame.item.take() {
+                    Some(item) => {
+                        frame.rotation = 0;
+                        show_contents(&mut entity, &frame);
+                        item
+                    }
+                    None => {
+                        entity.set_despawned(true);
+                        ItemStack::new(frame.kind, 1, None)
+                    }
+                };
+                if survival {
+                    drop_item(&mut commands, entity.instance(), position, dropped);
+                }
+            }
+            EntityInteraction::Interact(Hand::Main) => {
+                let slot = client.held_item_slot();
+                if frame.item.is_some() {
+                    frame.rotation = (frame.rotation + 1) % ROTATIONS;
+                } else if let Some(held) = inventory.slot(slot) {
+                    frame.item = Some(ItemStack::new(held.item, 1, held.nbt.clone()));
+                    if survival {
+                        use_up_one(&mut inventory, slot);
+                    }
+                } else {
+                    continue;
+                }
+                show_contents(&mut entity, &frame);
+            }
+            // Frames take the main hand's click, and where on the frame it
+            // was doesn't matter.
+            EntityInteraction::Interact(Hand::Off) | EntityInteraction::InteractAt { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use tracing::info;
+use valence::nbt::compound;
+use valence::prelude::*;
+
+use crate::command::{
+    find_client, sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console,
+};
+use crate::drops::drop_item;
+use crate::format::legacy_text;
+use crate::lang::Lang;
+use crate::permissions::Permissions;
+
+const GIVE: CommandInfo = CommandInfo {
+    name: "give",
+    aliases: &[],
+    usage: "/give <player> <item> [count] [name]",
+    description: "Give a player items, optionally with a custom name.",
+    permission: Some("plots.command.give"),
+    console: true,
+};
+
+const ITEM: CommandInfo = CommandInfo {
+    name: "item",
+    aliases: &[],
+    usage: "/item <item> [count] [name]",
+    description: "Give yourself items, optionally with a custom name.",
+    permission: Some("plots.command.item"),
+    console: false,
+};
+
+const CLEAR: CommandInfo = CommandInfo {
+    name: "clear",
+    aliases: &["clearinventory", "ci"],
+    usage: "/clear [player] [item]",
+    description: "Empty your or another player's inventory, or take away one kind of item.",
+    permission: Some("plots.command.clear"),
+    console: true,
+};
+
+/// At most this many stacks are given at once, as vanilla allows, so a
+/// typo in the count doesn't bury someone in dropped items.
+const MAX_GIVEN_STACKS: u32 = 100;
+
+/// The main inventory, hotbar first, in the order given items go in.
+fn storage_slots() -> impl Iterator<Item = u16> {
+    (36..45).chain(9..36)
+}
+
+/// Looks up an item by its ID, like `stone` or `minecraft:stone`.
+fn parse_item(name: &str) -> Option<ItemKind> {
+    ItemKind::from_str(name.strip_prefix("minecraft:").unwrap_or(name))
+}
+
+/// Items to give, as parsed from a command.
+struct Gift {
+    item: ItemKind,
+    count: u32,
+    nbt: Option<Compound>,
+}
+
+impl Gift {
+    /// Parses `<item> [count] [name]`, with the name in `&` color codes.
+    fn parse(args: &[String]) -> Option<Self> {
+        let (item, rest) = args.split_first()?;
+        let item = parse_item(item)?;
+        if item == ItemKind::Air {
+            return None;
+        }
+
+        let (count, name) = match rest.split_first() {
+            Some((count, name)) => (count.parse::<u32>().ok().filter(|&c| c > 0)?, name),
+            None => (1, rest),
+        };
+
+        let nbt = (!name.is_empty()).then(|| {
+            let name = serde_json::to_string(&legacy_text(&name.join(" ")))
+                .expect("text can always be serialized");
+            compound! { "display" => compound! { "Name" => name } }
+        });
+
+        Some(Self { item, count, nbt })
+    }
+
+    fn matches(&self, stack: &ItemStack) -> bool {
+        stack.item == self.item && stack.nbt == self.nbt
+    }
+
+    /// How many of these items fit in an inventory.
+    fn room_in(&self, inventory: &Inventory) -> u32 {
+        let max = u32::from(self.item.max_stack());
+        storage_slots()
+            .map(|slot| match inventory.slot(slot) {
+                None => max,
+                Some(stack) if self.matches(stack) => max.saturating_sub(u32::from(stack.count())),
+                Some(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Tops up matching stacks, then fills empty slots a full stack at a
+    /// time. Check there's room first.
+    fn add_to(&self, inventory: &mut Inventory) {
+        let max = self.item.max_stack();
+        let mut left = self.count;
+
+        for slot in storage_slots() {
+            if let Some(stack) = inventory.slot(slot).filter(|stack| self.matches(stack)) {
+                let added = u32::from(max.saturating_sub(stack.count())).min(left) as u8;
+                if added > 0 {
+                    let mut stack = stack.clone();
+                    stack.set_count(stack.count() + added);
+                    inventory.replace_slot(slot, Some(stack));
+                    left -= u32::from(added);
+                }
+            }
+        }
+
+        for slot in storage_slots() {
+            if left == 0 {
+                break;
+            }
+            if inventory.slot(slot).is_none() {
+                let count = left.min(u32::from(max)) as u8;
+                inventory.replace_slot(
+                    slot,
+                    Some(ItemStack::new(self.item, count, self.nbt.clone())),
+                );
+                left -= u32::from(count);
+            }
+        }
+    }
+}
+
+/// Puts as much of `stack` into an inventory as fits, the way `/give` does,
+/// and says how many went in.
+pub fn insert_stack(inventory: &mut Inventory, stack: &ItemStack) -> u8 {
+    let mut gift = Gift {
+        item: stack.item,
+        count: u32::from(stack.count()),
+        nbt: stack.nbt.clone(),
+    };
+    gift.count = gift.count.min(gift.room_in(inventory));
+    gift.add_to(inventory);
+    gift.count as u8
+}
+
+pub struct ItemsPlugin;
+
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(GIVE)
+            .add_command(ITEM)
+            .add_command(CLEAR)
+            .add_system_to_stage(EventLoop, give_command)
+            .add_system_to_stage(EventLoop, clear_command);
+    }
+}
+
+fn reply(
+    clients: &mut Query<(Entity, &mut Client, &mut Inventory)>,
+    consoles: &mut Query<&mut Console>,
+    sender: Entity,
+    text: Text,
+) {
+    if let Ok((_, mut client, _)) = clients.get_mut(sender) {
+        client.send_message(text);
+    } else if let Ok(mut console) = consoles.get_mut(sender) {
+        console.send_message(text);
+    }
+}
+
+/// Handles both `/give` and `/item`, which is `/give` to yourself.
+fn give_command(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &mut Inventory)>,
+    mut consoles: Query<&mut Console>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(GIVE.name) || c.is(ITEM.name)) {
+        let (info, target, args) = if event.is(GIVE.name) {
+            let Some((name, args)) = event.args.split_first() else {
+                let text = usage(&lang, event.sender, &GIVE);
+                reply(&mut clients, &mut consoles, event.sender, text);
+                continue;
+            };
+            let target = find_client(clients.iter().map(|(e, c, _)| (e, c)), name);
+            let Some(target) = target else {
+                let text = lang.tr(event.sender, "command.not_online", &[("name", name)]);
+                reply(&mut clients, &mut consoles, event.sender, text);
+                continue;
+            };
+            (&GIVE, target, args)
+        } else {
+            (&ITEM, event.sender, event.args.as_slice())
+        };
+
+        let Some(gift) = Gift::parse(args) else {
+            let text = match args.first() {
+                Some(item) if parse_item(item).is_none() => {
+                    lang.tr(event.sender, "items.unknown", &[("item", item)])
+                }
+                _ => usage(&lang, event.sender, info),
+            };
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        };
+
+        let max = u32::from(gift.item.max_stack());
+        if gift.count > max * MAX_GIVEN_STACKS {
+            let text = lang.tr(
+                event.sender,
+                "items.too_many",
+                &[("max", &(max * MAX_GIVEN_STACKS))],
+            );
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        }
+
+        let name = sender_name(
+            clients.get(event.sender).ok().map(|(_, c, _)| c),
+            consoles.get(event.sender).ok(),
+        );
+        let Ok((_, mut client, mut inventory)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        // What doesn't fit is dropped at their feet, a stack at a time.
+        let room = gift.room_in(&inventory);
+        Gift {
+            count: gift.count.min(room),
+            nbt: gift.nbt.clone(),
+            ..gift
+        }
+        .add_to(&mut inventory);
+        let mut left = gift.count.saturating_sub(room);
+        while left > 0 {
+            let count = left.min(max);
+            let stack = ItemStack::new(gift.item, count as u8, gift.nbt.clone());
+            drop_item(&mut commands, client.instance(), client.position(), stack);
+            left -= count;
+        }
+
+        let item = gift.item.to_str();
+        let username = client.username().to_string();
+        info!("{name} gave {username} {} {item}", gift.count);
+        if target != event.sender {
+            client.send_message(lang.tr(
+                target,
+                "items.received",
+                &[("count", &gift.count), ("item", &item)],
+            ));
+        }
+
+        let text = lang.tr(
+            event.sender,
+            "items.given",
+            &[("name", &username), ("count", &gift.count), ("item", &item)],
+        );
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn clear_command(
+    mut clients: Query<(Entity, &mut Client, &mut Inventory)>,
+    mut consoles: Query<&mut Console>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(CLEAR.name)) {
+        let (target, item) = match event.args.as_slice() {
+            [] => (Some(event.sender), None),
+            [name] => (
+                find_client(clients.iter().map(|(e, c, _)| (e, c)), name),
+                None,
+            ),
+            [name, item] => (
+                find_client(clients.iter().map(|(e, c, _)| (e, c)), name),
+                Some(item),
+            ),
+            _ => {
+                let text = usage(&lang, event.sender, &CLEAR);
+                reply(&mut clients, &mut consoles, event.sender, text);
+                continue;
+            }
+        };
+
+        // Consoles have to name someone.
+        let Some(target) = target.filter(|&target| clients.contains(target)) else {
+            let text = match event.args.first() {
+                Some(name) => lang.tr(event.sender, "command.not_online", &[("name", name)]),
+                None => usage(&lang, event.sender, &CLEAR),
+            };
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        };
+
+        if target != event.sender {
+            let allowed = clients.get(event.sender).map_or(true, |(_, sender, _)| {
+                permissions.has_permission(sender.uuid(), "plots.command.clear.others")
+            });
+            if !allowed {
+                let text = lang.tr(event.sender, "command.no_permission_others", &[]);
+                reply(&mut clients, &mut consoles, event.sender, text);
+                continue;
+            }
+        }
+
+        let kind = match item {
+            Some(item) => match parse_item(item) {
+                Some(kind) => Some(kind),
+                None => {
+                    let text = lang.tr(event.sender, "items.unknown", &[("item", item)]);
+                    reply(&mut clients, &mut consoles, event.sender, text);
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let name = sender_name(
+            clients.get(event.sender).ok().map(|(_, c, _)| c),
+            consoles.get(event.sender).ok(),
+        );
+        let Ok((_, mut client, mut inventory)) = clients.get_mut(target) else {
+            continue;
+        };
+
+        let mut removed = 0;
+        for slot in 0..inventory.slot_count() {
+            let Some(stack) = inventory.slot(slot) else {
+                continue;
+            };
+            if kind.map_or(true, |kind| stack.item == kind) {
+                removed += u32::from(stack.count());
+                inventory.replace_slot(slot, None);
+            }
+        }
+
+        let username = client.username().to_string();
+        info!("{name} cleared {removed} items from {username}'s inventory");
+        if target != event.sender {
+            client.send_message(lang.tr(target, "items.cleared_by", &[("count", &removed)]));
+        }
+
+        let text = lang.tr(
+            event.sender,
+            "items.cleared",
+            &[("name", &username), ("count", &removed)],
+        );
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
@@ -0,0 +1,110 @@
+use plotsirv::disconnect::{AddDisconnectSystem, ClientDisconnected};
+use tracing::info;
+use valence::prelude::*;
+
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::logging::player_span;
+use crate::permissions::Permissions;
+use crate::player_data::PlayerDataStore;
+
+pub struct JoinLeavePlugin;
+
+impl Plugin for JoinLeavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(announce_joins)
+            .add_disconnect_system(announce_leaves);
+    }
+}
+
+/// Lets staff come and go without a message, e.g. while vanished.
+const SILENT_JOIN: &str = "plots.silentjoin";
+
+/// Marks a client whose join was handled, so their leave is announced
+/// exactly once however the connection ends. A silent join stays silent when
+/// they leave, even if their permissions changed in between.
+#[derive(Component)]
+struct Announced {
+    silent: bool,
+}
+
+/// Sends a configured message to everyone, in their own language. An empty
+/// message isn't sent.
+fn broadcast(
+    clients: &mut Query<(Entity, &mut Client)>,
+    lang: &Lang,
+    message: &str,
+    username: &str,
+) {
+    if message.is_empty() {
+        return;
+    }
+
+    for (entity, mut client) in clients.iter_mut() {
+        if !client.is_disconnected() {
+            client.send_message(lang.text(entity, message, &[("name", &username)]));
+        }
+    }
+}
+
+fn announce_joins(
+    mut commands: Commands,
+    joined: Query<Entity, (With<Client>, Without<Announced>)>,
+    mut clients: Query<(Entity, &mut Client)>,
+    store: Res<PlayerDataStore>,
+    permissions: Res<Permissions>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+) {
+    for entity in &joined {
+        let Ok((_, client)) = clients.get(entity) else {
+            continue;
+        };
+
+        if client.is_disconnected() {
+            continue;
+        }
+
+        let username = client.username().to_string();
+        let message = if store.has_played_before(client.uuid()) {
+            &config.messages.join
+        } else {
+            &config.messages.first_join
+        };
+
+        let silent = permissions.has_permission(client.uuid(), SILENT_JOIN);
+
+        player_span(client).in_scope(|| info!(position = ?client.position(), "{username} joined"));
+        commands.entity(entity).insert(Announced { silent });
+
+        if !silent {
+            broadcast(&mut clients, &lang, message, &username);
+        }
+    }
+}
+
+fn announce_leaves(
+    mut commands: Commands,
+    mut events: EventReader<ClientDisconnected>,
+    announced: Query<&Announced>,
+    mut clients: Query<(Entity, &mut Client)>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+) {
+    for &ClientDisconnected { client: entity, .. } in events.iter() {
+        let Ok(&Announced { silent }) = announced.get(entity) else {
+            continue;
+        };
+        let Ok((_, client)) = clients.get(entity) else {
+            continue;
+        };
+
+        let username = client.username().to_string();
+        player_span(client).in_scope(|| info!(position = ?client.position(), "{username} left"));
+        commands.entity(entity).remove::<Announced>();
+
+        if !silent {
+            broadcast(&mut clients, &lang, &config.messages.leave, &username);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use plotsirv::disconnect::{AddDisconnectSystem, ClientRegistry};
+use tracing::{info, warn};
+use valence::client::event::ClientSettings;
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::{Config, LangConfig};
+use crate::format::{fill_placeholders, legacy_text};
+use crate::player_data::PlayerDataStore;
+
+const LANG: CommandInfo = CommandInfo {
+    name: "lang",
+    aliases: &["language"],
+    usage: "/lang [code]",
+    description: "Show or change the language of server messages.",
+    permission: None,
+    console: false,
+};
+
+/// The built-in English messages, which every other language falls back to.
+const BUILTIN_LANGUAGE: &str = "en_us";
+const BUILTIN_MESSAGES: &str = include_str!("../lang/en_us.toml");
+
+/// Arguments substituted into a message's `{name}` placeholders.
+pub type Args<'a> = [(&'a str, &'a dyn Display)];
+
+/// Message catalogs for every language, and the language each client reads.
+#[derive(Resource, Debug)]
+pub struct Lang {
+    default: String,
+    catalogs: HashMap<String, HashMap<String, String>>,
+    locales: HashMap<Entity, String>,
+}
+
+impl Lang {
+    /// Reads every `<code>.toml` in the configured directory on top of the
+    /// built-in messages. A missing directory just means no extra languages.
+    pub fn load(config: &LangConfig) -> anyhow::Result<Self> {
+        let mut catalogs = HashMap::new();
+        catalogs.insert(
+            BUILTIN_LANGUAGE.to_owned(),
+            parse_catalog(BUILTIN_MESSAGES).context("parsing built-in messages")?,
+        );
+
+        if config.dir.is_dir() {
+            let entries = fs::read_dir(&config.dir)
+                .with_context(|| format!("reading {}", config.dir.display()))?;
+
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().map_or(true, |ext| ext != "toml") {
+                    continue;
+                }
+                let Some(code) = language_code(&path) else {
+                    continue;
+                };
+
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                let messages = parse_catalog(&contents)
+                    .with_context(|| format!("parsing {}", path.display()))?;
+                catalogs
+                    .entry(code)
+                    .or_insert_with(HashMap::new)
+                    .extend(messages);
+            }
+        }
+
+        let default = config.default.to_ascii_lowercase();
+        if !catalogs.contains_key(&default) {
+            warn!("Default language {default} has no messages, using {BUILTIN_LANGUAGE}");
+        }
+
+        Ok(Self {
+            default,
+            catalogs,
+            locales: HashMap::new(),
+        })
+    }
+
+    /// Only the built-in messages, for when the language files can't be read.
+    pub fn builtin() -> Self {
+        Self::load(&LangConfig {
+            dir: Default::default(),
+            ..Default::default()
+        })
+        .expect("built-in messages are valid")
+    }
+
+    /// Switches to freshly loaded messages, keeping everyone's language.
+    pub fn replace_messages(&mut self, loaded: Lang) {
+        self.default = loaded.default;
+        self.catalogs = loaded.catalogs;
+    }
+
+    pub fn has_language(&self, code: &str) -> bool {
+        self.catalogs.contains_key(code)
+    }
+
+    /// Every known language code, sorted.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut codes: Vec<_> = self.catalogs.keys().map(String::as_str).collect();
+        codes.sort_unstable();
+        codes
+    }
+
+    /// The language a client reads, or the default.
+    pub fn locale(&self, client: Entity) -> &str {
+        self.locales
+            .get(&client)
+            .map_or(self.default.as_str(), String::as_str)
+    }
+
+    pub fn set_locale(&mut self, client: Entity, code: String) {
+        self.locales.insert(client, code);
+    }
+
+    /// Looks a key up in `lang`, then the default language, then the
+    /// built-in messages.
+    fn template(&self, lang: &str, key: &str) -> Option<&str> {
+        [lang, self.default.as_str(), BUILTIN_LANGUAGE]
+            .into_iter()
+            .find_map(|code| self.catalogs.get(code)?.get(key))
+            .map(String::as_str)
+    }
+
+    /// A message in a client's language. Unknown keys are shown as-is so
+    /// they're easy to spot.
+    pub fn tr(&self, client: Entity, key: &str, args: &Args) -> Text {
+        self.tr_in(self.locale(client), key, args)
+    }
+
+    /// A message in the server's default language, for the console and the
+    /// server list.
+    pub fn tr_default(&self, key: &str, args: &Args) -> Text {
+        self.tr_in(&self.default, key, args)
+    }
+
+    fn tr_in(&self, lang: &str, key: &str, args: &Args) -> Text {
+        let template = self.template(lang, key).unwrap_or(key);
+        legacy_text(&fill_placeholders(template, args))
+    }
+
+    /// Like [`tr`](Self::tr), but for config values that may be either a
+    /// message key or a literal template.
+    pub fn text(&self, client: Entity, key_or_template: &str, args: &Args) -> Text {
+        let template = self
+            .template(self.locale(client), key_or_template)
+            .unwrap_or(key_or_template);
+        legacy_text(&fill_placeholders(template, args))
+    }
+
+    /// Like [`text`](Self::text), in the server's default language.
+    pub fn text_default(&self, key_or_template: &str, args: &Args) -> Text {
+        let template = self
+            .template(&self.default, key_or_template)
+            .unwrap_or(key_or_template);
+        legacy_text(&fill_placeholders(template, args))
+    }
+
+    /// A message in a client's language as a plain string, for text that's
+    /// built up before being styled.
+    pub fn plain(&self, client: Entity, key: &str) -> &str {
+        self.template(self.locale(client), key).unwrap_or(key)
+    }
+
+    /// Like [`plain`](Self::plain), in the server's default language.
+    pub fn plain_default(&self, key: &str) -> &str {
+        self.template(&self.default, key).unwrap_or(key)
+    }
+}
+
+/// Flattens a TOML message file into dotted keys, so `[join] message = ..`
+/// becomes `join.message`.
+fn parse_catalog(contents: &str) -> anyhow::Result<HashMap<String, String>> {
+    fn flatten(prefix: &str, table: toml::value::Table, out: &mut HashMap<String, String>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{prefix}.{key}")
+            };
+
+            match value {
+                toml::Value::Table(table) => flatten(&key, table, out),
+                toml::Value::String(message) => {
+                    out.insert(key, message);
+                }
+                other => warn!("Ignoring message {key}: expected a string, found {other}"),
+            }
+        }
+    }
+
+    let table: toml::value::Table = toml::from_str(contents)?;
+    let mut out = HashMap::new();
+    flatten("", table, &mut out);
+    Ok(out)
+}
+
+fn language_code(path: &Path) -> Option<String> {
+    Some(path.file_stem()?.to_str()?.to_ascii_lowercase())
+}
+
+pub struct LangPlugin;
+
+impl Plugin for LangPlugin {
+    fn build(&self, app: &mut App) {
+        let lang = match Lang::load(&app.world.resource::<Config>().lang) {
+            Ok(lang) => lang,
+            Err(e) => {
+                warn!("Failed to load languages, using built-in messages: {e:#}");
+                Lang::builtin()
+            }
+        };
+        info!("Loaded languages: {}", lang.languages().join(", "));
+
+        app.insert_resource(lang)
+            .add_command(LANG)
+            .add_system(init_locales)
+            .add_system_to_stage(EventLoop, detect_locales)
+            .add_system_to_stage(EventLoop, lang_command)
+            .add_client_registry::<Lang>();
+    }
+}
+
+fn init_locales(
+    clients: Query<(Entity, &Client), Added<Client>>,
+    mut store: ResMut<PlayerDataStore>,
+    mut lang: ResMut<Lang>,
+) {
+    for (entity, client) in &clients {
+        if let Some(code) = store.get(client.uuid()).language.clone() {
+            if lang.has_language(&code) {
+                lang.set_locale(entity, code);
+            }
+        }
+    }
+}
+
+/// Uses the client's own language until the player picks one with `/lang`.
+fn detect_locales(
+    clients: Query<&Client>,
+    mut store: ResMut<PlayerDataStore>,
+    mut lang: ResMut<Lang>,
+    mut events: EventReader<ClientSettings>,
+) {
+    for event in events.iter() {
+        let Ok(client) = clients.get(event.client) else {
+            continue;
+        };
+
+        if store.get(client.uuid()).language.is_some() {
+            continue;
+        }
+
+        let code = event.locale.to_ascii_lowercase();
+        if lang.has_language(&code) {
+            lang.set_locale(event.client, code);
+        }
+    }
+}
+
+fn lang_command(
+    mut clients: Query<&mut Client>,
+    mut store: ResMut<PlayerDataStore>,
+    mut lang: ResMut<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(LANG.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let available = lang.languages().join(", ");
+
+        match event.args.as_slice() {
+            [] => {
+                let current = lang.locale(event.sender).to_owned();
+                client.send_message(lang.tr(
+                    event.sender,
+                    "lang.current",
+                    &[("lang", &current), ("available", &available)],
+                ));
+            }
+            [code] => {
+                let code = code.to_ascii_lowercase();
+                if !lang.has_language(&code) {
+                    client.send_message(lang.tr(
+                        event.sender,
+                        "lang.unknown",
+                        &[("lang", &code), ("available", &available)],
+                    ));
+                    continue;
+                }
+
+                store.get(client.uuid()).language = Some(code.clone());
+                store.save(client.uuid());
+                lang.set_locale(event.sender, code.clone());
+                client.send_message(lang.tr(event.sender, "lang.set", &[("lang", &code)]));
+            }
+            _ => client.send_message(usage(&lang, event.sender, &LANG)),
+        }
+    }
+}
+
+impl ClientRegistry for Lang {
+    fn forget(&mut self, client: Entity, _uuid: Uuid) {
+        self.locales.remove(&client);
+    }
+
+    fn entries(&self, client: Entity, _uuid: Uuid) -> usize {
+        usize::from(self.locales.contains_key(&client))
+    }
+}
@@ -0,0 +1,4 @@
+pub mod chunk_view;
+pub mod disconnect;
+pub mod edits;
+pub mod placement;
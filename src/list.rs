@@ -0,0 +1,167 @@
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::nick::DisplayName;
+use crate::permissions::Permissions;
+use crate::WorldName;
+
+const LIST: CommandInfo = CommandInfo {
+    name: "list",
+    aliases: &["online", "who"],
+    usage: "/list [page]",
+    description: "Show who's online, by rank.",
+    permission: None,
+    console: true,
+};
+
+const PAGE_SIZE: usize = 60;
+
+pub struct ListPlugin;
+
+impl Plugin for ListPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(LIST)
+            .add_system_to_stage(EventLoop, list_command);
+    }
+}
+
+struct Entry {
+    username: String,
+    display: Option<Text>,
+    group: String,
+    weight: i32,
+    world: String,
+}
+
+/// One player's name, which shows their rank and world when hovered and
+/// visits them when clicked.
+fn entry_text(lang: &Lang, sender: Entity, entry: &Entry) -> Text {
+    let name = entry
+        .display
+        .clone()
+        .unwrap_or_else(|| entry.username.clone().into_text());
+
+    name.on_hover_show_text(lang.tr(
+        sender,
+        "list.hover",
+        &[("group", &entry.group), ("world", &entry.world)],
+    ))
+    .on_click_run_command(format!("/plot visit {}", entry.username))
+}
+
+/// A page of players, each rank on its own line. Consoles get plain names.
+fn player_list(
+    lang: &Lang,
+    sender: Entity,
+    interactive: bool,
+    entries: &[Entry],
+    max_players: usize,
+    page: usize,
+) -> Text {
+    let pages = ((entries.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    if page == 0 || page > pages {
+        return lang.tr(sender, "list.no_page", &[("pages", &pages)]);
+    }
+
+    let mut out = lang.tr(
+        sender,
+        "list.header",
+        &[("online", &entries.len()), ("max", &max_players)],
+    );
+    if pages > 1 {
+        out = out + " " + lang.tr(sender, "list.page", &[("page", &page), ("pages", &pages)]);
+    }
+
+    let shown = &entries[(page - 1) * PAGE_SIZE..entries.len().min(page * PAGE_SIZE)];
+    let mut group = None;
+    for entry in shown {
+        if group != Some(&entry.group) {
+            group = Some(&entry.group);
+            out = out + "\n" + lang.tr(sender, "list.group", &[("group", &entry.group)]);
+        } else {
+            out = out + ", ".color(Color::GRAY);
+        }
+
+        out = if interactive {
+            out + entry_text(lang, sender, entry)
+        } else {
+            out + entry.username.as_str()
+        };
+    }
+
+    if page < pages {
+        let next = format!("/list {}", page + 1);
+        out = out
+            + "\n"
+            + lang
+                .tr(sender, "list.more", &[("command", &next)])
+                .on_click_run_command(next);
+    }
+
+    out
+}
+
+fn list_command(
+    mut clients: Query<(&mut Client, Option<&DisplayName>)>,
+    mut consoles: Query<&mut Console>,
+    worlds: Query<&WorldName>,
+    permissions: Res<Permissions>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(LIST.name)) {
+        let page = match event.args.as_slice() {
+            [] => Ok(1),
+            [page] => page.parse::<usize>().map_err(|_| ()),
+            _ => Err(()),
+        };
+
+        let reply = match page {
+            Ok(page) => {
+                let mut entries: Vec<_> = clients
+                    .iter()
+                    .filter(|(client, _)| !client.is_disconnected())
+                    .map(|(client, display)| {
+                        let group = permissions.group_of(client.uuid()).to_owned();
+                        Entry {
+                            username: client.username().to_string(),
+                            display: display.map(|d| d.0.clone()),
+                            weight: permissions.weight(&group),
+                            group,
+                            world: worlds
+                                .get(client.instance())
+                                .map_or_else(|_| "?".to_owned(), |w| w.0.clone()),
+                        }
+                    })
+                    .collect();
+
+                // Highest rank first, then by name.
+                entries.sort_by(|a, b| {
+                    b.weight
+                        .cmp(&a.weight)
+                        .then_with(|| a.group.cmp(&b.group))
+                        .then_with(|| a.username.to_lowercase().cmp(&b.username.to_lowercase()))
+                });
+
+                player_list(
+                    &lang,
+                    event.sender,
+                    clients.contains(event.sender),
+                    &entries,
+                    config.server.max_players,
+                    page,
+                )
+            }
+            Err(()) => usage(&lang, event.sender, &LIST),
+        };
+
+        if let Ok((mut client, _)) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tracing::{info_span, Level, Span};
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::filter::{filter_fn, EnvFilter, FilterExt, Targets};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::Registry;
+use valence::prelude::*;
+
+use crate::ban::now_secs;
+use crate::format::format_date;
+use crate::timings::{is_system_span, Timings};
+
+/// The target of chat and command audit lines, which also go to `chat.log`.
+pub const AUDIT: &str = "chat";
+
+/// How log lines are laid out on the console and in `latest.log`.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LogFormat {
+    /// One line per event, with the time, level, spans and target.
+    #[default]
+    Full,
+    /// Several lines per event, with the source location.
+    Pretty,
+    /// One short line per event.
+    Compact,
+    /// One JSON object per line, with the event's and its spans' fields as
+    /// keys, for log collectors.
+    Json,
+}
+
+/// Where and how much to log, from the command line, as logging starts
+/// before the config file is read.
+pub struct LogSettings {
+    /// A filter like `info` or `plotsirv=debug,valence=warn` for the console.
+    /// Falls back to `RUST_LOG`, then `info`.
+    pub level: Option<String>,
+    /// The same, for `latest.log`.
+    pub file_level: String,
+    pub format: LogFormat,
+    /// Where log files go, or `None` to only log to the console.
+    pub directory: Option<PathBuf>,
+    /// Logs to stderr rather than stdout, which is left for output.
+    pub stderr: bool,
+}
+
+/// Keeps the log file writers' background threads going. Dropping the last
+/// clone, or flushing any of them, writes out whatever is still queued.
+/// Clones are for threads that may have to exit without the app's.
+#[derive(Resource, Clone, Default)]
+pub struct LogGuards(Arc<Mutex<Vec<WorkerGuard>>>);
+
+impl LogGuards {
+    pub fn flush(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Exits the process once the logs are written out. Exiting skips
+    /// destructors, so without this the lines explaining why are lost.
+    pub fn exit(&self, code: i32) -> ! {
+        self.flush();
+        std::process::exit(code)
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Sets up logging to the console and, unless turned off, to `latest.log`
+/// and `chat.log`, each with its own filter. `chat.log` is always plain
+/// text, for people to read. `timings` gets the spans around systems, which
+/// are left out of the logs.
+pub fn init(settings: &LogSettings, timings: &Timings) -> anyhow::Result<LogGuards> {
+    let console_filter = match &settings.level {
+        Some(level) => EnvFilter::try_new(level).context("invalid --log-level")?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let console = if settings.stderr {
+        format_layer(settings.format, std::io::stderr, true)
+    } else {
+        format_layer(settings.format, std::io::stdout, true)
+    };
+    let mut layers = vec![
+        timings.layer().boxed(),
+        console
+            .with_filter(console_filter.and(filter_fn(|m| !is_system_span(m))))
+            .boxed(),
+    ];
+
+    let mut guards = vec![];
+    if let Some(dir) = &settings.directory {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+        let file_filter =
+            EnvFilter::try_new(&settings.file_level).context("invalid --log-file-level")?;
+        let (latest, guard) = open_writer(dir, "latest")?;
+        guards.push(guard);
+        layers.push(
+            format_layer(settings.format, latest, false)
+                .with_filter(file_filter.and(filter_fn(|m| !is_system_span(m))))
+                .boxed(),
+        );
+
+        let (chat_log, guard) = open_writer(dir, "chat")?;
+        guards.push(guard);
+        layers.push(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_target(false)
+                .with_writer(chat_log)
+                .with_filter(Targets::new().with_target(AUDIT, Level::INFO))
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry().with(layers).init();
+
+    Ok(LogGuards(Arc::new(Mutex::new(guards))))
+}
+
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        // Only the innermost span, as every span this server makes is a
+        // player's.
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
+/// A span for handling something a player did, so everything logged under
+/// it can be told apart by their UUID.
+pub fn player_span(client: &Client) -> Span {
+    info_span!("player", name = %client.username(), uuid = %client.uuid())
+}
+
+/// Opens a log file whose writes happen on a background thread, so a slow
+/// disk never holds up a tick. Nothing is dropped if the queue fills up.
+fn open_writer(
+    dir: &Path,
+    name: &'static str,
+) -> anyhow::Result<(tracing_appender::non_blocking::NonBlocking, WorkerGuard)> {
+    let log = DailyLog::open(dir, name)
+        .with_context(|| format!("failed to open {}", dir.join(name).display()))?;
+    Ok(NonBlockingBuilder::default().lossy(false).finish(log))
+}
+
+/// A log file that is gzipped away as `<name>-<date>.log.gz` when the day
+/// changes, so the current day's is always at `<name>.log`.
+struct DailyLog {
+    dir: PathBuf,
+    name: &'static str,
+    day: String,
+    file: File,
+}
+
+impl DailyLog {
+    fn open(dir: &Path, name: &'static str) -> io::Result<Self> {
+        let path = dir.join(format!("{name}.log"));
+        let today = format_date(now_secs());
+
+        // One left over from an earlier run belongs to the day it was last
+        // written to.
+        if let Ok(metadata) = fs::metadata(&path) {
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let day = format_date(modified);
+            if day != today && metadata.len() > 0 {
+                archive(dir, name, &day)?;
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            name,
+            day: today,
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+        })
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        let today = format_date(now_secs());
+        if today == self.day {
+            return Ok(());
+        }
+
+        self.file.flush()?;
+        archive(&self.dir, self.name, &self.day)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(format!("{}.log", self.name)))?;
+        self.day = today;
+        Ok(())
+    }
+}
+
+impl Write for DailyLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The writer thread is the only one that gets here, so this is the
+        // only place the file changes.
+        if let Err(e) = self.roll() {
+            eprintln!("Failed to rotate {}.log: {e}", self.name);
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Compresses `<name>.log` into the first free `<name>-<day>[-n].log.gz` and
+/// removes it.
+fn archive(dir: &Path, name: &str, day: &str) -> io::Result<()> {
+    let path = dir.join(format!("{name}.log"));
+    let mut target = dir.join(format!("{name}-{day}.log.gz"));
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(format!("{name}-{day}-{n}.log.gz"));
+        n += 1;
+    }
+
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    io::copy(&mut File::open(&path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
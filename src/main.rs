@@ -1,276 +1,972 @@
-use clap::{Parser, ValueEnum};
-use tracing::{info, warn};
+mod afk;
+mod announcements;
+mod armor_stands;
+mod ban;
+mod block_log;
+mod block_sync;
+mod border;
+mod boss_bar;
+mod broadcast;
+mod chunks;
+mod command;
+mod config;
+mod connection_limit;
+mod console;
+mod debug;
+mod drops;
+mod falling_blocks;
+mod fire;
+mod fluids;
+mod fly;
+mod format;
+mod game_mode;
+mod hazards;
+mod health;
+mod help;
+mod hud;
+mod inspect;
+mod item_frames;
+mod items;
+mod join_leave;
+mod lang;
+mod list;
+mod logging;
+mod maintenance;
+mod msg;
+mod mute;
+mod nick;
+mod paintings;
+mod permissions;
+mod persistence;
+mod player_data;
+mod probes;
+mod profiles;
+mod rate_limit;
+mod rcon;
+mod reload;
+mod resource_pack;
+mod runtime;
+mod seen;
+mod sessions;
+mod shutdown;
+mod sidebar;
+mod skin;
+mod sound;
+mod spawn;
+mod status;
+mod systemd;
+mod tablist;
+mod teleport;
+mod time;
+mod timings;
+mod tnt;
+mod tps;
+mod view_distance;
+mod void;
+mod weather;
+mod welcome;
+mod whitelist;
+mod worlds;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use plotsirv::disconnect::{AddDisconnectSystem, ClientRegistry, DisconnectLabel};
+use plotsirv::edits::edited_instance;
+use plotsirv::placement::{
+    decide_placement, resync_slot, settle_held_item, Click, Placement, Rejection,
+};
+use tracing::{error, info, warn};
 use valence::client::despawn_disconnected_clients;
 use valence::client::event::{
-    default_event_handler, FinishDigging, StartDigging, StartSneaking, UseItemOnBlock, ChatMessage,
+    default_event_handler, FinishDigging, StartDigging, UseItemOnBlock,
 };
 use valence::prelude::*;
+use valence_protocol::packets::s2c::play::DisconnectPlay;
 use valence_protocol::types::Hand;
+use valence_protocol::BlockFace;
+
+use crate::afk::AfkPlugin;
+use crate::announcements::AnnouncementsPlugin;
+use crate::armor_stands::ArmorStandsPlugin;
+use crate::ban::{BanList, BanPlugin, SharedBans};
+use crate::block_log::{Actor, BlockChanged, BlockLogPlugin};
+use crate::block_sync::{resend_block, BlockSyncPlugin};
+use crate::border::{inside_border, BorderPlugin, WorldBorder};
+use crate::boss_bar::BossBarPlugin;
+use crate::broadcast::BroadcastPlugin;
+use crate::chunks::ChunksPlugin;
+use crate::command::CommandPlugin;
+use crate::config::{Config, ConfigConnectionMode, Overrides, Secret};
+use crate::connection_limit::{ConnectionLimitPlugin, SharedConnectionLimits};
+use crate::console::ConsolePlugin;
+use crate::debug::DebugPlugin;
+use crate::drops::DropsPlugin;
+use crate::falling_blocks::FallingBlocksPlugin;
+use crate::fire::FirePlugin;
+use crate::fluids::FluidsPlugin;
+use crate::fly::{max_speed, Flight, FlyPlugin, FLY_PERMISSION};
+use crate::game_mode::GameModePlugin;
+use crate::hazards::HazardsPlugin;
+use crate::health::HealthPlugin;
+use crate::help::HelpPlugin;
+use crate::hud::{Hud, HudPlugin};
+use crate::inspect::{InspectPlugin, Inspecting};
+use crate::item_frames::ItemFramesPlugin;
+use crate::items::ItemsPlugin;
+use crate::join_leave::JoinLeavePlugin;
+use crate::lang::{Lang, LangPlugin};
+use crate::list::ListPlugin;
+use crate::logging::{player_span, LogFormat, LogSettings, AUDIT};
+use crate::maintenance::MaintenancePlugin;
+use crate::msg::MsgPlugin;
+use crate::mute::{MutePlugin, Mutes};
+use crate::nick::{DisplayName, NickPlugin};
+use crate::paintings::PaintingsPlugin;
+use crate::permissions::{Permissions, PermissionsPlugin};
+use crate::persistence::PersistencePlugin;
+use crate::player_data::{PlayerDataPlugin, PlayerDataStore};
+use crate::probes::ProbesPlugin;
+use crate::profiles::ProfilesPlugin;
+use crate::rate_limit::{limit_chat, PlayerChat, RateLimitPlugin};
+use crate::rcon::RconPlugin;
+use crate::reload::ReloadPlugin;
+use crate::resource_pack::ResourcePackPlugin;
+use crate::seen::SeenPlugin;
+use crate::sessions::SessionsPlugin;
+use crate::shutdown::ShutdownPlugin;
+use crate::sidebar::{Sidebar, SidebarPlugin};
+use crate::skin::SkinPlugin;
+use crate::sound::{Feedback, FeedbackSound, SoundPlugin};
+use crate::spawn::{send_to_spawn, SpawnPlugin};
+use crate::status::{Callbacks, SharedStatus, StatusPlugin};
+use crate::systemd::SystemdPlugin;
+use crate::tablist::TabListPlugin;
+use crate::teleport::TeleportPlugin;
+use crate::time::TimePlugin;
+use crate::timings::{Timings, TimingsPlugin};
+use crate::tnt::TntPlugin;
+use crate::tps::TpsPlugin;
+use crate::view_distance::ViewDistancePlugin;
+use crate::void::VoidPlugin;
+use crate::weather::WeatherPlugin;
+use crate::welcome::WelcomePlugin;
+use crate::whitelist::{SharedWhitelist, Whitelist, WhitelistPlugin};
+use crate::worlds::{check_spawn_world, Worlds, WorldsPlugin, STARTUP_WORLDS};
 
 const SPAWN_Y: i32 = 64;
 
-#[derive(ValueEnum, Clone, Debug)]
-enum CliConnectionMode {
-    Online,
-    Offline,
-    Bungeecord,
-    Velocity,
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+/// Records every allocation while the server runs, written out to
+/// `dhat-heap.json` when it's dropped on shutdown.
+#[cfg(feature = "dhat-heap")]
+pub static HEAP_PROFILER: std::sync::Mutex<Option<dhat::Profiler>> = std::sync::Mutex::new(None);
+
+const SECRET_VAR: &str = "PLOTSIRV_VELOCITY_SECRET";
+
+/// The off hand's slot in a player's inventory.
+const OFF_HAND_SLOT: u16 = 45;
+
+/// How long a player who joined while the spawn world was missing waits for
+/// it before being disconnected.
+const SPAWN_WAIT: Duration = Duration::from_secs(10);
+
+/// The name an instance is referred to by in config and commands.
+#[derive(Component, Clone, Debug)]
+pub struct WorldName(pub String);
+
+/// Disconnects a client, showing them the reason.
+pub fn kick(client: &mut Client, reason: impl Into<Text>) {
+    client.write_packet(&DisconnectPlay {
+        reason: Cow::Owned(reason.into()),
+    });
+    client.disconnect();
 }
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// The socket the server will listen for connections on.
+    /// The socket the server will listen for connections on. Overrides the
+    /// config file.
     #[arg(short, long)]
     address: Option<std::net::SocketAddr>,
 
-    // The method the server will use to authenticate to clients.
+    /// The method the server will use to authenticate to clients. Overrides
+    /// the config file.
     #[arg(short, long)]
-    connection_mode: Option<CliConnectionMode>,
+    connection_mode: Option<ConfigConnectionMode>,
 
-    /// Velocity encryption secret.
+    /// Velocity encryption secret. Deprecated, as it shows up in process
+    /// lists and shell history; the `PLOTSIRV_VELOCITY_SECRET` environment
+    /// variable beats it, and `--secret-file` beats both. Any of them
+    /// overrides the config file.
     #[arg(short, long)]
-    secret: Option<std::sync::Arc<str>>,
+    secret: Option<String>,
+
+    /// A file holding the Velocity encryption secret, with any trailing
+    /// newline ignored.
+    #[arg(long)]
+    secret_file: Option<std::path::PathBuf>,
+
     /// When in onine mode, validate the client's IP address on the Yggdrasil
     /// server.
     #[arg(short, long)]
     prevent_proxy_connections: bool,
+
+    /// Compress packets at least this many bytes long, or -1 for no
+    /// compression. Overrides the config file.
+    #[arg(long, allow_negative_numbers = true)]
+    compression_threshold: Option<i32>,
+
+    /// The most players allowed online at once. Overrides the config file.
+    #[arg(long)]
+    max_players: Option<usize>,
+
+    /// Only let whitelisted players join, whatever the config file says.
+    #[arg(long)]
+    whitelist: bool,
+
+    /// Start in maintenance mode, whatever the config file says.
+    #[arg(long)]
+    maintenance: bool,
+
+    /// How many threads run async tasks, like networking. Overrides the
+    /// config file.
+    #[arg(long)]
+    worker_threads: Option<usize>,
+
+    /// Run all async tasks on one thread, for debugging.
+    #[arg(long)]
+    single_thread: bool,
+
+    /// Path to the configuration file.
+    #[arg(long, default_value = config::DEFAULT_PATH)]
+    config: std::path::PathBuf,
+
+    /// What to log to the console, like `debug` or `info,valence=warn`.
+    /// Defaults to `RUST_LOG`, or `info` if that isn't set.
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// What to log to `latest.log`, in the same form as `--log-level`.
+    #[arg(long, default_value = "info")]
+    log_file_level: String,
+
+    /// How log lines are laid out. `json` is for log collectors.
+    #[arg(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Where log files are written.
+    #[arg(long, default_value = "logs")]
+    log_dir: std::path::PathBuf,
+
+    /// Only log to the console.
+    #[arg(long)]
+    no_log_files: bool,
+
+    /// Check the config, the spawn world and the permission, ban, mute,
+    /// whitelist and language files, then exit without starting the server.
+    #[arg(long)]
+    check: bool,
+
+    /// Print the config the server would run with, after the environment
+    /// and command line are applied and with secrets hidden, then exit.
+    #[arg(long)]
+    print_config: bool,
 }
 
 pub fn main() {
+    #[cfg(feature = "dhat-heap")]
+    HEAP_PROFILER
+        .lock()
+        .unwrap()
+        .replace(dhat::Profiler::new_heap());
+
     let cli = Args::parse();
-    let connection_mode = match cli.connection_mode.unwrap_or(CliConnectionMode::Online) {
-        CliConnectionMode::Online => ConnectionMode::Online {
-            prevent_proxy_connections: cli.prevent_proxy_connections,
+    // Checking and printing leave the log files alone, and keep stdout for
+    // the config.
+    let offline = cli.check || cli.print_config;
+    let timings = Timings::default();
+    let log_guards = match logging::init(
+        &LogSettings {
+            level: cli.log_level.clone(),
+            file_level: cli.log_file_level.clone(),
+            format: cli.log_format,
+            directory: (!cli.no_log_files && !offline).then(|| cli.log_dir.clone()),
+            stderr: offline,
         },
-        CliConnectionMode::Offline => ConnectionMode::Offline,
-        CliConnectionMode::Bungeecord => ConnectionMode::BungeeCord,
-        CliConnectionMode::Velocity => {
-            let secret = cli.secret.expect("Velocity encryption secret is required");
-            ConnectionMode::Velocity { secret }
+        &timings,
+    ) {
+        Ok(guards) => guards,
+        Err(e) => {
+            eprintln!("Failed to start logging: {e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let velocity_secret = match velocity_secret(&cli) {
+        Ok(secret) => secret,
+        Err(e) => {
+            error!("Failed to read the Velocity secret: {e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let overrides = Overrides {
+        address: cli.address,
+        connection_mode: cli.connection_mode,
+        velocity_secret,
+        prevent_proxy_connections: cli.prevent_proxy_connections,
+        compression_threshold: cli.compression_threshold,
+        max_players: cli.max_players,
+        worker_threads: cli.worker_threads,
+        single_thread: cli.single_thread,
+        whitelist: cli.whitelist,
+        maintenance: cli.maintenance,
+    };
+    let config = match Config::load(&cli.config, overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load config: {e:#}");
+            log_guards.exit(1);
         }
     };
-    tracing_subscriber::fmt().init();
-    let mut server_plugin = ServerPlugin::new(()).with_connection_mode(connection_mode);
 
-    if let Some(address) = cli.address {
-        server_plugin = server_plugin.with_address(address);
+    if cli.print_config {
+        match config.to_redacted_toml() {
+            Ok(toml) => print!("{toml}"),
+            Err(e) => {
+                error!("Failed to print the config: {e:#}");
+                log_guards.exit(1);
+            }
+        }
+        return;
+    }
+
+    if cli.check {
+        log_guards.exit(if check_files(&config) { 0 } else { 1 });
+    }
+
+    if let Err(e) = check_spawn_world(&config) {
+        error!("{e:#}");
+        log_guards.exit(1);
     }
 
-    info!("Starting server on {}", server_plugin.address);
+    let whitelist = match Whitelist::load(&config.whitelist) {
+        Ok(whitelist) => SharedWhitelist::new(whitelist),
+        Err(e) => {
+            error!("Failed to load whitelist: {e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let permissions = match Permissions::load() {
+        Ok(permissions) => permissions,
+        Err(e) => {
+            error!("Failed to load permissions: {e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let bans = match BanList::load() {
+        Ok(bans) => SharedBans::new(bans),
+        Err(e) => {
+            error!("Failed to load bans: {e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let mutes = match Mutes::load() {
+        Ok(mutes) => mutes,
+        Err(e) => {
+            error!("Failed to load mutes: {e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let status = SharedStatus::default();
+    let limits = SharedConnectionLimits::new(&config.connection_limit);
+    let callbacks = match Callbacks::new(
+        status.clone(),
+        whitelist.clone(),
+        bans.clone(),
+        limits.clone(),
+        &config.motd,
+    ) {
+        Ok(callbacks) => callbacks,
+        Err(e) => {
+            error!("{e:#}");
+            log_guards.exit(1);
+        }
+    };
+
+    let runtime = match runtime::start(&config.runtime) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start the async runtime: {e}");
+            log_guards.exit(1);
+        }
+    };
+
+    let server_plugin = ServerPlugin::new(callbacks)
+        .with_tokio_handle(Some(runtime))
+        .with_connection_mode(config.server.connection_mode())
+        .with_address(config.server.address)
+        .with_compression_threshold(config.server.compression_threshold());
+
+    // Valence binds its listener in the background and only logs it if that
+    // fails, leaving a server nobody can join. Binding here first means a
+    // taken or invalid address stops startup with the reason.
+    if let Err(e) = std::net::TcpListener::bind(server_plugin.address) {
+        error!("Failed to bind {}: {e}", server_plugin.address);
+        log_guards.exit(1);
+    }
+
+    info!(address = %server_plugin.address, "Starting server");
+    match server_plugin.compression_threshold {
+        Some(threshold) => info!("Compressing packets of {threshold} bytes or more"),
+        None => info!("Packet compression is off"),
+    }
 
-    // let server_plugin =
-    // server_plugin.with_tokio_handle(Some(tokio::runtime::Handle::current()));
     // let server_plugin = server_plugin.with_max_connections(1024);
 
+    let sneak_toggle = config.server.sneak_toggles_game_mode;
+
     App::new()
+        .insert_resource(log_guards)
+        .insert_resource(timings)
+        .insert_resource(config)
+        .insert_resource(status)
+        .insert_resource(limits)
+        .insert_resource(whitelist)
+        .insert_resource(bans)
+        .insert_resource(mutes)
+        .insert_resource(permissions)
         .add_plugin(server_plugin)
-        .add_system_to_stage(EventLoop, handle_message_events)
+        .add_plugin(TpsPlugin)
+        .add_plugin(TimingsPlugin)
+        .add_plugin(DebugPlugin)
+        .add_plugin(CommandPlugin)
+        .add_plugin(LangPlugin)
+        .add_plugin(PermissionsPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_plugin(RconPlugin)
+        .add_plugin(ProbesPlugin)
+        .add_plugin(SystemdPlugin)
+        .add_plugin(ShutdownPlugin)
+        .add_plugin(PersistencePlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(ReloadPlugin)
+        .add_plugin(StatusPlugin)
+        .add_plugin(ConnectionLimitPlugin)
+        .add_plugin(ProfilesPlugin)
+        .add_plugin(WorldsPlugin)
+        .add_plugin(ChunksPlugin)
+        .add_plugin(WhitelistPlugin)
+        .add_plugin(MaintenancePlugin)
+        .add_plugin(BanPlugin)
+        .add_plugin(MutePlugin)
+        .add_plugin(RateLimitPlugin)
+        .add_plugin(PlayerDataPlugin)
+        .add_plugin(SessionsPlugin)
+        .add_plugin(SeenPlugin)
+        .add_plugin(ListPlugin)
+        .add_plugin(FlyPlugin)
+        .add_plugin(TeleportPlugin)
+        .add_plugin(SpawnPlugin)
+        .add_plugin(ViewDistancePlugin)
+        .add_plugin(TabListPlugin)
+        .add_plugin(SidebarPlugin)
+        .add_plugin(BossBarPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(SkinPlugin)
+        .add_plugin(ResourcePackPlugin)
+        .add_plugin(HealthPlugin)
+        .add_plugin(HazardsPlugin)
+        .add_plugin(VoidPlugin)
+        .add_plugin(TimePlugin)
+        .add_plugin(WeatherPlugin)
+        .add_plugin(BorderPlugin)
+        .add_plugin(BlockLogPlugin)
+        .add_plugin(BlockSyncPlugin)
+        .add_plugin(InspectPlugin)
+        .add_plugin(JoinLeavePlugin)
+        .add_plugin(WelcomePlugin)
+        .add_plugin(MsgPlugin)
+        .add_plugin(BroadcastPlugin)
+        .add_plugin(AnnouncementsPlugin)
+        .add_plugin(HelpPlugin)
+        .add_plugin(NickPlugin)
+        .add_plugin(AfkPlugin)
+        .add_plugin(ItemsPlugin)
+        .add_plugin(DropsPlugin)
+        .add_plugin(FallingBlocksPlugin)
+        .add_plugin(ArmorStandsPlugin)
+        .add_plugin(ItemFramesPlugin)
+        .add_plugin(PaintingsPlugin)
+        .add_plugin(TntPlugin)
+        .add_plugin(FirePlugin)
+        .add_plugin(FluidsPlugin)
+        .add_plugin(GameModePlugin { sneak_toggle })
+        .add_system_to_stage(EventLoop, handle_message_events.after(limit_chat))
         .add_system_to_stage(EventLoop, default_event_handler)
         .add_system_to_stage(EventLoop, digging_creative_mode)
         .add_system_to_stage(EventLoop, digging_survival_mode)
+        .init_resource::<Placing>()
+        .init_resource::<UsedClicks>()
         .add_system_to_stage(EventLoop, place_blocks)
+        .add_system_to_stage(CoreStage::Last, forget_used_clicks)
+        .add_client_registry::<Placing>()
         .add_system_set(PlayerList::default_system_set())
         .add_startup_system(setup)
         .add_system(init_clients)
-        .add_system(despawn_disconnected_clients)
+        .add_system(place_waiting_clients.after(init_clients))
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            despawn_disconnected_clients.label(DisconnectLabel::Despawn),
+        )
         .run();
 }
 
-fn setup(world: &mut World) {
-    let mut instance = world
-        .resource::<Server>()
-        .new_instance(DimensionId::default());
+/// Loads everything else the server reads at startup and checks the spawn
+/// world is one it makes, reporting every problem rather than stopping at the
+/// first.
+fn check_files(config: &Config) -> bool {
+    let results = [
+        ("the whitelist", Whitelist::load(&config.whitelist).map(drop)),
+        ("permissions", Permissions::load().map(drop)),
+        ("bans", BanList::load().map(drop)),
+        ("mutes", Mutes::load().map(drop)),
+        ("languages", Lang::load(&config.lang).map(drop)),
+    ];
+
+    let mut ok = true;
+    for (what, result) in results {
+        if let Err(e) = result {
+            error!("Failed to load {what}: {e:#}");
+            ok = false;
+        }
+    }
+    if let Err(e) = check_spawn_world(config) {
+        error!("{e:#}");
+        ok = false;
+    }
+
+    if ok {
+        info!("{} and the files it uses are valid", config.path.display());
+    }
+    ok
+}
+
+/// The Velocity secret from `--secret`, the environment or `--secret-file`,
+/// with the later ones winning.
+fn velocity_secret(cli: &Args) -> anyhow::Result<Option<Secret>> {
+    let mut secret = cli.secret.clone();
+    if secret.is_some() {
+        warn!(
+            "--secret is deprecated, as it shows the secret to anyone who can list \
+             processes; use {SECRET_VAR} or --secret-file instead"
+        );
+    }
 
-    for z in -5..5 {
-        for x in -5..5 {
-            instance.insert_chunk([x, z], Chunk::default());
+    if let Ok(var) = std::env::var(SECRET_VAR) {
+        if !var.is_empty() {
+            secret = Some(var);
         }
     }
 
-    for z in -25..25 {
-        for x in -25..25 {
-            instance.set_block([x, SPAWN_Y, z], BlockState::GRASS_BLOCK);
+    if let Some(path) = &cli.secret_file {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let contents = contents.trim_end_matches(|c: char| c == '\n' || c == '\r');
+        if contents.is_empty() {
+            bail!("{} is empty", path.display());
         }
+        secret = Some(contents.to_owned());
     }
 
-    world.spawn(instance);
+    Ok(secret.map(Secret))
+}
+
+fn setup(world: &mut World) {
+    for name in STARTUP_WORLDS {
+        // Chunks are made as players come near them.
+        let instance = world
+            .resource::<Server>()
+            .new_instance(DimensionId::default());
+
+        world.spawn((instance, WorldName(name.into())));
+    }
 }
 
 fn init_clients(
-    mut clients: Query<&mut Client, Added<Client>>,
-    instances: Query<Entity, With<Instance>>,
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client), Added<Client>>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    mut store: ResMut<PlayerDataStore>,
+    permissions: Res<Permissions>,
+) {
+    for (entity, mut client) in &mut clients {
+        let fly = permissions.has_permission(client.uuid(), FLY_PERMISSION);
+        let max = max_speed(&permissions, client.uuid());
+        let data = store.get(client.uuid());
+
+        let mut entity = commands.entity(entity);
+        entity.insert((
+            Flight::new(data.fly && fly)
+                .with_speeds(data.fly_speed.min(max), data.walk_speed.min(max)),
+            Sidebar::new(data.sidebar),
+            Hud::new(data.hud),
+        ));
+        if worlds.get(&config.spawn.world).is_none()
+            || !send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances)
+        {
+            entity.insert(AwaitingSpawn {
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+/// A player who joined while the spawn world wasn't there to put them in.
+#[derive(Component)]
+struct AwaitingSpawn {
+    since: Instant,
+}
+
+/// Puts waiting players in the spawn world once it's there, or disconnects
+/// them if it doesn't turn up.
+fn place_waiting_clients(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &AwaitingSpawn)>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    lang: Res<Lang>,
 ) {
-    for mut client in &mut clients {
-        client.set_position([0.0, SPAWN_Y as f64 + 1.0, 0.0]);
-        client.set_instance(instances.single());
-        client.set_game_mode(GameMode::Creative);
-        client.send_message("Welcome to Valence! Build something cool.".italic());
+    for (entity, mut client, waiting) in &mut clients {
+        let placed = worlds.get(&config.spawn.world).is_some()
+            && send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances);
+        if placed {
+            commands.entity(entity).remove::<AwaitingSpawn>();
+        } else if waiting.since.elapsed() >= SPAWN_WAIT {
+            warn!(
+                "Disconnecting {} as spawn world {:?} never appeared",
+                client.username(),
+                config.spawn.world
+            );
+            kick(&mut client, lang.tr(entity, "server.no_spawn", &[]));
+            commands.entity(entity).remove::<AwaitingSpawn>();
+        }
     }
 }
 
 
-fn handle_message_events(mut clients: Query<&mut Client>, mut messages: EventReader<ChatMessage>) {
+fn handle_message_events(
+    mut clients: Query<(Entity, &mut Client)>,
+    display_names: Query<&DisplayName>,
+    mut messages: EventReader<PlayerChat>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
     for message in messages.iter() {
         let Ok(client) = clients.get_component::<Client>(message.client) else {
             warn!("Unable to find client for message: {:?}", message);
             continue;
         };
 
-        let message = message.message.to_string();
+        let name = match display_names.get(message.client) {
+            Ok(name) => name.0.clone(),
+            Err(_) => client.username().to_string().color(Color::YELLOW),
+        };
+
+        let sender = message.client;
+        let message = message.message.clone();
+        player_span(client).in_scope(|| info!(target: AUDIT, "<{}> {message}", client.username()));
+
+        for (entity, client) in &clients {
+            if entity != sender && mentions(&message, client.username().as_str()) {
+                sounds.send(FeedbackSound {
+                    client: entity,
+                    feedback: Feedback::Mention,
+                });
+            }
+        }
 
-        let formatted = format!("<{}>: ", client.username())
-            .bold()
-            .color(Color::YELLOW)
+        let formatted = "<".bold().color(Color::YELLOW)
+            + name.bold()
+            + ">: ".bold().color(Color::YELLOW)
             + message.into_text().not_bold().color(Color::WHITE);
 
-        clients.par_for_each_mut(16, |mut client| {
+        clients.par_for_each_mut(16, |(_, mut client)| {
             client.send_message(formatted.clone());
         })
     }
 }
 
+/// Whether a chat message contains `username` as a whole word.
+fn mentions(message: &str, username: &str) -> bool {
+    message
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|word| word.eq_ignore_ascii_case(username))
+}
+
 fn digging_creative_mode(
-    clients: Query<&Client>,
+    mut clients: Query<&mut Client, Without<Inspecting>>,
     mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
     mut events: EventReader<StartDigging>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut warned: Local<bool>,
 ) {
-    let mut instance = instances.single_mut();
-
     for event in events.iter() {
-        let Ok(client) = clients.get_component::<Client>(event.client) else {
+        let Ok(mut client) = clients.get_mut(event.client) else {
             continue;
         };
+        let instance = edited_instance(
+            &mut instances,
+            client.instance(),
+            client.username().as_str(),
+            &mut warned,
+        );
+        let Some(mut instance) = instance else {
+            continue;
+        };
+        if !inside_border(&borders, client.instance(), event.position) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            resend_block(&mut client, &instance, event.position);
+            continue;
+        }
         if client.game_mode() == GameMode::Creative {
+            if let Some(old) = instance.block(event.position).map(|b| b.state()) {
+                changes.send(BlockChanged {
+                    actor: Actor::of(&client),
+                    instance: client.instance(),
+                    position: event.position,
+                    old,
+                    new: BlockState::AIR,
+                });
+            }
             instance.set_block(event.position, BlockState::AIR);
         }
     }
 }
 
 fn digging_survival_mode(
-    clients: Query<&Client>,
+    mut clients: Query<&mut Client, Without<Inspecting>>,
     mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
     mut events: EventReader<FinishDigging>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut warned: Local<bool>,
 ) {
-    let mut instance = instances.single_mut();
-
     for event in events.iter() {
-        let Ok(client) = clients.get_component::<Client>(event.client) else {
+        let Ok(mut client) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let instance = edited_instance(
+            &mut instances,
+            client.instance(),
+            client.username().as_str(),
+            &mut warned,
+        );
+        let Some(mut instance) = instance else {
             continue;
         };
+        if !inside_border(&borders, client.instance(), event.position) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            resend_block(&mut client, &instance, event.position);
+            continue;
+        }
         if client.game_mode() == GameMode::Survival {
+            if let Some(old) = instance.block(event.position).map(|b| b.state()) {
+                changes.send(BlockChanged {
+                    actor: Actor::of(&client),
+                    instance: client.instance(),
+                    position: event.position,
+                    old,
+                    new: BlockState::AIR,
+                });
+            }
             instance.set_block(event.position, BlockState::AIR);
         }
     }
 }
 
 fn place_blocks(
-    mut clients: Query<(&Client, &mut Inventory)>,
+    mut clients: Query<(&mut Client, &mut Inventory, Option<&Inspecting>)>,
     mut instances: Query<&mut Instance>,
+    borders: Query<&WorldBorder>,
     mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut changes: EventWriter<BlockChanged>,
+    mut placing: ResMut<Placing>,
+    used: Res<UsedClicks>,
 ) {
-    let mut instance = instances.single_mut();
-
     for event in events.iter() {
-        let Ok((client, mut inventory)) = clients.get_mut(event.client) else {
+        let Ok((mut client, mut inventory, inspecting)) = clients.get_mut(event.client) else {
             warn!("Could not find client {:?}", event.client);
             continue;
         };
-        if event.hand != Hand::Main {
-            warn!("Item usage with {:?} not supported", event.hand);
-            continue;
-        }
-
-        // get the held item
-        let slot_id = client.held_item_slot();
-        let Some(stack) = inventory.slot(slot_id) else {
-            // no item in the slot
+        let instance = edited_instance(
+            &mut instances,
+            client.instance(),
+            client.username().as_str(),
+            &mut placing.warned,
+        );
+        let Some(mut instance) = instance else {
             continue;
         };
 
-        let Some(block_kind) = stack.item.to_block_kind() else {
-            // can't place this item as a block
-            continue;
+        // One click sends the main hand, then the off hand too if the main
+        // hand had nothing to place, so only that second event is acted on.
+        // Anything else from the main hand, including a refusal, is the
+        // whole click, as is the main hand being used for something other
+        // than a block, like lighting a fire.
+        //
+        // The held slot is read here rather than carried with the click. The
+        // event loop runs once per packet, in the order each client sent
+        // them, so it's the slot as of the click: a scroll sent after it
+        // hasn't been applied yet, and one sent before it already has. That
+        // ordering is Valence's, and nothing here tests it.
+        let passed = placing.main_hand_passed.remove(&event.client);
+        let slot_id = match event.hand {
+            Hand::Main => client.held_item_slot(),
+            Hand::Off
+                if passed == Some((event.position, event.face))
+                    && !used.0.contains(&event.client) =>
+            {
+                OFF_HAND_SLOT
+            }
+            Hand::Off => continue,
         };
 
-        if client.game_mode() == GameMode::Survival {
-            // check if the player has the item in their inventory and remove
-            // it.
-            let slot = if stack.count() > 1 {
-                let mut stack = stack.clone();
-                stack.set_count(stack.count() - 1);
-                Some(stack)
-            } else {
-                None
-            };
-            inventory.replace_slot(slot_id, slot);
+        // Inspecting players' clicks only look blocks up.
+        if inspecting.is_some() {
+            continue;
         }
 
-        // TODO: client.facing()?
-        let facing = match client.yaw().rem_euclid(360.0) {
-            yaw if !(45.0..315.0).contains(&yaw) => PropValue::South,
-            yaw if (45.0..135.0).contains(&yaw) => PropValue::West,
-            yaw if (135.0..225.0).contains(&yaw) => PropValue::North,
-            yaw if (225.0..315.0).contains(&yaw) => PropValue::East,
-
-            _ => unreachable!(),
+        // get the held item
+        let block_kind = inventory
+            .slot(slot_id)
+            .and_then(|stack| stack.item.to_block_kind());
+        let Some(block_kind) = block_kind else {
+            // nothing in the slot that can be placed as a block
+            if event.hand == Hand::Main {
+                placing
+                    .main_hand_passed
+                    .insert(event.client, (event.position, event.face));
+            }
+            continue;
         };
 
-        let mut block_state = block_kind.to_state();
+        let click = Click {
+            kind: block_kind,
+            position: event.position,
+            face: event.face,
+            cursor_y: event.cursor_pos[1],
+            yaw: client.yaw(),
+            pitch: client.pitch(),
+            feet: client.position(),
+        };
+        let placement = decide_placement(
+            &click,
+            |pos| instance.block(pos).map(|block| block.state()),
+            |pos| inside_border(&borders, client.instance(), pos),
+        );
+
+        match placement {
+            Placement::Place { position, old, new } => {
+                changes.send(BlockChanged {
+                    actor: Actor::of(&client),
+                    instance: client.instance(),
+                    position,
+                    old,
+                    new,
+                });
+                instance.set_block(position, new);
+            }
+            Placement::ClickedUnloaded => {}
+            Placement::Rejected { position, reason } => {
+                if reason == Rejection::OutsideBorder {
+                    sounds.send(FeedbackSound {
+                        client: event.client,
+                        feedback: Feedback::Denied,
+                    });
+                }
+                resend_block(&mut client, &instance, position);
+            }
+        }
 
-        let replace = instance.block(event.position).expect("chunk to be loaded").state().is_replaceable();
+        // Only once the block is placed is the item used up, so nothing that
+        // stops a placement can cost a survival player their item.
+        let survival = client.game_mode() == GameMode::Survival;
+        settle_held_item(&mut inventory, slot_id, &placement, survival);
+    }
+}
 
-        // TODO: Is there a better way to do this?
-        // - a has_prop api?
-        // - a is_stairs, is_slab, etc api?
-        let has_facing = block_state.get(PropName::Facing).is_some();
-        let has_half = block_state.get(PropName::Half).is_some();
+/// Undoes what a client showed of a placement that didn't happen: the block
+/// it put at `pos`, and in survival the item it used up.
+fn reject_placement(
+    client: &mut Client,
+    inventory: &mut Inventory,
+    instance: &Instance,
+    pos: BlockPos,
+    slot: u16,
+) {
+    resend_block(client, instance, pos);
+    if client.game_mode() == GameMode::Survival {
+        resync_slot(inventory, slot);
+    }
+}
 
-        let has_type = block_state.get(PropName::Type).is_some();
+/// What [`place_blocks`] keeps between clicks.
+#[derive(Resource, Default)]
+struct Placing {
+    /// Whether a missing instance has been warned about.
+    warned: bool,
+    /// Clients whose last main hand click had nothing to place, and what
+    /// they clicked, which is when their off hand gets a turn.
+    main_hand_passed: HashMap<Entity, (BlockPos, BlockFace)>,
+}
 
-        if has_facing {
-            block_state = block_state.set(PropName::Facing, facing);
-        }
+impl ClientRegistry for Placing {
+    fn forget(&mut self, client: Entity, _uuid: Uuid) {
+        self.main_hand_passed.remove(&client);
+    }
 
-        if has_half || has_type {
-            match event.face {
-                valence_protocol::BlockFace::Bottom => {
-                    block_state = block_state
-                        .set(PropName::Half, PropValue::Top)
-                        .set(PropName::Type, PropValue::Top);
-                }
-                valence_protocol::BlockFace::Top => {
-                    block_state = block_state
-                        .set(PropName::Half, PropValue::Bottom)
-                        .set(PropName::Type, PropValue::Bottom);
-                }
-                valence_protocol::BlockFace::North
-                | valence_protocol::BlockFace::South
-                | valence_protocol::BlockFace::West
-                | valence_protocol::BlockFace::East => {
-                    let top = event.cursor_pos[1] > 0.5;
-                    let val = match top {
-                        true => PropValue::Top,
-                        false => PropValue::Bottom,
-                    };
-                    block_state = block_state
-                        .set(PropName::Half, val)
-                        .set(PropName::Type, val);
-                }
-            }
-        }
+    fn entries(&self, client: Entity, _uuid: Uuid) -> usize {
+        usize::from(self.main_hand_passed.contains_key(&client))
+    }
+}
 
-        // !TODO:
-        // - Combine slabs
-        // - 2-high doors
-        // - Open/close (trap)doors
-        // - Stair bending
+/// Clients whose main hand was used on something this tick other than
+/// placing a block, so their off hand doesn't act on the same click.
+#[derive(Resource, Default)]
+pub struct UsedClicks(HashSet<Entity>);
 
-        let real_pos = if replace {
-            event.position
-        } else {
-            event.position.get_in_direction(event.face)
-        };
-        instance.set_block(real_pos, block_state);
+impl UsedClicks {
+    /// Marks a client's click as used by what their main hand holds.
+    pub fn mark(&mut self, client: Entity) {
+        self.0.insert(client);
     }
 }
+
+fn forget_used_clicks(mut used: ResMut<UsedClicks>) {
+    used.0.clear();
+}
@@ -0,0 +1,165 @@
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::Config;
+use crate::format::legacy_text;
+use crate::kick;
+use crate::lang::Lang;
+use crate::permissions::{Permissions, PermissionsChanged};
+use crate::persistence::Persistence;
+use crate::reload::ConfigReloaded;
+use crate::status::{Maintenance, SharedStatus};
+
+const MAINTENANCE: CommandInfo = CommandInfo {
+    name: "maintenance",
+    aliases: &[],
+    usage: "/maintenance <on|off> [message]",
+    description: "Close the server to everyone who can't bypass maintenance.",
+    permission: Some("plots.command.maintenance"),
+    console: true,
+};
+
+const BYPASS: &str = "plots.maintenance.bypass";
+
+pub struct MaintenancePlugin;
+
+impl Plugin for MaintenancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(MAINTENANCE)
+            .add_startup_system(init_maintenance)
+            .add_system(refresh_maintenance)
+            .add_system_to_stage(EventLoop, maintenance_command);
+    }
+}
+
+/// Tells the login check and server list whether the server is in
+/// maintenance mode.
+fn publish(status: &SharedStatus, config: &Config, permissions: &Permissions, lang: &Lang) {
+    let maintenance = &config.maintenance;
+
+    status.set_maintenance(maintenance.enabled.then(|| Maintenance {
+        motd: legacy_text(&maintenance.motd),
+        message: lang.text_default(&maintenance.message, &[]),
+        bypass: permissions.holders(BYPASS),
+    }));
+}
+
+fn init_maintenance(
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+) {
+    if config.maintenance.enabled {
+        info!("Starting in maintenance mode");
+    }
+    publish(&status, &config, &permissions, &lang);
+}
+
+/// Keeps who may bypass maintenance, and the messages, up to date.
+fn refresh_maintenance(
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut permissions_changed: EventReader<PermissionsChanged>,
+    mut reloaded: EventReader<ConfigReloaded>,
+) {
+    if permissions_changed.iter().count() + reloaded.iter().count() > 0 {
+        publish(&status, &config, &permissions, &lang);
+    }
+}
+
+fn maintenance_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    (mut config, persistence): (ResMut<Config>, Res<Persistence>),
+    permissions: Res<Permissions>,
+    status: Res<SharedStatus>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(MAINTENANCE.name)) {
+        let name = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+
+        let reply = match event.args.split_first() {
+            Some((action, message)) if action == "on" => {
+                if !message.is_empty() {
+                    config.maintenance.message = message.join(" ");
+                }
+                config.maintenance.enabled = true;
+                publish(&status, &config, &permissions, &lang);
+
+                for (entity, mut client) in &mut clients {
+                    if !client.is_disconnected()
+                        && !permissions.has_permission(client.uuid(), BYPASS)
+                    {
+                        kick(
+                            &mut client,
+                            lang.text(entity, &config.maintenance.message, &[]),
+                        );
+                    }
+                }
+
+                info!("{name} turned maintenance mode on");
+                saved_reply(
+                    config.save(&persistence),
+                    lang.tr(event.sender, "maintenance.enabled", &[]),
+                    event.sender,
+                    &lang,
+                )
+            }
+            Some((action, [])) if action == "off" => {
+                if config.maintenance.enabled {
+                    config.maintenance.enabled = false;
+                    publish(&status, &config, &permissions, &lang);
+
+                    // Only staff can be online during maintenance, but check
+                    // anyway.
+                    for (entity, mut client) in &mut clients {
+                        if entity != event.sender
+                            && permissions.has_permission(client.uuid(), BYPASS)
+                        {
+                            client.send_message(lang.tr(
+                                entity,
+                                "maintenance.ended",
+                                &[("name", &name)],
+                            ));
+                        }
+                    }
+
+                    info!("{name} turned maintenance mode off");
+                    saved_reply(
+                        config.save(&persistence),
+                        lang.tr(event.sender, "maintenance.disabled", &[]),
+                        event.sender,
+                        &lang,
+                    )
+                } else {
+                    lang.tr(event.sender, "maintenance.not_enabled", &[])
+                }
+            }
+            _ => usage(&lang, event.sender, &MAINTENANCE),
+        };
+
+        if let Ok((_, mut client)) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+fn saved_reply(saved: anyhow::Result<()>, reply: Text, sender: Entity, lang: &Lang) -> Text {
+    match saved {
+        Ok(()) => reply,
+        Err(e) => {
+            warn!("Failed to save maintenance mode: {e:#}");
+            lang.tr(sender, "maintenance.unsaved", &[])
+        }
+    }
+}
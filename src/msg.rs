@@ -0,0 +1,222 @@
+use plotsirv::disconnect::{AddDisconnectSystem, ClientDisconnected};
+use tracing::info;
+use valence::prelude::*;
+
+use crate::command::{find_client, usage, AddCommand, CommandExecution, CommandInfo};
+use crate::lang::Lang;
+use crate::logging::AUDIT;
+use crate::player_data::PlayerDataStore;
+
+const MSG: CommandInfo = CommandInfo {
+    name: "msg",
+    aliases: &["tell", "w", "whisper"],
+    usage: "/msg <player> <message>",
+    description: "Send a private message.",
+    permission: None,
+    console: false,
+};
+
+const REPLY: CommandInfo = CommandInfo {
+    name: "r",
+    aliases: &["reply"],
+    usage: "/r <message>",
+    description: "Reply to your last private message.",
+    permission: None,
+    console: false,
+};
+
+const SOCIALSPY: CommandInfo = CommandInfo {
+    name: "socialspy",
+    aliases: &[],
+    usage: "/socialspy",
+    description: "Toggle seeing everyone's private messages.",
+    permission: Some("plots.command.socialspy"),
+    console: false,
+};
+
+/// Per-client private messaging state.
+#[derive(Component, Default, Debug)]
+pub struct PrivateMessaging {
+    /// Who `/r` replies to: whoever the client last messaged or heard from.
+    reply_to: Option<Entity>,
+    pub social_spy: bool,
+}
+
+pub struct MsgPlugin;
+
+impl Plugin for MsgPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(MSG)
+            .add_command(REPLY)
+            .add_command(SOCIALSPY)
+            .add_system(init_messaging)
+            .add_system_to_stage(EventLoop, msg_commands)
+            .add_system_to_stage(EventLoop, socialspy_command)
+            .add_disconnect_system(forget_disconnected);
+    }
+}
+
+fn init_messaging(
+    mut commands: Commands,
+    clients: Query<(Entity, &Client), Added<Client>>,
+    mut store: ResMut<PlayerDataStore>,
+) {
+    for (entity, client) in &clients {
+        commands.entity(entity).insert(PrivateMessaging {
+            reply_to: None,
+            social_spy: store.get(client.uuid()).social_spy,
+        });
+    }
+}
+
+fn msg_commands(
+    mut clients: Query<(Entity, &mut Client, &mut PrivateMessaging)>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter() {
+        let (target, words) = if event.is(MSG.name) {
+            let [name, words @ ..] = event.args.as_slice() else {
+                if let Ok((_, mut sender, _)) = clients.get_mut(event.sender) {
+                    sender.send_message(usage(&lang, event.sender, &MSG));
+                }
+                continue;
+            };
+
+            let target = find_client(clients.iter().map(|(e, c, _)| (e, c)), name);
+            let Some(target) = target else {
+                if let Ok((_, mut sender, _)) = clients.get_mut(event.sender) {
+                    sender.send_message(lang.tr(
+                        event.sender,
+                        "command.not_online",
+                        &[("name", name)],
+                    ));
+                }
+                continue;
+            };
+
+            (target, words)
+        } else if event.is(REPLY.name) {
+            let Ok((_, mut sender, messaging)) = clients.get_mut(event.sender) else {
+                continue;
+            };
+
+            let Some(target) = messaging.reply_to else {
+                sender.send_message(lang.tr(event.sender, "msg.no_reply", &[]));
+                continue;
+            };
+
+            (target, event.args.as_slice())
+        } else {
+            continue;
+        };
+
+        if words.is_empty() {
+            let info = if event.is(MSG.name) { &MSG } else { &REPLY };
+            if let Ok((_, mut sender, _)) = clients.get_mut(event.sender) {
+                sender.send_message(usage(&lang, event.sender, info));
+            }
+            continue;
+        }
+
+        send_private_message(&mut clients, &lang, event.sender, target, &words.join(" "));
+    }
+}
+
+fn send_private_message(
+    clients: &mut Query<(Entity, &mut Client, &mut PrivateMessaging)>,
+    lang: &Lang,
+    from: Entity,
+    to: Entity,
+    message: &str,
+) {
+    if from == to {
+        if let Ok((_, mut sender, _)) = clients.get_mut(from) {
+            sender.send_message(lang.tr(from, "msg.self", &[]));
+        }
+        return;
+    }
+
+    let Ok([(_, mut sender, mut sender_messaging), (_, mut target, mut target_messaging)]) =
+        clients.get_many_mut([from, to])
+    else {
+        return;
+    };
+
+    if target.is_disconnected() {
+        let name = target.username().to_string();
+        sender.send_message(lang.tr(from, "command.not_online", &[("name", &name)]));
+        return;
+    }
+
+    let from_name = sender.username().to_string();
+    let to_name = target.username().to_string();
+
+    // Like public chat, the message is sent as plain text so formatting
+    // codes can't be injected.
+    sender.send_message(
+        lang.tr(from, "msg.sent", &[("name", &to_name)]) + message.to_owned().color(Color::WHITE),
+    );
+    target.send_message(
+        lang.tr(to, "msg.received", &[("name", &from_name)])
+            + message.to_owned().color(Color::WHITE),
+    );
+
+    sender_messaging.reply_to = Some(to);
+    target_messaging.reply_to = Some(from);
+
+    info!(target: AUDIT, "[{from_name} -> {to_name}] {message}");
+
+    for (entity, mut spy, messaging) in clients.iter_mut() {
+        if messaging.social_spy && entity != from && entity != to {
+            let prefix = lang.tr(entity, "msg.spy", &[("from", &from_name), ("to", &to_name)]);
+            spy.send_message(prefix + message.to_owned().color(Color::DARK_GRAY));
+        }
+    }
+}
+
+fn socialspy_command(
+    mut clients: Query<(&mut Client, &mut PrivateMessaging)>,
+    mut store: ResMut<PlayerDataStore>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SOCIALSPY.name)) {
+        let Ok((mut client, mut messaging)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        if !event.args.is_empty() {
+            client.send_message(usage(&lang, event.sender, &SOCIALSPY));
+            continue;
+        }
+
+        messaging.social_spy = !messaging.social_spy;
+        store.get(client.uuid()).social_spy = messaging.social_spy;
+        store.save(client.uuid());
+
+        let key = if messaging.social_spy {
+            "social_spy.enabled"
+        } else {
+            "social_spy.disabled"
+        };
+        client.send_message(lang.tr(event.sender, key, &[]));
+    }
+}
+
+/// Stops anyone replying to a client who has left.
+fn forget_disconnected(
+    mut events: EventReader<ClientDisconnected>,
+    mut messaging: Query<&mut PrivateMessaging>,
+) {
+    let gone: Vec<Entity> = events.iter().map(|event| event.client).collect();
+    if gone.is_empty() {
+        return;
+    }
+
+    for mut messaging in &mut messaging {
+        if messaging.reply_to.map_or(false, |e| gone.contains(&e)) {
+            messaging.reply_to = None;
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::ban::{duration_and_reason, now_secs};
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::format::format_duration;
+use crate::lang::Lang;
+use crate::persistence::{Persistence, WriteKind};
+use crate::profiles::{KnownPlayer, Profiles};
+
+const MUTE: CommandInfo = CommandInfo {
+    name: "mute",
+    aliases: &[],
+    usage: "/mute <player> [duration] [reason]",
+    description: "Stop a player chatting, for good or for a while like 30m.",
+    permission: Some("plots.command.mute"),
+    console: true,
+};
+
+const UNMUTE: CommandInfo = CommandInfo {
+    name: "unmute",
+    aliases: &[],
+    usage: "/unmute <player>",
+    description: "Let a muted player chat again.",
+    permission: Some("plots.command.unmute"),
+    console: true,
+};
+
+const DEFAULT_FILE: &str = "mutes.toml";
+
+/// A mute on a player. Lifted and expired mutes are kept as history, like
+/// bans.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Mute {
+    pub uuid: Uuid,
+    /// The player's name when they were muted.
+    pub name: String,
+    pub reason: Option<String>,
+    pub muted_by: String,
+    /// Unix timestamps, in seconds.
+    pub created: u64,
+    pub expires: Option<u64>,
+    /// Who lifted the mute with `/unmute`, if anyone.
+    pub lifted_by: Option<String>,
+}
+
+impl Mute {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.lifted_by.is_none() && self.expires.map_or(true, |expires| now < expires)
+    }
+}
+
+/// Every mute ever made, stored as TOML.
+#[derive(Resource, Default, Debug)]
+pub struct Mutes {
+    mutes: Vec<Mute>,
+    path: PathBuf,
+}
+
+/// The on-disk form of the mute list.
+#[derive(Serialize, Deserialize, Default)]
+struct MuteFile {
+    mutes: Vec<Mute>,
+}
+
+impl Mutes {
+    /// Reads the mute list, starting with none if the file doesn't exist yet.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = PathBuf::from(DEFAULT_FILE);
+
+        let file: MuteFile = if path.exists() {
+            let contents =
+                fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+            toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))?
+        } else {
+            MuteFile::default()
+        };
+
+        Ok(Self {
+            mutes: file.mutes,
+            path,
+        })
+    }
+
+    /// The most recent mute currently keeping a player quiet.
+    pub fn active(&self, uuid: Uuid) -> Option<&Mute> {
+        let now = now_secs();
+        self.mutes
+            .iter()
+            .rev()
+            .find(|mute| mute.is_active(now) && mute.uuid == uuid)
+    }
+
+    /// Adds a mute and saves the list.
+    pub fn add(&mut self, mute: Mute, persistence: &Persistence) -> anyhow::Result<()> {
+        self.mutes.push(mute);
+        self.save(persistence)
+    }
+
+    /// Lifts every active mute on a player, returning whether there were
+    /// any.
+    fn lift(&mut self, uuid: Uuid, lifted_by: &str) -> bool {
+        let now = now_secs();
+        let mut lifted = false;
+
+        for mute in &mut self.mutes {
+            if mute.is_active(now) && mute.uuid == uuid {
+                mute.lifted_by = Some(lifted_by.to_owned());
+                lifted = true;
+            }
+        }
+
+        lifted
+    }
+
+    /// Queues the list to be written. Failing to write it is reported
+    /// later, as a [`WriteFailed`](crate::persistence::WriteFailed).
+    fn save(&self, persistence: &Persistence) -> anyhow::Result<()> {
+        let file = MuteFile {
+            mutes: self.mutes.clone(),
+        };
+        let contents = toml::to_string(&file)?;
+        persistence.write(WriteKind::Mutes, self.path.clone(), contents.into_bytes());
+        Ok(())
+    }
+}
+
+/// Tells a muted player why they can't chat, and for how long.
+pub fn muted_message(lang: &Lang, client: Entity, mute: &Mute) -> Text {
+    let reason = mute
+        .reason
+        .as_deref()
+        .unwrap_or_else(|| lang.plain(client, "mute.default_reason"));
+
+    match mute.expires {
+        None => lang.tr(client, "mute.muted", &[("reason", &reason)]),
+        Some(expires) => {
+            let remaining =
+                format_duration(Duration::from_secs(expires.saturating_sub(now_secs())));
+            lang.tr(
+                client,
+                "mute.muted_temporary",
+                &[("reason", &reason), ("remaining", &remaining)],
+            )
+        }
+    }
+}
+
+pub struct MutePlugin;
+
+impl Plugin for MutePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(MUTE)
+            .add_command(UNMUTE)
+            .add_system_to_stage(EventLoop, mute_command)
+            .add_system_to_stage(EventLoop, unmute_command);
+    }
+}
+
+/// Sends a command's reply to whoever ran it.
+fn reply(
+    clients: &mut Query<(Entity, &mut Client)>,
+    consoles: &mut Query<&mut Console>,
+    sender: Entity,
+    reply: Text,
+) {
+    if let Ok((_, mut client)) = clients.get_mut(sender) {
+        client.send_message(reply);
+    } else if let Ok(mut console) = consoles.get_mut(sender) {
+        console.send_message(reply);
+    }
+}
+
+fn online(clients: &Query<(Entity, &mut Client)>, uuid: Uuid) -> Option<Entity> {
+    clients
+        .iter()
+        .find(|(_, client)| client.uuid() == uuid)
+        .map(|(entity, _)| entity)
+}
+
+/// The reply to a change, or a warning if it couldn't be saved.
+fn saved_reply(saved: anyhow::Result<()>, reply: Text, sender: Entity, lang: &Lang) -> Text {
+    match saved {
+        Ok(()) => reply,
+        Err(e) => {
+            warn!("Failed to save mutes: {e:#}");
+            lang.tr(sender, "mute.unsaved", &[])
+        }
+    }
+}
+
+fn mute_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    mut mutes: ResMut<Mutes>,
+    mut profiles: ResMut<Profiles>,
+    persistence: Res<Persistence>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(MUTE.name)) {
+        let Some((target, rest)) = event.args.split_first() else {
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, &MUTE),
+            );
+            continue;
+        };
+
+        let Some(KnownPlayer { uuid, name }) = profiles.resolve(target, event) else {
+            continue;
+        };
+
+        if mutes.active(uuid).is_some() {
+            let text = lang.tr(event.sender, "mute.already", &[("name", &name)]);
+            reply(&mut clients, &mut consoles, event.sender, text);
+            continue;
+        }
+
+        let (duration, reason) = duration_and_reason(rest);
+        let muted_by = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+        let now = now_secs();
+
+        let mute = Mute {
+            uuid,
+            name: name.clone(),
+            reason,
+            muted_by: muted_by.clone(),
+            created: now,
+            expires: duration.map(|d| now.saturating_add(d.as_secs())),
+            lifted_by: None,
+        };
+
+        info!(
+            "{muted_by} muted {name} {}: {}",
+            duration.map_or("permanently".to_owned(), |d| format!(
+                "for {}",
+                format_duration(d)
+            )),
+            mute.reason.as_deref().unwrap_or("no reason given"),
+        );
+
+        if let Some(target) = online(&clients, uuid) {
+            let text = muted_message(&lang, target, &mute);
+            reply(&mut clients, &mut consoles, target, text);
+        }
+
+        let text = match duration {
+            None => lang.tr(event.sender, "mute.done", &[("name", &name)]),
+            Some(duration) => lang.tr(
+                event.sender,
+                "mute.done_temporary",
+                &[("name", &name), ("duration", &format_duration(duration))],
+            ),
+        };
+        let text = saved_reply(mutes.add(mute, &persistence), text, event.sender, &lang);
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn unmute_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    mut mutes: ResMut<Mutes>,
+    mut profiles: ResMut<Profiles>,
+    persistence: Res<Persistence>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(UNMUTE.name)) {
+        let [target] = event.args.as_slice() else {
+            reply(
+                &mut clients,
+                &mut consoles,
+                event.sender,
+                usage(&lang, event.sender, &UNMUTE),
+            );
+            continue;
+        };
+
+        let Some(player) = profiles.resolve(target, event) else {
+            continue;
+        };
+
+        let lifted_by = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+
+        let text = if mutes.lift(player.uuid, &lifted_by) {
+            info!("{lifted_by} unmuted {}", player.name);
+
+            if let Some(target) = online(&clients, player.uuid) {
+                let text = lang.tr(target, "mute.unmuted_notice", &[]);
+                reply(&mut clients, &mut consoles, target, text);
+            }
+
+            let text = lang.tr(event.sender, "mute.unmuted", &[("name", &player.name)]);
+            saved_reply(mutes.save(&persistence), text, event.sender, &lang)
+        } else {
+            lang.tr(event.sender, "mute.not_muted", &[("name", &player.name)])
+        };
+
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
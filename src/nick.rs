@@ -0,0 +1,161 @@
+use valence::prelude::*;
+
+use crate::afk::Afk;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::format::{legacy_text, strip_legacy};
+use crate::lang::Lang;
+use crate::permissions::Permissions;
+use crate::player_data::PlayerDataStore;
+
+const NICK: CommandInfo = CommandInfo {
+    name: "nick",
+    aliases: &["nickname"],
+    usage: "/nick <name|off>",
+    description: "Change the name shown for you in chat and the tab list.",
+    permission: Some("plots.command.nick"),
+    console: false,
+};
+
+const MIN_NICK_LEN: usize = 3;
+const MAX_NICK_LEN: usize = 16;
+
+/// The name shown for a client in chat and the tab list. Without one, the
+/// username is used.
+#[derive(Component, Clone, Debug)]
+pub struct DisplayName(pub Text);
+
+pub struct NickPlugin;
+
+impl Plugin for NickPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(NICK)
+            .add_system(init_display_names)
+            .add_system_to_stage(EventLoop, nick_command)
+            .add_system(sync_display_names);
+    }
+}
+
+/// Checks that a nickname is a sensible length and only uses the characters
+/// allowed in usernames, not counting color codes. Errors are message keys.
+fn validate_nick(nick: &str) -> Result<(), &'static str> {
+    let visible = strip_legacy(nick);
+
+    if !(MIN_NICK_LEN..=MAX_NICK_LEN).contains(&visible.chars().count()) {
+        return Err("nick.length");
+    }
+
+    if !visible
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err("nick.characters");
+    }
+
+    Ok(())
+}
+
+fn init_display_names(
+    mut commands: Commands,
+    clients: Query<(Entity, &Client), Added<Client>>,
+    mut store: ResMut<PlayerDataStore>,
+) {
+    for (entity, client) in &clients {
+        if let Some(nick) = &store.get(client.uuid()).nickname {
+            commands
+                .entity(entity)
+                .insert(DisplayName(legacy_text(nick)));
+        }
+    }
+}
+
+fn nick_command(
+    mut commands: Commands,
+    mut clients: Query<&mut Client>,
+    mut store: ResMut<PlayerDataStore>,
+    mut player_list: ResMut<PlayerList>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(NICK.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let [nick] = event.args.as_slice() else {
+            client.send_message(usage(&lang, event.sender, &NICK));
+            continue;
+        };
+
+        if nick.eq_ignore_ascii_case("off") {
+            store.get(client.uuid()).nickname = None;
+            store.save(client.uuid());
+            commands.entity(event.sender).remove::<DisplayName>();
+            if let Some(entry) = player_list.get_mut(client.uuid()) {
+                entry.set_display_name(None);
+            }
+            client.send_message(lang.tr(event.sender, "nick.removed", &[]));
+            continue;
+        }
+
+        if let Err(key) = validate_nick(nick) {
+            client.send_message(lang.tr(
+                event.sender,
+                key,
+                &[("min", &MIN_NICK_LEN), ("max", &MAX_NICK_LEN)],
+            ));
+            continue;
+        }
+
+        store.get(client.uuid()).nickname = Some(nick.clone());
+        store.save(client.uuid());
+        commands
+            .entity(event.sender)
+            .insert(DisplayName(legacy_text(nick)));
+
+        client.send_message(lang.tr(event.sender, "nick.set", &[("nick", nick)]));
+    }
+}
+
+/// Shows display names in the tab list in the format of each player's
+/// group, tagging AFK players. Checked every tick since the entry may not
+/// exist yet when a client joins, and may be replaced later.
+fn sync_display_names(
+    clients: Query<(&Client, Option<&DisplayName>, Option<&Afk>)>,
+    mut player_list: ResMut<PlayerList>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+) {
+    // Everyone sees the same tab list, so the tag is in the default language.
+    let afk_tag = lang.tr_default("afk.tag", &[]);
+
+    for (client, name, afk) in &clients {
+        let Some(entry) = player_list.get_mut(client.uuid()) else {
+            continue;
+        };
+
+        let (prefix, suffix) = permissions.name_format(client.uuid());
+        let plain = prefix.is_empty() && suffix.is_empty();
+
+        let wanted = match (name, afk) {
+            (None, None) if plain => None,
+            (Some(name), None) if plain => Some(name.0.clone()),
+            (name, afk) => {
+                // A username has no colors of its own, so it takes the
+                // format's. A nickname keeps whatever it was given.
+                let name = match name {
+                    Some(name) => legacy_text(prefix) + name.0.clone(),
+                    None => legacy_text(&format!("{prefix}{}", client.username())),
+                };
+                let name = name + legacy_text(suffix);
+                Some(match afk {
+                    Some(_) => name + afk_tag.clone(),
+                    None => name,
+                })
+            }
+        };
+
+        if entry.display_name() != wanted.as_ref() {
+            entry.set_display_name(wanted);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use plotsirv::placement::use_up_one;
+use rand::seq::SliceRandom;
+use valence::client::event::{InteractWithEntity, UseItemOnBlock};
+use valence::prelude::*;
+use valence_protocol::types::{EntityInteraction, Hand};
+use valence_protocol::BlockFace;
+
+use crate::block_log::BlockChanged;
+use crate::border::{inside_border, WorldBorder};
+use crate::drops::drop_item;
+use crate::inspect::Inspecting;
+use crate::reject_placement;
+use crate::sound::{Feedback, FeedbackSound};
+use crate::UsedClicks;
+
+/// The paintings players can place, as registry ID, width and height in
+/// blocks. Earth, wind, water and fire come after these, and only exist to
+/// be given out by commands.
+const VARIANTS: &[(i32, i32, i32)] = &[
+    (0, 1, 1),  // kebab
+    (1, 1, 1),  // aztec
+    (2, 1, 1),  // alban
+    (3, 1, 1),  // aztec2
+    (4, 1, 1),  // bomb
+    (5, 1, 1),  // plant
+    (6, 1, 1),  // wasteland
+    (7, 2, 1),  // pool
+    (8, 2, 1),  // courbet
+    (9, 2, 1),  // sea
+    (10, 2, 1), // sunset
+    (11, 2, 1), // creebet
+    (12, 1, 2), // wanderer
+    (13, 1, 2), // graham
+    (14, 2, 2), // match
+    (15, 2, 2), // bust
+    (16, 2, 2), // stage
+    (17, 2, 2), // void
+    (18, 2, 2), // skull_and_roses
+    (19, 2, 2), // wither
+    (20, 4, 2), // fighters
+    (21, 4, 4), // pointer
+    (22, 4, 4), // pigscene
+    (23, 4, 4), // burning_skull
+    (24, 4, 3), // skeleton
+    (29, 4, 3), // donkey_kong
+];
+
+/// A painting placed by a player, and the blocks it covers and hangs on.
+#[derive(Component, Debug)]
+struct Painting {
+    covers: Vec<BlockPos>,
+    hangs_on: Vec<BlockPos>,
+}
+
+pub struct PaintingsPlugin;
+
+impl Plugin for PaintingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(EventLoop, place_paintings)
+            .add_system_to_stage(EventLoop, punch_paintings)
+            .add_system(pop_unsupported_paintings);
+    }
+}
+
+fn offset(pos: BlockPos, (x, y, z): (i32, i32, i32), times: i32) -> BlockPos {
+    BlockPos::new(pos.x + x * times, pos.y + y * times, pos.z + z * times)
+}
+
+/// The blocks a painting `width` by `height` in front of `wall` would cover,
+/// and the wall blocks behind them, laid out as vanilla lays them out.
+fn area(
+    wall: BlockPos,
+    face: BlockFace,
+    width: i32,
+    height: i32,
+) -> (Vec<BlockPos>, Vec<BlockPos>) {
+    // Outwards from the wall, and along it a quarter turn anticlockwise
+    // from that, which is to the right as seen from the front.
+    let (out, along) = match face {
+        BlockFace::North => ((0, 0, -1), (-1, 0, 0)),
+        BlockFace::South => ((0, 0, 1), (1, 0, 0)),
+        BlockFace::West => ((-1, 0, 0), (0, 0, 1)),
+        _ => ((1, 0, 0), (0, 0, -1)),
+    };
+    let (left, down) = ((width - 1) / -2, (height - 1) / -2);
+
+    let mut covers = Vec::new();
+    let mut hangs_on = Vec::new();
+    for i in 0..width {
+        for j in 0..height {
+            let behind = offset(offset(wall, along, i + left), (0, 1, 0), j + down);
+            hangs_on.push(behind);
+            covers.push(offset(behind, out, 1));
+        }
+    }
+    (covers, hangs_on)
+}
+
+/// The block an entity at `position` is in.
+fn block_at(position: DVec3) -> BlockPos {
+    BlockPos::new(
+        position.x.floor() as i32,
+        position.y.floor() as i32,
+        position.z.floor() as i32,
+    )
+}
+
+fn is_frame(kind: EntityKind) -> bool {
+    matches!(kind, EntityKind::ItemFrame | EntityKind::GlowItemFrame)
+}
+
+fn is_solid(instance: &Instance, pos: BlockPos) -> bool {
+    instance.block(pos).map_or(false, |block| {
+        !block.state().is_replaceable() && block.state().collision_shapes().next().is_some()
+    })
+}
+
+fn is_clear(instance: &Instance, pos: BlockPos) -> bool {
+    instance.block(pos).map_or(false, |block| {
+        block.state().collision_shapes().next().is_none()
+    })
+}
+
+/// Hangs a painting on the side of a block a player uses one on. As in
+/// vanilla, it's one of the biggest paintings that fit, picked at random;
+/// a painting fits if there's solid wall behind all of it and nothing in
+/// front, including other paintings and item frames.
+fn place_paintings(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &mut Inventory), Without<Inspecting>>,
+    instances: Query<&Instance>,
+    hung: Query<(&McEntity, Option<&Painting>)>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut sounds: EventWriter<FeedbackSound>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, mut inventory)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        let slot = client.held_item_slot();
+        if inventory.slot(slot).map(|stack| stack.item) != Some(ItemKind::Painting) {
+            continue;
+        }
+        used.mark(event.client);
+        let Ok(instance) = instances.get(client.instance()) else {
+            continue;
+        };
+
+        let pos = event.position.get_in_direction(event.face);
+        if matches!(event.face, BlockFace::Top | BlockFace::Bottom) {
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+        if !inside_border(&borders, client.instance(), pos) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        }
+
+        let taken: Vec<BlockPos> = hung
+            .iter()
+            .filter(|(entity, _)| !entity.is_despawned() && entity.instance() == client.instance())
+            .flat_map(|(entity, painting)| match painting {
+                Some(painting) => painting.covers.clone(),
+                None if is_frame(entity.kind()) => vec![block_at(entity.position())],
+                None => Vec::new(),
+            })
+            .collect();
+
+        let fitting: Vec<_> = VARIANTS
+            .iter()
+            .filter_map(|&(id, width, height)| {
+                let (covers, hangs_on) = area(event.position, event.face, width, height);
+                let fits = hangs_on.iter().all(|&pos| is_solid(instance, pos))
+                    && covers.iter().all(|&pos| is_clear(instance, pos))
+                    && covers.iter().all(|pos| !taken.contains(pos))
+                    && covers
+                        .iter()
+                        .all(|&pos| inside_border(&borders, client.instance(), pos));
+                fits.then_some((id, width * height, covers, hangs_on))
+            })
+            .collect();
+        let biggest = fitting.iter().map(|&(_, size, _, _)| size).max();
+        let biggest: Vec<_> = fitting
+            .into_iter()
+            .filter(|&(_, size, _, _)| Some(size) == biggest)
+            .collect();
+        let Some((id, _, covers, hangs_on)) = biggest.choose(&mut rand::thread_rng()).cloned()
+        else {
+            reject_placement(&mut client, &mut inventory, instance, pos, slot);
+            continue;
+        };
+
+        let mut entity = McEntity::new(EntityKind::Painting, client.instance());
+        entity.set_position([pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5]);
+        // Paintings are positioned by the block they're in front of and the
+        // way they face, which the client works the rest out from.
+        entity.set_object_data(match event.face {
+            BlockFace::North => 2,
+            BlockFace::South => 3,
+            BlockFace::West => 4,
+            _ => 5,
+        });
+        if let TrackedData::Painting(data) = entity.data_mut() {
+            data.set_variant(id);
+        }
+        commands.spawn((entity, Painting { covers, hangs_on }));
+
+        if client.game_mode() == GameMode::Survival {
+            use_up_one(&mut inventory, slot);
+        }
+    }
+}
+
+/// Punching a painting takes it down, dropping it in survival.
+fn punch_paintings(
+    mut commands: Commands,
+    clients: Query<&Client>,
+    mut paintings: Query<&mut McEntity, With<Painting>>,
+    entities: Res<McEntityManager>,
+    borders: Query<&WorldBorder>,
+    mut events: EventReader<InteractWithEntity>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
+    for event in events.iter() {
+        if event.interact != EntityInteraction::Attack {
+            continue;
+        }
+        let Some(painting) = entities.get_with_protocol_id(event.entity_id) else {
+            continue;
+        };
+        let (Ok(mut entity), Ok(client)) = (paintings.get_mut(painting), clients.get(event.client))
+        else {
+            continue;
+        };
+        if entity.is_despawned() || client.instance() != entity.instance() {
+            continue;
+        }
+
+        let position = entity.position();
+        if !inside_border(&borders, entity.instance(), block_at(position)) {
+            sounds.send(FeedbackSound {
+                client: event.client,
+                feedback: Feedback::Denied,
+            });
+            continue;
+        }
+
+        entity.set_despawned(true);
+        if client.game_mode() == GameMode::Survival {
+            let stack = ItemStack::new(ItemKind::Painting, 1, None);
+            drop_item(&mut commands, entity.instance(), position, stack);
+        }
+    }
+}
+
+/// Paintings fall off when a block they hang on is broken, dropping as an
+/// item.
+fn pop_unsupported_paintings(
+    mut commands: Commands,
+    mut paintings: Query<(&mut McEntity, &Painting)>,
+    mut events: EventReader<BlockChanged>,
+) {
+    for event in events
+        .iter()
+        .filter(|e| e.new.collision_shapes().next().is_none())
+    {
+        for (mut entity, painting) in &mut paintings {
+            if entity.is_despawned()
+                || entity.instance() != event.instance
+                || !painting.hangs_on.contains(&event.position)
+            {
+                continue;
+            }
+            entity.set_despawned(true);
+            let stack = ItemStack::new(ItemKind::Painting, 1, None);
+            drop_item(&mut commands, entity.instance(), entity.position(), stack);
+        }
+    }
+}
@@ -0,0 +1,804 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::ConfigGameMode;
+use crate::lang::Lang;
+use crate::persistence::{Persistence, WriteKind};
+use crate::profiles::Profiles;
+
+const PERM: CommandInfo = CommandInfo {
+    name: "perm",
+    aliases: &["perms"],
+    usage: "/perm group <list|create|delete|info|add|remove|inherit|uninherit> [group] [node] | /perm player <info|setgroup|add|remove> <player> [group|node] | /perm check <player> <node> | /perm reload",
+    description: "Manage permission groups and players.",
+    permission: Some("plots.command.perm"),
+    console: true,
+};
+
+const DEFAULT_FILE: &str = "permissions.toml";
+
+/// A named set of permission nodes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Group {
+    /// Groups with a higher weight rank above others, such as in `/list`.
+    pub weight: i32,
+    /// Groups whose permissions this one includes, unless it overrides them.
+    pub inherits: Vec<String>,
+    /// Nodes like `plots.command.tp` or `plots.*`. A leading `-` takes a
+    /// permission away.
+    pub permissions: Vec<String>,
+    /// Game modes for members by world name, in place of the world's own.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub game_modes: BTreeMap<String, ConfigGameMode>,
+    /// The fastest members can go with `/speed`. No limit but `/speed`'s
+    /// own if unset.
+    pub max_speed: Option<u8>,
+    /// How members' names look in the tab list, like `&c[Admin] {name}`.
+    /// Names are shown as they are if unset.
+    pub format: Option<String>,
+}
+
+/// A player's group and the nodes given to them directly, which beat their
+/// group's.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PlayerPermissions {
+    /// The player's name when they were last changed, for reading the file.
+    pub name: String,
+    /// Falls back to the default group.
+    pub group: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+/// The on-disk form of the permissions.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct PermissionsFile {
+    /// The group of every player without one of their own.
+    default_group: String,
+    groups: BTreeMap<String, Group>,
+    /// Keyed by UUID.
+    players: BTreeMap<Uuid, PlayerPermissions>,
+}
+
+impl Default for PermissionsFile {
+    fn default() -> Self {
+        let admin = Group {
+            weight: 100,
+            inherits: vec!["default".into()],
+            permissions: vec!["*".into()],
+            format: Some("&c[Admin] {name}".into()),
+            ..Group::default()
+        };
+
+        Self {
+            default_group: "default".into(),
+            groups: BTreeMap::from([
+                ("default".into(), Group::default()),
+                ("admin".into(), admin),
+            ]),
+            players: BTreeMap::new(),
+        }
+    }
+}
+
+/// Sent after permissions change, for anything that depends on them.
+pub struct PermissionsChanged;
+
+/// Whether each player has one permission, worked out ahead of time.
+#[derive(Clone, Debug, Default)]
+pub struct PermissionHolders {
+    /// The answer for players not listed in the file.
+    default: bool,
+    players: HashMap<Uuid, bool>,
+}
+
+impl PermissionHolders {
+    pub fn includes(&self, uuid: Uuid) -> bool {
+        self.players.get(&uuid).copied().unwrap_or(self.default)
+    }
+}
+
+/// Who may do what, read from a TOML file of groups and players.
+#[derive(Resource, Debug)]
+pub struct Permissions {
+    file: PermissionsFile,
+    path: PathBuf,
+}
+
+impl Permissions {
+    /// Reads the permissions. If the file doesn't exist yet, it's written
+    /// with a `default` group and an `admin` group that can do anything, so
+    /// there's something to put players in.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = PathBuf::from(DEFAULT_FILE);
+
+        if !path.exists() {
+            let permissions = Self {
+                file: PermissionsFile::default(),
+                path,
+            };
+            // Nothing is running yet, so this is written straight away.
+            fs::write(&permissions.path, toml::to_string(&permissions.file)?)
+                .with_context(|| format!("writing {}", permissions.path.display()))?;
+            info!(
+                "Wrote default permissions to {}",
+                permissions.path.display()
+            );
+            return Ok(permissions);
+        }
+
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(path, &contents)
+    }
+
+    /// Reads the permissions again while the server runs. A save that's
+    /// still queued is read from memory rather than waited for, and a
+    /// missing file is queued to be written with the defaults.
+    pub fn reload(persistence: &Persistence) -> anyhow::Result<Self> {
+        let path = PathBuf::from(DEFAULT_FILE);
+
+        let Some(contents) = persistence.read(&path)? else {
+            let permissions = Self {
+                file: PermissionsFile::default(),
+                path,
+            };
+            permissions.save(persistence)?;
+            info!(
+                "Wrote default permissions to {}",
+                permissions.path.display()
+            );
+            return Ok(permissions);
+        };
+
+        let contents =
+            String::from_utf8(contents).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(path, &contents)
+    }
+
+    fn parse(path: PathBuf, contents: &str) -> anyhow::Result<Self> {
+        let file: PermissionsFile =
+            toml::from_str(contents).with_context(|| format!("parsing {}", path.display()))?;
+
+        ensure!(
+            file.groups.contains_key(&file.default_group),
+            "the default group `{}` doesn't exist",
+            file.default_group
+        );
+
+        Ok(Self { file, path })
+    }
+
+    /// Whether a player has a permission. The player's own nodes are checked
+    /// first, then their group's, then the groups it inherits from. Within
+    /// each, an exact node beats a wildcard and a narrower wildcard beats a
+    /// wider one.
+    pub fn has_permission(&self, uuid: Uuid, permission: &str) -> bool {
+        if let Some(allowed) = self
+            .file
+            .players
+            .get(&uuid)
+            .and_then(|player| decide(&player.permissions, permission))
+        {
+            return allowed;
+        }
+
+        self.group_has_permission(self.group_of(uuid), permission)
+    }
+
+    /// Whether a group has a permission, through its own nodes or the
+    /// groups it inherits from.
+    fn group_has_permission(&self, group: &str, permission: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![group];
+
+        // Depth-first, so a group's own nodes come before its parents'.
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            let Some(group) = self.file.groups.get(name) else {
+                continue;
+            };
+            if let Some(allowed) = decide(&group.permissions, permission) {
+                return allowed;
+            }
+            pending.extend(group.inherits.iter().rev().map(String::as_str));
+        }
+
+        false
+    }
+
+    /// Everyone a permission applies to, for checks away from the tick
+    /// thread like the one at login.
+    pub fn holders(&self, permission: &str) -> PermissionHolders {
+        PermissionHolders {
+            default: self.group_has_permission(&self.file.default_group, permission),
+            players: self
+                .file
+                .players
+                .keys()
+                .map(|&uuid| (uuid, self.has_permission(uuid, permission)))
+                .collect(),
+        }
+    }
+
+    /// Whether a player may run a command.
+    pub fn may_run(&self, uuid: Uuid, info: &CommandInfo) -> bool {
+        info.permission
+            .map_or(true, |permission| self.has_permission(uuid, permission))
+    }
+
+    pub fn group_of(&self, uuid: Uuid) -> &str {
+        self.file
+            .players
+            .get(&uuid)
+            .and_then(|player| player.group.as_deref())
+            .unwrap_or(&self.file.default_group)
+    }
+
+    /// The first value a player's group, or a group it inherits from, gives
+    /// for a setting. Groups are searched in the same order as for
+    /// permissions.
+    fn group_setting<'a, T>(
+        &'a self,
+        uuid: Uuid,
+        setting: impl Fn(&'a Group) -> Option<T>,
+    ) -> Option<T> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![self.group_of(uuid)];
+
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            let Some(group) = self.file.groups.get(name) else {
+                continue;
+            };
+            if let Some(value) = setting(group) {
+                return Some(value);
+            }
+            pending.extend(group.inherits.iter().rev().map(String::as_str));
+        }
+
+        None
+    }
+
+    /// The game mode a player's group puts them in in a world, if any.
+    pub fn game_mode(&self, uuid: Uuid, world: &str) -> Option<ConfigGameMode> {
+        self.group_setting(uuid, |group| group.game_modes.get(world).copied())
+    }
+
+    /// The fastest a player's group lets them set `/speed`, if it's limited.
+    pub fn max_speed(&self, uuid: Uuid) -> Option<u8> {
+        self.group_setting(uuid, |group| group.max_speed)
+    }
+
+    /// What goes before and after a player's name, split from their group's
+    /// format at `{name}`. Both are empty if the group has no format.
+    pub fn name_format(&self, uuid: Uuid) -> (&str, &str) {
+        match self.group_setting(uuid, |group| group.format.as_deref()) {
+            Some(format) => format.split_once("{name}").unwrap_or((format, "")),
+            None => ("", ""),
+        }
+    }
+
+    /// The weight of a group, or 0 if it doesn't exist.
+    pub fn weight(&self, group: &str) -> i32 {
+        self.file.groups.get(group).map_or(0, |group| group.weight)
+    }
+
+    fn player(&mut self, uuid: Uuid, name: &str) -> &mut PlayerPermissions {
+        let player = self.file.players.entry(uuid).or_default();
+        player.name = name.to_owned();
+        player
+    }
+
+    /// Whether `group` is `ancestor` or inherits from it, however indirectly.
+    fn inherits_from(&self, group: &str, ancestor: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![group];
+
+        while let Some(name) = pending.pop() {
+            if name == ancestor {
+                return true;
+            }
+            if visited.insert(name) {
+                if let Some(group) = self.file.groups.get(name) {
+                    pending.extend(group.inherits.iter().map(String::as_str));
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Queues the permissions to be written. Failing to write them is
+    /// reported later, as a [`WriteFailed`](crate::persistence::WriteFailed).
+    fn save(&self, persistence: &Persistence) -> anyhow::Result<()> {
+        let contents = toml::to_string(&self.file)?;
+        persistence.write(
+            WriteKind::Permissions,
+            self.path.clone(),
+            contents.into_bytes(),
+        );
+        Ok(())
+    }
+}
+
+/// How closely a node pattern covers a permission, if at all.
+fn specificity(pattern: &str, permission: &str) -> Option<usize> {
+    if pattern == permission {
+        return Some(usize::MAX);
+    }
+    if pattern == "*" {
+        return Some(0);
+    }
+
+    let prefix = pattern.strip_suffix(".*")?;
+    let rest = permission.strip_prefix(prefix)?;
+    rest.starts_with('.').then_some(prefix.len() + 1)
+}
+
+/// What a list of nodes says about a permission, if anything. When two
+/// nodes are equally specific, the later one wins.
+fn decide(nodes: &[String], permission: &str) -> Option<bool> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let (allowed, pattern) = match node.strip_prefix('-') {
+                Some(pattern) => (false, pattern),
+                None => (true, node.as_str()),
+            };
+            specificity(pattern, permission).map(|s| (s, allowed))
+        })
+        .max_by_key(|(s, _)| *s)
+        .map(|(_, allowed)| allowed)
+}
+
+/// Whether a node is worth storing: dotted lowercase words or wildcards,
+/// optionally starting with `-`.
+fn valid_node(node: &str) -> bool {
+    let node = node.strip_prefix('-').unwrap_or(node);
+    !node.is_empty()
+        && node.split('.').all(|part| {
+            part == "*"
+                || (!part.is_empty()
+                    && part.chars().all(|c| {
+                        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-'
+                    }))
+        })
+}
+
+pub struct PermissionsPlugin;
+
+impl Plugin for PermissionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(PERM)
+            .add_event::<PermissionsChanged>()
+            .add_system_to_stage(EventLoop, perm_command);
+    }
+}
+
+/// Adds or removes a node from a list, or says why it couldn't.
+fn edit_nodes(nodes: &mut Vec<String>, node: &str, add: bool) -> Result<(), &'static str> {
+    let present = nodes.iter().position(|n| n == node);
+
+    match (add, present) {
+        (true, Some(_)) => Err("perm.already"),
+        (true, None) => {
+            nodes.push(node.to_owned());
+            Ok(())
+        }
+        (false, Some(idx)) => {
+            nodes.remove(idx);
+            Ok(())
+        }
+        (false, None) => Err("perm.missing"),
+    }
+}
+
+fn past(action: &str) -> &'static str {
+    if action == "add" {
+        "added"
+    } else {
+        "removed"
+    }
+}
+
+fn list_or_none(lang: &Lang, sender: Entity, items: &[String]) -> String {
+    if items.is_empty() {
+        lang.plain(sender, "perm.none").to_owned()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn perm_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    (mut permissions, persistence): (ResMut<Permissions>, Res<Persistence>),
+    mut profiles: ResMut<Profiles>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+    mut changed: EventWriter<PermissionsChanged>,
+) {
+    for event in events.iter().filter(|c| c.is(PERM.name)) {
+        let sender = event.sender;
+        let args: Vec<_> = event.args.iter().map(|a| a.to_ascii_lowercase()).collect();
+        let args: Vec<_> = args.iter().map(String::as_str).collect();
+
+        // Changes are logged and saved after the match; replies that don't
+        // change anything skip that.
+        let (reply, change) = match args.as_slice() {
+            ["group", "list"] => {
+                let groups: Vec<_> = permissions.file.groups.keys().cloned().collect();
+                let reply = lang.tr(
+                    sender,
+                    "perm.groups",
+                    &[("count", &groups.len()), ("groups", &groups.join(", "))],
+                );
+                (reply, None)
+            }
+            ["group", "create", group] => {
+                if permissions.file.groups.contains_key(*group) {
+                    (
+                        lang.tr(sender, "perm.group_exists", &[("group", group)]),
+                        None,
+                    )
+                } else {
+                    permissions
+                        .file
+                        .groups
+                        .insert(group.to_string(), Group::default());
+                    (
+                        lang.tr(sender, "perm.created", &[("group", group)]),
+                        Some(format!("created the group {group}")),
+                    )
+                }
+            }
+            ["group", "delete", group] => {
+                if *group == permissions.file.default_group {
+                    (lang.tr(sender, "perm.delete_default", &[]), None)
+                } else if permissions.file.groups.remove(*group).is_none() {
+                    (lang.tr(sender, "perm.no_group", &[("group", group)]), None)
+                } else {
+                    for other in permissions.file.groups.values_mut() {
+                        other.inherits.retain(|g| g != group);
+                    }
+                    for player in permissions.file.players.values_mut() {
+                        if player.group.as_deref() == Some(*group) {
+                            player.group = None;
+                        }
+                    }
+                    (
+                        lang.tr(sender, "perm.deleted", &[("group", group)]),
+                        Some(format!("deleted the group {group}")),
+                    )
+                }
+            }
+            ["group", "info", group] => match permissions.file.groups.get(*group) {
+                Some(info) => {
+                    let reply = lang.tr(
+                        sender,
+                        "perm.group_info",
+                        &[
+                            ("group", group),
+                            ("inherits", &list_or_none(&lang, sender, &info.inherits)),
+                            (
+                                "permissions",
+                                &list_or_none(&lang, sender, &info.permissions),
+                            ),
+                        ],
+                    );
+                    (reply, None)
+                }
+                None => (lang.tr(sender, "perm.no_group", &[("group", group)]), None),
+            },
+            ["group", action @ ("add" | "remove"), group, node] => {
+                let add = *action == "add";
+                if !valid_node(node) {
+                    (lang.tr(sender, "perm.invalid", &[("node", node)]), None)
+                } else if let Some(info) = permissions.file.groups.get_mut(*group) {
+                    match edit_nodes(&mut info.permissions, node, add) {
+                        Ok(()) => {
+                            let key = if add { "perm.added" } else { "perm.removed" };
+                            (
+                                lang.tr(sender, key, &[("target", group), ("node", node)]),
+                                Some(format!("{} {node} for the group {group}", past(action))),
+                            )
+                        }
+                        Err(key) => (
+                            lang.tr(sender, key, &[("target", group), ("node", node)]),
+                            None,
+                        ),
+                    }
+                } else {
+                    (lang.tr(sender, "perm.no_group", &[("group", group)]), None)
+                }
+            }
+            ["group", action @ ("inherit" | "uninherit"), group, parent] => {
+                let groups = &permissions.file.groups;
+                if !groups.contains_key(*group) {
+                    (lang.tr(sender, "perm.no_group", &[("group", group)]), None)
+                } else if !groups.contains_key(*parent) {
+                    (lang.tr(sender, "perm.no_group", &[("group", parent)]), None)
+                } else if *action == "inherit" {
+                    if permissions.inherits_from(parent, group) {
+                        let reply = lang.tr(
+                            sender,
+                            "perm.cycle",
+                            &[("group", group), ("parent", parent)],
+                        );
+                        (reply, None)
+                    } else {
+                        let info = permissions.file.groups.get_mut(*group).unwrap();
+                        if !info.inherits.iter().any(|g| g == parent) {
+                            info.inherits.push(parent.to_string());
+                        }
+                        (
+                            lang.tr(
+                                sender,
+                                "perm.inherited",
+                                &[("group", group), ("parent", parent)],
+                            ),
+                            Some(format!("made {group} inherit from {parent}")),
+                        )
+                    }
+                } else {
+                    let info = permissions.file.groups.get_mut(*group).unwrap();
+                    info.inherits.retain(|g| g != parent);
+                    (
+                        lang.tr(
+                            sender,
+                            "perm.uninherited",
+                            &[("group", group), ("parent", parent)],
+                        ),
+                        Some(format!("made {group} stop inheriting from {parent}")),
+                    )
+                }
+            }
+            ["player", "info", _] => {
+                let Some(player) = profiles.resolve(&event.args[2], event) else {
+                    continue;
+                };
+                let nodes = permissions
+                    .file
+                    .players
+                    .get(&player.uuid)
+                    .map(|p| p.permissions.clone())
+                    .unwrap_or_default();
+                let reply = lang.tr(
+                    sender,
+                    "perm.player_info",
+                    &[
+                        ("name", &player.name),
+                        ("group", &permissions.group_of(player.uuid)),
+                        ("permissions", &list_or_none(&lang, sender, &nodes)),
+                    ],
+                );
+                (reply, None)
+            }
+            ["player", "setgroup", _, group] => {
+                if !permissions.file.groups.contains_key(*group) {
+                    (lang.tr(sender, "perm.no_group", &[("group", group)]), None)
+                } else {
+                    let Some(player) = profiles.resolve(&event.args[2], event) else {
+                        continue;
+                    };
+                    permissions.player(player.uuid, &player.name).group = Some(group.to_string());
+                    (
+                        lang.tr(
+                            sender,
+                            "perm.set_group",
+                            &[("name", &player.name), ("group", group)],
+                        ),
+                        Some(format!("put {} in {group}", player.name)),
+                    )
+                }
+            }
+            ["player", action @ ("add" | "remove"), _, node] => {
+                let add = *action == "add";
+                if !valid_node(node) {
+                    (lang.tr(sender, "perm.invalid", &[("node", node)]), None)
+                } else {
+                    let Some(player) = profiles.resolve(&event.args[2], event) else {
+                        continue;
+                    };
+                    let nodes = &mut permissions.player(player.uuid, &player.name).permissions;
+                    let args: [(&str, &dyn std::fmt::Display); 2] =
+                        [("target", &player.name), ("node", node)];
+                    match edit_nodes(nodes, node, add) {
+                        Ok(()) => {
+                            let key = if add { "perm.added" } else { "perm.removed" };
+                            (
+                                lang.tr(sender, key, &args),
+                                Some(format!("{} {node} for {}", past(action), player.name)),
+                            )
+                        }
+                        Err(key) => (lang.tr(sender, key, &args), None),
+                    }
+                }
+            }
+            ["check", _, node] => {
+                let Some(player) = profiles.resolve(&event.args[1], event) else {
+                    continue;
+                };
+                let key = if permissions.has_permission(player.uuid, node) {
+                    "perm.check_yes"
+                } else {
+                    "perm.check_no"
+                };
+                let reply = lang.tr(sender, key, &[("name", &player.name), ("node", node)]);
+                (reply, None)
+            }
+            ["reload"] => {
+                // Read through persistence, so a change still queued to be
+                // saved isn't lost.
+                match Permissions::reload(&persistence) {
+                    Ok(new) => {
+                        *permissions = new;
+                        changed.send(PermissionsChanged);
+                        info!("Reloaded permissions");
+                        (lang.tr(sender, "perm.reloaded", &[]), None)
+                    }
+                    Err(e) => {
+                        warn!("Failed to reload permissions: {e:#}");
+                        let reply = lang.tr(
+                            sender,
+                            "perm.reload_failed",
+                            &[("error", &format!("{e:#}"))],
+                        );
+                        (reply, None)
+                    }
+                }
+            }
+            _ => (usage(&lang, sender, &PERM), None),
+        };
+
+        let reply = match change {
+            Some(change) => {
+                let name = sender_name(clients.get(sender).ok(), consoles.get(sender).ok());
+                info!("{name} {change}");
+                changed.send(PermissionsChanged);
+                match permissions.save(&persistence) {
+                    Ok(()) => reply,
+                    Err(e) => {
+                        warn!("Failed to save permissions: {e:#}");
+                        lang.tr(sender, "perm.unsaved", &[])
+                    }
+                }
+            }
+            None => reply,
+        };
+
+        if let Ok(mut client) = clients.get_mut(sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT: Uuid = Uuid::from_u128(1);
+    const BUILDER: Uuid = Uuid::from_u128(2);
+    const LOOPED: Uuid = Uuid::from_u128(3);
+
+    fn permissions() -> Permissions {
+        let contents = r#"
+            default_group = "default"
+
+            [groups.default]
+            permissions = ["plots.fly", "plots.command.spawn"]
+
+            [groups.builder]
+            inherits = ["default"]
+            permissions = ["plots.command.*", "-plots.command.stop"]
+
+            [groups.a]
+            inherits = ["b"]
+
+            [groups.b]
+            inherits = ["a"]
+            permissions = ["plots.b"]
+
+            [players.00000000-0000-0000-0000-000000000002]
+            group = "builder"
+            permissions = ["-plots.fly"]
+
+            [players.00000000-0000-0000-0000-000000000003]
+            group = "a"
+        "#;
+        Permissions::parse(DEFAULT_FILE.into(), contents).unwrap()
+    }
+
+    fn nodes(nodes: &[&str]) -> Vec<String> {
+        nodes.iter().map(|&node| node.to_owned()).collect()
+    }
+
+    #[test]
+    fn exact_beats_wildcard() {
+        let permission = "plots.command.tp";
+        assert_eq!(
+            decide(&nodes(&["plots.*", "-plots.command.tp"]), permission),
+            Some(false)
+        );
+        assert_eq!(
+            decide(&nodes(&["-plots.command.tp", "plots.*"]), permission),
+            Some(false)
+        );
+        assert_eq!(
+            decide(&nodes(&["-*", "plots.command.tp"]), permission),
+            Some(true)
+        );
+        // A narrower wildcard beats a wider one.
+        assert_eq!(
+            decide(&nodes(&["-plots.*", "plots.command.*"]), permission),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn negation_overrides_a_wildcard() {
+        let nodes = nodes(&["*", "-plots.command.stop"]);
+        assert_eq!(decide(&nodes, "plots.command.stop"), Some(false));
+        assert_eq!(decide(&nodes, "plots.command.tp"), Some(true));
+    }
+
+    #[test]
+    fn equally_specific_nodes_go_to_the_later() {
+        assert_eq!(decide(&nodes(&["*", "-*"]), "plots.fly"), Some(false));
+        assert_eq!(decide(&nodes(&["-*", "*"]), "plots.fly"), Some(true));
+        assert_eq!(
+            decide(&nodes(&["plots.fly", "-plots.fly"]), "plots.fly"),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn wildcards_only_cover_whole_parts() {
+        assert_eq!(specificity("plots.*", "plots.fly"), Some(6));
+        assert_eq!(specificity("plots.*", "plots"), None);
+        assert_eq!(specificity("plots.*", "plotsirv.fly"), None);
+        assert_eq!(specificity("plots.fly", "plots.flying"), None);
+        assert_eq!(decide(&nodes(&["plots.fly"]), "plots.command.tp"), None);
+    }
+
+    #[test]
+    fn player_nodes_beat_their_groups() {
+        let permissions = permissions();
+        // The default group, which builder inherits, gives flight.
+        assert!(permissions.has_permission(DEFAULT, "plots.fly"));
+        assert!(!permissions.has_permission(BUILDER, "plots.fly"));
+        // Otherwise the builder gets what the groups give.
+        assert!(permissions.has_permission(BUILDER, "plots.command.tp"));
+        assert!(!permissions.has_permission(BUILDER, "plots.command.stop"));
+        assert!(permissions.has_permission(BUILDER, "plots.command.spawn"));
+        assert!(!permissions.has_permission(DEFAULT, "plots.command.tp"));
+    }
+
+    #[test]
+    fn cyclic_inheritance_ends() {
+        let permissions = permissions();
+        assert!(permissions.has_permission(LOOPED, "plots.b"));
+        assert!(!permissions.has_permission(LOOPED, "plots.fly"));
+        assert!(permissions.inherits_from("a", "b"));
+        assert!(permissions.inherits_from("b", "a"));
+        assert!(!permissions.inherits_from("a", "default"));
+        assert!(permissions.inherits_from("builder", "default"));
+        assert!(!permissions.inherits_from("default", "builder"));
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use flume::TrySendError;
+use tracing::warn;
+use valence::prelude::*;
+
+/// How many writes can wait for the disk before each kind's
+/// [`WriteKind::drops_when_full`] applies.
+const QUEUE_LENGTH: usize = 1024;
+
+/// What's being written, which decides what happens when the disk can't
+/// keep up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteKind {
+    /// A player's data after something about them changed, or as they
+    /// leave, when it's about to be dropped from memory.
+    PlayerData,
+    /// A player's data saved only to count their playtime so far.
+    Playtime,
+    Bans,
+    Mutes,
+    Whitelist,
+    Permissions,
+    /// The config file, after a command changed it.
+    Config,
+    /// Block changes added to the end of a day's log.
+    BlockLog,
+}
+
+impl WriteKind {
+    /// Whether a write can be dropped rather than wait for room in the
+    /// queue. Playtime is saved again a minute later with everything this
+    /// save had, so losing one costs nothing; anything else would be lost
+    /// for good, so the tick waits instead.
+    fn drops_when_full(self) -> bool {
+        match self {
+            WriteKind::Playtime => true,
+            WriteKind::PlayerData
+            | WriteKind::Bans
+            | WriteKind::Mutes
+            | WriteKind::Whitelist
+            | WriteKind::Permissions
+            | WriteKind::Config
+            | WriteKind::BlockLog => false,
+        }
+    }
+}
+
+struct FileWrite {
+    kind: WriteKind,
+    path: PathBuf,
+    contents: Arc<[u8]>,
+    /// Which write to this path it is, so the thread only forgets the
+    /// pending contents if nothing newer was queued since.
+    id: u64,
+}
+
+/// The contents of every write that's been queued but not yet done, so
+/// reads see them without waiting for the disk.
+#[derive(Default)]
+struct Pending {
+    next_id: u64,
+    files: HashMap<PathBuf, (u64, Arc<[u8]>)>,
+}
+
+/// Contents to add to the end of a file, for logs.
+struct FileAppend {
+    kind: WriteKind,
+    path: PathBuf,
+    contents: Vec<u8>,
+}
+
+enum Command {
+    Write(FileWrite),
+    Append(FileAppend),
+    /// Answered once everything sent before it is on disk.
+    Flush(flume::Sender<()>),
+}
+
+/// A write that couldn't be done, sent back so whatever asked for it can
+/// try again or give up.
+#[derive(Clone, Debug)]
+pub struct WriteFailed {
+    pub kind: WriteKind,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Writes files on a thread of their own, so a slow disk never holds up a
+/// tick. Everything the server saves while running goes through here.
+/// Writes queued up while the last batch was being written go out
+/// together, and only the newest of several to the same file is written.
+#[derive(Resource, Clone)]
+pub struct Persistence {
+    commands: flume::Sender<Command>,
+    failures: flume::Receiver<WriteFailed>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Persistence {
+    pub fn start() -> Self {
+        let (commands, queue) = flume::bounded(QUEUE_LENGTH);
+        let (failed, failures) = flume::unbounded();
+        let pending = Arc::new(Mutex::new(Pending::default()));
+
+        let written = pending.clone();
+        std::thread::Builder::new()
+            .name("persistence".into())
+            .spawn(move || run(queue, failed, written))
+            .expect("spawning the persistence thread");
+
+        Self {
+            commands,
+            failures,
+            pending,
+        }
+    }
+
+    /// Queues `contents` to replace the file at `path`, creating the
+    /// directories it's in.
+    pub fn write(&self, kind: WriteKind, path: PathBuf, contents: Vec<u8>) {
+        let contents: Arc<[u8]> = contents.into();
+        let (id, previous) = {
+            let mut pending = self.pending.lock().unwrap();
+            let id = pending.next_id;
+            pending.next_id += 1;
+            let previous = pending.files.insert(path.clone(), (id, contents.clone()));
+            (id, previous)
+        };
+        let write = Command::Write(FileWrite {
+            kind,
+            path,
+            contents,
+            id,
+        });
+
+        if !kind.drops_when_full() {
+            let _ = self.commands.send(write);
+            return;
+        }
+        if let Err(TrySendError::Full(Command::Write(write))) = self.commands.try_send(write) {
+            warn!(
+                "Not saving {} as the disk isn't keeping up",
+                write.path.display()
+            );
+            // The write before it, if any, is still queued.
+            let mut pending = self.pending.lock().unwrap();
+            if pending.files.get(&write.path).map(|(id, _)| *id) == Some(id) {
+                match previous {
+                    Some(previous) => pending.files.insert(write.path, previous),
+                    None => pending.files.remove(&write.path),
+                };
+            }
+        }
+    }
+
+    /// Queues `contents` to be added to the end of the file at `path`,
+    /// creating it and the directories it's in. Appends to a file are
+    /// written in the order they were queued.
+    pub fn append(&self, kind: WriteKind, path: PathBuf, contents: Vec<u8>) {
+        let _ = self.commands.send(Command::Append(FileAppend {
+            kind,
+            path,
+            contents,
+        }));
+    }
+
+    /// How many writes are waiting for the disk.
+    pub fn pending_writes(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// The file at `path` as it will be once everything queued so far is
+    /// written, or `None` if there's no such file. Queued contents come
+    /// from memory, so this never waits for the writing thread.
+    pub fn read(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some((_, contents)) = self.pending.lock().unwrap().files.get(path) {
+            return Ok(Some(contents.to_vec()));
+        }
+        match fs::read(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Whether there's a file at `path`, or one queued to be written.
+    pub fn exists(&self, path: &Path) -> bool {
+        self.pending.lock().unwrap().files.contains_key(path) || path.exists()
+    }
+
+    /// Waits until everything queued so far has been written. Only for
+    /// shutdown: anything else that wants to see a queued write should
+    /// [`Self::read`] it.
+    pub fn flush(&self) {
+        let (done, wait) = flume::bounded(1);
+        if self.commands.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Persistence::start())
+            .add_event::<WriteFailed>()
+            .add_system(report_failures);
+    }
+}
+
+fn report_failures(persistence: Res<Persistence>, mut events: EventWriter<WriteFailed>) {
+    for failure in persistence.failures.try_iter() {
+        warn!(
+            "Failed to save {}: {}",
+            failure.path.display(),
+            failure.error
+        );
+        events.send(failure);
+    }
+}
+
+fn run(
+    queue: flume::Receiver<Command>,
+    failed: flume::Sender<WriteFailed>,
+    pending: Arc<Mutex<Pending>>,
+) {
+    while let Ok(first) = queue.recv() {
+        let mut writes: Vec<FileWrite> = Vec::new();
+        let mut by_path = HashMap::new();
+        let mut appends: Vec<FileAppend> = Vec::new();
+        let mut appends_by_path = HashMap::new();
+        let mut flushes = Vec::new();
+
+        for command in std::iter::once(first).chain(queue.try_iter()) {
+            match command {
+                Command::Write(write) => match by_path.get(&write.path) {
+                    Some(&i) => writes[i] = write,
+                    None => {
+                        by_path.insert(write.path.clone(), writes.len());
+                        writes.push(write);
+                    }
+                },
+                Command::Append(append) => match appends_by_path.get(&append.path) {
+                    Some(&i) => appends[i].contents.extend(append.contents),
+                    None => {
+                        appends_by_path.insert(append.path.clone(), appends.len());
+                        appends.push(append);
+                    }
+                },
+                Command::Flush(done) => flushes.push(done),
+            }
+        }
+
+        for write in writes {
+            let result = write_file(&write.path, &write.contents);
+
+            let mut pending = pending.lock().unwrap();
+            if pending.files.get(&write.path).map(|(id, _)| *id) == Some(write.id) {
+                pending.files.remove(&write.path);
+            }
+            drop(pending);
+
+            if let Err(e) = result {
+                let _ = failed.send(WriteFailed {
+                    kind: write.kind,
+                    path: write.path,
+                    error: format!("{e:#}"),
+                });
+            }
+        }
+        for append in appends {
+            if let Err(e) = append_file(&append.path, &append.contents) {
+                let _ = failed.send(WriteFailed {
+                    kind: append.kind,
+                    path: append.path,
+                    error: format!("{e:#}"),
+                });
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Replaces a file by writing next to it and renaming, so a crash midway
+/// leaves the old file rather than half of the new one.
+fn write_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents).with_context(|| format!("writing {}", path.display()))?;
+    fs::rename(&temporary, path).with_context(|| format!("writing {}", path.display()))
+}
+
+fn append_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("writing {}", path.display()))
+}
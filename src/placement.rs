@@ -0,0 +1,987 @@
+use valence::prelude::*;
+use valence_protocol::BlockFace;
+
+/// How wide a player is, and how tall standing up.
+const PLAYER_WIDTH: f64 = 0.6;
+const PLAYER_HEIGHT: f64 = 1.8;
+
+/// Whether a block in `state` at `pos` would be inside a player whose feet
+/// are at `feet`. Blocks there's nothing to bump into, like torches, never
+/// are.
+pub fn inside_player(state: BlockState, pos: BlockPos, feet: DVec3) -> bool {
+    let half = PLAYER_WIDTH / 2.0;
+    let player_min = feet - DVec3::new(half, 0.0, half);
+    let player_max = feet + DVec3::new(half, PLAYER_HEIGHT, half);
+    let origin = DVec3::new(pos.x as f64, pos.y as f64, pos.z as f64);
+
+    state.collision_shapes().any(|shape| {
+        let min = origin + DVec3::new(shape.min.x, shape.min.y, shape.min.z);
+        let max = origin + DVec3::new(shape.max.x, shape.max.y, shape.max.z);
+        min.x < player_max.x
+            && max.x > player_min.x
+            && min.y < player_max.y
+            && max.y > player_min.y
+            && min.z < player_max.z
+            && max.z > player_min.z
+    })
+}
+
+/// Which way a block with a Facing property is turned when it's placed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    /// Faces the way the player is looking, like stairs climbing away from
+    /// them.
+    Away,
+    /// Faces back at the player, like the front of a furnace or chest.
+    Toward,
+    /// Faces out of the side of the block it was placed against, like a
+    /// ladder. Placed against a top or bottom, it faces the player instead.
+    FromFace,
+    /// Turned a quarter clockwise from the way the player is looking, like
+    /// an anvil.
+    Sideways,
+    /// Faces the way the player is looking, up and down included, like the
+    /// face of an observer.
+    AwayInAnyDirection,
+    /// Faces back at the player, up and down included, like a piston.
+    TowardInAnyDirection,
+    /// Faces out of whichever face of a block it was placed against, top
+    /// and bottom included, like an end rod.
+    FromAnyFace,
+}
+
+impl Orientation {
+    /// How blocks of `kind` are turned. Most face the player, which is also
+    /// right for anything new that isn't listed.
+    pub fn of(kind: BlockKind) -> Self {
+        let name = kind.to_str();
+        if ["_stairs", "_door", "_fence_gate", "_bed"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+        {
+            Orientation::Away
+        } else if ["ladder", "lever", "tripwire_hook"].contains(&name)
+            || name.ends_with("_trapdoor")
+            || name.ends_with("_button")
+        {
+            Orientation::FromFace
+        } else if ["anvil", "chipped_anvil", "damaged_anvil"].contains(&name) {
+            Orientation::Sideways
+        } else if name == "observer" {
+            Orientation::AwayInAnyDirection
+        } else if ["piston", "sticky_piston", "dispenser", "dropper", "barrel"].contains(&name) {
+            Orientation::TowardInAnyDirection
+        } else if ["end_rod", "lightning_rod"].contains(&name) {
+            Orientation::FromAnyFace
+        } else {
+            Orientation::Toward
+        }
+    }
+}
+
+/// A client's rotation made safe to do maths with: clients send whatever
+/// they like, so anything that isn't a number counts as 0, yaw is brought
+/// into `0..360` and pitch is clamped to straight up or down.
+pub fn sane_rotation(yaw: f32, pitch: f32) -> (f32, f32) {
+    let finite_or_zero = |angle: f32| if angle.is_finite() { angle } else { 0.0 };
+    let yaw = finite_or_zero(yaw).rem_euclid(360.0);
+    (
+        // A tiny negative yaw rounds up to 360 itself.
+        if yaw < 360.0 { yaw } else { 0.0 },
+        finite_or_zero(pitch).clamp(-90.0, 90.0),
+    )
+}
+
+/// The way a player looking along `yaw` faces, ignoring pitch.
+pub fn facing(yaw: f32) -> PropValue {
+    // TODO: client.facing()?
+    match yaw.rem_euclid(360.0) {
+        yaw if (45.0..135.0).contains(&yaw) => PropValue::West,
+        yaw if (135.0..225.0).contains(&yaw) => PropValue::North,
+        yaw if (225.0..315.0).contains(&yaw) => PropValue::East,
+        _ => PropValue::South,
+    }
+}
+
+/// The way a player looking along `yaw` and `pitch` faces most, which is up
+/// or down when they're looking more that way than any other, as vanilla
+/// works it out.
+pub fn looking(yaw: f32, pitch: f32) -> PropValue {
+    let (yaw_rad, pitch_rad) = (yaw.to_radians(), pitch.to_radians());
+    let horizontal = pitch_rad.cos() * yaw_rad.sin().abs().max(yaw_rad.cos().abs());
+    if pitch_rad.sin().abs() <= horizontal {
+        facing(yaw)
+    } else if pitch > 0.0 {
+        PropValue::Down
+    } else {
+        PropValue::Up
+    }
+}
+
+fn opposite(direction: PropValue) -> PropValue {
+    match direction {
+        PropValue::North => PropValue::South,
+        PropValue::South => PropValue::North,
+        PropValue::East => PropValue::West,
+        PropValue::West => PropValue::East,
+        PropValue::Up => PropValue::Down,
+        PropValue::Down => PropValue::Up,
+        other => other,
+    }
+}
+
+fn face_direction(face: BlockFace) -> PropValue {
+    match face {
+        BlockFace::Bottom => PropValue::Down,
+        BlockFace::Top => PropValue::Up,
+        BlockFace::North => PropValue::North,
+        BlockFace::South => PropValue::South,
+        BlockFace::West => PropValue::West,
+        BlockFace::East => PropValue::East,
+    }
+}
+
+/// The axis a block like a log lies along when placed against `face`, which
+/// is the way that face points whatever way the player is looking.
+fn face_axis(face: BlockFace) -> PropValue {
+    match face {
+        BlockFace::Bottom | BlockFace::Top => PropValue::Y,
+        BlockFace::North | BlockFace::South => PropValue::Z,
+        BlockFace::West | BlockFace::East => PropValue::X,
+    }
+}
+
+fn clockwise(direction: PropValue) -> PropValue {
+    match direction {
+        PropValue::North => PropValue::East,
+        PropValue::East => PropValue::South,
+        PropValue::South => PropValue::West,
+        PropValue::West => PropValue::North,
+        other => other,
+    }
+}
+
+/// The Facing of a block of `kind` placed by a player looking along `yaw`
+/// and `pitch` who clicked `face` of another block. Only the families that
+/// can face up or down look at pitch.
+pub fn placed_facing(kind: BlockKind, yaw: f32, pitch: f32, face: BlockFace) -> PropValue {
+    let (yaw, pitch) = sane_rotation(yaw, pitch);
+    let horizontal = facing(yaw);
+    match (Orientation::of(kind), face) {
+        (Orientation::Away, _) => horizontal,
+        (Orientation::Sideways, _) => clockwise(horizontal),
+        (Orientation::AwayInAnyDirection, _) => looking(yaw, pitch),
+        (Orientation::TowardInAnyDirection, _) => opposite(looking(yaw, pitch)),
+        (Orientation::FromAnyFace, _) => face_direction(face),
+        (Orientation::FromFace, BlockFace::Top | BlockFace::Bottom) | (Orientation::Toward, _) => {
+            opposite(horizontal)
+        }
+        (Orientation::FromFace, _) => face_direction(face),
+    }
+}
+
+/// Whether `state` is a slab of `kind` with room for its other half. Only
+/// slabs' Type is ever Top or Bottom.
+fn is_half_slab(state: BlockState, kind: BlockKind) -> bool {
+    state.to_kind() == kind
+        && matches!(
+            state.get(PropName::Type),
+            Some(PropValue::Top | PropValue::Bottom)
+        )
+}
+
+/// Whether placing `kind` against `face` of `clicked`, `cursor_y` of the way
+/// up it, fills in the other half of the slab clicked rather than going next
+/// to it. As in vanilla, that's when the click lands on the slab's open
+/// half: the top of a bottom slab, the underside of a top slab, or the side
+/// of either on the half that's empty.
+pub fn fills_clicked_slab(
+    kind: BlockKind,
+    clicked: BlockState,
+    face: BlockFace,
+    cursor_y: f32,
+) -> bool {
+    if !is_half_slab(clicked, kind) {
+        return false;
+    }
+    let side = matches!(
+        face,
+        BlockFace::North | BlockFace::South | BlockFace::West | BlockFace::East
+    );
+    let upper = cursor_y > 0.5;
+    match clicked.get(PropName::Type) {
+        Some(PropValue::Bottom) => matches!(face, BlockFace::Top) || (side && upper),
+        _ => matches!(face, BlockFace::Bottom) || (side && !upper),
+    }
+}
+
+/// The double slab made by placing `kind` where `existing` is, if that's a
+/// half slab of the same kind.
+pub fn combined_slab(kind: BlockKind, existing: BlockState) -> Option<BlockState> {
+    is_half_slab(existing, kind).then(|| {
+        existing
+            .set(PropName::Type, PropValue::Double)
+            .set(PropName::Waterlogged, PropValue::False)
+    })
+}
+
+/// Whether a block placed against `face` of another block, `cursor_y` of the
+/// way up it, goes in the top half of its space.
+fn in_top_half(face: BlockFace, cursor_y: f32) -> bool {
+    match face {
+        BlockFace::Bottom => true,
+        BlockFace::Top => false,
+        BlockFace::North | BlockFace::South | BlockFace::West | BlockFace::East => cursor_y > 0.5,
+    }
+}
+
+/// The state a block of `kind` is placed in by a player looking along `yaw`
+/// and `pitch` who clicked `face` of another block, `cursor_y` of the way up
+/// it. The rotation goes through [`sane_rotation`] first, so any is fine.
+pub fn placed_state(
+    kind: BlockKind,
+    yaw: f32,
+    pitch: f32,
+    face: BlockFace,
+    cursor_y: f32,
+) -> BlockState {
+    let (yaw, pitch) = sane_rotation(yaw, pitch);
+    let mut block_state = kind.to_state();
+
+    // TODO: Is there a better way to do this?
+    // - a has_prop api?
+    // - a is_stairs, is_slab, etc api?
+    if block_state.get(PropName::Facing).is_some() {
+        block_state = block_state.set(PropName::Facing, placed_facing(kind, yaw, pitch, face));
+    }
+    if block_state.get(PropName::Axis).is_some() {
+        block_state = block_state.set(PropName::Axis, face_axis(face));
+    }
+    // Levers, buttons and grindstones also say whether they're on a floor,
+    // wall or ceiling. On a floor or ceiling they face the way the player
+    // does, as in vanilla.
+    if block_state.get(PropName::Face).is_some() {
+        let (attached, direction) = match face {
+            BlockFace::Top => (PropValue::Floor, facing(yaw)),
+            BlockFace::Bottom => (PropValue::Ceiling, facing(yaw)),
+            _ => (PropValue::Wall, face_direction(face)),
+        };
+        block_state = block_state
+            .set(PropName::Face, attached)
+            .set(PropName::Facing, direction);
+    }
+
+    let half = match in_top_half(face, cursor_y) {
+        true => PropValue::Top,
+        false => PropValue::Bottom,
+    };
+    // Other blocks use the same properties for other things, like a chest's
+    // Type saying whether it's joined to another, or a door's Half which of
+    // its two blocks it is. Those start out as something other than Bottom,
+    // which only slabs' Type and stairs' and trapdoors' Half do.
+    if block_state.get(PropName::Type) == Some(PropValue::Bottom) {
+        block_state = block_state.set(PropName::Type, half);
+    }
+    if block_state.get(PropName::Half) == Some(PropValue::Bottom) {
+        block_state = block_state.set(PropName::Half, half);
+    }
+
+    // !TODO:
+    // - 2-high doors
+    // - Open/close (trap)doors
+    // - Stair bending
+
+    block_state
+}
+
+/// A click with a block in hand, as much of it as where the block goes
+/// depends on.
+#[derive(Clone, Copy, Debug)]
+pub struct Click {
+    pub kind: BlockKind,
+    /// The block clicked, and which face of it.
+    pub position: BlockPos,
+    pub face: BlockFace,
+    /// How far up the clicked face the cursor was.
+    pub cursor_y: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Where the feet of the player placing the block are.
+    pub feet: DVec3,
+}
+
+/// What a click with a block in hand comes to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Placement {
+    /// `new` goes at `position`, in place of `old`.
+    Place {
+        position: BlockPos,
+        old: BlockState,
+        new: BlockState,
+    },
+    /// The block clicked isn't loaded, so there's nowhere to put anything.
+    ClickedUnloaded,
+    /// Nothing goes at `position`, though the client may already show it
+    /// there.
+    Rejected {
+        position: BlockPos,
+        reason: Rejection,
+    },
+}
+
+/// Why a block couldn't go where a click put it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rejection {
+    OutsideBorder,
+    /// The block would go over the edge into a chunk that isn't loaded.
+    Unloaded,
+    /// Something that can't be replaced is already there.
+    Occupied,
+    /// It would be inside the player placing it.
+    InsidePlayer,
+}
+
+/// Works out where a click puts a block and in what state, reading blocks
+/// with `block_at`, which gives `None` where chunks aren't loaded. Nothing
+/// is changed.
+pub fn decide_placement(
+    click: &Click,
+    block_at: impl Fn(BlockPos) -> Option<BlockState>,
+    inside_border: impl Fn(BlockPos) -> bool,
+) -> Placement {
+    // Clicks past the edge of what's been generated, or from a client
+    // that's behind, place nothing.
+    let Some(clicked) = block_at(click.position) else {
+        return Placement::ClickedUnloaded;
+    };
+    let replace = clicked.is_replaceable()
+        || fills_clicked_slab(click.kind, clicked, click.face, click.cursor_y);
+
+    let position = if replace {
+        click.position
+    } else {
+        click.position.get_in_direction(click.face)
+    };
+    let reject = |reason| Placement::Rejected { position, reason };
+
+    if !inside_border(position) {
+        return reject(Rejection::OutsideBorder);
+    }
+    let Some(old) = block_at(position) else {
+        return reject(Rejection::Unloaded);
+    };
+    // Clicking a replaceable block like grass or water places into it;
+    // otherwise the block next to it has to be free, so a torch or someone
+    // else's block there isn't overwritten. Half a slab is free for the
+    // other half.
+    let new = match combined_slab(click.kind, old) {
+        Some(double) => double,
+        None if old.is_replaceable() => placed_state(
+            click.kind,
+            click.yaw,
+            click.pitch,
+            click.face,
+            click.cursor_y,
+        ),
+        None => return reject(Rejection::Occupied),
+    };
+    // Other entities don't stop a placement yet, but the player placing
+    // does, or they'd be stuck in their own block.
+    if inside_player(new, position, click.feet) {
+        return reject(Rejection::InsidePlayer);
+    }
+
+    Placement::Place { position, old, new }
+}
+
+/// Settles the item in `slot` once a placement is decided. In survival, it's
+/// used up only if the block went down; otherwise the slot is sent back to
+/// the client as it is, since it already guessed the item was used.
+pub fn settle_held_item(
+    inventory: &mut Inventory,
+    slot: u16,
+    placement: &Placement,
+    survival: bool,
+) {
+    if !survival {
+        return;
+    }
+    match placement {
+        Placement::Place { .. } => use_up_one(inventory, slot),
+        Placement::ClickedUnloaded | Placement::Rejected { .. } => resync_slot(inventory, slot),
+    }
+}
+
+/// Takes one item from a slot, emptying it if that was the last. The client
+/// guessed this already, but not necessarily for the same slot or stack, so
+/// the slot is always sent back to it: replace_slot marks it as changed, and
+/// the inventory sync sends it at the end of the tick with a new state id,
+/// which the client takes over whatever it guessed.
+pub fn use_up_one(inventory: &mut Inventory, slot: u16) {
+    // The stack is taken out and put back one smaller, rather than cloned.
+    if let Some(mut stack) = inventory.replace_slot(slot, None) {
+        if stack.count() > 1 {
+            stack.set_count(stack.count() - 1);
+            inventory.replace_slot(slot, Some(stack));
+        }
+    }
+}
+
+/// Sends a client the stack in one of its slots again, undoing what it
+/// predicted a placement that didn't happen would do to it.
+pub fn resync_slot(inventory: &mut Inventory, slot: u16) {
+    // Taking the stack out and putting it back marks the slot as changed,
+    // so it's sent even though it ends up the same.
+    let stack = inventory.replace_slot(slot, None);
+    inventory.replace_slot(slot, stack);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDES: [BlockFace; 4] = [
+        BlockFace::North,
+        BlockFace::South,
+        BlockFace::West,
+        BlockFace::East,
+    ];
+
+    /// A property of `kind` placed against `face`, `cursor_y` of the way up.
+    fn placed(
+        kind: BlockKind,
+        face: BlockFace,
+        cursor_y: f32,
+        prop: PropName,
+    ) -> Option<PropValue> {
+        placed_state(kind, 0.0, 0.0, face, cursor_y).get(prop)
+    }
+
+    /// Checks that `prop` of `kind` goes in the bottom half on a floor, the
+    /// top half on a ceiling, and the half that was clicked on a side.
+    fn assert_halves(kind: BlockKind, prop: PropName) {
+        assert_eq!(
+            placed(kind, BlockFace::Top, 1.0, prop),
+            Some(PropValue::Bottom)
+        );
+        assert_eq!(
+            placed(kind, BlockFace::Bottom, 0.0, prop),
+            Some(PropValue::Top)
+        );
+        for face in SIDES {
+            assert_eq!(
+                placed(kind, face, 0.25, prop),
+                Some(PropValue::Bottom),
+                "{face:?}"
+            );
+            assert_eq!(
+                placed(kind, face, 0.75, prop),
+                Some(PropValue::Top),
+                "{face:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn slab_type_follows_the_face() {
+        assert_halves(BlockKind::OakSlab, PropName::Type);
+    }
+
+    #[test]
+    fn stair_half_follows_the_face() {
+        assert_halves(BlockKind::OakStairs, PropName::Half);
+    }
+
+    #[test]
+    fn trapdoor_half_follows_the_face() {
+        assert_halves(BlockKind::OakTrapdoor, PropName::Half);
+    }
+
+    #[test]
+    fn facing_per_family_and_quadrant() {
+        use PropValue::{East, North, South, Up, West};
+
+        // One block of each family, and which way it faces for a player
+        // looking south, west, north and east, placed on top of a block.
+        let cases = [
+            (BlockKind::OakStairs, [South, West, North, East]),
+            (BlockKind::Furnace, [North, East, South, West]),
+            (BlockKind::OakTrapdoor, [North, East, South, West]),
+            (BlockKind::Anvil, [West, North, East, South]),
+            (BlockKind::Observer, [South, West, North, East]),
+            (BlockKind::Piston, [North, East, South, West]),
+            (BlockKind::EndRod, [Up, Up, Up, Up]),
+        ];
+
+        for (kind, expected) in cases {
+            for (quadrant, expected) in expected.into_iter().enumerate() {
+                let yaw = quadrant as f32 * 90.0;
+                // Anywhere within the quadrant counts, wrapped or not.
+                for yaw in [yaw, yaw + 30.0, yaw - 30.0, yaw + 360.0, yaw - 360.0] {
+                    assert_eq!(
+                        placed_facing(kind, yaw, 0.0, BlockFace::Top),
+                        expected,
+                        "{kind:?} at yaw {yaw}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wall_blocks_face_out_of_the_clicked_side() {
+        for kind in [BlockKind::Ladder, BlockKind::OakTrapdoor, BlockKind::Lever] {
+            for face in SIDES {
+                assert_eq!(
+                    placed_facing(kind, 0.0, 0.0, face),
+                    face_direction(face),
+                    "{kind:?} on {face:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lever_and_button_attach_to_the_clicked_face() {
+        for kind in [BlockKind::Lever, BlockKind::StoneButton] {
+            let state = placed_state(kind, 90.0, 0.0, BlockFace::Top, 1.0);
+            assert_eq!(
+                state.get(PropName::Face),
+                Some(PropValue::Floor),
+                "{kind:?}"
+            );
+            assert_eq!(
+                state.get(PropName::Facing),
+                Some(PropValue::West),
+                "{kind:?}"
+            );
+
+            let state = placed_state(kind, 90.0, 0.0, BlockFace::Bottom, 0.0);
+            assert_eq!(
+                state.get(PropName::Face),
+                Some(PropValue::Ceiling),
+                "{kind:?}"
+            );
+            assert_eq!(
+                state.get(PropName::Facing),
+                Some(PropValue::West),
+                "{kind:?}"
+            );
+
+            for face in SIDES {
+                let state = placed_state(kind, 90.0, 0.0, face, 0.5);
+                assert_eq!(state.get(PropName::Face), Some(PropValue::Wall), "{kind:?}");
+                assert_eq!(
+                    state.get(PropName::Facing),
+                    Some(face_direction(face)),
+                    "{kind:?} on {face:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slab_fills_bottom_slab_from_above_or_upper_side() {
+        let slab = BlockKind::OakSlab.to_state();
+        let fills = |face, cursor_y| fills_clicked_slab(BlockKind::OakSlab, slab, face, cursor_y);
+
+        assert!(fills(BlockFace::Top, 0.5));
+        assert!(!fills(BlockFace::Bottom, 0.0));
+        for face in SIDES {
+            assert!(fills(face, 0.75), "{face:?}");
+            assert!(!fills(face, 0.25), "{face:?}");
+            // Like vanilla, exactly halfway is the bottom half, which the
+            // slab already takes up.
+            assert!(!fills(face, 0.5), "{face:?}");
+        }
+    }
+
+    #[test]
+    fn slab_fills_top_slab_from_below_or_lower_side() {
+        let slab = BlockKind::OakSlab
+            .to_state()
+            .set(PropName::Type, PropValue::Top);
+        let fills = |face, cursor_y| fills_clicked_slab(BlockKind::OakSlab, slab, face, cursor_y);
+
+        assert!(fills(BlockFace::Bottom, 0.5));
+        assert!(!fills(BlockFace::Top, 1.0));
+        for face in SIDES {
+            assert!(fills(face, 0.25), "{face:?}");
+            assert!(!fills(face, 0.75), "{face:?}");
+            assert!(fills(face, 0.5), "{face:?}");
+        }
+    }
+
+    #[test]
+    fn slab_never_fills_a_full_or_different_slab() {
+        let double = BlockKind::OakSlab
+            .to_state()
+            .set(PropName::Type, PropValue::Double);
+        let other = BlockKind::StoneSlab.to_state();
+        let faces = [BlockFace::Top, BlockFace::Bottom].into_iter().chain(SIDES);
+
+        for face in faces {
+            for cursor_y in [0.25, 0.5, 0.75] {
+                assert!(!fills_clicked_slab(
+                    BlockKind::OakSlab,
+                    double,
+                    face,
+                    cursor_y
+                ));
+                assert!(!fills_clicked_slab(
+                    BlockKind::OakSlab,
+                    other,
+                    face,
+                    cursor_y
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn looking_straight_up_or_down() {
+        for yaw in [0.0, 45.0, 90.0, 180.0, 270.0, 359.0] {
+            assert_eq!(looking(yaw, -90.0), PropValue::Up, "yaw {yaw}");
+            assert_eq!(looking(yaw, 90.0), PropValue::Down, "yaw {yaw}");
+        }
+    }
+
+    #[test]
+    fn looking_at_the_threshold() {
+        // Looking straight ahead, up or down wins past 45 degrees. Exactly
+        // at it, the horizontal direction does.
+        for (yaw, horizontal) in [
+            (0.0, PropValue::South),
+            (90.0, PropValue::West),
+            (180.0, PropValue::North),
+            (270.0, PropValue::East),
+        ] {
+            assert_eq!(looking(yaw, 45.0), horizontal, "yaw {yaw}");
+            assert_eq!(looking(yaw, -45.0), horizontal, "yaw {yaw}");
+            assert_eq!(looking(yaw, 44.9), horizontal, "yaw {yaw}");
+            assert_eq!(looking(yaw, 45.1), PropValue::Down, "yaw {yaw}");
+            assert_eq!(looking(yaw, -45.1), PropValue::Up, "yaw {yaw}");
+        }
+
+        // Looking diagonally, less of the view is horizontal, so up or down
+        // wins sooner.
+        assert_eq!(looking(45.0, 35.0), PropValue::West);
+        assert_eq!(looking(45.0, 36.0), PropValue::Down);
+        assert_eq!(looking(45.0, -36.0), PropValue::Up);
+    }
+
+    #[test]
+    fn sane_rotation_zeroes_non_finite_angles() {
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(sane_rotation(bad, 10.0), (0.0, 10.0), "{bad}");
+            assert_eq!(sane_rotation(10.0, bad), (10.0, 0.0), "{bad}");
+            assert_eq!(sane_rotation(bad, bad), (0.0, 0.0), "{bad}");
+        }
+    }
+
+    #[test]
+    fn sane_rotation_brings_huge_angles_into_range() {
+        let yaws = [
+            1e30,
+            -1e30,
+            f32::MAX,
+            f32::MIN,
+            -1e-7,
+            -1e-30,
+            -360.0,
+            720.0,
+        ];
+        for yaw in yaws {
+            let (sane, _) = sane_rotation(yaw, 0.0);
+            assert!((0.0..360.0).contains(&sane), "{yaw} became {sane}");
+        }
+
+        assert_eq!(sane_rotation(0.0, 1e30).1, 90.0);
+        assert_eq!(sane_rotation(0.0, -1e30).1, -90.0);
+        assert_eq!(sane_rotation(0.0, f32::MAX).1, 90.0);
+        assert_eq!(sane_rotation(0.0, f32::MIN).1, -90.0);
+    }
+
+    #[test]
+    fn placed_state_survives_bad_rotations() {
+        let kinds = [
+            BlockKind::OakStairs,
+            BlockKind::Furnace,
+            BlockKind::Observer,
+            BlockKind::Piston,
+            BlockKind::Lever,
+            BlockKind::OakSlab,
+        ];
+
+        for kind in kinds {
+            // Anything that isn't a number is treated as 0, the cursor
+            // included.
+            let zero = placed_state(kind, 0.0, 0.0, BlockFace::Top, 0.0);
+            for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+                assert_eq!(
+                    placed_state(kind, bad, bad, BlockFace::Top, bad),
+                    zero,
+                    "{kind:?} with {bad}"
+                );
+            }
+
+            // Huge angles wrap and clamp like any others.
+            assert_eq!(
+                placed_state(kind, 1e30, 1e30, BlockFace::North, 0.25),
+                placed_state(kind, 120.0, 90.0, BlockFace::North, 0.25),
+                "{kind:?}"
+            );
+            assert_eq!(
+                placed_facing(kind, f32::MIN, f32::MIN, BlockFace::Top),
+                placed_facing(kind, 0.0, -90.0, BlockFace::Top),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn chest_type_is_left_alone() {
+        let faces = [BlockFace::Top, BlockFace::Bottom].into_iter().chain(SIDES);
+        for face in faces {
+            for cursor_y in [0.25, 0.75] {
+                assert_eq!(
+                    placed(BlockKind::Chest, face, cursor_y, PropName::Type),
+                    Some(PropValue::Single),
+                    "{face:?} at {cursor_y}"
+                );
+            }
+        }
+    }
+
+    /// The first hotbar slot of a player inventory.
+    const HOTBAR: u16 = 36;
+
+    /// Stone at y 64 around the origin and air above it, except that a
+    /// torch stands at 1, 65, 0 and grass grows at 0, 65, 1. Nothing past
+    /// x 2 is loaded.
+    fn world(pos: BlockPos) -> Option<BlockState> {
+        if pos.x > 2 {
+            return None;
+        }
+        Some(match (pos.x, pos.y, pos.z) {
+            (1, 65, 0) => BlockState::TORCH,
+            (0, 65, 1) => BlockState::GRASS,
+            (_, 64, _) => BlockState::STONE,
+            _ => BlockState::AIR,
+        })
+    }
+
+    /// A click with planks on `face` of the block at `position`, by a player
+    /// standing well clear of it.
+    fn click(position: BlockPos, face: BlockFace) -> Click {
+        Click {
+            kind: BlockKind::OakPlanks,
+            position,
+            face,
+            cursor_y: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+            feet: DVec3::new(10.5, 65.0, 10.5),
+        }
+    }
+
+    fn decide(click: &Click) -> Placement {
+        decide_placement(click, world, |pos| pos.z > -5)
+    }
+
+    /// A player inventory holding `count` planks in the first hotbar slot.
+    fn holding(count: u8) -> Inventory {
+        let mut inventory = Inventory::new(InventoryKind::Player);
+        inventory.replace_slot(
+            HOTBAR,
+            Some(ItemStack::new(ItemKind::OakPlanks, count, None)),
+        );
+        inventory
+    }
+
+    fn count_after(placement: &Placement, survival: bool) -> Option<u8> {
+        let mut inventory = holding(5);
+        settle_held_item(&mut inventory, HOTBAR, placement, survival);
+        inventory.slot(HOTBAR).map(|stack| stack.count())
+    }
+
+    #[test]
+    fn places_next_to_a_solid_block() {
+        assert_eq!(
+            decide(&click(BlockPos::new(0, 64, 0), BlockFace::Top)),
+            Placement::Place {
+                position: BlockPos::new(0, 65, 0),
+                old: BlockState::AIR,
+                new: BlockState::OAK_PLANKS,
+            }
+        );
+    }
+
+    #[test]
+    fn places_into_a_replaceable_block() {
+        // Clicking the grass itself, or the stone under it, both replace it.
+        for (position, face) in [
+            (BlockPos::new(0, 65, 1), BlockFace::North),
+            (BlockPos::new(0, 64, 1), BlockFace::Top),
+        ] {
+            assert_eq!(
+                decide(&click(position, face)),
+                Placement::Place {
+                    position: BlockPos::new(0, 65, 1),
+                    old: BlockState::GRASS,
+                    new: BlockState::OAK_PLANKS,
+                },
+                "{position:?} {face:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn clicking_air_places_into_it() {
+        assert_eq!(
+            decide(&click(BlockPos::new(0, 70, 0), BlockFace::Top)),
+            Placement::Place {
+                position: BlockPos::new(0, 70, 0),
+                old: BlockState::AIR,
+                new: BlockState::OAK_PLANKS,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_should() {
+        let cases = [
+            // Above the torch.
+            (
+                click(BlockPos::new(1, 64, 0), BlockFace::Top),
+                Placement::Rejected {
+                    position: BlockPos::new(1, 65, 0),
+                    reason: Rejection::Occupied,
+                },
+            ),
+            // Beside the stone, into the stone next to it.
+            (
+                click(BlockPos::new(0, 64, 0), BlockFace::East),
+                Placement::Rejected {
+                    position: BlockPos::new(1, 64, 0),
+                    reason: Rejection::Occupied,
+                },
+            ),
+            // Over the edge of what's loaded.
+            (
+                click(BlockPos::new(2, 64, 0), BlockFace::East),
+                Placement::Rejected {
+                    position: BlockPos::new(3, 64, 0),
+                    reason: Rejection::Unloaded,
+                },
+            ),
+            // Past the border.
+            (
+                click(BlockPos::new(0, 64, -5), BlockFace::North),
+                Placement::Rejected {
+                    position: BlockPos::new(0, 64, -6),
+                    reason: Rejection::OutsideBorder,
+                },
+            ),
+            (
+                Click {
+                    feet: DVec3::new(0.5, 65.0, 0.5),
+                    ..click(BlockPos::new(0, 64, 0), BlockFace::Top)
+                },
+                Placement::Rejected {
+                    position: BlockPos::new(0, 65, 0),
+                    reason: Rejection::InsidePlayer,
+                },
+            ),
+            (
+                click(BlockPos::new(5, 64, 0), BlockFace::Top),
+                Placement::ClickedUnloaded,
+            ),
+        ];
+        for (click, expected) in cases {
+            assert_eq!(decide(&click), expected, "{click:?}");
+        }
+    }
+
+    #[test]
+    fn only_placing_uses_up_the_item() {
+        let placed = decide(&click(BlockPos::new(0, 64, 0), BlockFace::Top));
+        assert_eq!(count_after(&placed, true), Some(4));
+
+        let failed = [
+            Placement::ClickedUnloaded,
+            Placement::Rejected {
+                position: BlockPos::new(0, 65, 0),
+                reason: Rejection::OutsideBorder,
+            },
+            Placement::Rejected {
+                position: BlockPos::new(0, 65, 0),
+                reason: Rejection::Unloaded,
+            },
+            Placement::Rejected {
+                position: BlockPos::new(0, 65, 0),
+                reason: Rejection::Occupied,
+            },
+            Placement::Rejected {
+                position: BlockPos::new(0, 65, 0),
+                reason: Rejection::InsidePlayer,
+            },
+        ];
+        for placement in failed {
+            assert_eq!(count_after(&placement, true), Some(5), "{placement:?}");
+        }
+    }
+
+    #[test]
+    fn creative_keeps_the_stack() {
+        let placed = decide(&click(BlockPos::new(0, 64, 0), BlockFace::Top));
+        assert_eq!(count_after(&placed, false), Some(5));
+    }
+
+    /// Whether `slot` is marked as changed, so the inventory sync sends it
+    /// at the end of the tick.
+    fn marked(inventory: &Inventory, slot: u16) -> bool {
+        inventory.modified & (1 << slot) != 0
+    }
+
+    #[test]
+    fn placing_the_last_item_empties_the_slot() {
+        let placed = decide(&click(BlockPos::new(0, 64, 0), BlockFace::Top));
+        let mut inventory = holding(1);
+        inventory.modified = 0;
+        settle_held_item(&mut inventory, HOTBAR, &placed, true);
+        assert!(inventory.slot(HOTBAR).is_none());
+        // The client is sent the now empty slot, whatever it guessed.
+        assert!(marked(&inventory, HOTBAR));
+    }
+
+    #[test]
+    fn every_survival_click_sends_the_held_slot() {
+        let placed = decide(&click(BlockPos::new(0, 64, 0), BlockFace::Top));
+        let mut inventory = holding(5);
+        inventory.modified = 0;
+        settle_held_item(&mut inventory, HOTBAR, &placed, true);
+        assert_eq!(inventory.slot(HOTBAR).map(|stack| stack.count()), Some(4));
+        assert!(marked(&inventory, HOTBAR));
+
+        // A refused placement sends the stack back as it was.
+        let refused = Placement::Rejected {
+            position: BlockPos::new(0, 65, 0),
+            reason: Rejection::Occupied,
+        };
+        inventory.modified = 0;
+        settle_held_item(&mut inventory, HOTBAR, &refused, true);
+        assert_eq!(
+            inventory
+                .slot(HOTBAR)
+                .map(|stack| (stack.item, stack.count())),
+            Some((ItemKind::OakPlanks, 4))
+        );
+        assert!(marked(&inventory, HOTBAR));
+
+        // Creative inventories are left alone, so there's nothing to send.
+        inventory.modified = 0;
+        settle_held_item(&mut inventory, HOTBAR, &placed, false);
+        assert!(!marked(&inventory, HOTBAR));
+    }
+}
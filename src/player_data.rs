@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use plotsirv::disconnect::{AddDisconnectSystem, ClientRegistry};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use valence::prelude::*;
+
+use crate::ban::now_secs;
+use crate::config::ConfigGameMode;
+use crate::fly::DEFAULT_SPEED;
+use crate::persistence::{Persistence, WriteKind};
+use crate::tps::{Deferrable, Tps};
+
+const DEFAULT_DIR: &str = "playerdata";
+
+/// How often online players' playtime is saved, so a crash loses at most
+/// this much.
+const PLAYTIME_SAVE_SECS: u64 = 60;
+
+/// Settings that follow a player across sessions.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PlayerData {
+    /// Whether the player may fly outside of creative mode.
+    pub fly: bool,
+    /// Multipliers set with `/speed`.
+    pub fly_speed: u8,
+    pub walk_speed: u8,
+    /// Whether the sidebar scoreboard is shown.
+    pub sidebar: bool,
+    /// Whether the action bar coordinate display is shown.
+    pub hud: bool,
+    /// Whether feedback sounds are played.
+    pub sounds: bool,
+    /// Whether other players' private messages are shown.
+    pub social_spy: bool,
+    /// A name shown instead of the username, with `&` color codes.
+    pub nickname: Option<String>,
+    /// The game mode last chosen with `/gamemode`, used instead of each
+    /// world's default.
+    pub game_mode: Option<ConfigGameMode>,
+    /// The language chosen with `/lang`, used instead of the client's own.
+    pub language: Option<String>,
+    /// The view distance chosen with `/viewdistance`, if it's lower than
+    /// the server's.
+    pub view_distance: Option<u8>,
+    /// The username the player last joined with.
+    pub last_name: Option<String>,
+    /// Unix timestamps, in seconds. Players from before these were tracked
+    /// have no first join.
+    pub first_join: Option<u64>,
+    pub last_login: Option<u64>,
+    pub last_logout: Option<u64>,
+    /// Total time spent online, in seconds.
+    pub playtime_secs: u64,
+}
+
+impl Default for PlayerData {
+    fn default() -> Self {
+        Self {
+            fly: false,
+            fly_speed: DEFAULT_SPEED,
+            walk_speed: DEFAULT_SPEED,
+            sidebar: true,
+            hud: false,
+            sounds: true,
+            social_spy: false,
+            nickname: None,
+            game_mode: None,
+            language: None,
+            view_distance: None,
+            last_name: None,
+            first_join: None,
+            last_login: None,
+            last_logout: None,
+            playtime_secs: 0,
+        }
+    }
+}
+
+/// When a player has been around, for `/seen` and anything else that
+/// cares how active players are.
+#[derive(Clone, Debug)]
+pub struct Activity {
+    pub name: Option<String>,
+    /// Unix timestamps, in seconds.
+    pub first_join: Option<u64>,
+    pub last_login: Option<u64>,
+    pub last_logout: Option<u64>,
+    /// Including the current session.
+    pub playtime: Duration,
+    pub online: bool,
+}
+
+/// Player data for online players, stored as one TOML file per UUID.
+#[derive(Resource)]
+pub struct PlayerDataStore {
+    dir: PathBuf,
+    loaded: HashMap<Uuid, PlayerData>,
+    /// For each online player, when their playtime was last counted.
+    sessions: HashMap<Uuid, u64>,
+    /// Players whose file couldn't be read or moved aside, so saving would
+    /// overwrite it with defaults.
+    unsaved: HashSet<Uuid>,
+    persistence: Persistence,
+}
+
+impl FromWorld for PlayerDataStore {
+    fn from_world(world: &mut World) -> Self {
+        Self::new(DEFAULT_DIR, world.resource::<Persistence>().clone())
+    }
+}
+
+impl PlayerDataStore {
+    pub fn new(dir: impl Into<PathBuf>, persistence: Persistence) -> Self {
+        Self {
+            dir: dir.into(),
+            loaded: HashMap::new(),
+            sessions: HashMap::new(),
+            unsaved: HashSet::new(),
+            persistence,
+        }
+    }
+
+    fn path(&self, uuid: Uuid) -> PathBuf {
+        self.dir.join(format!("{uuid}.toml"))
+    }
+
+    /// Returns the data for a player, reading it from disk the first time.
+    /// A file that can't be read is moved aside, so the defaults used in
+    /// its place don't overwrite it.
+    pub fn get(&mut self, uuid: Uuid) -> &mut PlayerData {
+        if !self.loaded.contains_key(&uuid) {
+            let data = match self.read(uuid) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("Failed to load player data for {uuid}: {e:#}");
+                    self.set_aside(uuid);
+                    PlayerData::default()
+                }
+            };
+            self.loaded.insert(uuid, data);
+        }
+
+        self.loaded.get_mut(&uuid).unwrap()
+    }
+
+    /// Renames a player's unreadable file to end in `.bad`, or if that
+    /// fails, stops their data being saved until they leave.
+    fn set_aside(&mut self, uuid: Uuid) {
+        let path = self.path(uuid);
+        let aside = path.with_extension("toml.bad");
+        match fs::rename(&path, &aside) {
+            Ok(()) => warn!("Moved {} to {}", path.display(), aside.display()),
+            Err(e) => {
+                warn!(
+                    "Not saving player data for {uuid}, as {} couldn't be moved aside: {e}",
+                    path.display()
+                );
+                self.unsaved.insert(uuid);
+            }
+        }
+    }
+
+    fn read(&self, uuid: Uuid) -> anyhow::Result<PlayerData> {
+        // A player who rejoins straight away may still have their last
+        // session's save queued, which this reads rather than the disk.
+        let path = self.path(uuid);
+        let Some(contents) = self.persistence.read(&path)? else {
+            return Ok(PlayerData::default());
+        };
+
+        let contents =
+            String::from_utf8(contents).with_context(|| format!("reading {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Whether a player has data saved from an earlier session.
+    pub fn has_played_before(&self, uuid: Uuid) -> bool {
+        self.persistence.exists(&self.path(uuid))
+    }
+
+    /// Queues a loaded player's data to be written back to disk.
+    pub fn save(&self, uuid: Uuid) {
+        self.save_as(uuid, WriteKind::PlayerData);
+    }
+
+    fn save_as(&self, uuid: Uuid, kind: WriteKind) {
+        if self.unsaved.contains(&uuid) {
+            return;
+        }
+        let Some(data) = self.loaded.get(&uuid) else {
+            return;
+        };
+
+        match toml::to_string(data) {
+            Ok(contents) => self
+                .persistence
+                .write(kind, self.path(uuid), contents.into_bytes()),
+            Err(e) => warn!("Failed to save player data for {uuid}: {e}"),
+        }
+    }
+
+    /// Waits until every save queued so far is on disk, for shutdown.
+    pub fn flush(&self) {
+        self.persistence.flush();
+    }
+
+    /// Saves a player's data and drops it from memory.
+    pub fn unload(&mut self, uuid: Uuid) {
+        if self.sessions.contains_key(&uuid) {
+            self.count_playtime(uuid);
+            self.get(uuid).last_logout = Some(now_secs());
+            self.sessions.remove(&uuid);
+        }
+
+        self.save(uuid);
+        self.loaded.remove(&uuid);
+        self.unsaved.remove(&uuid);
+    }
+
+    /// Starts counting a player's playtime. Nothing is written yet, so
+    /// [`Self::has_played_before`] still tells new players apart for the
+    /// rest of the tick they join on.
+    fn start_session(&mut self, uuid: Uuid, name: &str) {
+        let now = now_secs();
+        let first = !self.has_played_before(uuid);
+
+        let data = self.get(uuid);
+        if first {
+            data.first_join = Some(now);
+        }
+        data.last_login = Some(now);
+        data.last_name = Some(name.to_owned());
+
+        self.sessions.insert(uuid, now);
+    }
+
+    /// Adds the time since playtime was last counted to a player's total.
+    fn count_playtime(&mut self, uuid: Uuid) {
+        let now = now_secs();
+        let Some(since) = self.sessions.insert(uuid, now) else {
+            return;
+        };
+
+        self.get(uuid).playtime_secs += now.saturating_sub(since);
+    }
+
+    /// A player's activity, or `None` if they've never joined. Reads from
+    /// disk for offline players without keeping their data loaded.
+    pub fn activity(&self, uuid: Uuid) -> Option<Activity> {
+        let read;
+        let data = match self.loaded.get(&uuid) {
+            Some(data) => data,
+            None if self.has_played_before(uuid) => match self.read(uuid) {
+                Ok(data) => {
+                    read = data;
+                    &read
+                }
+                Err(e) => {
+                    warn!("Failed to load player data for {uuid}: {e:#}");
+                    return None;
+                }
+            },
+            None => return None,
+        };
+
+        let session = self
+            .sessions
+            .get(&uuid)
+            .map_or(0, |since| now_secs().saturating_sub(*since));
+
+        Some(Activity {
+            name: data.last_name.clone(),
+            first_join: data.first_join,
+            last_login: data.last_login,
+            last_logout: data.last_logout,
+            playtime: Duration::from_secs(data.playtime_secs + session),
+            online: self.sessions.contains_key(&uuid),
+        })
+    }
+}
+
+pub struct PlayerDataPlugin;
+
+impl Plugin for PlayerDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerDataStore>()
+            .add_system(start_sessions)
+            .add_system(save_playtime)
+            .add_client_registry::<PlayerDataStore>();
+    }
+}
+
+fn start_sessions(clients: Query<&Client, Added<Client>>, mut store: ResMut<PlayerDataStore>) {
+    for client in &clients {
+        store.start_session(client.uuid(), client.username().as_str());
+    }
+}
+
+/// Saves online players' playtime every so often, so a crash doesn't lose
+/// whole sessions. When the tick is running late these wait, as they're
+/// still due next tick.
+fn save_playtime(mut store: ResMut<PlayerDataStore>, tps: Res<Tps>) {
+    let now = now_secs();
+    let due: Vec<_> = store
+        .sessions
+        .iter()
+        .filter(|(_, since)| now.saturating_sub(**since) >= PLAYTIME_SAVE_SECS)
+        .map(|(uuid, _)| *uuid)
+        .collect();
+    if due.is_empty() || tps.defer(Deferrable::PlaytimeSaves) {
+        return;
+    }
+
+    for uuid in due {
+        store.count_playtime(uuid);
+        store.save_as(uuid, WriteKind::Playtime);
+    }
+}
+
+impl ClientRegistry for PlayerDataStore {
+    fn forget(&mut self, _client: Entity, uuid: Uuid) {
+        self.unload(uuid);
+    }
+
+    fn entries(&self, _client: Entity, uuid: Uuid) -> usize {
+        usize::from(self.loaded.contains_key(&uuid))
+            + usize::from(self.sessions.contains_key(&uuid))
+            + usize::from(self.unsaved.contains(&uuid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::{Stage, SystemStage};
+
+    use super::*;
+    use crate::tps::Clock;
+
+    const PLAYER: Uuid = Uuid::from_u128(1);
+
+    #[test]
+    fn playtime_saves_wait_for_a_tick_with_time_to_spare() {
+        let mut store = PlayerDataStore::new(DEFAULT_DIR, Persistence::start());
+        store.loaded.insert(PLAYER, PlayerData::default());
+        let since = now_secs() - PLAYTIME_SAVE_SECS;
+        store.sessions.insert(PLAYER, since);
+        // Keeps the save itself from writing a file.
+        store.unsaved.insert(PLAYER);
+
+        let clock = Clock::manual();
+        let mut world = World::new();
+        world.insert_resource(store);
+        world.insert_resource(Tps::with_clock(clock.clone()));
+        let mut stage = SystemStage::single_threaded().with_system(save_playtime);
+
+        // A tick that has already used its whole budget puts the save off.
+        world.resource_mut::<Tps>().start_tick();
+        clock.advance(Duration::from_millis(50));
+        stage.run(&mut world);
+        world.resource_mut::<Tps>().finish_tick();
+
+        let store = world.resource::<PlayerDataStore>();
+        assert_eq!(store.sessions[&PLAYER], since);
+        assert_eq!(store.loaded[&PLAYER].playtime_secs, 0);
+        let deferred = world.resource::<Tps>().deferred(Duration::from_secs(60));
+        assert_eq!(deferred, [(Deferrable::PlaytimeSaves, 1)]);
+
+        // The next tick, on time, counts it.
+        world.resource_mut::<Tps>().start_tick();
+        stage.run(&mut world);
+        world.resource_mut::<Tps>().finish_tick();
+
+        let store = world.resource::<PlayerDataStore>();
+        assert!(store.sessions[&PLAYER] > since);
+        assert!(store.loaded[&PLAYER].playtime_secs >= PLAYTIME_SAVE_SECS);
+    }
+}
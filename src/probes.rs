@@ -0,0 +1,192 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+use valence::prelude::*;
+use valence_protocol::MINECRAFT_VERSION;
+
+use crate::config::Config;
+use crate::status::SharedStatus;
+use crate::WorldName;
+
+/// `/healthz` fails once the last tick ended this long ago.
+const STALL: Duration = Duration::from_secs(5);
+
+/// Probes send a request line and a few headers; anything longer is
+/// refused.
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// How long a connection gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the probes report, written by the tick thread and read by the
+/// listener without going near the ECS.
+struct ProbeState {
+    started: Instant,
+    /// Milliseconds after `started` that the last tick ended, or 0 before
+    /// the first.
+    last_tick: AtomicU64,
+    ready: AtomicBool,
+}
+
+impl ProbeState {
+    fn is_alive(&self) -> bool {
+        let last_tick = self.last_tick.load(Ordering::Relaxed);
+        let now = self.started.elapsed().as_millis() as u64;
+        last_tick > 0 && now.saturating_sub(last_tick) < STALL.as_millis() as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+/// The health check listener, if `server.health_bind` is set.
+#[derive(Resource)]
+pub struct Probes {
+    state: Arc<ProbeState>,
+    stop: flume::Sender<()>,
+}
+
+impl Probes {
+    /// Closes the listener, for when the server exits.
+    pub fn stop(&self) {
+        let _ = self.stop.send(());
+    }
+}
+
+pub struct ProbesPlugin;
+
+impl Plugin for ProbesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(start_probes)
+            .add_system_to_stage(CoreStage::Last, update_probes);
+    }
+}
+
+fn start_probes(mut commands: Commands, server: Res<Server>, config: Res<Config>) {
+    let Some(address) = config.server.health_bind else {
+        return;
+    };
+
+    let state = Arc::new(ProbeState {
+        started: Instant::now(),
+        last_tick: AtomicU64::new(0),
+        ready: AtomicBool::new(false),
+    });
+    let (stop, stopped) = flume::bounded(1);
+    commands.insert_resource(Probes {
+        state: state.clone(),
+        stop,
+    });
+
+    server.tokio_handle().spawn(async move {
+        if let Err(e) = listen(address, state, stopped).await {
+            warn!("Health checks stopped: {e:#}");
+        }
+    });
+}
+
+/// Records that a tick finished, and whether the server is ready: the
+/// worlds are loaded and logins aren't being turned away for a shutdown.
+fn update_probes(
+    probes: Option<Res<Probes>>,
+    worlds: Query<(), With<WorldName>>,
+    status: Res<SharedStatus>,
+) {
+    let Some(probes) = probes else {
+        return;
+    };
+
+    let now = probes.state.started.elapsed().as_millis() as u64;
+    probes.state.last_tick.store(now.max(1), Ordering::Relaxed);
+    probes.state.ready.store(
+        !worlds.is_empty() && status.is_accepting(),
+        Ordering::Relaxed,
+    );
+}
+
+async fn listen(
+    address: SocketAddr,
+    state: Arc<ProbeState>,
+    stopped: flume::Receiver<()>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("binding health checks to {address}"))?;
+    info!("Health checks listening on {address}");
+
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = stopped.recv_async() => return Ok(()),
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(REQUEST_TIMEOUT, serve(stream, &state)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => debug!("Health check from {remote} failed: {e:#}"),
+                Err(_) => debug!("Health check from {remote} timed out"),
+            }
+        });
+    }
+}
+
+/// Answers one HTTP request and closes the connection.
+async fn serve(mut stream: TcpStream, state: &ProbeState) -> anyhow::Result<()> {
+    // Only the request line matters, but the headers are read too so the
+    // connection isn't reset while the client is still sending them.
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+        if request.len() > MAX_REQUEST_LENGTH {
+            bail!("request too long");
+        }
+    }
+
+    let line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = std::str::from_utf8(line)?.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET" | "HEAD", "/healthz") if state.is_alive() => ("200 OK", "ok".to_owned()),
+        ("GET" | "HEAD", "/healthz") => ("503 Service Unavailable", "not ticking".to_owned()),
+        ("GET" | "HEAD", "/readyz") if state.is_ready() => ("200 OK", "ready".to_owned()),
+        ("GET" | "HEAD", "/readyz") => ("503 Service Unavailable", "not ready".to_owned()),
+        ("GET" | "HEAD", "/version") => (
+            "200 OK",
+            format!(
+                "{} {} (Minecraft {MINECRAFT_VERSION})",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+        ),
+        ("GET" | "HEAD", _) => ("404 Not Found", "not found".to_owned()),
+        _ => ("405 Method Not Allowed", "method not allowed".to_owned()),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    if method != "HEAD" {
+        response += &body;
+    }
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
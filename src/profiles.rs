@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use tokio::runtime::Handle;
+use tracing::warn;
+use valence::prelude::*;
+
+use crate::command::{CommandExecution, Console};
+use crate::lang::Lang;
+use crate::skin::fetch_profile;
+
+/// A player's UUID and the name they went by when it was looked up.
+#[derive(Clone, Debug)]
+pub struct KnownPlayer {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+/// A name lookup that finished on the async runtime, with the command that
+/// asked for it.
+struct Lookup {
+    name: String,
+    command: CommandExecution,
+    result: anyhow::Result<Option<KnownPlayer>>,
+}
+
+/// Resolves player names to UUIDs for commands that act on players who may
+/// not be online. Players seen since startup are remembered; other names are
+/// looked up through the Mojang API, and the command that needed them runs
+/// again once the answer is in.
+#[derive(Resource)]
+pub struct Profiles {
+    /// Keyed by lowercase name.
+    known: HashMap<String, KnownPlayer>,
+    /// Whether the server is in offline mode, where UUIDs come from names.
+    offline: bool,
+    runtime: Handle,
+    http: reqwest::Client,
+    sender: flume::Sender<Lookup>,
+    receiver: flume::Receiver<Lookup>,
+}
+
+impl Profiles {
+    fn new(server: &Server) -> Self {
+        let (sender, receiver) = flume::unbounded();
+        Self {
+            known: HashMap::new(),
+            offline: matches!(server.connection_mode(), ConnectionMode::Offline),
+            runtime: server.tokio_handle().clone(),
+            http: reqwest::Client::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    fn remember(&mut self, name: &str, player: KnownPlayer) {
+        self.known.insert(name.to_ascii_lowercase(), player);
+    }
+
+    /// Finds the player with a name. If it has to be looked up, this returns
+    /// `None` and `command` is run again when the lookup finishes.
+    pub fn resolve(&mut self, name: &str, command: &CommandExecution) -> Option<KnownPlayer> {
+        if let Some(player) = self.known.get(&name.to_ascii_lowercase()) {
+            return Some(player.clone());
+        }
+
+        if self.offline {
+            let player = KnownPlayer {
+                uuid: offline_uuid(name),
+                name: name.to_owned(),
+            };
+            self.remember(name, player.clone());
+            return Some(player);
+        }
+
+        let http = self.http.clone();
+        let results = self.sender.clone();
+        let (name, command) = (name.to_owned(), command.clone());
+
+        self.runtime.spawn(async move {
+            let result = fetch_profile(&http, &name).await.and_then(|profile| {
+                profile
+                    .map(|profile| {
+                        Ok(KnownPlayer {
+                            uuid: Uuid::parse_str(&profile.id).context("parsing profile UUID")?,
+                            name: profile.name,
+                        })
+                    })
+                    .transpose()
+            });
+            let _ = results.send(Lookup {
+                name,
+                command,
+                result,
+            });
+        });
+
+        None
+    }
+}
+
+/// The UUID Valence gives a player in offline mode.
+fn offline_uuid(name: &str) -> Uuid {
+    let hash = Sha256::digest(name);
+    Uuid::from_slice(&hash[..16]).expect("the slice is 16 bytes long")
+}
+
+pub struct ProfilesPlugin;
+
+impl Plugin for ProfilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(init_profiles)
+            .add_system(remember_clients)
+            .add_system(finish_lookups);
+    }
+}
+
+fn init_profiles(mut commands: Commands, server: Res<Server>) {
+    commands.insert_resource(Profiles::new(&server));
+}
+
+fn remember_clients(clients: Query<&Client, Added<Client>>, mut profiles: ResMut<Profiles>) {
+    for client in &clients {
+        let name = client.username().to_string();
+        profiles.remember(
+            &name,
+            KnownPlayer {
+                uuid: client.uuid(),
+                name: name.clone(),
+            },
+        );
+    }
+}
+
+fn finish_lookups(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    mut profiles: ResMut<Profiles>,
+    lang: Res<Lang>,
+    mut commands: EventWriter<CommandExecution>,
+) {
+    let lookups: Vec<_> = profiles.receiver.try_iter().collect();
+
+    for lookup in lookups {
+        let sender = lookup.command.sender;
+        let reply = match lookup.result {
+            Ok(Some(player)) => {
+                let name = player.name.clone();
+                profiles.remember(&lookup.name, player.clone());
+                profiles.remember(&name, player);
+                commands.send(lookup.command);
+                continue;
+            }
+            Ok(None) => lang.tr(sender, "command.unknown_player", &[("name", &lookup.name)]),
+            Err(e) => {
+                warn!("Failed to look up {}: {e:#}", lookup.name);
+                lang.tr(sender, "command.lookup_failed", &[("name", &lookup.name)])
+            }
+        };
+
+        if let Ok(mut client) = clients.get_mut(sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(sender) {
+            console.send_message(reply);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+use valence::client::event::{ChatCommand, ChatMessage};
+use valence::prelude::*;
+
+use crate::ban::now_secs;
+use crate::config::{Config, RateLimitConfig};
+use crate::kick;
+use crate::lang::Lang;
+use crate::logging::player_span;
+use crate::mute::{muted_message, Mute, Mutes};
+use crate::permissions::Permissions;
+use crate::persistence::Persistence;
+
+/// A chat message that's allowed through: its sender isn't muted or
+/// spamming.
+#[derive(Clone, Debug)]
+pub struct PlayerChat {
+    pub client: Entity,
+    pub message: String,
+}
+
+/// A command that's allowed through, without the leading slash.
+#[derive(Clone, Debug)]
+pub struct PlayerCommand {
+    pub client: Entity,
+    pub command: String,
+}
+
+/// Up to `burst` actions at once, refilling at a steady rate.
+pub struct Bucket {
+    tokens: f32,
+    updated: Instant,
+    /// Whether the last action was refused, so a run of refusals only
+    /// counts against the player once.
+    limited: bool,
+}
+
+impl Bucket {
+    pub fn new(burst: f32) -> Self {
+        Self {
+            tokens: burst,
+            updated: Instant::now(),
+            limited: false,
+        }
+    }
+
+    pub fn take(&mut self, burst: f32, per_second: f32, now: Instant) -> bool {
+        let refill = now.duration_since(self.updated).as_secs_f32() * per_second;
+        self.tokens = (self.tokens + refill).min(burst);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.limited = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+enum Verdict {
+    Allowed,
+    /// Refused, but already counted.
+    Refused,
+    /// Refused, and the player has now gone over a limit this many times.
+    Strike(u32),
+}
+
+#[derive(Component)]
+pub struct RateLimit {
+    chat: Bucket,
+    commands: Bucket,
+    strikes: u32,
+    last_strike: Instant,
+}
+
+impl RateLimit {
+    fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            chat: Bucket::new(config.chat_burst),
+            commands: Bucket::new(config.command_burst),
+            strikes: 0,
+            last_strike: Instant::now(),
+        }
+    }
+
+    fn check(&mut self, chat: bool, config: &RateLimitConfig) -> Verdict {
+        let now = Instant::now();
+        if now.duration_since(self.last_strike) >= Duration::from_secs(config.forgive_secs) {
+            self.strikes = 0;
+        }
+
+        let (bucket, burst, per_second) = if chat {
+            (&mut self.chat, config.chat_burst, config.chat_per_second)
+        } else {
+            (
+                &mut self.commands,
+                config.command_burst,
+                config.command_per_second,
+            )
+        };
+
+        if bucket.take(burst, per_second, now) {
+            return Verdict::Allowed;
+        }
+        if std::mem::replace(&mut bucket.limited, true) {
+            return Verdict::Refused;
+        }
+
+        self.strikes += 1;
+        self.last_strike = now;
+        Verdict::Strike(self.strikes)
+    }
+}
+
+/// Throttles chat and commands, passing on what gets through as
+/// [`PlayerChat`] and [`PlayerCommand`]. Tab completion isn't limited, as
+/// clients ask for it on every keystroke.
+pub struct RateLimitPlugin;
+
+impl Plugin for RateLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerChat>()
+            .add_event::<PlayerCommand>()
+            .add_system(init_rate_limits)
+            .add_system_to_stage(EventLoop, limit_chat)
+            .add_system_to_stage(EventLoop, limit_commands);
+    }
+}
+
+fn init_rate_limits(
+    mut commands: Commands,
+    clients: Query<Entity, Added<Client>>,
+    config: Res<Config>,
+) {
+    for entity in &clients {
+        commands
+            .entity(entity)
+            .insert(RateLimit::new(&config.rate_limit));
+    }
+}
+
+/// Whether a player gets past the rate limit.
+fn allow(
+    client: &Client,
+    limit: Option<Mut<RateLimit>>,
+    chat: bool,
+    permissions: &Permissions,
+    config: &RateLimitConfig,
+) -> Verdict {
+    // Players get their limits a tick after joining.
+    let Some(mut limit) = limit else {
+        return Verdict::Allowed;
+    };
+    if permissions.has_permission(client.uuid(), "plots.ratelimit.bypass") {
+        return Verdict::Allowed;
+    }
+
+    limit.check(chat, config)
+}
+
+/// Warns a player for going over a limit, or mutes or kicks them if they
+/// keep doing it.
+fn punish(
+    entity: Entity,
+    client: &mut Client,
+    strikes: u32,
+    mutes: &mut Mutes,
+    persistence: &Persistence,
+    config: &RateLimitConfig,
+    lang: &Lang,
+) {
+    let _span = player_span(client).entered();
+    if strikes >= config.kick_after {
+        info!("Kicking {} for spamming", client.username());
+        kick(client, lang.tr(entity, "rate_limit.kicked", &[]));
+    } else if strikes >= config.mute_after && mutes.active(client.uuid()).is_none() {
+        let now = now_secs();
+        let mute = Mute {
+            uuid: client.uuid(),
+            name: client.username().to_string(),
+            reason: Some(lang.plain_default("rate_limit.mute_reason").to_owned()),
+            muted_by: "Server".to_owned(),
+            created: now,
+            expires: Some(now + config.mute_secs),
+            lifted_by: None,
+        };
+
+        info!("Muted {} for spamming", client.username());
+        client.send_message(muted_message(lang, entity, &mute));
+        if let Err(e) = mutes.add(mute, persistence) {
+            warn!("Failed to save mutes: {e:#}");
+        }
+    } else {
+        client.send_message(lang.tr(entity, "rate_limit.warning", &[]));
+    }
+}
+
+pub fn limit_chat(
+    mut clients: Query<(&mut Client, Option<&mut RateLimit>)>,
+    (mut mutes, persistence): (ResMut<Mutes>, Res<Persistence>),
+    permissions: Res<Permissions>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut messages: EventReader<ChatMessage>,
+    mut allowed: EventWriter<PlayerChat>,
+) {
+    for message in messages.iter() {
+        let Ok((mut client, limit)) = clients.get_mut(message.client) else {
+            continue;
+        };
+
+        // Muted players are told so, and their messages go nowhere.
+        if let Some(mute) = mutes.active(client.uuid()) {
+            client.send_message(muted_message(&lang, message.client, mute));
+            continue;
+        }
+
+        match allow(&client, limit, true, &permissions, &config.rate_limit) {
+            Verdict::Allowed => allowed.send(PlayerChat {
+                client: message.client,
+                message: message.message.to_string(),
+            }),
+            Verdict::Refused => {}
+            Verdict::Strike(strikes) => punish(
+                message.client,
+                &mut client,
+                strikes,
+                &mut mutes,
+                &persistence,
+                &config.rate_limit,
+                &lang,
+            ),
+        }
+    }
+}
+
+pub fn limit_commands(
+    mut clients: Query<(&mut Client, Option<&mut RateLimit>)>,
+    (mut mutes, persistence): (ResMut<Mutes>, Res<Persistence>),
+    permissions: Res<Permissions>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<ChatCommand>,
+    mut allowed: EventWriter<PlayerCommand>,
+) {
+    for event in events.iter() {
+        let Ok((mut client, limit)) = clients.get_mut(event.client) else {
+            continue;
+        };
+
+        match allow(&client, limit, false, &permissions, &config.rate_limit) {
+            Verdict::Allowed => allowed.send(PlayerCommand {
+                client: event.client,
+                command: event.command.to_string(),
+            }),
+            Verdict::Refused => {}
+            Verdict::Strike(strikes) => punish(
+                event.client,
+                &mut client,
+                strikes,
+                &mut mutes,
+                &persistence,
+                &config.rate_limit,
+                &lang,
+            ),
+        }
+    }
+}
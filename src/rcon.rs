@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+use valence::prelude::*;
+
+use crate::command::{Console, ConsoleCommand};
+use crate::config::{Config, RconConfig};
+
+/// Packet types of the Source RCON protocol. The server answers logins with
+/// the same type number clients use for commands.
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+
+/// The largest body a response packet carries; longer output is split.
+const MAX_RESPONSE_BODY: usize = 4096;
+/// The largest request accepted, well above what clients send.
+const MAX_REQUEST_LENGTH: i32 = 4096 + 10;
+
+/// How long a command gets to reply before RCON answers with whatever it
+/// has, which may be nothing.
+const REPLY_TIMEOUT_TICKS: u64 = 100;
+
+const PASSWORD_VAR: &str = "PLOTSIRV_RCON_PASSWORD";
+
+/// How long to wait after a failed accept before the next, so an error that
+/// keeps happening, like running out of file descriptors, doesn't spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A command from an RCON connection, waiting to be run on the tick thread.
+struct RconRequest {
+    command: String,
+    output: flume::Sender<String>,
+}
+
+#[derive(Resource)]
+struct RconRequests(flume::Receiver<RconRequest>);
+
+/// An RCON command in progress, on its [`Console`] entity.
+#[derive(Component)]
+struct PendingReply {
+    output: flume::Sender<String>,
+    started: u64,
+}
+
+/// Failed logins by address, for locking out password guessers.
+struct Failures {
+    max: u32,
+    lockout: Duration,
+    by_ip: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+}
+
+impl Failures {
+    fn is_locked(&self, ip: IpAddr) -> bool {
+        let by_ip = self.by_ip.lock().unwrap();
+        by_ip.get(&ip).map_or(false, |&(count, first)| {
+            count >= self.max && first.elapsed() < self.lockout
+        })
+    }
+
+    /// Counts a failed login, returning whether the address is now locked
+    /// out.
+    fn record(&self, ip: IpAddr) -> bool {
+        let mut by_ip = self.by_ip.lock().unwrap();
+        let entry = by_ip.entry(ip).or_insert((0, Instant::now()));
+        if entry.1.elapsed() >= self.lockout {
+            *entry = (0, Instant::now());
+        }
+        entry.0 += 1;
+        entry.0 >= self.max
+    }
+
+    fn clear(&self, ip: IpAddr) {
+        self.by_ip.lock().unwrap().remove(&ip);
+    }
+}
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+/// Reads a packet, or `None` if the connection closed between packets.
+async fn read_packet(stream: &mut TcpStream) -> io::Result<Option<Packet>> {
+    let length = match stream.read_i32_le().await {
+        Ok(length) => length,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if !(10..=MAX_REQUEST_LENGTH).contains(&length) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad packet length {length}"),
+        ));
+    }
+
+    let mut buf = vec![0; length as usize];
+    stream.read_exact(&mut buf).await?;
+
+    let id = i32::from_le_bytes(buf[0..4].try_into().unwrap());
+    let kind = i32::from_le_bytes(buf[4..8].try_into().unwrap());
+    // The body ends with a null byte, and the packet with another.
+    let body = String::from_utf8_lossy(&buf[8..buf.len() - 2]).into_owned();
+
+    Ok(Some(Packet { id, kind, body }))
+}
+
+async fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(body.len() + 14);
+    buf.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(body);
+    buf.extend_from_slice(&[0, 0]);
+    stream.write_all(&buf).await
+}
+
+/// Splits output into response bodies, never inside a character.
+fn split_output(output: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = output;
+
+    while rest.len() > MAX_RESPONSE_BODY {
+        let mut end = MAX_RESPONSE_BODY;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+
+    chunks.push(rest);
+    chunks
+}
+
+async fn listen(
+    address: SocketAddr,
+    password: Arc<str>,
+    failures: Arc<Failures>,
+    requests: flume::Sender<RconRequest>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("binding RCON to {address}"))?;
+    info!("RCON listening on {address}");
+
+    loop {
+        // Accept errors are about the connection being accepted or the
+        // system running short, not the listener, so they don't end it.
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept an RCON connection: {e}");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+
+        if failures.is_locked(remote.ip()) {
+            debug!("Refused RCON connection from locked out {}", remote.ip());
+            continue;
+        }
+
+        let (password, failures, requests) = (password.clone(), failures.clone(), requests.clone());
+
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, remote, &password, &failures, &requests).await {
+                debug!("RCON connection from {remote} ended: {e:#}");
+            }
+        });
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    remote: SocketAddr,
+    password: &str,
+    failures: &Failures,
+    requests: &flume::Sender<RconRequest>,
+) -> anyhow::Result<()> {
+    let mut authenticated = false;
+
+    while let Some(packet) = read_packet(&mut stream).await? {
+        match packet.kind {
+            AUTH => {
+                if packet.body == password {
+                    authenticated = true;
+                    failures.clear(remote.ip());
+                    info!("RCON login from {remote}");
+                    write_packet(&mut stream, packet.id, AUTH_RESPONSE, b"").await?;
+                } else {
+                    authenticated = false;
+                    let locked = failures.record(remote.ip());
+                    warn!("Failed RCON login from {}", remote.ip());
+                    write_packet(&mut stream, -1, AUTH_RESPONSE, b"").await?;
+
+                    if locked {
+                        warn!("Locked {} out of RCON after repeated failures", remote.ip());
+                        return Ok(());
+                    }
+                }
+            }
+            EXEC_COMMAND if authenticated => {
+                let (output, result) = flume::bounded(1);
+                let request = RconRequest {
+                    command: packet.body,
+                    output,
+                };
+                requests.send(request).context("server stopped")?;
+
+                // An empty string if the command was dropped without output.
+                let output = result.recv_async().await.unwrap_or_default();
+                for chunk in split_output(&output) {
+                    write_packet(&mut stream, packet.id, RESPONSE_VALUE, chunk.as_bytes()).await?;
+                }
+            }
+            // Clients find the end of a multi-packet response by following
+            // their command with an empty response packet, which the Source
+            // server mirrors and then follows with this marker.
+            RESPONSE_VALUE if authenticated => {
+                write_packet(&mut stream, packet.id, RESPONSE_VALUE, b"").await?;
+                write_packet(&mut stream, packet.id, RESPONSE_VALUE, &[0, 1, 0, 0]).await?;
+            }
+            _ => {
+                write_packet(&mut stream, -1, AUTH_RESPONSE, b"").await?;
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub struct RconPlugin;
+
+impl Plugin for RconPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(start_rcon)
+            .add_system_to_stage(CoreStage::First, start_commands)
+            .add_system_to_stage(CoreStage::Last, finish_commands);
+    }
+}
+
+fn password(config: &RconConfig) -> String {
+    std::env::var(PASSWORD_VAR).unwrap_or_else(|_| config.password.clone())
+}
+
+fn start_rcon(mut commands: Commands, server: Res<Server>, config: Res<Config>) {
+    let config = &config.rcon;
+    if !config.enabled {
+        return;
+    }
+
+    let password = password(config);
+    if password.is_empty() {
+        warn!("RCON is enabled but has no password; set one in the config or {PASSWORD_VAR}");
+        return;
+    }
+
+    let failures = Arc::new(Failures {
+        max: config.max_failures.max(1),
+        lockout: Duration::from_secs(config.lockout_secs),
+        by_ip: Mutex::default(),
+    });
+    let (sender, receiver) = flume::unbounded();
+    commands.insert_resource(RconRequests(receiver));
+
+    let address = config.address;
+    server.tokio_handle().spawn(async move {
+        if let Err(e) = listen(address, password.into(), failures, sender).await {
+            warn!("RCON stopped: {e:#}");
+        }
+    });
+}
+
+/// Gives each RCON command its own console to collect the replies in.
+fn start_commands(
+    mut commands: Commands,
+    requests: Option<Res<RconRequests>>,
+    server: Res<Server>,
+    mut console_commands: EventWriter<ConsoleCommand>,
+) {
+    let Some(requests) = requests else {
+        return;
+    };
+
+    for request in requests.0.try_iter() {
+        let sender = commands
+            .spawn((
+                Console::new("RCON"),
+                PendingReply {
+                    output: request.output,
+                    started: server.current_tick(),
+                },
+            ))
+            .id();
+
+        console_commands.send(ConsoleCommand {
+            sender,
+            command: request.command,
+        });
+    }
+}
+
+fn finish_commands(
+    mut commands: Commands,
+    mut consoles: Query<(Entity, &mut Console, &PendingReply)>,
+    server: Res<Server>,
+) {
+    for (entity, mut console, pending) in &mut consoles {
+        let timed_out = server.current_tick() - pending.started >= REPLY_TIMEOUT_TICKS;
+
+        if console.has_output() || timed_out {
+            let _ = pending.output.send(console.take_output());
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use tracing::{error, info, warn};
+use valence::prelude::*;
+
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::permissions::{Permissions, PermissionsChanged};
+use crate::persistence::Persistence;
+
+const RELOAD: CommandInfo = CommandInfo {
+    name: "reload",
+    aliases: &[],
+    usage: "/reload",
+    description: "Re-read the configuration, language and permission files.",
+    permission: Some("plots.command.reload"),
+    console: true,
+};
+
+/// Sent after the config file has been re-read, for systems that cache
+/// anything derived from it. The language and permission files have been
+/// re-read by then too.
+pub struct ConfigReloaded;
+
+pub struct ReloadPlugin;
+
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(RELOAD)
+            .add_event::<ConfigReloaded>()
+            .add_system_to_stage(EventLoop, reload_command);
+    }
+}
+
+/// Everything `/reload` reads, loaded in full before any of it is used so a
+/// mistake in one file leaves all of them as they were.
+struct Loaded {
+    config: Config,
+    lang: Lang,
+    permissions: Permissions,
+}
+
+/// The config and permissions are read through [`Persistence`], so a change
+/// still queued to be saved is what's read rather than lost.
+fn load(old: &Config, persistence: &Persistence) -> anyhow::Result<Loaded> {
+    let config = old.reload(persistence)?;
+    let lang = Lang::load(&config.lang)?;
+    let permissions = Permissions::reload(persistence)?;
+
+    Ok(Loaded {
+        config,
+        lang,
+        permissions,
+    })
+}
+
+/// Puts back the settings that are only read at startup, so the config in
+/// memory keeps describing the running server. Returns the ones the file
+/// changed.
+fn keep_startup_settings(old: &Config, new: &mut Config) -> Vec<String> {
+    fn keep<T: PartialEq + Clone>(name: &str, old: &T, new: &mut T, skipped: &mut Vec<String>) {
+        if old != new {
+            *new = old.clone();
+            skipped.push(name.to_owned());
+        }
+    }
+
+    let mut skipped = Vec::new();
+
+    keep(
+        "server.address",
+        &old.server.address,
+        &mut new.server.address,
+        &mut skipped,
+    );
+    keep(
+        "server.health_bind",
+        &old.server.health_bind,
+        &mut new.server.health_bind,
+        &mut skipped,
+    );
+    keep(
+        "server.connection_mode",
+        &old.server.connection_mode,
+        &mut new.server.connection_mode,
+        &mut skipped,
+    );
+    keep(
+        "server.prevent_proxy_connections",
+        &old.server.prevent_proxy_connections,
+        &mut new.server.prevent_proxy_connections,
+        &mut skipped,
+    );
+    keep(
+        "server.velocity_secret",
+        &old.server.velocity_secret,
+        &mut new.server.velocity_secret,
+        &mut skipped,
+    );
+    keep(
+        "server.compression_threshold",
+        &old.server.compression_threshold,
+        &mut new.server.compression_threshold,
+        &mut skipped,
+    );
+    keep(
+        "server.sneak_toggles_game_mode",
+        &old.server.sneak_toggles_game_mode,
+        &mut new.server.sneak_toggles_game_mode,
+        &mut skipped,
+    );
+    keep(
+        "motd.favicon",
+        &old.motd.favicon,
+        &mut new.motd.favicon,
+        &mut skipped,
+    );
+    // Turned on and off with `/whitelist`, which also keeps the file in sync.
+    keep(
+        "whitelist.enabled",
+        &old.whitelist.enabled,
+        &mut new.whitelist.enabled,
+        &mut skipped,
+    );
+    keep(
+        "whitelist.file",
+        &old.whitelist.file,
+        &mut new.whitelist.file,
+        &mut skipped,
+    );
+    // Also turned on and off with a command, `/maintenance`.
+    keep(
+        "maintenance.enabled",
+        &old.maintenance.enabled,
+        &mut new.maintenance.enabled,
+        &mut skipped,
+    );
+    keep("rcon", &old.rcon, &mut new.rcon, &mut skipped);
+    keep("runtime", &old.runtime, &mut new.runtime, &mut skipped);
+    keep(
+        "block_log",
+        &old.block_log,
+        &mut new.block_log,
+        &mut skipped,
+    );
+
+    // Borders are set up when a world is created, and changed with
+    // `/worldborder`.
+    for (name, world) in &mut new.worlds {
+        keep(
+            &format!("worlds.{name}.border"),
+            &old.world(name).border,
+            &mut world.border,
+            &mut skipped,
+        );
+    }
+    for (name, world) in &old.worlds {
+        if world.border.is_some() && !new.worlds.contains_key(name) {
+            new.worlds.insert(name.clone(), world.clone());
+            skipped.push(format!("worlds.{name}.border"));
+        }
+    }
+
+    skipped.sort_unstable();
+    skipped
+}
+
+fn reload_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    (mut config, persistence): (ResMut<Config>, Res<Persistence>),
+    mut lang: ResMut<Lang>,
+    mut permissions: ResMut<Permissions>,
+    mut events: EventReader<CommandExecution>,
+    mut reloaded: EventWriter<ConfigReloaded>,
+    mut permissions_changed: EventWriter<PermissionsChanged>,
+) {
+    for event in events.iter().filter(|c| c.is(RELOAD.name)) {
+        let replies = if !event.args.is_empty() {
+            vec![usage(&lang, event.sender, &RELOAD)]
+        } else {
+            match load(&config, &persistence) {
+                Ok(mut loaded) => {
+                    let skipped = keep_startup_settings(&config, &mut loaded.config);
+                    // What was kept isn't in the file, and saving shouldn't
+                    // put it there.
+                    if let Err(e) = loaded.config.mark_saved() {
+                        warn!("Failed to take in the reloaded config: {e:#}");
+                    }
+
+                    *config = loaded.config;
+                    lang.replace_messages(loaded.lang);
+                    *permissions = loaded.permissions;
+                    reloaded.send(ConfigReloaded);
+                    permissions_changed.send(PermissionsChanged);
+
+                    let name = sender_name(
+                        clients.get(event.sender).ok(),
+                        consoles.get(event.sender).ok(),
+                    );
+                    info!("{name} reloaded the config");
+
+                    let mut replies = vec![lang.tr(event.sender, "reload.done", &[])];
+                    if !skipped.is_empty() {
+                        let settings = skipped.join(", ");
+                        warn!("Not reloaded as they only apply at startup: {settings}");
+                        replies.push(lang.tr(
+                            event.sender,
+                            "reload.skipped",
+                            &[("settings", &settings)],
+                        ));
+                    }
+                    replies
+                }
+                Err(e) => {
+                    error!("Failed to reload config: {e:#}");
+                    vec![lang.tr(
+                        event.sender,
+                        "reload.failed",
+                        &[("error", &format!("{e:#}"))],
+                    )]
+                }
+            }
+        };
+
+        for reply in replies {
+            if let Ok(mut client) = clients.get_mut(event.sender) {
+                client.send_message(reply);
+            } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+                console.send_message(reply);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use tracing::{info, warn};
+use valence::client::event::ResourcePackStatusChange;
+use valence::prelude::*;
+use valence_protocol::types::ResourcePackStatus;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::format::legacy_text;
+use crate::kick;
+use crate::lang::Lang;
+
+const PACK: CommandInfo = CommandInfo {
+    name: "pack",
+    aliases: &["resourcepack"],
+    usage: "/pack",
+    description: "Get the server resource pack prompt again.",
+    permission: None,
+    console: false,
+};
+
+pub struct ResourcePackPlugin;
+
+impl Plugin for ResourcePackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(PACK)
+            .add_system(offer_on_join)
+            .add_system_to_stage(EventLoop, pack_command)
+            .add_system_to_stage(EventLoop, handle_pack_status);
+    }
+}
+
+fn offer_pack(client: &mut Client, config: &Config) -> bool {
+    let pack = &config.resource_pack;
+    let Some(url) = &pack.url else {
+        return false;
+    };
+
+    client.set_resource_pack(
+        url,
+        &pack.sha1.to_ascii_lowercase(),
+        pack.required,
+        pack.prompt.as_deref().map(legacy_text),
+    );
+    true
+}
+
+fn offer_on_join(mut clients: Query<&mut Client, Added<Client>>, config: Res<Config>) {
+    for mut client in &mut clients {
+        offer_pack(&mut client, &config);
+    }
+}
+
+fn pack_command(
+    mut clients: Query<&mut Client>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(PACK.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        if !event.args.is_empty() {
+            client.send_message(usage(&lang, event.sender, &PACK));
+        } else if !offer_pack(&mut client, &config) {
+            client.send_message(lang.tr(event.sender, "resource_pack.none", &[]));
+        }
+    }
+}
+
+fn handle_pack_status(
+    mut clients: Query<&mut Client>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<ResourcePackStatusChange>,
+) {
+    for event in events.iter() {
+        let Ok(mut client) = clients.get_mut(event.client) else {
+            continue;
+        };
+
+        match event.status {
+            ResourcePackStatus::SuccessfullyLoaded => {
+                info!("{} loaded the resource pack", client.username());
+            }
+            ResourcePackStatus::Accepted => {}
+            ResourcePackStatus::Declined if config.resource_pack.required => {
+                info!(
+                    "Kicking {} for declining the resource pack",
+                    client.username()
+                );
+                kick(
+                    &mut client,
+                    lang.tr(event.client, "resource_pack.required", &[]),
+                );
+            }
+            ResourcePackStatus::Declined => {
+                client.send_message(lang.tr(event.client, "resource_pack.declined", &[]));
+            }
+            ResourcePackStatus::FailedDownload => {
+                warn!("{} failed to download the resource pack", client.username());
+            }
+        }
+    }
+}
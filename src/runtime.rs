@@ -0,0 +1,32 @@
+use tokio::runtime::{Builder, Handle};
+use tracing::info;
+
+use crate::config::RuntimeConfig;
+
+/// Starts the async runtime that Valence and everything of ours that waits
+/// on the network or disk share, so there's only ever one.
+pub fn start(config: &RuntimeConfig) -> std::io::Result<Handle> {
+    let runtime = if config.single_thread {
+        info!("Running async tasks on a single thread");
+        Builder::new_current_thread().enable_all().build()?
+    } else {
+        let mut builder = Builder::new_multi_thread();
+        if config.worker_threads > 0 {
+            builder.worker_threads(config.worker_threads);
+            info!("Running async tasks on {} threads", config.worker_threads);
+        } else {
+            info!("Running async tasks on one thread per core");
+        }
+        builder.enable_all().build()?
+    };
+
+    let handle = runtime.handle().clone();
+
+    // A single-threaded runtime only runs while something blocks on it, and
+    // either kind stops when dropped, so it gets a thread to live on.
+    std::thread::Builder::new()
+        .name("async runtime".into())
+        .spawn(move || runtime.block_on(std::future::pending::<()>()))?;
+
+    Ok(handle)
+}
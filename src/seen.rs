@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use valence::prelude::*;
+
+use crate::ban::now_secs;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::format::{format_date, format_duration};
+use crate::lang::Lang;
+use crate::player_data::{Activity, PlayerDataStore};
+use crate::profiles::Profiles;
+
+const SEEN: CommandInfo = CommandInfo {
+    name: "seen",
+    aliases: &[],
+    usage: "/seen <player>",
+    description: "Show when a player was last online.",
+    permission: None,
+    console: true,
+};
+
+const PLAYTIME: CommandInfo = CommandInfo {
+    name: "playtime",
+    aliases: &[],
+    usage: "/playtime [player]",
+    description: "Show how long you or another player have played.",
+    permission: None,
+    console: true,
+};
+
+pub struct SeenPlugin;
+
+impl Plugin for SeenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(SEEN)
+            .add_command(PLAYTIME)
+            .add_system_to_stage(EventLoop, seen_command)
+            .add_system_to_stage(EventLoop, playtime_command);
+    }
+}
+
+fn describe(lang: &Lang, sender: Entity, name: &str, activity: &Activity) -> Text {
+    let last_seen = activity.last_logout.or(activity.last_login);
+    let status = if activity.online {
+        lang.tr(sender, "seen.online", &[("name", &name)])
+    } else if let Some(at) = last_seen {
+        let ago = format_duration(Duration::from_secs(now_secs().saturating_sub(at)));
+        lang.tr(sender, "seen.offline", &[("name", &name), ("ago", &ago)])
+    } else {
+        lang.tr(sender, "seen.offline_unknown", &[("name", &name)])
+    };
+
+    let first_join = activity.first_join.map_or_else(
+        || lang.plain(sender, "seen.unknown").to_owned(),
+        format_date,
+    );
+
+    status
+        + "\n"
+        + lang.tr(sender, "seen.first_join", &[("date", &first_join)])
+        + "\n"
+        + lang.tr(
+            sender,
+            "seen.playtime",
+            &[("time", &format_duration(activity.playtime))],
+        )
+}
+
+fn seen_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    store: Res<PlayerDataStore>,
+    mut profiles: ResMut<Profiles>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SEEN.name)) {
+        let reply = match event.args.as_slice() {
+            [target] => {
+                let Some(player) = profiles.resolve(target, event) else {
+                    continue;
+                };
+
+                match store.activity(player.uuid) {
+                    Some(activity) => {
+                        let name = activity.name.as_deref().unwrap_or(&player.name);
+                        describe(&lang, event.sender, name, &activity)
+                    }
+                    None => lang.tr(event.sender, "seen.never", &[("name", &player.name)]),
+                }
+            }
+            _ => usage(&lang, event.sender, &SEEN),
+        };
+
+        if let Ok(mut client) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+fn playtime_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    store: Res<PlayerDataStore>,
+    mut profiles: ResMut<Profiles>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(PLAYTIME.name)) {
+        let reply = match event.args.as_slice() {
+            [] => match clients.get(event.sender) {
+                Ok(client) => {
+                    let playtime = store
+                        .activity(client.uuid())
+                        .map_or(Duration::ZERO, |activity| activity.playtime);
+                    lang.tr(
+                        event.sender,
+                        "playtime.own",
+                        &[("time", &format_duration(playtime))],
+                    )
+                }
+                // Consoles have to name someone.
+                Err(_) => usage(&lang, event.sender, &PLAYTIME),
+            },
+            [target] => {
+                let Some(player) = profiles.resolve(target, event) else {
+                    continue;
+                };
+
+                match store.activity(player.uuid) {
+                    Some(activity) => {
+                        let name = activity.name.as_deref().unwrap_or(&player.name);
+                        lang.tr(
+                            event.sender,
+                            "playtime.other",
+                            &[
+                                ("name", &name),
+                                ("time", &format_duration(activity.playtime)),
+                            ],
+                        )
+                    }
+                    None => lang.tr(event.sender, "seen.never", &[("name", &player.name)]),
+                }
+            }
+            _ => usage(&lang, event.sender, &PLAYTIME),
+        };
+
+        if let Ok(mut client) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashSet;
+
+use plotsirv::disconnect::{Connection, DisconnectPlugin};
+use tracing::info;
+use valence::prelude::*;
+
+use crate::kick;
+use crate::lang::Lang;
+
+pub struct SessionsPlugin;
+
+impl Plugin for SessionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(DisconnectPlugin::<Client>::default())
+            // Before anything sees a new client as just added.
+            .add_system_to_stage(CoreStage::PreUpdate, hold_new_sessions::<Client>);
+    }
+}
+
+/// What [`hold_new_sessions`] needs of a client, so it can be tested with a
+/// stand-in.
+trait Session: Connection {
+    /// Disconnects the session, telling it it logged in elsewhere.
+    fn replace(&mut self, lang: &Lang, entity: Entity);
+}
+
+impl Session for Client {
+    fn replace(&mut self, lang: &Lang, entity: Entity) {
+        info!(
+            "Disconnecting {}'s old session, as they logged in again",
+            self.username()
+        );
+        kick(self, lang.tr(entity, "server.logged_in_elsewhere", &[]));
+    }
+}
+
+/// A client that logged in while an older session for the same account was
+/// still there. Its client is kept here rather than on its entity until that
+/// session is despawned, so nothing sets it up alongside the old one.
+#[derive(Component)]
+struct HeldLogin<C: Session> {
+    client: C,
+}
+
+/// Disconnects the old session when an account logs in again, after a drop
+/// the server hadn't noticed yet or from somewhere else entirely, so two
+/// clients never play as one player. The new session waits in an
+/// [`HeldLogin`] until the old one is gone, then gets its client back,
+/// which sets it up like any other joining client. Anything the old session
+/// saves as it leaves is done by then, so it can't overwrite the new one.
+fn hold_new_sessions<C: Session>(world: &mut World) {
+    let online: HashSet<Uuid> = world
+        .query::<&C>()
+        .iter(world)
+        .map(|client| client.uuid())
+        .collect();
+
+    // Sessions whose old one has gone get their client back first, so this
+    // tick sets them up.
+    let ready: Vec<_> = world
+        .query::<(Entity, &HeldLogin<C>)>()
+        .iter(world)
+        .filter(|(_, waiting)| !online.contains(&waiting.client.uuid()))
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in ready {
+        let mut entity = world.entity_mut(entity);
+        if let Some(HeldLogin { client }) = entity.remove::<HeldLogin<C>>() {
+            entity.insert(client);
+        }
+    }
+
+    let joined: Vec<_> = world
+        .query_filtered::<(Entity, &C), Added<C>>()
+        .iter(world)
+        .filter(|(_, client)| !client.is_disconnected())
+        .map(|(entity, client)| (entity, client.uuid()))
+        .collect();
+    for (new, uuid) in joined {
+        // Replaced by another login this tick.
+        if world.get::<C>(new).map_or(true, C::is_disconnected) {
+            continue;
+        }
+
+        // A login still waiting on an older session is replaced too, and
+        // never joins.
+        let waiting: Vec<_> = world
+            .query::<(Entity, &HeldLogin<C>)>()
+            .iter(world)
+            .filter(|(_, waiting)| waiting.client.uuid() == uuid)
+            .map(|(entity, _)| entity)
+            .collect();
+        world.resource_scope(|world, lang: Mut<Lang>| {
+            for entity in waiting {
+                let held = world.entity_mut(entity).remove::<HeldLogin<C>>();
+                if let Some(HeldLogin { mut client }) = held {
+                    client.replace(&lang, entity);
+                }
+                world.despawn(entity);
+            }
+        });
+
+        let mut old_sessions = false;
+        world.resource_scope(|world, lang: Mut<Lang>| {
+            for (old, mut client) in world.query::<(Entity, &mut C)>().iter_mut(world) {
+                if old == new || client.uuid() != uuid {
+                    continue;
+                }
+                old_sessions = true;
+                if !client.is_disconnected() {
+                    client.replace(&lang, old);
+                }
+            }
+        });
+        if !old_sessions {
+            continue;
+        }
+
+        let mut entity = world.entity_mut(new);
+        if let Some(client) = entity.remove::<C>() {
+            entity.insert(HeldLogin { client });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Stands in for a client's connection.
+    #[derive(Component)]
+    struct Connection {
+        uuid: Uuid,
+        disconnected: bool,
+        /// Set once it's told it logged in elsewhere.
+        told: Arc<AtomicBool>,
+    }
+
+    impl Connection {
+        fn new(uuid: Uuid) -> Self {
+            Self {
+                uuid,
+                disconnected: false,
+                told: Arc::default(),
+            }
+        }
+    }
+
+    impl plotsirv::disconnect::Connection for Connection {
+        fn uuid(&self) -> Uuid {
+            self.uuid
+        }
+
+        fn is_disconnected(&self) -> bool {
+            self.disconnected
+        }
+    }
+
+    impl Session for Connection {
+        fn replace(&mut self, _lang: &Lang, _entity: Entity) {
+            self.disconnected = true;
+            self.told.store(true, Ordering::Relaxed);
+        }
+    }
+
+    const PLAYER: Uuid = Uuid::from_u128(1);
+
+    /// Runs a tick, with `connect` joining before it. Disconnected sessions
+    /// are despawned at the end, as Valence does.
+    fn tick(world: &mut World, connect: &[Uuid]) -> Vec<Entity> {
+        let joined = connect
+            .iter()
+            .map(|&uuid| world.spawn(Connection::new(uuid)).id())
+            .collect();
+
+        hold_new_sessions::<Connection>(world);
+
+        let disconnected: Vec<_> = world
+            .query::<(Entity, &Connection)>()
+            .iter(world)
+            .filter(|(_, connection)| connection.disconnected)
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in disconnected {
+            world.despawn(entity);
+        }
+        world.clear_trackers();
+        joined
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Lang::builtin());
+        world
+    }
+
+    fn connected(world: &World, entity: Entity) -> bool {
+        world.get::<Connection>(entity).is_some()
+    }
+
+    fn waiting(world: &World, entity: Entity) -> bool {
+        world.get::<HeldLogin<Connection>>(entity).is_some()
+    }
+
+    /// Clients that would be set up as joining this tick.
+    fn just_joined(world: &mut World) -> Vec<Entity> {
+        world
+            .query_filtered::<Entity, Added<Connection>>()
+            .iter(world)
+            .collect()
+    }
+
+    #[test]
+    fn second_login_waits_for_the_first_to_go() {
+        let mut world = world();
+        let old = tick(&mut world, &[PLAYER])[0];
+
+        let new = tick(&mut world, &[PLAYER])[0];
+        // The old session was disconnected and despawned at the end of the
+        // tick, while the new one waited without being set up.
+        assert!(world.get_entity(old).is_none());
+        assert!(!connected(&world, new));
+        assert!(waiting(&world, new));
+
+        // The next tick, the new session joins as if it had just connected.
+        hold_new_sessions::<Connection>(&mut world);
+        assert!(connected(&world, new));
+        assert!(!waiting(&world, new));
+        assert_eq!(just_joined(&mut world), [new]);
+    }
+
+    #[test]
+    fn other_accounts_join_straight_away() {
+        let mut world = world();
+        tick(&mut world, &[PLAYER]);
+
+        let other = tick(&mut world, &[Uuid::from_u128(2)])[0];
+        assert!(connected(&world, other));
+        assert!(!waiting(&world, other));
+    }
+
+    #[test]
+    fn only_the_latest_waiting_login_joins() {
+        let mut world = world();
+        tick(&mut world, &[PLAYER]);
+
+        // Both log in on the same tick as each other, so neither can go
+        // until the old one has. The second replaces the first.
+        let told = Arc::new(AtomicBool::new(false));
+        let first = world
+            .spawn(Connection {
+                told: told.clone(),
+                ..Connection::new(PLAYER)
+            })
+            .id();
+        let second = tick(&mut world, &[PLAYER])[0];
+        let held: Vec<_> = [first, second]
+            .into_iter()
+            .filter(|&entity| waiting(&world, entity))
+            .collect();
+        assert_eq!(held, [second]);
+        assert!(told.load(Ordering::Relaxed));
+        assert!(world.get_entity(first).is_none());
+
+        let latest = tick(&mut world, &[PLAYER])[0];
+        hold_new_sessions::<Connection>(&mut world);
+
+        let sessions = world
+            .query::<&Connection>()
+            .iter(&world)
+            .filter(|connection| !connection.disconnected)
+            .count();
+        assert_eq!(sessions, 1);
+        assert!(connected(&world, latest));
+        assert!(world.get_entity(first).is_none());
+        assert!(world.get_entity(second).is_none());
+    }
+}
@@ -0,0 +1,368 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+use valence::prelude::*;
+
+use crate::boss_bar::{BossBar, BossBarColor, BossBarDivision, BossBarId, BossBarTarget, BossBars};
+use crate::command::{sender_name, usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::Config;
+use crate::format::{format_duration, parse_duration};
+use crate::kick;
+use crate::lang::Lang;
+use crate::logging::LogGuards;
+use crate::persistence::Persistence;
+use crate::player_data::PlayerDataStore;
+use crate::probes::Probes;
+use crate::status::SharedStatus;
+
+const STOP: CommandInfo = CommandInfo {
+    name: "stop",
+    aliases: &[],
+    usage: "/stop [delay|cancel]",
+    description: "Shut the server down, now or after a countdown like 60 or 5m.",
+    permission: Some("plots.command.stop"),
+    console: true,
+};
+
+/// Seconds before a delayed stop at which everyone is reminded in chat.
+const ANNOUNCE_AT: &[u64] = &[600, 300, 120, 60, 30, 10, 5, 4, 3, 2, 1];
+
+#[derive(Resource)]
+enum Shutdown {
+    /// Counting down to a delayed stop.
+    Countdown {
+        /// Ticks until the stop, and in total.
+        remaining: u64,
+        total: u64,
+        bar: BossBarId,
+        /// The seconds left when the bar was last updated.
+        shown: u64,
+    },
+    /// Stopping at the end of this tick.
+    Requested,
+    /// Everyone has been sent away; the server exits once the disconnects
+    /// have gone out.
+    Stopping { since: u64 },
+}
+
+/// SIGINT and SIGTERM, caught on the async runtime.
+#[derive(Resource)]
+struct Signals(flume::Receiver<&'static str>);
+
+/// Exits the process if shutting down takes too long, saying which step it
+/// was stuck on.
+#[derive(Clone)]
+struct Watchdog(Arc<Mutex<&'static str>>);
+
+impl Watchdog {
+    fn start(timeout: Duration, log_guards: LogGuards) -> Self {
+        let watchdog = Self(Arc::new(Mutex::new("starting to shut down")));
+        let step = watchdog.0.clone();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            error!(
+                "Shutdown took longer than {}s while {}; exiting anyway",
+                timeout.as_secs(),
+                step.lock().unwrap()
+            );
+            log_guards.exit(1);
+        });
+
+        watchdog
+    }
+
+    fn step(&self, step: &'static str) {
+        *self.0.lock().unwrap() = step;
+    }
+}
+
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(STOP)
+            .add_startup_system(catch_signals)
+            .add_system_to_stage(CoreStage::First, handle_signals)
+            .add_system_to_stage(EventLoop, stop_command)
+            .add_system(run_countdown)
+            .add_system(begin_shutdown.after(run_countdown))
+            .add_system_to_stage(CoreStage::Last, finish_shutdown);
+    }
+}
+
+fn catch_signals(mut commands: Commands, server: Res<Server>) {
+    let (sender, receiver) = flume::unbounded();
+
+    server.tokio_handle().spawn(async move {
+        loop {
+            let Some(signal) = next_signal().await else {
+                warn!("Couldn't listen for shutdown signals");
+                return;
+            };
+            if sender.send(signal).is_err() {
+                return;
+            }
+        }
+    });
+
+    commands.insert_resource(Signals(receiver));
+}
+
+#[cfg(unix)]
+async fn next_signal() -> Option<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt()).ok()?;
+    let mut terminate = signal(SignalKind::terminate()).ok()?;
+
+    tokio::select! {
+        _ = interrupt.recv() => Some("SIGINT"),
+        _ = terminate.recv() => Some("SIGTERM"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> Option<&'static str> {
+    tokio::signal::ctrl_c().await.ok()?;
+    Some("Ctrl-C")
+}
+
+/// Starts shutting down on a signal. A second signal while already stopping
+/// exits straight away, for when a save is stuck.
+fn handle_signals(
+    mut commands: Commands,
+    signals: Option<Res<Signals>>,
+    shutdown: Option<Res<Shutdown>>,
+    mut bars: ResMut<BossBars>,
+    log_guards: Res<LogGuards>,
+) {
+    let Some(signals) = signals else {
+        return;
+    };
+
+    for signal in signals.0.try_iter() {
+        match shutdown.as_deref() {
+            Some(Shutdown::Requested | Shutdown::Stopping { .. }) => {
+                warn!("Received {signal} again; exiting without finishing");
+                log_guards.exit(1);
+            }
+            Some(Shutdown::Countdown { bar, .. }) => {
+                bars.remove(*bar);
+            }
+            None => {}
+        }
+
+        info!("Received {signal}, stopping");
+        commands.insert_resource(Shutdown::Requested);
+    }
+}
+
+fn stop_command(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    shutdown: Option<Res<Shutdown>>,
+    mut bars: ResMut<BossBars>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(STOP.name)) {
+        let name = sender_name(
+            clients.get(event.sender).ok().map(|(_, c)| c),
+            consoles.get(event.sender).ok(),
+        );
+        let countdown = match shutdown.as_deref() {
+            Some(Shutdown::Countdown { bar, .. }) => Some(*bar),
+            _ => None,
+        };
+
+        let reply = match event.args.as_slice() {
+            [] => {
+                if let Some(bar) = countdown {
+                    bars.remove(bar);
+                }
+                info!("{name} stopped the server");
+                commands.insert_resource(Shutdown::Requested);
+                None
+            }
+            [arg] if arg == "cancel" => match countdown {
+                Some(bar) => {
+                    bars.remove(bar);
+                    commands.remove_resource::<Shutdown>();
+                    info!("{name} cancelled the shutdown");
+                    for (entity, mut client) in &mut clients {
+                        client.send_message(lang.tr(entity, "shutdown.cancelled", &[]));
+                    }
+                    None
+                }
+                None => Some(lang.tr(event.sender, "shutdown.not_scheduled", &[])),
+            },
+            [delay] => {
+                // A bare number is seconds.
+                let delay = delay
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|&secs| secs > 0)
+                    .map(Duration::from_secs)
+                    .or_else(|| parse_duration(delay));
+
+                match delay {
+                    Some(delay) => {
+                        if let Some(bar) = countdown {
+                            bars.remove(bar);
+                        }
+
+                        let total = delay.as_secs().saturating_mul(20);
+                        let bar = BossBar::new(Text::default(), BossBarTarget::All)
+                            .with_style(BossBarColor::Red, BossBarDivision::NoDivision);
+
+                        info!("{name} scheduled a stop in {}", format_duration(delay));
+                        commands.insert_resource(Shutdown::Countdown {
+                            remaining: total,
+                            total,
+                            bar: bars.create(bar),
+                            shown: 0,
+                        });
+                        None
+                    }
+                    None => Some(usage(&lang, event.sender, &STOP)),
+                }
+            }
+            _ => Some(usage(&lang, event.sender, &STOP)),
+        };
+
+        if let Some(reply) = reply {
+            if let Ok((_, mut client)) = clients.get_mut(event.sender) {
+                client.send_message(reply);
+            } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+                console.send_message(reply);
+            }
+        }
+    }
+}
+
+/// Keeps the boss bar up to date during a delayed stop and reminds everyone
+/// in chat as it gets close.
+fn run_countdown(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut shutdown: Option<ResMut<Shutdown>>,
+    mut bars: ResMut<BossBars>,
+    lang: Res<Lang>,
+) {
+    let Some(shutdown) = shutdown.as_deref_mut() else {
+        return;
+    };
+    let Shutdown::Countdown {
+        remaining,
+        total,
+        bar,
+        shown,
+    } = shutdown
+    else {
+        return;
+    };
+
+    *remaining = remaining.saturating_sub(1);
+    if *remaining == 0 {
+        bars.remove(*bar);
+        *shutdown = Shutdown::Requested;
+        return;
+    }
+
+    let secs = remaining.saturating_add(19) / 20;
+    if secs == *shown {
+        return;
+    }
+
+    let first = *shown == 0;
+    *shown = secs;
+
+    let time = format_duration(Duration::from_secs(secs));
+    if let Some(bar) = bars.get_mut(*bar) {
+        bar.set_title(lang.tr_default("shutdown.bar", &[("time", &time)]));
+        bar.set_progress(*remaining as f32 / *total as f32);
+    }
+
+    if first || ANNOUNCE_AT.contains(&secs) {
+        for (entity, mut client) in &mut clients {
+            client.send_message(lang.tr(entity, "shutdown.countdown", &[("time", &time)]));
+        }
+    }
+}
+
+/// Turns new logins away, disconnects everyone and saves what's left to
+/// save. Player data is mostly written as it changes, but saving it once
+/// more means nothing depends on that, and ends everyone's session.
+fn begin_shutdown(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut shutdown: Option<ResMut<Shutdown>>,
+    mut store: ResMut<PlayerDataStore>,
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    server: Res<Server>,
+    log_guards: Res<LogGuards>,
+) {
+    let Some(shutdown) = shutdown.as_deref_mut() else {
+        return;
+    };
+    if !matches!(shutdown, Shutdown::Requested) {
+        return;
+    }
+
+    info!("Stopping the server");
+    let watchdog = Watchdog::start(
+        Duration::from_secs(config.shutdown.watchdog_secs),
+        log_guards.clone(),
+    );
+
+    watchdog.step("refusing new logins");
+    status.stop_accepting(lang.text_default(&config.shutdown.message, &[]));
+
+    watchdog.step("disconnecting players");
+    for (entity, mut client) in &mut clients {
+        kick(
+            &mut client,
+            lang.text(entity, &config.shutdown.message, &[]),
+        );
+    }
+
+    watchdog.step("saving player data");
+    for (_, client) in &clients {
+        store.unload(client.uuid());
+    }
+    store.flush();
+
+    watchdog.step("sending disconnect messages");
+    *shutdown = Shutdown::Stopping {
+        since: server.current_tick(),
+    };
+}
+
+/// Exits once the disconnect messages have had a tick to go out, and the
+/// last block changes and saves are written.
+fn finish_shutdown(
+    shutdown: Option<Res<Shutdown>>,
+    persistence: Res<Persistence>,
+    log_guards: Res<LogGuards>,
+    probes: Option<Res<Probes>>,
+    server: Res<Server>,
+) {
+    if let Some(Shutdown::Stopping { since }) = shutdown.as_deref() {
+        if server.current_tick() > *since {
+            persistence.flush();
+            if let Some(probes) = probes {
+                probes.stop();
+            }
+            info!("Stopped");
+            log_guards.flush();
+            // Exiting skips destructors, so the heap profile is written
+            // here or not at all.
+            #[cfg(feature = "dhat-heap")]
+            crate::HEAP_PROFILER.lock().unwrap().take();
+            std::process::exit(0);
+        }
+    }
+}
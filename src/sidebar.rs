@@ -0,0 +1,231 @@
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{
+    DisplayObjective, UpdateObjectives, UpdateScore, UpdateTeams,
+};
+use valence_protocol::packets::s2c::update_objectives::{ObjectiveMode, ObjectiveRenderType};
+use valence_protocol::packets::s2c::update_score::UpdateScoreAction;
+use valence_protocol::packets::s2c::update_teams::{
+    CollisionRule, NameTagVisibility, TeamColor, TeamFlags, TeamMode,
+};
+use valence_protocol::VarInt;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::format::{fill_placeholders, legacy_text, truncate_legacy};
+use crate::lang::Lang;
+use crate::player_data::PlayerDataStore;
+use crate::tps::{Deferrable, Tps};
+use crate::WorldName;
+
+const SIDEBAR: CommandInfo = CommandInfo {
+    name: "sidebar",
+    aliases: &[],
+    usage: "/sidebar <on|off>",
+    description: "Show or hide the sidebar.",
+    permission: None,
+    console: false,
+};
+
+const OBJECTIVE: &str = "plotsirv";
+const TITLE: &str = "&6&lplotsirv";
+
+/// The sidebar display slot for `DisplayObjective`.
+const SIDEBAR_SLOT: u8 = 1;
+
+/// Longest line the sidebar will show, not counting formatting codes.
+const MAX_LINE_LEN: usize = 40;
+
+/// The sidebar lines last sent to a client. Each line is the prefix of its
+/// own team, so changing a line only needs one team update.
+#[derive(Component, Default, Debug)]
+pub struct Sidebar {
+    enabled: bool,
+    shown: bool,
+    lines: Vec<String>,
+}
+
+impl Sidebar {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+}
+
+pub struct SidebarPlugin;
+
+impl Plugin for SidebarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(SIDEBAR)
+            .add_system_to_stage(EventLoop, sidebar_command)
+            .add_system(update_sidebars);
+    }
+}
+
+fn sidebar_command(
+    mut clients: Query<(&mut Client, &mut Sidebar)>,
+    mut store: ResMut<PlayerDataStore>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SIDEBAR.name)) {
+        let Ok((mut client, mut sidebar)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let enabled = match event.args.as_slice() {
+            [arg] if arg == "on" => true,
+            [arg] if arg == "off" => false,
+            _ => {
+                client.send_message(usage(&lang, event.sender, &SIDEBAR));
+                continue;
+            }
+        };
+
+        sidebar.enabled = enabled;
+        store.get(client.uuid()).sidebar = enabled;
+        store.save(client.uuid());
+
+        let key = if enabled {
+            "sidebar.shown"
+        } else {
+            "sidebar.hidden"
+        };
+        client.send_message(lang.tr(event.sender, key, &[]));
+    }
+}
+
+/// The invisible score holder for a line: a unique color code followed by a
+/// reset, so it renders as nothing.
+fn line_entry(idx: usize) -> String {
+    format!("\u{a7}{:x}\u{a7}r", idx)
+}
+
+fn line_team(idx: usize) -> String {
+    format!("{OBJECTIVE}{idx}")
+}
+
+fn render_lines(
+    lang: &Lang,
+    entity: Entity,
+    client: &Client,
+    world: &str,
+    online: usize,
+) -> Vec<String> {
+    let pos = client.position();
+    let line = |key, name, value: &dyn std::fmt::Display| {
+        fill_placeholders(lang.plain(entity, key), &[(name, value)])
+    };
+
+    [
+        String::new(),
+        line("sidebar.world", "world", &world),
+        line("sidebar.online", "online", &online),
+        String::new(),
+        line("sidebar.x", "x", &pos.x.floor()),
+        line("sidebar.y", "y", &pos.y.floor()),
+        line("sidebar.z", "z", &pos.z.floor()),
+    ]
+    .into_iter()
+    .map(|line| truncate_legacy(&line, MAX_LINE_LEN).to_owned())
+    .collect()
+}
+
+fn update_sidebars(
+    mut clients: Query<(Entity, &mut Client, &mut Sidebar)>,
+    worlds: Query<&WorldName>,
+    lang: Res<Lang>,
+    tps: Res<Tps>,
+) {
+    if tps.defer(Deferrable::Sidebar) {
+        return;
+    }
+
+    let online = clients.iter().len();
+
+    for (entity, mut client, mut sidebar) in &mut clients {
+        if !sidebar.enabled {
+            if sidebar.shown {
+                client.write_packet(&UpdateObjectives {
+                    objective_name: OBJECTIVE,
+                    mode: ObjectiveMode::Remove,
+                });
+                for idx in 0..sidebar.lines.len() {
+                    client.write_packet(&UpdateTeams {
+                        team_name: &line_team(idx),
+                        mode: TeamMode::RemoveTeam,
+                    });
+                }
+                sidebar.shown = false;
+                sidebar.lines.clear();
+            }
+            continue;
+        }
+
+        let world = worlds
+            .get(client.instance())
+            .map_or("", |name| name.0.as_str());
+        let lines = render_lines(&lang, entity, &client, world, online);
+
+        if !sidebar.shown {
+            client.write_packet(&UpdateObjectives {
+                objective_name: OBJECTIVE,
+                mode: ObjectiveMode::Create {
+                    objective_display_name: legacy_text(TITLE),
+                    render_type: ObjectiveRenderType::Integer,
+                },
+            });
+            client.write_packet(&DisplayObjective {
+                position: SIDEBAR_SLOT,
+                score_name: OBJECTIVE,
+            });
+            sidebar.shown = true;
+        }
+
+        for (idx, line) in lines.iter().enumerate() {
+            let team_name = line_team(idx);
+
+            match sidebar.lines.get(idx) {
+                Some(old) if old == line => continue,
+                Some(_) => client.write_packet(&UpdateTeams {
+                    team_name: &team_name,
+                    mode: TeamMode::UpdateTeamInfo {
+                        team_display_name: Text::default(),
+                        friendly_flags: TeamFlags::new(),
+                        name_tagvisibility: NameTagVisibility::Always,
+                        collision_rule: CollisionRule::Always,
+                        team_color: TeamColor::Reset,
+                        team_prefix: legacy_text(line),
+                        team_suffix: Text::default(),
+                    },
+                }),
+                None => {
+                    let entry = line_entry(idx);
+                    client.write_packet(&UpdateTeams {
+                        team_name: &team_name,
+                        mode: TeamMode::CreateTeam {
+                            team_display_name: Text::default(),
+                            friendly_flags: TeamFlags::new(),
+                            name_tagvisibility: NameTagVisibility::Always,
+                            collision_rule: CollisionRule::Always,
+                            team_color: TeamColor::Reset,
+                            team_prefix: legacy_text(line),
+                            team_suffix: Text::default(),
+                            entities: vec![&entry],
+                        },
+                    });
+                    // Higher scores are drawn first, so count down.
+                    client.write_packet(&UpdateScore {
+                        entity_name: &entry,
+                        action: UpdateScoreAction::Update {
+                            objective_name: OBJECTIVE,
+                            objective_score: VarInt((lines.len() - idx) as i32),
+                        },
+                    });
+                }
+            }
+        }
+
+        sidebar.lines = lines;
+    }
+}
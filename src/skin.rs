@@ -0,0 +1,272 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use valence::player_list::PlayerListEntry;
+use valence::prelude::*;
+use valence_protocol::types::Property;
+
+use crate::config::Config;
+
+const PROFILE_URL: &str = "https://api.mojang.com/users/profiles/minecraft";
+const SESSION_URL: &str = "https://sessionserver.mojang.com/session/minecraft/profile";
+
+/// A resolved skin, as stored in the on-disk cache. Names without a premium
+/// account are cached too, with no textures, so they aren't looked up again
+/// on every join.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedSkin {
+    fetched_at: u64,
+    textures: Option<CachedProperty>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedProperty {
+    value: String,
+    signature: Option<String>,
+}
+
+/// A premium account, as the Mojang API reports it.
+#[derive(Deserialize, Debug)]
+pub struct Profile {
+    /// The UUID, without dashes.
+    pub id: String,
+    /// The name with its canonical capitalization.
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct SessionResponse {
+    properties: Vec<SessionProperty>,
+}
+
+#[derive(Deserialize)]
+struct SessionProperty {
+    name: String,
+    value: String,
+    signature: Option<String>,
+}
+
+/// A skin lookup that finished on the async runtime.
+struct ResolvedSkin {
+    uuid: Uuid,
+    textures: Property,
+}
+
+#[derive(Resource)]
+struct SkinResolver {
+    http: reqwest::Client,
+    sender: flume::Sender<ResolvedSkin>,
+    receiver: flume::Receiver<ResolvedSkin>,
+}
+
+impl Default for SkinResolver {
+    fn default() -> Self {
+        let (sender, receiver) = flume::unbounded();
+        Self {
+            http: reqwest::Client::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+pub struct SkinPlugin;
+
+impl Plugin for SkinPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SkinResolver>()
+            .add_system(request_skins)
+            .add_system(apply_skins);
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Starts a lookup for every new client that didn't arrive with textures,
+/// which is everyone in offline mode and anyone a proxy didn't forward
+/// properties for.
+fn request_skins(
+    clients: Query<&Client, Added<Client>>,
+    resolver: Res<SkinResolver>,
+    server: Res<Server>,
+    config: Res<Config>,
+) {
+    if !config.skins.enabled {
+        return;
+    }
+
+    for client in &clients {
+        if client.properties().iter().any(|p| p.name == "textures") {
+            continue;
+        }
+
+        let uuid = client.uuid();
+        let username = client.username().to_string();
+        let http = resolver.http.clone();
+        let sender = resolver.sender.clone();
+        let cache_dir = config.skins.cache_dir.clone();
+        let ttl = Duration::from_secs(config.skins.cache_ttl_secs);
+
+        server.tokio_handle().spawn(async move {
+            match resolve(&http, &cache_dir, &username, ttl).await {
+                Ok(Some(textures)) => {
+                    let _ = sender.send(ResolvedSkin { uuid, textures });
+                }
+                Ok(None) => debug!("{username} has no premium skin"),
+                // Failing to find a skin isn't worth bothering anyone about;
+                // the player just keeps the default one.
+                Err(e) => debug!("Skin lookup for {username} failed: {e:#}"),
+            }
+        });
+    }
+}
+
+async fn resolve(
+    http: &reqwest::Client,
+    cache_dir: &Path,
+    username: &str,
+    ttl: Duration,
+) -> anyhow::Result<Option<Property>> {
+    let path = cache_dir.join(format!("{}.json", username.to_ascii_lowercase()));
+
+    if let Some(cached) = read_cache(&path).await {
+        if now_secs().saturating_sub(cached.fetched_at) < ttl.as_secs() {
+            return Ok(cached.textures.map(into_property));
+        }
+    }
+
+    let textures = fetch(http, username).await?;
+
+    let cached = CachedSkin {
+        fetched_at: now_secs(),
+        textures: textures.clone(),
+    };
+    if let Err(e) = write_cache(&path, &cached).await {
+        warn!("Failed to cache skin for {username}: {e:#}");
+    }
+
+    Ok(textures.map(into_property))
+}
+
+/// Looks up the premium account with a username, if there is one.
+pub async fn fetch_profile(
+    http: &reqwest::Client,
+    username: &str,
+) -> anyhow::Result<Option<Profile>> {
+    let response = http
+        .get(format!("{PROFILE_URL}/{username}"))
+        .send()
+        .await
+        .context("looking up profile")?;
+
+    // Mojang answers 204 or 404 for names without an account.
+    if response.status() == reqwest::StatusCode::NO_CONTENT
+        || response.status() == reqwest::StatusCode::NOT_FOUND
+    {
+        return Ok(None);
+    }
+
+    let profile = response
+        .error_for_status()?
+        .json()
+        .await
+        .context("parsing profile")?;
+    Ok(Some(profile))
+}
+
+async fn fetch(http: &reqwest::Client, username: &str) -> anyhow::Result<Option<CachedProperty>> {
+    let Some(profile) = fetch_profile(http, username).await? else {
+        return Ok(None);
+    };
+
+    let session: SessionResponse = http
+        .get(format!("{SESSION_URL}/{}?unsigned=false", profile.id))
+        .send()
+        .await
+        .context("fetching session profile")?
+        .error_for_status()?
+        .json()
+        .await
+        .context("parsing session profile")?;
+
+    Ok(session
+        .properties
+        .into_iter()
+        .find(|p| p.name == "textures")
+        .map(|p| CachedProperty {
+            value: p.value,
+            signature: p.signature,
+        }))
+}
+
+fn into_property(cached: CachedProperty) -> Property {
+    Property {
+        name: "textures".into(),
+        value: cached.value,
+        signature: cached.signature,
+    }
+}
+
+async fn read_cache(path: &Path) -> Option<CachedSkin> {
+    let contents = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+async fn write_cache(path: &Path, skin: &CachedSkin) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec(skin)?).await?;
+    Ok(())
+}
+
+/// Swaps resolved skins into the player list. The client caches profiles by
+/// entry, so the entry has to be removed and re-added for viewers to see the
+/// new textures.
+fn apply_skins(
+    clients: Query<&Client>,
+    resolver: Res<SkinResolver>,
+    mut player_list: ResMut<PlayerList>,
+    mut waiting: Local<Vec<ResolvedSkin>>,
+) {
+    let resolved: Vec<_> = waiting.drain(..).chain(resolver.receiver.try_iter()).collect();
+
+    for skin in resolved {
+        let Some(client) = clients
+            .iter()
+            .find(|c| c.uuid() == skin.uuid && !c.is_disconnected())
+        else {
+            continue;
+        };
+
+        // The lookup can beat the player list to adding the entry; try again
+        // next tick.
+        let Some(old) = player_list.remove(skin.uuid) else {
+            waiting.push(skin);
+            continue;
+        };
+
+        let mut properties = client.properties().to_vec();
+        properties.retain(|p| p.name != "textures");
+        properties.push(skin.textures);
+
+        player_list.insert(
+            skin.uuid,
+            PlayerListEntry::new()
+                .with_username(client.username().as_str())
+                .with_properties(properties)
+                .with_game_mode(old.game_mode())
+                .with_ping(old.ping())
+                .with_display_name(old.display_name().cloned())
+                .with_listed(old.is_listed()),
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use valence::prelude::*;
+use valence_protocol::sound::{Sound, SoundCategory};
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::player_data::PlayerDataStore;
+
+const SOUNDS: CommandInfo = CommandInfo {
+    name: "sounds",
+    aliases: &[],
+    usage: "/sounds <on|off>",
+    description: "Turn feedback sounds on or off.",
+    permission: None,
+    console: false,
+};
+
+/// Plays a sound to one client at their own position.
+pub fn play_sound(
+    client: &mut Client,
+    sound: Sound,
+    category: SoundCategory,
+    pitch: f32,
+    volume: f32,
+) {
+    let position = client.position();
+    play_sound_at(client, sound, category, position, pitch, volume);
+}
+
+/// Plays a sound to one client, coming from `position`.
+pub fn play_sound_at(
+    client: &mut Client,
+    sound: Sound,
+    category: SoundCategory,
+    position: impl Into<DVec3>,
+    pitch: f32,
+    volume: f32,
+) {
+    client.play_sound(sound, category, position, volume, pitch);
+}
+
+/// Sounds that accompany feedback in chat. Which sound each one plays is
+/// configured under `[sounds]`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feedback {
+    Teleport,
+    Denied,
+    Mention,
+}
+
+/// Asks for a feedback sound to be played to a client, unless they've
+/// turned sounds off.
+#[derive(Clone, Copy, Debug)]
+pub struct FeedbackSound {
+    pub client: Entity,
+    pub feedback: Feedback,
+}
+
+/// Whether a client hears feedback sounds.
+#[derive(Component, Debug)]
+struct SoundsEnabled(bool);
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FeedbackSound>()
+            .add_command(SOUNDS)
+            .add_system(init_sounds)
+            .add_system_to_stage(EventLoop, sounds_command)
+            .add_system(play_feedback_sounds);
+    }
+}
+
+fn init_sounds(
+    mut commands: Commands,
+    clients: Query<(Entity, &Client), Added<Client>>,
+    mut store: ResMut<PlayerDataStore>,
+) {
+    for (entity, client) in &clients {
+        let enabled = store.get(client.uuid()).sounds;
+        commands.entity(entity).insert(SoundsEnabled(enabled));
+    }
+}
+
+fn sounds_command(
+    mut clients: Query<(&mut Client, &mut SoundsEnabled)>,
+    mut store: ResMut<PlayerDataStore>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SOUNDS.name)) {
+        let Ok((mut client, mut enabled)) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        enabled.0 = match event.args.as_slice() {
+            [arg] if arg == "on" => true,
+            [arg] if arg == "off" => false,
+            _ => {
+                client.send_message(usage(&lang, event.sender, &SOUNDS));
+                continue;
+            }
+        };
+
+        store.get(client.uuid()).sounds = enabled.0;
+        store.save(client.uuid());
+
+        let key = if enabled.0 {
+            "sounds.enabled"
+        } else {
+            "sounds.disabled"
+        };
+        client.send_message(lang.tr(event.sender, key, &[]));
+    }
+}
+
+fn play_feedback_sounds(
+    mut clients: Query<(&mut Client, &SoundsEnabled)>,
+    config: Res<Config>,
+    mut events: EventReader<FeedbackSound>,
+) {
+    for event in events.iter() {
+        let Ok((mut client, enabled)) = clients.get_mut(event.client) else {
+            continue;
+        };
+
+        if !enabled.0 {
+            continue;
+        }
+
+        let sounds = &config.sounds;
+        let name = match event.feedback {
+            Feedback::Teleport => &sounds.teleport,
+            Feedback::Denied => &sounds.denied,
+            Feedback::Mention => &sounds.mention,
+        };
+
+        // An empty name turns a sound off, and the config is validated on
+        // load, so anything else is known.
+        if let Some(sound) = Sound::from_str(name) {
+            play_sound(&mut client, sound, SoundCategory::Master, 1.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use plotsirv::placement::sane_rotation;
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::chunks::generate_chunk;
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::{Config, SpawnConfig, MAX_SPAWN_Y, MIN_SPAWN_Y};
+use crate::lang::Lang;
+use crate::persistence::Persistence;
+use crate::teleport::teleport;
+use crate::worlds::Worlds;
+
+const SPAWN: CommandInfo = CommandInfo {
+    name: "spawn",
+    aliases: &[],
+    usage: "/spawn",
+    description: "Teleport to the server spawn.",
+    permission: None,
+    console: false,
+};
+
+const SETSPAWN: CommandInfo = CommandInfo {
+    name: "setspawn",
+    aliases: &[],
+    usage: "/setspawn",
+    description: "Set the server spawn to your position.",
+    permission: Some("plots.command.setspawn"),
+    console: false,
+};
+
+/// Moving further than this cancels a pending `/spawn` warmup.
+const WARMUP_MAX_MOVEMENT: f64 = 0.5;
+
+/// A `/spawn` waiting for the player to stand still long enough.
+#[derive(Component, Debug)]
+struct SpawnWarmup {
+    started: Instant,
+    origin: DVec3,
+}
+
+pub struct SpawnPlugin;
+
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(SPAWN)
+            .add_command(SETSPAWN)
+            .add_system_to_stage(EventLoop, spawn_command)
+            .add_system_to_stage(EventLoop, setspawn_command)
+            .add_system(tick_warmups);
+    }
+}
+
+/// Finds the instance for the configured spawn world, making sure the chunk
+/// at the spawn position is loaded so players don't fall into the void.
+pub fn spawn_instance(
+    spawn: &SpawnConfig,
+    worlds: &Worlds,
+    instances: &mut Query<&mut Instance>,
+) -> Option<Entity> {
+    let entity = worlds.get(&spawn.world)?;
+    let mut instance = instances.get_mut(entity).ok()?;
+
+    let chunk_pos = ChunkPos::at(spawn.x, spawn.z);
+    if instance.chunk(chunk_pos).is_none() {
+        info!("Loading spawn chunk {chunk_pos:?}");
+        generate_chunk(&mut instance, chunk_pos);
+    }
+
+    Some(entity)
+}
+
+/// Places a client at the configured spawn point.
+pub fn send_to_spawn(
+    client: &mut Client,
+    spawn: &SpawnConfig,
+    worlds: &Worlds,
+    instances: &mut Query<&mut Instance>,
+) -> bool {
+    let Some(instance) = spawn_instance(spawn, worlds, instances) else {
+        warn!("Spawn world {:?} does not exist", spawn.world);
+        return false;
+    };
+
+    teleport(client, instance, [spawn.x, spawn.y, spawn.z]);
+    client.set_yaw(spawn.yaw);
+    client.set_pitch(spawn.pitch);
+    true
+}
+
+/// Whether `/spawn` should make this player wait before teleporting.
+fn needs_warmup(client: &Client, spawn: &SpawnConfig) -> bool {
+    spawn.warmup_secs > 0 && matches!(client.game_mode(), GameMode::Survival | GameMode::Adventure)
+}
+
+fn spawn_command(
+    mut commands: Commands,
+    mut clients: Query<&mut Client>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SPAWN.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        if !event.args.is_empty() {
+            client.send_message(usage(&lang, event.sender, &SPAWN));
+            continue;
+        }
+
+        if needs_warmup(&client, &config.spawn) {
+            commands.entity(event.sender).insert(SpawnWarmup {
+                started: Instant::now(),
+                origin: client.position(),
+            });
+            client.send_message(lang.tr(
+                event.sender,
+                "spawn.warmup",
+                &[("seconds", &config.spawn.warmup_secs)],
+            ));
+            continue;
+        }
+
+        let key = if send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances) {
+            "spawn.teleported"
+        } else {
+            "spawn.unavailable"
+        };
+        client.send_message(lang.tr(event.sender, key, &[]));
+    }
+}
+
+fn setspawn_command(
+    mut clients: Query<&mut Client>,
+    worlds: Res<Worlds>,
+    mut config: ResMut<Config>,
+    persistence: Res<Persistence>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(SETSPAWN.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let Some(world) = worlds.name_of(client.instance()) else {
+            continue;
+        };
+
+        let pos = client.position();
+        if !(MIN_SPAWN_Y..MAX_SPAWN_Y).contains(&pos.y) {
+            client.send_message(lang.tr(
+                event.sender,
+                "spawn.out_of_bounds",
+                &[("min", &MIN_SPAWN_Y), ("max", &MAX_SPAWN_Y)],
+            ));
+            continue;
+        }
+
+        // The rotation is written to the config and sent to everyone who
+        // spawns, so whatever the client claimed it was is tidied up first.
+        let (yaw, pitch) = sane_rotation(client.yaw(), client.pitch());
+        config.spawn = SpawnConfig {
+            world: world.to_owned(),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            yaw,
+            pitch,
+            ..config.spawn.clone()
+        };
+
+        let reply = match config.save(&persistence) {
+            Ok(()) => lang.tr(
+                event.sender,
+                "spawn.set",
+                &[
+                    ("x", &format!("{:.1}", pos.x)),
+                    ("y", &format!("{:.1}", pos.y)),
+                    ("z", &format!("{:.1}", pos.z)),
+                    ("world", &world),
+                ],
+            ),
+            Err(e) => {
+                warn!("Failed to save config: {e:#}");
+                lang.tr(event.sender, "spawn.set_unsaved", &[])
+            }
+        };
+
+        client.send_message(reply);
+    }
+}
+
+fn tick_warmups(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Client, &SpawnWarmup)>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+) {
+    let warmup = Duration::from_secs(config.spawn.warmup_secs);
+
+    for (entity, mut client, pending) in &mut clients {
+        if client.position().distance(pending.origin) > WARMUP_MAX_MOVEMENT {
+            commands.entity(entity).remove::<SpawnWarmup>();
+            client.send_message(lang.tr(entity, "spawn.cancelled", &[]));
+            continue;
+        }
+
+        if pending.started.elapsed() < warmup {
+            continue;
+        }
+
+        commands.entity(entity).remove::<SpawnWarmup>();
+        if send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances) {
+            client.send_message(lang.tr(entity, "spawn.teleported", &[]));
+        }
+    }
+}
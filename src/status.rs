@@ -0,0 +1,314 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context};
+use async_trait::async_trait;
+use valence::prelude::*;
+use valence::server::{AsyncCallbacks, NewClientInfo, ServerListPing, SharedServer};
+use valence_protocol::types::PlayerSampleEntry;
+
+use crate::ban::SharedBans;
+use crate::config::{Config, MotdConfig};
+use crate::connection_limit::{Attempt, SharedConnectionLimits};
+use crate::format::legacy_text;
+use crate::lang::Lang;
+use crate::permissions::{PermissionHolders, Permissions, PermissionsChanged};
+use crate::reload::ConfigReloaded;
+use crate::whitelist::SharedWhitelist;
+
+/// Lets a player join past the player cap, up to the extra slots.
+const JOIN_FULL: &str = "plots.join.full";
+
+/// Most players listed in the server list hover sample.
+const MAX_SAMPLE: usize = 12;
+
+/// How long a login may take between passing the player cap and showing up
+/// as a client before its reserved slot is released.
+const PENDING_LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// What the server list ping reports. Written by the tick thread and read by
+/// the async status handler.
+#[derive(Default, Debug)]
+pub struct StatusInfo {
+    motd: Text,
+    /// Why a login was refused for a full server, in the default language.
+    full: Text,
+    /// Set once the server is shutting down, with the message new logins
+    /// are refused with.
+    stopping: Option<Text>,
+    maintenance: Option<Maintenance>,
+    online: usize,
+    max_players: usize,
+    /// The cap for players who may join a full server.
+    hard_max_players: usize,
+    priority: HashSet<Uuid>,
+    full_bypass: PermissionHolders,
+    sample: Vec<PlayerSampleEntry<'static>>,
+    /// Logins that were let in but haven't spawned as a client yet. They
+    /// count towards the player cap so a burst of joins can't overshoot it.
+    /// Entries are dropped once the client spawns or after a timeout, so a
+    /// connection lost mid-login never leaks a slot.
+    pending: HashMap<Uuid, Instant>,
+}
+
+impl StatusInfo {
+    fn player_count(&self) -> usize {
+        self.online + self.pending.len()
+    }
+}
+
+/// What the server shows while in maintenance mode, and who it still lets
+/// in.
+#[derive(Debug)]
+pub struct Maintenance {
+    pub motd: Text,
+    pub message: Text,
+    pub bypass: PermissionHolders,
+}
+
+/// A handle to the [`StatusInfo`] shared with [`Callbacks`].
+#[derive(Resource, Clone, Default)]
+pub struct SharedStatus(Arc<RwLock<StatusInfo>>);
+
+impl SharedStatus {
+    /// Refuses every login from now on.
+    pub fn stop_accepting(&self, message: Text) {
+        self.0.write().unwrap().stopping = Some(message);
+    }
+
+    /// Whether logins are let in at all, which stops when the server starts
+    /// shutting down.
+    pub fn is_accepting(&self) -> bool {
+        self.0.read().unwrap().stopping.is_none()
+    }
+
+    /// Puts the server into maintenance mode, or takes it out with `None`.
+    pub fn set_maintenance(&self, maintenance: Option<Maintenance>) {
+        self.0.write().unwrap().maintenance = maintenance;
+    }
+}
+
+pub struct Callbacks {
+    status: SharedStatus,
+    whitelist: SharedWhitelist,
+    bans: SharedBans,
+    limits: SharedConnectionLimits,
+    favicon: Option<Box<[u8]>>,
+}
+
+impl Callbacks {
+    pub fn new(
+        status: SharedStatus,
+        whitelist: SharedWhitelist,
+        bans: SharedBans,
+        limits: SharedConnectionLimits,
+        config: &MotdConfig,
+    ) -> anyhow::Result<Self> {
+        let favicon = match &config.favicon {
+            Some(path) => Some(load_favicon(path)?),
+            None => None,
+        };
+
+        Ok(Self {
+            status,
+            whitelist,
+            bans,
+            limits,
+            favicon,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCallbacks for Callbacks {
+    async fn server_list_ping(
+        &self,
+        _shared: &SharedServer,
+        remote_addr: SocketAddr,
+        _protocol_version: i32,
+    ) -> ServerListPing {
+        if !self.limits.allow(remote_addr.ip(), Attempt::Ping) {
+            return ServerListPing::Ignore;
+        }
+
+        let status = self.status.0.read().unwrap();
+
+        ServerListPing::Respond {
+            online_players: status.player_count() as i32,
+            max_players: status.max_players as i32,
+            player_sample: Cow::Owned(status.sample.clone()),
+            description: match &status.maintenance {
+                Some(maintenance) => maintenance.motd.clone(),
+                None => status.motd.clone(),
+            },
+            favicon_png: self.favicon.as_deref().unwrap_or_default(),
+        }
+    }
+
+    async fn login(&self, _shared: &SharedServer, info: &NewClientInfo) -> Result<(), Text> {
+        if !self.limits.allow(info.ip, Attempt::Login) {
+            return Err(self.limits.rejection());
+        }
+
+        {
+            let status = self.status.0.read().unwrap();
+            if let Some(message) = &status.stopping {
+                return Err(message.clone());
+            }
+            if let Some(maintenance) = &status.maintenance {
+                if !maintenance.bypass.includes(info.uuid) {
+                    return Err(maintenance.message.clone());
+                }
+            }
+        }
+
+        if let Some(rejection) = self.bans.read().rejection(info.uuid, info.ip) {
+            return Err(rejection);
+        }
+
+        {
+            let whitelist = self.whitelist.read();
+            if !whitelist.allows(info.uuid) {
+                return Err(whitelist.rejection());
+            }
+        }
+
+        let mut status = self.status.0.write().unwrap();
+
+        let priority =
+            status.priority.contains(&info.uuid) || status.full_bypass.includes(info.uuid);
+        let cap = if priority {
+            status.hard_max_players
+        } else {
+            status.max_players
+        };
+        if status.player_count() >= cap {
+            return Err(status.full.clone());
+        }
+
+        status.pending.insert(info.uuid, Instant::now());
+        Ok(())
+    }
+}
+
+/// Reads a favicon, checking that it's a 64x64 PNG as the client requires.
+/// Valence takes care of base64-encoding it into the status response.
+fn load_favicon(path: &Path) -> anyhow::Result<Box<[u8]>> {
+    let bytes = fs::read(path).with_context(|| format!("reading favicon {}", path.display()))?;
+
+    // The IHDR chunk always comes first, so the dimensions are at a fixed
+    // offset after the signature and chunk header.
+    ensure!(
+        bytes.len() >= 24 && bytes.starts_with(PNG_SIGNATURE) && &bytes[12..16] == b"IHDR",
+        "favicon {} is not a PNG image",
+        path.display()
+    );
+
+    let width = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(bytes[20..24].try_into().unwrap());
+    if (width, height) != (64, 64) {
+        bail!(
+            "favicon {} must be 64x64 pixels, but it is {width}x{height}",
+            path.display()
+        );
+    }
+
+    Ok(bytes.into_boxed_slice())
+}
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SharedStatus>()
+            .add_startup_system(init_motd)
+            .add_system(reload_motd)
+            .add_system(update_players);
+    }
+}
+
+fn render_motd(status: &SharedStatus, config: &Config, permissions: &Permissions, lang: &Lang) {
+    let motd = &config.motd;
+    let server = &config.server;
+    let mut info = status.0.write().unwrap();
+
+    info.motd = legacy_text(&format!("{}\n{}", motd.line1, motd.line2));
+    info.full = lang.tr_default("server.full", &[]);
+    info.max_players = server.max_players;
+    info.hard_max_players = server.max_players + server.extra_slots;
+    info.priority = server.priority_players.iter().copied().collect();
+    info.full_bypass = permissions.holders(JOIN_FULL);
+}
+
+fn init_motd(
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+) {
+    render_motd(&status, &config, &permissions, &lang);
+}
+
+fn reload_motd(
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    permissions: Res<Permissions>,
+    lang: Res<Lang>,
+    mut permissions_changed: EventReader<PermissionsChanged>,
+    mut reloaded: EventReader<ConfigReloaded>,
+) {
+    if permissions_changed.iter().count() + reloaded.iter().count() > 0 {
+        render_motd(&status, &config, &permissions, &lang);
+    }
+}
+
+fn update_players(
+    clients: Query<&Client>,
+    status: Res<SharedStatus>,
+    config: Res<Config>,
+    server: Res<Server>,
+) {
+    let mut info = status.0.write().unwrap();
+    info.online = clients.iter().filter(|c| !c.is_disconnected()).count();
+
+    let now = Instant::now();
+    info.pending.retain(|uuid, started| {
+        now - *started < PENDING_LOGIN_TIMEOUT && !clients.iter().any(|c| c.uuid() == *uuid)
+    });
+
+    if server.current_tick() % 20 != 0 {
+        return;
+    }
+
+    info.sample = if !config.motd.hover.is_empty() {
+        // Names in the sample are shown verbatim, so they double as free-form
+        // hover text.
+        config
+            .motd
+            .hover
+            .iter()
+            .map(|line| PlayerSampleEntry {
+                name: Cow::Owned(line.replace('&', "\u{a7}")),
+                id: Uuid::nil(),
+            })
+            .collect()
+    } else if config.motd.player_sample {
+        clients
+            .iter()
+            .take(MAX_SAMPLE)
+            .map(|client| PlayerSampleEntry {
+                name: Cow::Owned(client.username().to_string()),
+                id: client.uuid(),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+}
@@ -0,0 +1,241 @@
+use valence::prelude::*;
+
+pub struct SystemdPlugin;
+
+impl Plugin for SystemdPlugin {
+    fn build(&self, app: &mut App) {
+        // There's no systemd anywhere else.
+        #[cfg(unix)]
+        unix::add_notifier(app);
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use tracing::{info, warn};
+    use valence::prelude::*;
+
+    use crate::status::SharedStatus;
+    use crate::worlds::Worlds;
+
+    /// Starts notifying systemd, if it's listening.
+    pub fn add_notifier(app: &mut App) {
+        let Some(notifier) = Notifier::from_env() else {
+            return;
+        };
+
+        if let Some((interval, _)) = notifier.watchdog {
+            info!(
+                "Pinging the systemd watchdog every {}ms",
+                interval.as_millis()
+            );
+        }
+        app.insert_resource(notifier)
+            .add_system_to_stage(CoreStage::Last, notify_systemd);
+    }
+
+    /// Tells systemd how startup and shutdown are going, for `Type=notify`
+    /// units, over the socket it gives in `NOTIFY_SOCKET`.
+    #[derive(Resource)]
+    struct Notifier {
+        socket: UnixDatagram,
+        path: PathBuf,
+        ready: bool,
+        stopping: bool,
+        /// How often to ping the watchdog, and when it was last pinged.
+        watchdog: Option<(Duration, Instant)>,
+    }
+
+    impl Notifier {
+        /// `None` when not started by systemd, or it wants no notifications.
+        fn from_env() -> Option<Self> {
+            let path = std::env::var_os("NOTIFY_SOCKET")?;
+            if path.as_bytes().starts_with(b"@") {
+                warn!(
+                    "NOTIFY_SOCKET is an abstract socket, which isn't supported; \
+                     not notifying systemd"
+                );
+                return None;
+            }
+
+            Self::new(path.into(), watchdog_interval())
+        }
+
+        /// Notifies the socket at `path`, pinging the watchdog every
+        /// `watchdog` if given.
+        fn new(path: PathBuf, watchdog: Option<Duration>) -> Option<Self> {
+            let socket = match UnixDatagram::unbound() {
+                Ok(socket) => socket,
+                Err(e) => {
+                    warn!("Failed to create a socket to notify systemd: {e}");
+                    return None;
+                }
+            };
+
+            Some(Self {
+                socket,
+                path,
+                ready: false,
+                stopping: false,
+                watchdog: watchdog.map(|interval| (interval, Instant::now())),
+            })
+        }
+
+        fn notify(&self, state: &str) {
+            if let Err(e) = self.socket.send_to(state.as_bytes(), &self.path) {
+                warn!("Failed to notify systemd of {state}: {e}");
+            }
+        }
+
+        /// Sends whatever's changed since the last update, given whether
+        /// any worlds are loaded and whether logins are let in.
+        fn update(&mut self, loaded: bool, accepting: bool) {
+            if !self.ready && accepting && loaded {
+                self.notify("READY=1");
+                self.ready = true;
+            }
+            if !self.stopping && !accepting {
+                self.notify("STOPPING=1");
+                self.stopping = true;
+            }
+
+            if let Some((interval, last)) = self.watchdog {
+                if last.elapsed() >= interval {
+                    self.notify("WATCHDOG=1");
+                    self.watchdog = Some((interval, Instant::now()));
+                }
+            }
+        }
+    }
+
+    /// How often to ping systemd's watchdog, if it's watching this process.
+    fn watchdog_interval() -> Option<Duration> {
+        if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+            if pid.parse::<u32>().ok()? != std::process::id() {
+                return None;
+            }
+        }
+
+        let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        // Half the timeout, as systemd suggests, so one slow tick isn't fatal.
+        (usec > 0).then(|| Duration::from_micros(usec / 2))
+    }
+
+    /// Reports readiness once the worlds are loaded, and stopping once
+    /// logins are turned away for a shutdown. The watchdog is pinged from
+    /// here so that a stuck tick stops the pings.
+    fn notify_systemd(
+        mut notifier: ResMut<Notifier>,
+        worlds: Res<Worlds>,
+        status: Res<SharedStatus>,
+    ) {
+        notifier.update(!worlds.is_empty(), status.is_accepting());
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::ErrorKind;
+
+        use super::*;
+
+        /// Stands in for systemd, listening on a socket in a fresh
+        /// directory, which is removed when it's dropped.
+        struct Systemd {
+            dir: PathBuf,
+            socket: UnixDatagram,
+        }
+
+        impl Systemd {
+            fn listen(name: &str) -> Self {
+                let dir =
+                    std::env::temp_dir().join(format!("plotsirv-{name}-{}", std::process::id()));
+                let _ = std::fs::remove_dir_all(&dir);
+                std::fs::create_dir_all(&dir).unwrap();
+                let socket = UnixDatagram::bind(dir.join("notify")).unwrap();
+                socket.set_nonblocking(true).unwrap();
+                Self { dir, socket }
+            }
+
+            fn path(&self) -> PathBuf {
+                self.dir.join("notify")
+            }
+
+            /// Everything sent since last asked.
+            fn received(&self) -> Vec<String> {
+                let mut buf = [0; 64];
+                let mut received = Vec::new();
+                loop {
+                    match self.socket.recv(&mut buf) {
+                        Ok(len) => received.push(String::from_utf8_lossy(&buf[..len]).into()),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => return received,
+                        Err(e) => panic!("receiving: {e}"),
+                    }
+                }
+            }
+        }
+
+        impl Drop for Systemd {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.dir);
+            }
+        }
+
+        #[test]
+        fn ready_then_stopping_once_each() {
+            let systemd = Systemd::listen("notify");
+            let mut notifier = Notifier::new(systemd.path(), None).unwrap();
+
+            // Nothing's ready until a world is loaded.
+            notifier.update(false, true);
+            assert!(systemd.received().is_empty());
+
+            notifier.update(true, true);
+            notifier.update(true, true);
+            assert_eq!(systemd.received(), ["READY=1"]);
+
+            notifier.update(true, false);
+            notifier.update(true, false);
+            assert_eq!(systemd.received(), ["STOPPING=1"]);
+        }
+
+        #[test]
+        fn stopping_before_ready_never_says_ready() {
+            let systemd = Systemd::listen("early-stop");
+            let mut notifier = Notifier::new(systemd.path(), None).unwrap();
+
+            notifier.update(true, false);
+            assert_eq!(systemd.received(), ["STOPPING=1"]);
+        }
+
+        #[test]
+        fn pings_the_watchdog() {
+            let systemd = Systemd::listen("watchdog");
+            let mut notifier = Notifier::new(systemd.path(), Some(Duration::ZERO)).unwrap();
+
+            notifier.update(false, true);
+            notifier.update(false, true);
+            assert_eq!(systemd.received(), ["WATCHDOG=1", "WATCHDOG=1"]);
+        }
+
+        #[test]
+        fn reads_the_socket_from_the_environment() {
+            let systemd = Systemd::listen("env");
+
+            // The only test that touches the environment, so nothing races
+            // it.
+            std::env::set_var("NOTIFY_SOCKET", "@abstract");
+            assert!(Notifier::from_env().is_none());
+
+            std::env::set_var("NOTIFY_SOCKET", systemd.path());
+            let notifier = Notifier::from_env().unwrap();
+            std::env::remove_var("NOTIFY_SOCKET");
+            notifier.notify("READY=1");
+            assert_eq!(systemd.received(), ["READY=1"]);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use plotsirv::disconnect::ClientDisconnected;
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{SetTabListHeaderAndFooter, UpdateTeams};
+use valence_protocol::packets::s2c::update_teams::{
+    CollisionRule, NameTagVisibility, TeamColor, TeamFlags, TeamMode,
+};
+
+use crate::config::Config;
+use crate::format::{fill_placeholders, last_color_code, legacy_text};
+use crate::permissions::{Permissions, PermissionsChanged};
+use crate::tps::Tps;
+
+/// Group weights are turned into team names counting down from here, so
+/// heavier groups sort first.
+const RANK_OFFSET: i32 = 5000;
+
+/// The longest team name clients accept.
+const MAX_TEAM_NAME: usize = 16;
+
+/// The header and footer last sent to a client.
+#[derive(Component, Default, Debug)]
+pub struct TabListDisplay {
+    header: String,
+    footer: String,
+    sent: bool,
+}
+
+/// How a group's team shows its members' names.
+#[derive(Clone, PartialEq, Debug)]
+struct RankTeam {
+    prefix: String,
+    suffix: String,
+    color: TeamColor,
+}
+
+/// The rank teams a client has been sent, and who it was told is in each,
+/// by username.
+#[derive(Component, Default, Debug)]
+pub struct RankTeams {
+    teams: HashMap<String, RankTeam>,
+    members: HashMap<String, String>,
+}
+
+pub struct TabListPlugin;
+
+impl Plugin for TabListPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(init_tab_list)
+            .add_system(update_tab_list.after(init_tab_list))
+            .add_system(update_rank_teams.after(init_tab_list));
+    }
+}
+
+fn init_tab_list(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands
+            .entity(entity)
+            .insert((TabListDisplay::default(), RankTeams::default()));
+    }
+}
+
+/// Clients list teams by name, so a group's team name starts with its rank.
+fn team_name(group: &str, weight: i32) -> String {
+    let rank = (RANK_OFFSET - weight).clamp(0, 9999);
+    format!("{rank:04}{group}")
+        .chars()
+        .take(MAX_TEAM_NAME)
+        .collect()
+}
+
+fn team_color(code: Option<char>) -> TeamColor {
+    match code {
+        Some('0') => TeamColor::Black,
+        Some('1') => TeamColor::DarkBlue,
+        Some('2') => TeamColor::DarkGreen,
+        Some('3') => TeamColor::DarkCyan,
+        Some('4') => TeamColor::DarkRed,
+        Some('5') => TeamColor::Purple,
+        Some('6') => TeamColor::Gold,
+        Some('7') => TeamColor::Gray,
+        Some('8') => TeamColor::DarkGray,
+        Some('9') => TeamColor::Blue,
+        Some('a') => TeamColor::BrightGreen,
+        Some('b') => TeamColor::Cyan,
+        Some('c') => TeamColor::Red,
+        Some('d') => TeamColor::Pink,
+        Some('e') => TeamColor::Yellow,
+        Some('f') => TeamColor::White,
+        _ => TeamColor::Reset,
+    }
+}
+
+/// Puts everyone in a scoreboard team for their permission group, which
+/// sorts the tab list by rank and colors names above heads to match the
+/// group's format. Only worked out again when someone joins or leaves or
+/// the permissions change.
+fn update_rank_teams(
+    mut clients: Query<(&mut Client, &mut RankTeams)>,
+    joined: Query<(), Added<RankTeams>>,
+    permissions: Res<Permissions>,
+    mut changed: EventReader<PermissionsChanged>,
+    mut disconnected: EventReader<ClientDisconnected>,
+) {
+    let changed = changed.iter().count() > 0;
+    let disconnected = disconnected.iter().count() > 0;
+    if joined.is_empty() && !changed && !disconnected {
+        return;
+    }
+
+    let mut teams = HashMap::new();
+    let mut members = HashMap::new();
+    for (client, _) in &clients {
+        if client.is_disconnected() {
+            continue;
+        }
+
+        let group = permissions.group_of(client.uuid());
+        let name = team_name(group, permissions.weight(group));
+        let (prefix, suffix) = permissions.name_format(client.uuid());
+        teams.entry(name.clone()).or_insert_with(|| RankTeam {
+            prefix: prefix.to_owned(),
+            suffix: suffix.to_owned(),
+            color: team_color(last_color_code(prefix)),
+        });
+        members.insert(client.username().to_string(), name);
+    }
+
+    for (mut client, mut sent) in &mut clients {
+        for (member, team) in &sent.members {
+            if members.get(member) != Some(team) && teams.contains_key(team) {
+                client.write_packet(&UpdateTeams {
+                    team_name: team,
+                    mode: TeamMode::RemoveEntities {
+                        entities: vec![member.as_str()],
+                    },
+                });
+            }
+        }
+
+        // Removing a team takes whoever was still in it along.
+        for team in sent.teams.keys() {
+            if !teams.contains_key(team) {
+                client.write_packet(&UpdateTeams {
+                    team_name: team,
+                    mode: TeamMode::RemoveTeam,
+                });
+            }
+        }
+
+        for (team, look) in &teams {
+            let prefix = legacy_text(&look.prefix);
+            let suffix = legacy_text(&look.suffix);
+            match sent.teams.get(team) {
+                Some(old) if old == look => {}
+                Some(_) => client.write_packet(&UpdateTeams {
+                    team_name: team,
+                    mode: TeamMode::UpdateTeamInfo {
+                        team_display_name: Text::default(),
+                        friendly_flags: TeamFlags::new(),
+                        name_tagvisibility: NameTagVisibility::Always,
+                        collision_rule: CollisionRule::Always,
+                        team_color: look.color,
+                        team_prefix: prefix,
+                        team_suffix: suffix,
+                    },
+                }),
+                None => client.write_packet(&UpdateTeams {
+                    team_name: team,
+                    mode: TeamMode::CreateTeam {
+                        team_display_name: Text::default(),
+                        friendly_flags: TeamFlags::new(),
+                        name_tagvisibility: NameTagVisibility::Always,
+                        collision_rule: CollisionRule::Always,
+                        team_color: look.color,
+                        team_prefix: prefix,
+                        team_suffix: suffix,
+                        entities: vec![],
+                    },
+                }),
+            }
+        }
+
+        for (member, team) in &members {
+            if sent.members.get(member) != Some(team) {
+                client.write_packet(&UpdateTeams {
+                    team_name: team,
+                    mode: TeamMode::AddEntities {
+                        entities: vec![member.as_str()],
+                    },
+                });
+            }
+        }
+
+        sent.teams = teams.clone();
+        sent.members = members.clone();
+    }
+}
+
+/// Re-renders each client's header and footer, sending them only if the text
+/// changed, and refreshes everyone's ping in the list.
+fn update_tab_list(
+    mut clients: Query<(&mut Client, &mut TabListDisplay)>,
+    mut player_list: ResMut<PlayerList>,
+    config: Res<Config>,
+    tps: Res<Tps>,
+    mut last_refresh: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    let due = last_refresh.map_or(true, |last| {
+        now - last >= Duration::from_secs(config.tab_list.refresh_secs.max(1))
+    });
+
+    if due {
+        *last_refresh = Some(now);
+    }
+
+    let online = clients.iter().len();
+    let mspt = format!("{:.1}", tps.mspt());
+    let tps = format!("{:.1}", tps.get());
+
+    for (mut client, mut display) in &mut clients {
+        // Newly joined clients get their tab list right away.
+        if !due && display.sent {
+            continue;
+        }
+
+        let ping = client.ping();
+
+        if let Some(entry) = player_list.get_mut(client.uuid()) {
+            if entry.ping() != ping {
+                entry.set_ping(ping);
+            }
+        }
+
+        let username = client.username().to_string();
+        let values: [(&str, &dyn std::fmt::Display); 6] = [
+            ("online", &online),
+            ("max_players", &config.server.max_players),
+            ("tps", &tps),
+            ("mspt", &mspt),
+            ("player", &username),
+            ("ping", &ping),
+        ];
+
+        let header = fill_placeholders(&config.tab_list.header, &values);
+        let footer = fill_placeholders(&config.tab_list.footer, &values);
+
+        if display.sent && header == display.header && footer == display.footer {
+            continue;
+        }
+
+        client.write_packet(&SetTabListHeaderAndFooter {
+            header: legacy_text(&header),
+            footer: legacy_text(&footer),
+        });
+
+        display.header = header;
+        display.footer = footer;
+        display.sent = true;
+    }
+}
@@ -0,0 +1,350 @@
+use std::time::{Duration, Instant};
+
+use plotsirv::disconnect::{AddDisconnectSystem, ClientDisconnected};
+use valence::prelude::*;
+
+use crate::command::{find_client, usage, AddCommand, CommandExecution, CommandInfo};
+use crate::lang::Lang;
+use crate::sound::{Feedback, FeedbackSound};
+
+const TP: CommandInfo = CommandInfo {
+    name: "tp",
+    aliases: &["teleport"],
+    usage: "/tp <player> | /tp <x> <y> <z>",
+    description: "Teleport to a player or position.",
+    permission: Some("plots.command.tp"),
+    console: false,
+};
+
+const TPHERE: CommandInfo = CommandInfo {
+    name: "tphere",
+    aliases: &[],
+    usage: "/tphere <player>",
+    description: "Teleport a player to you.",
+    permission: Some("plots.command.tphere"),
+    console: false,
+};
+
+const TPA: CommandInfo = CommandInfo {
+    name: "tpa",
+    aliases: &[],
+    usage: "/tpa <player>",
+    description: "Ask to teleport to a player.",
+    permission: None,
+    console: false,
+};
+
+const TPACCEPT: CommandInfo = CommandInfo {
+    name: "tpaccept",
+    aliases: &[],
+    usage: "/tpaccept",
+    description: "Accept a pending teleport request.",
+    permission: None,
+    console: false,
+};
+
+const TPDENY: CommandInfo = CommandInfo {
+    name: "tpdeny",
+    aliases: &[],
+    usage: "/tpdeny",
+    description: "Deny a pending teleport request.",
+    permission: None,
+    console: false,
+};
+
+/// How long a `/tpa` request stays valid.
+const REQUEST_EXPIRY: Duration = Duration::from_secs(60);
+
+/// A `/tpa` request from `requester` to teleport to `target`.
+#[derive(Clone, Copy, Debug)]
+struct TeleportRequest {
+    requester: Entity,
+    target: Entity,
+    sent: Instant,
+}
+
+#[derive(Resource, Default)]
+struct TeleportRequests {
+    pending: Vec<TeleportRequest>,
+}
+
+impl TeleportRequests {
+    /// Removes and returns the most recent request sent to `target`.
+    fn take_latest(&mut self, target: Entity) -> Option<TeleportRequest> {
+        let idx = self.pending.iter().rposition(|r| r.target == target)?;
+        Some(self.pending.remove(idx))
+    }
+}
+
+pub struct TeleportPlugin;
+
+impl Plugin for TeleportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeleportRequests>()
+            .add_command(TP)
+            .add_command(TPHERE)
+            .add_command(TPA)
+            .add_command(TPACCEPT)
+            .add_command(TPDENY)
+            .add_system_to_stage(EventLoop, tp_commands)
+            .add_system_to_stage(EventLoop, tpa_commands)
+            .add_system(expire_requests)
+            .add_disconnect_system(cancel_disconnected_requests);
+    }
+}
+
+/// Moves a client to a position, switching instances first if needed so the
+/// two always change together.
+pub fn teleport(client: &mut Client, instance: Entity, position: impl Into<DVec3>) {
+    if client.instance() != instance {
+        client.set_instance(instance);
+    }
+    client.set_position(position);
+}
+
+fn tp_commands(
+    mut clients: Query<(Entity, &mut Client)>,
+    lang: Res<Lang>,
+    mut commands: EventReader<CommandExecution>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
+    for command in commands.iter() {
+        let here = if command.is(TP.name) {
+            false
+        } else if command.is(TPHERE.name) {
+            true
+        } else {
+            continue;
+        };
+
+        if let (false, [x, y, z]) = (here, command.args.as_slice()) {
+            let coord = |v: &String| v.parse::<f64>().ok().filter(|v| v.is_finite());
+            let (Some(x), Some(y), Some(z)) = (coord(x), coord(y), coord(z)) else {
+                if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                    sender.send_message(usage(&lang, command.sender, &TP));
+                }
+                continue;
+            };
+
+            let Ok((_, mut sender)) = clients.get_mut(command.sender) else {
+                continue;
+            };
+            sender.set_position([x, y, z]);
+            sounds.send(FeedbackSound {
+                client: command.sender,
+                feedback: Feedback::Teleport,
+            });
+            sender.send_message(lang.tr(
+                command.sender,
+                "teleport.to_position",
+                &[
+                    ("x", &format!("{x:.1}")),
+                    ("y", &format!("{y:.1}")),
+                    ("z", &format!("{z:.1}")),
+                ],
+            ));
+            continue;
+        }
+
+        let [name] = command.args.as_slice() else {
+            let info = if here { &TPHERE } else { &TP };
+            if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                sender.send_message(usage(&lang, command.sender, info));
+            }
+            continue;
+        };
+
+        let Some(other) = find_client(clients.iter().map(|(e, c)| (e, &*c)), name) else {
+            if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                sender.send_message(lang.tr(
+                    command.sender,
+                    "command.not_online",
+                    &[("name", name)],
+                ));
+            }
+            continue;
+        };
+
+        let (from, to) = if here {
+            (other, command.sender)
+        } else {
+            (command.sender, other)
+        };
+
+        if from == to {
+            continue;
+        }
+
+        let Ok([(from, mut from_client), (to, mut to_client)]) = clients.get_many_mut([from, to]) else {
+            continue;
+        };
+
+        let (instance, position) = (to_client.instance(), to_client.position());
+        teleport(&mut from_client, instance, position);
+        announce(&lang, (from, &mut from_client), (to, &mut to_client));
+        sounds.send(FeedbackSound {
+            client: from,
+            feedback: Feedback::Teleport,
+        });
+    }
+}
+
+fn tpa_commands(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut requests: ResMut<TeleportRequests>,
+    lang: Res<Lang>,
+    mut commands: EventReader<CommandExecution>,
+    mut sounds: EventWriter<FeedbackSound>,
+) {
+    for command in commands.iter() {
+        if command.is(TPA.name) {
+            let [name] = command.args.as_slice() else {
+                if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                    sender.send_message(usage(&lang, command.sender, &TPA));
+                }
+                continue;
+            };
+
+            let target = find_client(clients.iter().map(|(e, c)| (e, &*c)), name)
+                .filter(|&target| target != command.sender);
+
+            let Some(target) = target else {
+                if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                    sender.send_message(lang.tr(
+                        command.sender,
+                        "command.not_online",
+                        &[("name", name)],
+                    ));
+                }
+                continue;
+            };
+
+            let Ok([(_, mut sender), (_, mut target_client)]) =
+                clients.get_many_mut([command.sender, target])
+            else {
+                continue;
+            };
+
+            // Only keep the newest request between any two players.
+            requests
+                .pending
+                .retain(|r| !(r.requester == command.sender && r.target == target));
+            requests.pending.push(TeleportRequest {
+                requester: command.sender,
+                target,
+                sent: Instant::now(),
+            });
+
+            let target_name = target_client.username().to_string();
+            sender.send_message(lang.tr(
+                command.sender,
+                "teleport.request_sent",
+                &[("name", &target_name)],
+            ));
+            let sender_name = sender.username().to_string();
+            target_client.send_message(lang.tr(
+                target,
+                "teleport.request_received",
+                &[("name", &sender_name)],
+            ));
+        } else if command.is(TPACCEPT.name) || command.is(TPDENY.name) {
+            let accept = command.is(TPACCEPT.name);
+
+            let Some(request) = requests.take_latest(command.sender) else {
+                if let Ok((_, mut sender)) = clients.get_mut(command.sender) {
+                    sender.send_message(lang.tr(command.sender, "teleport.no_requests", &[]));
+                }
+                continue;
+            };
+
+            let Ok([(_, mut requester), (_, mut target)]) =
+                clients.get_many_mut([request.requester, request.target])
+            else {
+                continue;
+            };
+
+            if accept {
+                let (instance, position) = (target.instance(), target.position());
+                teleport(&mut requester, instance, position);
+                announce(
+                    &lang,
+                    (request.requester, &mut requester),
+                    (request.target, &mut target),
+                );
+                sounds.send(FeedbackSound {
+                    client: request.requester,
+                    feedback: Feedback::Teleport,
+                });
+            } else {
+                let target_name = target.username().to_string();
+                requester.send_message(lang.tr(
+                    request.requester,
+                    "teleport.denied",
+                    &[("name", &target_name)],
+                ));
+                target.send_message(lang.tr(request.target, "teleport.you_denied", &[]));
+            }
+        }
+    }
+}
+
+/// Tells both parties about a completed teleport.
+fn announce(lang: &Lang, from: (Entity, &mut Client), to: (Entity, &mut Client)) {
+    let (from_name, to_name) = (from.1.username().to_string(), to.1.username().to_string());
+    from.1.send_message(lang.tr(from.0, "teleport.to_player", &[("name", &to_name)]));
+    to.1.send_message(lang.tr(to.0, "teleport.from_player", &[("name", &from_name)]));
+}
+
+fn expire_requests(
+    mut clients: Query<&mut Client>,
+    mut requests: ResMut<TeleportRequests>,
+    lang: Res<Lang>,
+) {
+    let now = Instant::now();
+
+    requests.pending.retain(|request| {
+        if now.duration_since(request.sent) < REQUEST_EXPIRY {
+            return true;
+        }
+
+        if let Ok(mut requester) = clients.get_mut(request.requester) {
+            requester.send_message(lang.tr(request.requester, "teleport.expired", &[]));
+        }
+        false
+    });
+}
+
+fn cancel_disconnected_requests(
+    mut events: EventReader<ClientDisconnected>,
+    mut clients: Query<(Entity, &mut Client)>,
+    mut requests: ResMut<TeleportRequests>,
+    lang: Res<Lang>,
+) {
+    let disconnected: Vec<Entity> = events.iter().map(|event| event.client).collect();
+
+    if disconnected.is_empty() {
+        return;
+    }
+
+    requests.pending.retain(|request| {
+        let requester_left = disconnected.contains(&request.requester);
+        let target_left = disconnected.contains(&request.target);
+
+        if !requester_left && !target_left {
+            return true;
+        }
+
+        let (other, key) = if requester_left {
+            (request.target, "teleport.cancelled_to_you")
+        } else {
+            (request.requester, "teleport.cancelled")
+        };
+
+        if let Ok((_, mut client)) = clients.get_mut(other) {
+            if !client.is_disconnected() {
+                client.send_message(lang.tr(other, key, &[]));
+            }
+        }
+        false
+    });
+}
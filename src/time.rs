@@ -0,0 +1,167 @@
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::UpdateTime;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::WorldName;
+
+const TIME: CommandInfo = CommandInfo {
+    name: "time",
+    aliases: &[],
+    usage: "/time <set <day|noon|night|midnight|ticks>|add <ticks>>",
+    description: "Change the time of day in your world.",
+    permission: Some("plots.command.time"),
+    console: false,
+};
+
+const DAY_LENGTH: i64 = 24000;
+
+/// How many ticks between time updates sent to clients.
+const SYNC_INTERVAL: u64 = 20;
+
+/// The time of day in an instance.
+#[derive(Component, Default, Debug)]
+pub struct WorldTime {
+    pub world_age: i64,
+    pub time_of_day: i64,
+    /// Set when the time jumps, so clients hear about it straight away.
+    changed: bool,
+}
+
+impl WorldTime {
+    pub fn set_time_of_day(&mut self, time_of_day: i64) {
+        self.time_of_day = time_of_day.rem_euclid(DAY_LENGTH);
+        self.changed = true;
+    }
+}
+
+/// The instance a client last had the time sent for, so joining or changing
+/// worlds triggers an update.
+#[derive(Component, Default, Debug)]
+struct TimeViewer {
+    synced_instance: Option<Entity>,
+}
+
+pub struct TimePlugin;
+
+impl Plugin for TimePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(TIME)
+            .add_system(init_world_time)
+            .add_system(init_time_viewers)
+            .add_system(advance_time)
+            .add_system_to_stage(EventLoop, time_command)
+            .add_system(sync_time.after(advance_time).after(init_time_viewers))
+            .add_system(clear_time_changes.after(sync_time));
+    }
+}
+
+fn init_world_time(mut commands: Commands, instances: Query<Entity, Added<Instance>>) {
+    for entity in &instances {
+        commands.entity(entity).insert(WorldTime::default());
+    }
+}
+
+fn init_time_viewers(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(TimeViewer::default());
+    }
+}
+
+fn advance_time(mut worlds: Query<(&mut WorldTime, &WorldName)>, config: Res<Config>) {
+    for (mut time, name) in &mut worlds {
+        time.world_age += 1;
+        if config.world(&name.0).daylight_cycle {
+            time.time_of_day = (time.time_of_day + 1).rem_euclid(DAY_LENGTH);
+        }
+    }
+}
+
+fn parse_time(arg: &str) -> Option<i64> {
+    match arg {
+        "day" => Some(1000),
+        "noon" => Some(6000),
+        "night" => Some(13000),
+        "midnight" => Some(18000),
+        ticks => ticks.parse().ok(),
+    }
+}
+
+fn time_command(
+    mut clients: Query<&mut Client>,
+    mut worlds: Query<(&mut WorldTime, &WorldName)>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(TIME.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let Ok((mut time, name)) = worlds.get_mut(client.instance()) else {
+            continue;
+        };
+
+        let new_time = match event.args.as_slice() {
+            [action, value] if action == "set" => parse_time(value),
+            [action, value] if action == "add" => {
+                value.parse::<i64>().ok().map(|ticks| time.time_of_day + ticks)
+            }
+            _ => None,
+        };
+
+        let Some(new_time) = new_time else {
+            client.send_message(usage(&lang, event.sender, &TIME));
+            continue;
+        };
+
+        time.set_time_of_day(new_time);
+        client.send_message(lang.tr(
+            event.sender,
+            "time.set",
+            &[("world", &name.0), ("time", &time.time_of_day)],
+        ));
+    }
+}
+
+/// Sends the time to clients periodically, right after joining or changing
+/// worlds, and whenever a world's time jumps.
+fn sync_time(
+    mut clients: Query<(&mut Client, &mut TimeViewer)>,
+    worlds: Query<(&WorldTime, &WorldName)>,
+    config: Res<Config>,
+    server: Res<Server>,
+) {
+    let periodic = server.current_tick() % SYNC_INTERVAL == 0;
+
+    for (mut client, mut viewer) in &mut clients {
+        let Ok((time, name)) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        let moved = viewer.synced_instance != Some(client.instance());
+        if !periodic && !moved && !time.changed {
+            continue;
+        }
+        viewer.synced_instance = Some(client.instance());
+
+        let time_of_day = time.time_of_day;
+        let cycle = config.world(&name.0).daylight_cycle;
+
+        client.write_packet(&UpdateTime {
+            world_age: time.world_age,
+            // A negative time of day stops the client from advancing it on
+            // its own.
+            time_of_day: if cycle { time_of_day } else { -time_of_day.max(1) },
+        });
+    }
+}
+
+fn clear_time_changes(mut worlds: Query<&mut WorldTime>) {
+    for mut time in &mut worlds {
+        if time.changed {
+            time.changed = false;
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::format::format_duration;
+use crate::lang::Lang;
+
+const TIMINGS: CommandInfo = CommandInfo {
+    name: "timings",
+    aliases: &[],
+    usage: "/timings <seconds>",
+    description: "Find the systems taking up the most tick time.",
+    permission: Some("plots.command.timings"),
+    console: true,
+};
+
+const MAX_SECONDS: u64 = 300;
+
+/// How many systems a report lists.
+const TOP_SYSTEMS: usize = 10;
+
+/// Whether a span is one Bevy puts around a system run, which only
+/// `/timings` is interested in.
+pub fn is_system_span(metadata: &Metadata) -> bool {
+    metadata.is_span() && metadata.name() == "system" && metadata.target().starts_with("bevy_ecs")
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Spent {
+    total: Duration,
+    runs: u32,
+}
+
+#[derive(Default)]
+struct Shared {
+    capturing: AtomicBool,
+    by_system: Mutex<HashMap<String, Spent>>,
+}
+
+/// Adds up how long each system ran while a capture is going, from the spans
+/// Bevy puts around them. Systems run on several threads, so this is shared
+/// with the [`TimingsLayer`] rather than kept in the ECS.
+#[derive(Resource, Clone, Default)]
+pub struct Timings(Arc<Shared>);
+
+impl Timings {
+    /// The tracing layer that does the timing, for [`crate::logging::init`].
+    pub fn layer(&self) -> TimingsLayer {
+        TimingsLayer(self.0.clone())
+    }
+
+    fn start(&self) {
+        self.0.by_system.lock().unwrap().clear();
+        self.0.capturing.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops capturing, and gives the systems slowest first.
+    fn finish(&self) -> Vec<(String, Spent)> {
+        self.0.capturing.store(false, Ordering::Relaxed);
+        let mut systems: Vec<_> = self.0.by_system.lock().unwrap().drain().collect();
+        systems.sort_unstable_by(|(_, a), (_, b)| b.total.cmp(&a.total));
+        systems
+    }
+}
+
+/// What's kept on a system's span while it's being timed.
+struct SystemSpan {
+    name: String,
+    entered: Option<Instant>,
+}
+
+pub struct TimingsLayer(Arc<Shared>);
+
+impl<S> Layer<S> for TimingsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !self.0.capturing.load(Ordering::Relaxed) || !is_system_span(attrs.metadata()) {
+            return;
+        }
+
+        let mut name = NameVisitor(None);
+        attrs.record(&mut name);
+        if let (Some(name), Some(span)) = (name.0, ctx.span(id)) {
+            span.extensions_mut().insert(SystemSpan {
+                name,
+                entered: None,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if !self.0.capturing.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            if let Some(system) = span.extensions_mut().get_mut::<SystemSpan>() {
+                system.entered = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(system) = extensions.get_mut::<SystemSpan>() else {
+            return;
+        };
+        let Some(entered) = system.entered.take() else {
+            return;
+        };
+
+        // A capture that finished while the system ran doesn't want it.
+        if self.0.capturing.load(Ordering::Relaxed) {
+            let mut by_system = self.0.by_system.lock().unwrap();
+            let spent = by_system.entry(system.name.clone()).or_default();
+            spent.total += entered.elapsed();
+            spent.runs += 1;
+        }
+    }
+}
+
+/// Picks the system's name out of its span's fields.
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// The capture `/timings` is running, and who to tell about it.
+#[derive(Resource, Default)]
+struct RunningCapture(Option<Capture>);
+
+struct Capture {
+    sender: Entity,
+    length: Duration,
+    until: Instant,
+    start_tick: u64,
+}
+
+pub struct TimingsPlugin;
+
+impl Plugin for TimingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunningCapture>()
+            .add_command(TIMINGS)
+            .add_system_to_stage(EventLoop, timings_command)
+            .add_system(finish_capture);
+    }
+}
+
+fn reply(
+    clients: &mut Query<&mut Client>,
+    consoles: &mut Query<&mut Console>,
+    sender: Entity,
+    reply: Text,
+) {
+    if let Ok(mut client) = clients.get_mut(sender) {
+        client.send_message(reply);
+    } else if let Ok(mut console) = consoles.get_mut(sender) {
+        console.send_message(reply);
+    }
+}
+
+fn timings_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    mut running: ResMut<RunningCapture>,
+    timings: Res<Timings>,
+    server: Res<Server>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(TIMINGS.name)) {
+        let seconds = match event.args.as_slice() {
+            [seconds] => seconds.parse::<u64>().ok().filter(|&s| s > 0),
+            _ => None,
+        };
+
+        let text = match seconds {
+            None => usage(&lang, event.sender, &TIMINGS),
+            Some(_) if running.0.is_some() => lang.tr(event.sender, "timings.running", &[]),
+            Some(seconds) if seconds > MAX_SECONDS => {
+                lang.tr(event.sender, "timings.too_long", &[("max", &MAX_SECONDS)])
+            }
+            Some(seconds) => {
+                let length = Duration::from_secs(seconds);
+                timings.start();
+                running.0 = Some(Capture {
+                    sender: event.sender,
+                    length,
+                    until: Instant::now() + length,
+                    start_tick: server.current_tick(),
+                });
+                lang.tr(
+                    event.sender,
+                    "timings.started",
+                    &[("time", &format_duration(length))],
+                )
+            }
+        };
+        reply(&mut clients, &mut consoles, event.sender, text);
+    }
+}
+
+fn finish_capture(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    mut running: ResMut<RunningCapture>,
+    timings: Res<Timings>,
+    server: Res<Server>,
+    lang: Res<Lang>,
+) {
+    if !matches!(&running.0, Some(capture) if Instant::now() >= capture.until) {
+        return;
+    }
+    let Some(capture) = running.0.take() else {
+        return;
+    };
+
+    let systems = timings.finish();
+    let ticks = (server.current_tick() - capture.start_tick).max(1);
+    let sender = capture.sender;
+
+    let mut text = lang.tr(
+        sender,
+        "timings.header",
+        &[
+            ("time", &format_duration(capture.length)),
+            ("ticks", &ticks),
+        ],
+    );
+    if systems.is_empty() {
+        text = text + "\n" + lang.tr(sender, "timings.none", &[]);
+    }
+    for (name, spent) in systems.iter().take(TOP_SYSTEMS) {
+        let total = spent.total.as_secs_f64() * 1000.0;
+        text = text
+            + "\n"
+            + lang.tr(
+                sender,
+                "timings.entry",
+                &[
+                    ("total", &format!("{total:.1}")),
+                    ("name", name),
+                    ("per_tick", &format!("{:.2}", total / ticks as f64)),
+                    ("runs", &spent.runs),
+                ],
+            );
+    }
+
+    reply(&mut clients, &mut consoles, sender, text);
+}
@@ -0,0 +1,356 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+use valence::client::event::UseItemOnBlock;
+use valence::prelude::*;
+use valence_protocol::particle::Particle;
+use valence_protocol::sound::{Sound, SoundCategory};
+use valence_protocol::types::Hand;
+
+use crate::block_log::{Actor, BlockChanged};
+use crate::block_sync::resend_block;
+use crate::border::{inside_border, WorldBorder};
+use crate::config::Config;
+use crate::drops::drop_item;
+use crate::inspect::Inspecting;
+use crate::sound::play_sound_at;
+use crate::{UsedClicks, WorldName};
+
+/// How long lit TNT takes to go off, in ticks.
+pub const FUSE: u32 = 80;
+
+/// TNT caught in an explosion goes off after somewhere in this many ticks,
+/// so a pile of it goes off in a ripple rather than all at once.
+const CHAIN_FUSE: std::ops::Range<u32> = 10..30;
+
+/// How strong TNT's explosions are. Players up to twice this far away are
+/// thrown back, and one in this many blocks broken drops as an item.
+const POWER: f32 = 4.0;
+
+/// How far apart the points each ray of an explosion checks are, in blocks.
+const RAY_STEP: f64 = 0.3;
+
+/// How far away explosions are heard and seen.
+const EFFECT_DISTANCE: f64 = 64.0;
+
+/// TNT that has been lit.
+#[derive(Component, Debug)]
+struct PrimedTnt {
+    fuse: u32,
+    /// Who lit it, or lit the TNT that set it off. The blocks it breaks are
+    /// logged as theirs, even once they've left.
+    lit_by: Actor,
+}
+
+pub struct TntPlugin;
+
+impl Plugin for TntPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(EventLoop, light_tnt)
+            .add_system(explode_tnt);
+    }
+}
+
+/// Whether TNT can be lit in `world`: it has to be allowed there, and not
+/// turned off for the whole server.
+pub fn allowed_in(config: &Config, world: &WorldName) -> bool {
+    config.tnt.enabled && config.world(&world.0).tnt
+}
+
+/// The block something at `position` is in.
+fn block_at(position: DVec3) -> BlockPos {
+    BlockPos::new(
+        position.x.floor() as i32,
+        position.y.floor() as i32,
+        position.z.floor() as i32,
+    )
+}
+
+/// Lights TNT at `pos`, which goes off after `fuse` ticks.
+pub fn prime(commands: &mut Commands, instance: Entity, pos: BlockPos, fuse: u32, lit_by: Actor) {
+    let mut entity = McEntity::new(EntityKind::Tnt, instance);
+    entity.set_position([pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5]);
+    if let TrackedData::Tnt(data) = entity.data_mut() {
+        data.set_fuse(fuse as i32);
+    }
+    commands.spawn((entity, PrimedTnt { fuse, lit_by }));
+}
+
+/// How well blocks of `kind` stand up to explosions, roughly as in vanilla.
+fn blast_resistance(kind: BlockKind) -> f32 {
+    let name = kind.to_str();
+    match kind {
+        BlockKind::Air | BlockKind::CaveAir | BlockKind::VoidAir => 0.0,
+        BlockKind::Bedrock
+        | BlockKind::Barrier
+        | BlockKind::EndPortalFrame
+        | BlockKind::EndPortal
+        | BlockKind::EndGateway
+        | BlockKind::CommandBlock
+        | BlockKind::ChainCommandBlock
+        | BlockKind::RepeatingCommandBlock
+        | BlockKind::StructureBlock
+        | BlockKind::Jigsaw
+        | BlockKind::Light => 3_600_000.0,
+        BlockKind::Obsidian
+        | BlockKind::CryingObsidian
+        | BlockKind::AncientDebris
+        | BlockKind::NetheriteBlock
+        | BlockKind::RespawnAnchor
+        | BlockKind::EnchantingTable
+        | BlockKind::Anvil
+        | BlockKind::ChippedAnvil
+        | BlockKind::DamagedAnvil => 1200.0,
+        BlockKind::Water | BlockKind::Lava => 100.0,
+        BlockKind::Tnt => 0.0,
+        _ if name.contains("glass") => 0.3,
+        _ if name.ends_with("_leaves") || name.ends_with("_wool") || name.ends_with("_carpet") => {
+            0.2
+        }
+        _ if name.contains("deepslate") && !name.ends_with("_ore") => 6.0,
+        _ if name.ends_with("_ore") => 3.0,
+        _ if name.ends_with("_planks")
+            || name.ends_with("_log")
+            || name.ends_with("_wood")
+            || name.ends_with("_stem") =>
+        {
+            3.0
+        }
+        _ if name.contains("stone")
+            || name.contains("brick")
+            || name.contains("concrete") && !name.ends_with("_powder")
+            || name.ends_with("_block") =>
+        {
+            6.0
+        }
+        _ if name.contains("dirt")
+            || name.contains("sand")
+            || name.contains("gravel")
+            || name.contains("grass")
+            || name.ends_with("_powder")
+            || name == "clay"
+            || name == "farmland" =>
+        {
+            0.5
+        }
+        _ => 1.0,
+    }
+}
+
+/// Lights TNT that a player uses flint and steel on, where TNT is allowed.
+fn light_tnt(
+    mut commands: Commands,
+    mut clients: Query<(&mut Client, &Inventory, Option<&Inspecting>)>,
+    mut instances: Query<(&mut Instance, &WorldName)>,
+    borders: Query<&WorldBorder>,
+    config: Res<Config>,
+    mut events: EventReader<UseItemOnBlock>,
+    mut changes: EventWriter<BlockChanged>,
+    mut used: ResMut<UsedClicks>,
+) {
+    for event in events.iter().filter(|e| e.hand == Hand::Main) {
+        let Ok((mut client, inventory, inspecting)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        // Inspecting players' clicks only look blocks up.
+        if inspecting.is_some() {
+            continue;
+        }
+        let held = inventory
+            .slot(client.held_item_slot())
+            .map(|stack| stack.item);
+        if held != Some(ItemKind::FlintAndSteel) {
+            continue;
+        }
+        used.mark(event.client);
+        let instance_entity = client.instance();
+        let Ok((mut instance, world)) = instances.get_mut(instance_entity) else {
+            continue;
+        };
+        let pos = event.position;
+        let Some(old) = instance.block(pos).map(|block| block.state()) else {
+            continue;
+        };
+        if old.to_kind() != BlockKind::Tnt {
+            continue;
+        }
+        if !allowed_in(&config, world) || !inside_border(&borders, instance_entity, pos) {
+            resend_block(&mut client, &instance, pos);
+            continue;
+        }
+
+        let actor = Actor::of(&client);
+        changes.send(BlockChanged {
+            actor: actor.clone(),
+            instance: instance_entity,
+            position: pos,
+            old,
+            new: BlockState::AIR,
+        });
+        instance.set_block(pos, BlockState::AIR);
+        prime(&mut commands, instance_entity, pos, FUSE, actor);
+
+        let center = DVec3::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5);
+        for (mut client, _, _) in &mut clients {
+            if client.instance() == instance_entity
+                && client.position().distance(center) <= EFFECT_DISTANCE
+            {
+                play_sound_at(
+                    &mut client,
+                    Sound::EntityTntPrimed,
+                    SoundCategory::Block,
+                    center,
+                    1.0,
+                    1.0,
+                );
+            }
+        }
+    }
+}
+
+/// The blocks an explosion at `center` breaks. Like vanilla, it sends rays
+/// out in every direction, each with a little random strength, which tough
+/// blocks use up faster than weak ones.
+fn blast(instance: &Instance, center: DVec3, rng: &mut impl Rng) -> HashSet<BlockPos> {
+    let mut broken = HashSet::new();
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let on_surface = [x, y, z].iter().any(|&n| n == 0 || n == 15);
+                if !on_surface {
+                    continue;
+                }
+                let direction = (DVec3::new(x as f64, y as f64, z as f64) / 15.0 * 2.0
+                    - DVec3::ONE)
+                    .normalize();
+
+                let mut strength = POWER * rng.gen_range(0.7..1.3);
+                let mut at = center;
+                while strength > 0.0 {
+                    let pos = block_at(at);
+                    let Some(block) = instance.block(pos) else {
+                        break;
+                    };
+                    let kind = block.state().to_kind();
+                    if !block.state().is_air() {
+                        strength -= (blast_resistance(kind) + 0.3) * RAY_STEP as f32;
+                        if strength > 0.0 {
+                            broken.insert(pos);
+                        }
+                    }
+                    at += direction * RAY_STEP;
+                    strength -= 0.75 * RAY_STEP as f32;
+                }
+            }
+        }
+    }
+    broken
+}
+
+/// Sets off TNT whose fuse has run out. It breaks blocks around it, only
+/// inside the world border, sets off other TNT and throws players back.
+/// TNT that goes off in water breaks nothing.
+fn explode_tnt(
+    mut commands: Commands,
+    mut tnt: Query<(&mut McEntity, &mut PrimedTnt)>,
+    mut instances: Query<&mut Instance>,
+    mut clients: Query<&mut Client>,
+    borders: Query<&WorldBorder>,
+    mut changes: EventWriter<BlockChanged>,
+) {
+    let mut rng = rand::thread_rng();
+    for (mut entity, mut primed) in &mut tnt {
+        if entity.is_despawned() {
+            continue;
+        }
+        primed.fuse = primed.fuse.saturating_sub(1);
+        if primed.fuse > 0 {
+            continue;
+        }
+
+        entity.set_despawned(true);
+        let instance_entity = entity.instance();
+        let Ok(mut instance) = instances.get_mut(instance_entity) else {
+            continue;
+        };
+        let center = entity.position() + DVec3::new(0.0, 0.0625, 0.0);
+
+        let in_water = instance
+            .block(block_at(center))
+            .map_or(false, |block| block.state().to_kind() == BlockKind::Water);
+        let broken = if in_water {
+            HashSet::new()
+        } else {
+            blast(&instance, center, &mut rng)
+        };
+        for pos in broken {
+            if !inside_border(&borders, instance_entity, pos) {
+                continue;
+            }
+            let Some(old) = instance.block(pos).map(|block| block.state()) else {
+                continue;
+            };
+            changes.send(BlockChanged {
+                actor: primed.lit_by.clone(),
+                instance: instance_entity,
+                position: pos,
+                old,
+                new: BlockState::AIR,
+            });
+            instance.set_block(pos, BlockState::AIR);
+
+            let kind = old.to_kind();
+            if kind == BlockKind::Tnt {
+                let fuse = rng.gen_range(CHAIN_FUSE);
+                prime(&mut commands, instance_entity, pos, fuse, primed.lit_by.clone());
+            } else if rng.gen::<f32>() < 1.0 / POWER {
+                if let Some(item) = ItemKind::from_str(kind.to_str()) {
+                    let at = DVec3::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5);
+                    drop_item(
+                        &mut commands,
+                        instance_entity,
+                        at,
+                        ItemStack::new(item, 1, None),
+                    );
+                }
+            }
+        }
+
+        let reach = f64::from(POWER) * 2.0;
+        let pitch = (1.0 + (rng.gen::<f32>() - rng.gen::<f32>()) * 0.2) * 0.7;
+        for mut client in &mut clients {
+            if client.instance() != instance_entity {
+                continue;
+            }
+            let away = client.position() - center;
+            if away.length() <= EFFECT_DISTANCE {
+                play_sound_at(
+                    &mut client,
+                    Sound::EntityGenericExplode,
+                    SoundCategory::Block,
+                    center,
+                    pitch,
+                    4.0,
+                );
+                client.play_particle(
+                    &Particle::ExplosionEmitter,
+                    true,
+                    center,
+                    [0.0, 0.0, 0.0],
+                    1.0,
+                    1,
+                );
+            }
+
+            // Players are thrown back harder the closer they are, without
+            // working out how much cover they had.
+            let closeness = 1.0 - away.length() / reach;
+            if client.game_mode() == GameMode::Spectator || closeness <= 0.0 {
+                continue;
+            }
+            let push = away.try_normalize().unwrap_or(DVec3::Y) * closeness;
+            // In blocks per tick, which the velocity is set in per second.
+            client.set_velocity((push * 20.0).as_vec3());
+        }
+    }
+}
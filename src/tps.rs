@@ -0,0 +1,461 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use valence::prelude::*;
+
+use crate::command::{AddCommand, CommandExecution, CommandInfo, Console};
+use crate::format::fill_placeholders;
+use crate::lang::Lang;
+
+const TPS: CommandInfo = CommandInfo {
+    name: "tps",
+    aliases: &["lag", "mspt"],
+    usage: "/tps",
+    description: "Show whether the server is keeping up.",
+    permission: None,
+    console: true,
+};
+
+/// The windows `/tps` averages over. Ticks are kept for the longest.
+const WINDOWS: [(&str, Duration); 3] = [
+    ("5s", Duration::from_secs(5)),
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+];
+
+/// How long a tick can take while still keeping up with 20 ticks a second.
+const TICK_BUDGET: Duration = Duration::from_millis(50);
+
+/// Once a tick has run this long, work that can wait is put off to the next
+/// one. Less than the whole budget, as Valence still has packets to send
+/// after the systems are done.
+const DEFER_AFTER: Duration = Duration::from_millis(40);
+
+/// Work that can be put off to a later tick when the server is behind,
+/// without players seeing anything worse than a short delay. Everything
+/// else, like placing and breaking blocks, always runs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Deferrable {
+    ChunkLoading,
+    ChunkUnloading,
+    Hud,
+    Sidebar,
+    PlaytimeSaves,
+}
+
+impl Deferrable {
+    const ALL: [Deferrable; 5] = [
+        Deferrable::ChunkLoading,
+        Deferrable::ChunkUnloading,
+        Deferrable::Hud,
+        Deferrable::Sidebar,
+        Deferrable::PlaytimeSaves,
+    ];
+
+    fn lang_key(self) -> &'static str {
+        match self {
+            Deferrable::ChunkLoading => "tps.chunk_loading",
+            Deferrable::ChunkUnloading => "tps.chunk_unloading",
+            Deferrable::Hud => "tps.hud",
+            Deferrable::Sidebar => "tps.sidebar",
+            Deferrable::PlaytimeSaves => "tps.playtime_saves",
+        }
+    }
+}
+
+/// Where [`Tps`] gets the time. Tests set it by hand, so how long their ticks
+/// take is up to them rather than to how busy the machine is.
+#[derive(Clone, Default, Debug)]
+pub struct Clock(Option<Arc<Mutex<Instant>>>);
+
+impl Clock {
+    #[cfg(test)]
+    pub fn manual() -> Self {
+        Self(Some(Arc::new(Mutex::new(Instant::now()))))
+    }
+
+    #[cfg(test)]
+    pub fn advance(&self, by: Duration) {
+        if let Some(now) = &self.0 {
+            *now.lock().unwrap() += by;
+        }
+    }
+
+    fn now(&self) -> Instant {
+        match &self.0 {
+            Some(now) => *now.lock().unwrap(),
+            None => Instant::now(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TickTime {
+    end: Instant,
+    took: Duration,
+    /// Whether each kind of [`Deferrable`] work was put off this tick.
+    deferred: [bool; Deferrable::ALL.len()],
+}
+
+/// How fast the server has been ticking, and how long its ticks took, over
+/// the last few minutes.
+#[derive(Resource, Default, Debug)]
+pub struct Tps {
+    clock: Clock,
+    /// When the first tick started, so averages over windows longer than the
+    /// uptime aren't dragged down.
+    started: Option<Instant>,
+    /// When the running tick started.
+    tick_start: Option<Instant>,
+    /// How often each kind of [`Deferrable`] work was put off in the running
+    /// tick. Atomic so the systems asking don't need to take turns.
+    deferring: [AtomicU32; Deferrable::ALL.len()],
+    /// The ticks that ended within the longest window, oldest first.
+    ticks: VecDeque<TickTime>,
+}
+
+impl Tps {
+    #[cfg(test)]
+    pub fn with_clock(clock: Clock) -> Self {
+        Self {
+            clock,
+            ..Self::default()
+        }
+    }
+
+    /// Ticks per second over the last 5 seconds, at most 20.
+    pub fn get(&self) -> f64 {
+        self.average(WINDOWS[0].1).min(20.0)
+    }
+
+    /// The average tick time over the last 5 seconds, in milliseconds.
+    pub fn mspt(&self) -> f64 {
+        self.tick_times(WINDOWS[0].1).0.as_secs_f64() * 1000.0
+    }
+
+    /// Ticks per second over the last `window`. Can go a little over 20 when
+    /// the server catches up after a slow tick.
+    pub fn average(&self, window: Duration) -> f64 {
+        let Some(started) = self.started else {
+            return 20.0;
+        };
+        let now = self.clock.now();
+        let span = window.min(now - started);
+        if span.is_zero() {
+            return 20.0;
+        }
+
+        let count = self.recent(now, window).count();
+        count as f64 / span.as_secs_f64()
+    }
+
+    /// The average and longest tick over the last `window`.
+    pub fn tick_times(&self, window: Duration) -> (Duration, Duration) {
+        let (mut total, mut longest, mut count) = (Duration::ZERO, Duration::ZERO, 0);
+        for tick in self.recent(self.clock.now(), window) {
+            total += tick.took;
+            longest = longest.max(tick.took);
+            count += 1;
+        }
+
+        if count == 0 {
+            return (Duration::ZERO, Duration::ZERO);
+        }
+        (total / count, longest)
+    }
+
+    /// Whether the running tick is far enough through its budget that `work`
+    /// should wait for a later one. Systems doing deferrable work ask this
+    /// before starting, and again as they go for anything long.
+    pub fn defer(&self, work: Deferrable) -> bool {
+        let over = self
+            .tick_start
+            .map_or(false, |start| self.clock.now() - start >= DEFER_AFTER);
+        if over {
+            self.deferring[work as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        over
+    }
+
+    /// How many ticks over the last `window` put off each kind of work, for
+    /// the kinds that were put off at all.
+    pub fn deferred(&self, window: Duration) -> Vec<(Deferrable, usize)> {
+        let now = self.clock.now();
+        Deferrable::ALL
+            .into_iter()
+            .map(|work| {
+                let ticks = self
+                    .recent(now, window)
+                    .filter(|tick| tick.deferred[work as usize])
+                    .count();
+                (work, ticks)
+            })
+            .filter(|&(_, ticks)| ticks > 0)
+            .collect()
+    }
+
+    fn recent(&self, now: Instant, window: Duration) -> impl Iterator<Item = &TickTime> {
+        self.ticks
+            .iter()
+            .rev()
+            .take_while(move |tick| now - tick.end <= window)
+    }
+
+    pub fn start_tick(&mut self) {
+        let now = self.clock.now();
+        self.started.get_or_insert(now);
+        self.tick_start = Some(now);
+    }
+
+    pub fn finish_tick(&mut self) {
+        let Some(start) = self.tick_start.take() else {
+            return;
+        };
+        let end = self.clock.now();
+        let deferred = std::array::from_fn(|i| self.deferring[i].swap(0, Ordering::Relaxed) > 0);
+        self.ticks.push_back(TickTime {
+            end,
+            took: end - start,
+            deferred,
+        });
+
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        while let Some(oldest) = self.ticks.front() {
+            if end - oldest.end <= longest {
+                break;
+            }
+            self.ticks.pop_front();
+        }
+    }
+}
+
+pub struct TpsPlugin;
+
+impl Plugin for TpsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tps>()
+            .add_command(TPS)
+            .add_system_to_stage(CoreStage::First, start_tick)
+            .add_system_to_stage(CoreStage::Last, finish_tick)
+            .add_system_to_stage(EventLoop, tps_command);
+    }
+}
+
+fn start_tick(mut tps: ResMut<Tps>) {
+    tps.start_tick();
+}
+
+fn finish_tick(mut tps: ResMut<Tps>) {
+    tps.finish_tick();
+}
+
+/// Colors a TPS the way Paper does. Catching up shows as `*20.0`.
+fn colored_tps(tps: f64) -> String {
+    let color = match tps {
+        tps if tps > 18.0 => "&a",
+        tps if tps > 16.0 => "&e",
+        _ => "&c",
+    };
+    if tps > 20.0 {
+        format!("{color}*20.0")
+    } else {
+        format!("{color}{tps:.1}")
+    }
+}
+
+/// Colors a tick time by how much of the tick's budget it used.
+fn colored_tick_time(time: Duration) -> String {
+    let color = if time < TICK_BUDGET * 4 / 5 {
+        "&a"
+    } else if time < TICK_BUDGET {
+        "&e"
+    } else {
+        "&c"
+    };
+    format!("{color}{:.1}ms", time.as_secs_f64() * 1000.0)
+}
+
+fn tps_command(
+    mut clients: Query<&mut Client>,
+    mut consoles: Query<&mut Console>,
+    tps: Res<Tps>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(TPS.name)) {
+        let windows: Vec<_> = WINDOWS.iter().map(|(name, _)| *name).collect();
+        let averages: Vec<_> = WINDOWS
+            .iter()
+            .map(|&(_, window)| colored_tps(tps.average(window)))
+            .collect();
+        let (average, longest) = tps.tick_times(WINDOWS[0].1);
+
+        let mut reply = lang.tr(
+            event.sender,
+            "tps.tps",
+            &[
+                ("windows", &windows.join(", ")),
+                ("tps", &averages.join("&6, ")),
+            ],
+        ) + "\n"
+            + lang.tr(
+                event.sender,
+                "tps.tick_times",
+                &[
+                    ("window", &WINDOWS[0].0),
+                    ("average", &colored_tick_time(average)),
+                    ("longest", &colored_tick_time(longest)),
+                ],
+            );
+
+        let deferred = tps.deferred(WINDOWS[1].1);
+        if !deferred.is_empty() {
+            let work: Vec<_> = deferred
+                .iter()
+                .map(|&(work, ticks)| {
+                    fill_placeholders(
+                        lang.plain(event.sender, work.lang_key()),
+                        &[("ticks", &ticks)],
+                    )
+                })
+                .collect();
+            reply = reply
+                + "\n"
+                + lang.tr(
+                    event.sender,
+                    "tps.deferred",
+                    &[("window", &WINDOWS[1].0), ("work", &work.join("&6, "))],
+                );
+        }
+
+        if let Ok(mut client) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::{Stage, SystemStage};
+
+    use super::*;
+
+    /// How long one placement takes, and how long making one chunk does, on
+    /// the test's clock.
+    const PLACEMENT: Duration = Duration::from_millis(1);
+    const CHUNK: Duration = Duration::from_millis(1);
+
+    /// Placements each flooded tick, which take up most of its budget.
+    const FLOOD: usize = 30;
+    const FLOODED_TICKS: usize = 5;
+
+    /// Chunks waiting to be made when the flood starts, far more than fit
+    /// in a tick.
+    const CHUNKS: usize = 200;
+
+    #[derive(Resource, Default)]
+    struct Load {
+        /// Placements sent for the next tick.
+        sent: usize,
+        placed: usize,
+        /// Chunks waiting to be made.
+        queued: usize,
+        made: usize,
+    }
+
+    /// Handles every placement sent, however late the tick is running, as
+    /// gameplay always does.
+    fn place(mut load: ResMut<Load>, tps: Res<Tps>) {
+        while load.sent > 0 {
+            tps.clock.advance(PLACEMENT);
+            load.sent -= 1;
+            load.placed += 1;
+        }
+    }
+
+    /// Makes queued chunks until the tick runs late.
+    fn make_chunks(mut load: ResMut<Load>, tps: Res<Tps>) {
+        while load.queued > 0 && !tps.defer(Deferrable::ChunkLoading) {
+            tps.clock.advance(CHUNK);
+            load.queued -= 1;
+            load.made += 1;
+        }
+    }
+
+    struct Ticker {
+        world: World,
+        stages: [SystemStage; 3],
+    }
+
+    impl Ticker {
+        fn new() -> Self {
+            let mut world = World::new();
+            world.insert_resource(Tps::with_clock(Clock::manual()));
+            world.insert_resource(Load {
+                queued: CHUNKS,
+                ..Load::default()
+            });
+            let stages = [
+                SystemStage::single_threaded().with_system(start_tick),
+                SystemStage::single_threaded()
+                    .with_system(place)
+                    .with_system(make_chunks.after(place)),
+                SystemStage::single_threaded().with_system(finish_tick),
+            ];
+            Self { world, stages }
+        }
+
+        /// Runs a tick with `placements` sent for it, returning how long it
+        /// took.
+        fn tick(&mut self, placements: usize) -> Duration {
+            self.world.resource_mut::<Load>().sent = placements;
+            for stage in &mut self.stages {
+                stage.run(&mut self.world);
+            }
+            self.world.resource::<Tps>().ticks.back().unwrap().took
+        }
+
+        fn load(&self) -> &Load {
+            self.world.resource::<Load>()
+        }
+    }
+
+    #[test]
+    fn floods_of_placements_keep_ticks_in_budget() {
+        let mut ticker = Ticker::new();
+
+        for _ in 0..FLOODED_TICKS {
+            let took = ticker.tick(FLOOD);
+            // Without putting chunks off, this tick would take over 200 ms.
+            // The placements take 30 ms, and chunks are made until 40 ms.
+            assert_eq!(took, DEFER_AFTER);
+            // Every placement is handled the tick it's sent.
+            assert_eq!(ticker.load().sent, 0);
+        }
+        assert_eq!(ticker.load().placed, FLOOD * FLOODED_TICKS);
+
+        // The chunks waited rather than stalling the ticks, and the wait
+        // shows in /tps.
+        assert!(ticker.load().queued > 0);
+        assert!(ticker.load().made > 0);
+        let deferred = ticker.world.resource::<Tps>().deferred(WINDOWS[1].1);
+        assert!(deferred
+            .iter()
+            .any(|&(work, ticks)| work == Deferrable::ChunkLoading && ticks == FLOODED_TICKS));
+
+        // Once the flood is over, the rest are made over the next few ticks.
+        for _ in 0..CHUNKS {
+            if ticker.load().queued == 0 {
+                break;
+            }
+            let took = ticker.tick(0);
+            assert!(took <= DEFER_AFTER, "a catching up tick took {took:?}");
+        }
+        assert_eq!(ticker.load().queued, 0);
+        assert_eq!(ticker.load().made, CHUNKS);
+    }
+}
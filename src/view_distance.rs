@@ -0,0 +1,115 @@
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::{Config, MIN_VIEW_DISTANCE};
+use crate::lang::Lang;
+use crate::player_data::PlayerDataStore;
+use crate::reload::ConfigReloaded;
+
+const VIEWDISTANCE: CommandInfo = CommandInfo {
+    name: "viewdistance",
+    aliases: &["vd"],
+    usage: "/viewdistance <chunks|reset>",
+    description: "See less far than the server allows, for a slow connection.",
+    permission: None,
+    console: false,
+};
+
+pub struct ViewDistancePlugin;
+
+impl Plugin for ViewDistancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(VIEWDISTANCE)
+            .add_system(init_view_distances)
+            .add_system(reload_view_distances)
+            .add_system_to_stage(EventLoop, viewdistance_command);
+    }
+}
+
+/// The server's view distance, or the player's own if it's lower.
+fn view_distance_for(store: &mut PlayerDataStore, config: &Config, uuid: Uuid) -> u8 {
+    let server = config.server.view_distance;
+    store
+        .get(uuid)
+        .view_distance
+        .map_or(server, |own| own.min(server))
+}
+
+fn init_view_distances(
+    mut clients: Query<&mut Client, Added<Client>>,
+    mut store: ResMut<PlayerDataStore>,
+    config: Res<Config>,
+) {
+    for mut client in &mut clients {
+        let distance = view_distance_for(&mut store, &config, client.uuid());
+        client.set_view_distance(distance);
+    }
+}
+
+/// Valence loads and unloads chunks around each client to match its view
+/// distance, so setting it is all a change to the config needs.
+fn reload_view_distances(
+    mut clients: Query<&mut Client>,
+    mut store: ResMut<PlayerDataStore>,
+    config: Res<Config>,
+    mut events: EventReader<ConfigReloaded>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for mut client in &mut clients {
+        let distance = view_distance_for(&mut store, &config, client.uuid());
+        client.set_view_distance(distance);
+    }
+}
+
+fn viewdistance_command(
+    mut clients: Query<&mut Client>,
+    mut store: ResMut<PlayerDataStore>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(VIEWDISTANCE.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+        let uuid = client.uuid();
+        let max = config.server.view_distance;
+
+        let choice = match event.args.as_slice() {
+            [arg] if arg == "reset" => None,
+            [arg] => match arg.parse::<u8>() {
+                Ok(distance) if (MIN_VIEW_DISTANCE..=max).contains(&distance) => Some(distance),
+                Ok(_) => {
+                    client.send_message(lang.tr(
+                        event.sender,
+                        "view_distance.out_of_range",
+                        &[("min", &MIN_VIEW_DISTANCE), ("max", &max)],
+                    ));
+                    continue;
+                }
+                Err(_) => {
+                    client.send_message(usage(&lang, event.sender, &VIEWDISTANCE));
+                    continue;
+                }
+            },
+            _ => {
+                client.send_message(usage(&lang, event.sender, &VIEWDISTANCE));
+                continue;
+            }
+        };
+
+        store.get(uuid).view_distance = choice;
+        store.save(uuid);
+
+        let distance = view_distance_for(&mut store, &config, uuid);
+        client.set_view_distance(distance);
+        client.send_message(lang.tr(
+            event.sender,
+            "view_distance.set",
+            &[("distance", &distance)],
+        ));
+    }
+}
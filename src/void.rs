@@ -0,0 +1,63 @@
+use valence::prelude::*;
+
+use crate::config::Config;
+use crate::health::{DamageCause, DamageEvent, Health};
+use crate::lang::Lang;
+use crate::spawn::send_to_spawn;
+use crate::worlds::Worlds;
+
+/// Damage dealt each time void damage is applied, matching vanilla.
+const VOID_DAMAGE: f32 = 4.0;
+
+/// How many ticks between applications of void damage.
+const VOID_DAMAGE_INTERVAL: u64 = 10;
+
+pub struct VoidPlugin;
+
+impl Plugin for VoidPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(rescue_from_void);
+    }
+}
+
+/// Deals with clients who have fallen below the configured void level: in
+/// survival with void damage enabled they take damage until they die,
+/// otherwise they're put back at spawn.
+fn rescue_from_void(
+    mut clients: Query<(Entity, &mut Client, &Health)>,
+    mut instances: Query<&mut Instance>,
+    worlds: Res<Worlds>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    server: Res<Server>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for (entity, mut client, health) in &mut clients {
+        if client.position().y >= config.void.y as f64 || health.is_dead() {
+            continue;
+        }
+
+        let void_damage = worlds
+            .name_of(client.instance())
+            .map_or(false, |name| config.world(name).void_damage);
+
+        match client.game_mode() {
+            GameMode::Spectator => continue,
+            GameMode::Survival | GameMode::Adventure if void_damage => {
+                if server.current_tick() % VOID_DAMAGE_INTERVAL == 0 {
+                    damage.send(DamageEvent {
+                        client: entity,
+                        amount: VOID_DAMAGE,
+                        cause: DamageCause::Void,
+                    });
+                }
+            }
+            _ => {
+                client.set_velocity([0.0, 0.0, 0.0]);
+                if send_to_spawn(&mut client, &config.spawn, &worlds, &mut instances) {
+                    client.send_message(lang.tr(entity, "void.rescued", &[]));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{GameEvent, GameStateChangeReason};
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo};
+use crate::config::Config;
+use crate::lang::Lang;
+use crate::WorldName;
+
+const WEATHER: CommandInfo = CommandInfo {
+    name: "weather",
+    aliases: &[],
+    usage: "/weather <clear|rain|thunder> [seconds]",
+    description: "Change the weather in your world.",
+    permission: Some("plots.command.weather"),
+    console: false,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+    Thunder,
+}
+
+impl WeatherKind {
+    fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "clear" => Some(Self::Clear),
+            "rain" => Some(Self::Rain),
+            "thunder" => Some(Self::Thunder),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Clear => "clear",
+            Self::Rain => "rain",
+            Self::Thunder => "thunder",
+        }
+    }
+}
+
+/// The weather in an instance. Anything but clear weather can be given a
+/// number of ticks after which it clears up again.
+#[derive(Component, Default, Debug)]
+pub struct Weather {
+    pub kind: WeatherKind,
+    pub remaining: Option<u64>,
+}
+
+/// The weather a client was last told about, and in which instance.
+#[derive(Component, Default, Debug)]
+struct WeatherViewer {
+    instance: Option<Entity>,
+    shown: WeatherKind,
+}
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(WEATHER)
+            .add_system(init_weather)
+            .add_system(init_weather_viewers)
+            .add_system(advance_weather)
+            .add_system_to_stage(EventLoop, weather_command)
+            .add_system(
+                sync_weather
+                    .after(advance_weather)
+                    .after(init_weather_viewers),
+            );
+    }
+}
+
+fn init_weather(mut commands: Commands, instances: Query<Entity, Added<Instance>>) {
+    for entity in &instances {
+        commands.entity(entity).insert(Weather::default());
+    }
+}
+
+fn init_weather_viewers(mut commands: Commands, clients: Query<Entity, Added<Client>>) {
+    for entity in &clients {
+        commands.entity(entity).insert(WeatherViewer::default());
+    }
+}
+
+fn advance_weather(mut worlds: Query<(&mut Weather, &WorldName)>, config: Res<Config>) {
+    for (mut weather, name) in &mut worlds {
+        if config.world(&name.0).lock_weather {
+            if weather.kind != WeatherKind::Clear {
+                *weather = Weather::default();
+            }
+            continue;
+        }
+
+        match weather.remaining {
+            Some(0) => *weather = Weather::default(),
+            Some(ref mut ticks) => *ticks -= 1,
+            None => {}
+        }
+    }
+}
+
+fn weather_command(
+    mut clients: Query<&mut Client>,
+    mut worlds: Query<(&mut Weather, &WorldName)>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(WEATHER.name)) {
+        let Ok(mut client) = clients.get_mut(event.sender) else {
+            continue;
+        };
+
+        let Ok((mut weather, name)) = worlds.get_mut(client.instance()) else {
+            continue;
+        };
+
+        let parsed = match event.args.as_slice() {
+            [kind] => WeatherKind::parse(kind).map(|kind| (kind, None)),
+            [kind, secs] => WeatherKind::parse(kind)
+                .zip(secs.parse::<u64>().ok())
+                .map(|(kind, secs)| (kind, Some(secs * 20))),
+            _ => None,
+        };
+
+        let Some((kind, remaining)) = parsed else {
+            client.send_message(usage(&lang, event.sender, &WEATHER));
+            continue;
+        };
+
+        if config.world(&name.0).lock_weather {
+            client.send_message(lang.tr(event.sender, "weather.locked", &[]));
+            continue;
+        }
+
+        *weather = Weather { kind, remaining };
+        let kind = lang.plain(event.sender, &format!("weather.{}", kind.name()));
+        client.send_message(lang.tr(
+            event.sender,
+            "weather.set",
+            &[("world", &name.0), ("weather", &kind)],
+        ));
+    }
+}
+
+fn game_event(reason: GameStateChangeReason, value: f32) -> GameEvent {
+    GameEvent { reason, value }
+}
+
+/// Tells clients when the weather they should see changes. Thunder sets the
+/// thunder level too, which is what darkens the sky on the client.
+fn sync_weather(mut clients: Query<(&mut Client, &mut WeatherViewer)>, worlds: Query<&Weather>) {
+    for (mut client, mut viewer) in &mut clients {
+        let Ok(weather) = worlds.get(client.instance()) else {
+            continue;
+        };
+
+        let kind = weather.kind;
+        // Clients reset to clear weather when they join or change worlds, so
+        // joining mid-storm still gets the rain.
+        let shown = if viewer.instance == Some(client.instance()) {
+            viewer.shown
+        } else {
+            WeatherKind::Clear
+        };
+        viewer.instance = Some(client.instance());
+        viewer.shown = kind;
+        if shown == kind {
+            continue;
+        }
+
+        let (rain, thunder) = match kind {
+            WeatherKind::Clear => (0.0, 0.0),
+            WeatherKind::Rain => (1.0, 0.0),
+            WeatherKind::Thunder => (1.0, 1.0),
+        };
+
+        if shown == WeatherKind::Clear {
+            client.write_packet(&game_event(GameStateChangeReason::BeginRaining, 0.0));
+        } else if kind == WeatherKind::Clear {
+            client.write_packet(&game_event(GameStateChangeReason::EndRaining, 0.0));
+        }
+        client.write_packet(&game_event(GameStateChangeReason::RainLevelChange, rain));
+        client.write_packet(&game_event(
+            GameStateChangeReason::ThunderLevelChange,
+            thunder,
+        ));
+    }
+}
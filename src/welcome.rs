@@ -0,0 +1,193 @@
+use valence::prelude::*;
+use valence_protocol::packets::s2c::play::{SetSubtitleText, SetTitleAnimationTimes, SetTitleText};
+use valence_protocol::sound::{Sound, SoundCategory};
+
+use crate::config::{Config, JoinSequence};
+use crate::lang::Lang;
+use crate::player_data::PlayerDataStore;
+
+pub struct WelcomePlugin;
+
+impl Plugin for WelcomePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(welcome_clients);
+    }
+}
+
+/// One packet of a join sequence, with placeholders already filled in.
+#[derive(Clone, PartialEq, Debug)]
+pub enum JoinPacket {
+    TitleTimes {
+        fade_in: u32,
+        stay: u32,
+        fade_out: u32,
+    },
+    Subtitle(Text),
+    Title(Text),
+    Message(Text),
+    Sound(Sound),
+}
+
+/// Works out what to send a joining player in their language, in order,
+/// kept separate from sending it so a sequence can be checked without a
+/// client.
+pub fn join_packets(
+    lang: &Lang,
+    client: Entity,
+    sequence: &JoinSequence,
+    username: &str,
+) -> Vec<JoinPacket> {
+    let render = |template: &str| lang.text(client, template, &[("name", &username)]);
+    let mut packets = Vec::new();
+
+    // The title goes last, as it's what shows it with the times and
+    // subtitle already set.
+    if sequence.title.is_some() || sequence.subtitle.is_some() {
+        packets.push(JoinPacket::TitleTimes {
+            fade_in: sequence.fade_in,
+            stay: sequence.stay,
+            fade_out: sequence.fade_out,
+        });
+        packets.push(JoinPacket::Subtitle(render(
+            sequence.subtitle.as_deref().unwrap_or_default(),
+        )));
+        packets.push(JoinPacket::Title(render(
+            sequence.title.as_deref().unwrap_or_default(),
+        )));
+    }
+
+    packets.extend(
+        sequence
+            .lines
+            .iter()
+            .map(|line| JoinPacket::Message(render(line))),
+    );
+
+    // The config is validated on load, so an unknown sound never gets here.
+    if let Some(sound) = sequence.sound.as_deref().and_then(Sound::from_str) {
+        packets.push(JoinPacket::Sound(sound));
+    }
+
+    packets
+}
+
+fn send(client: &mut Client, packet: JoinPacket) {
+    match packet {
+        JoinPacket::TitleTimes {
+            fade_in,
+            stay,
+            fade_out,
+        } => client.write_packet(&SetTitleAnimationTimes {
+            fade_in: fade_in as i32,
+            stay: stay as i32,
+            fade_out: fade_out as i32,
+        }),
+        JoinPacket::Subtitle(subtitle) => client.write_packet(&SetSubtitleText {
+            subtitle_text: subtitle,
+        }),
+        JoinPacket::Title(title) => client.write_packet(&SetTitleText { title_text: title }),
+        JoinPacket::Message(text) => client.send_message(text),
+        JoinPacket::Sound(sound) => {
+            let position = client.position();
+            client.play_sound(sound, SoundCategory::Master, position, 1.0, 1.0);
+        }
+    }
+}
+
+fn welcome_clients(
+    mut clients: Query<(Entity, &mut Client), Added<Client>>,
+    store: Res<PlayerDataStore>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+) {
+    for (entity, mut client) in &mut clients {
+        let sequence = match &config.welcome.first_join {
+            Some(first_join) if !store.has_played_before(client.uuid()) => first_join,
+            _ => &config.welcome.join,
+        };
+
+        for packet in join_packets(&lang, entity, sequence, client.username().as_str()) {
+            send(&mut client, packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::legacy_text;
+
+    fn packets(sequence: &JoinSequence) -> Vec<JoinPacket> {
+        join_packets(&Lang::builtin(), Entity::from_raw(0), sequence, "Steve")
+    }
+
+    #[test]
+    fn full_sequence_in_order() {
+        let sequence = JoinSequence {
+            title: Some("&6Hi {name}".into()),
+            subtitle: Some("Welcome back".into()),
+            fade_in: 5,
+            stay: 40,
+            fade_out: 15,
+            lines: vec!["First, {name}".into(), "Second".into()],
+            sound: Some("entity.player.levelup".into()),
+        };
+
+        assert_eq!(
+            packets(&sequence),
+            [
+                JoinPacket::TitleTimes {
+                    fade_in: 5,
+                    stay: 40,
+                    fade_out: 15,
+                },
+                JoinPacket::Subtitle(legacy_text("Welcome back")),
+                JoinPacket::Title(legacy_text("&6Hi Steve")),
+                JoinPacket::Message(legacy_text("First, Steve")),
+                JoinPacket::Message(legacy_text("Second")),
+                JoinPacket::Sound(Sound::EntityPlayerLevelup),
+            ]
+        );
+    }
+
+    #[test]
+    fn subtitle_alone_still_sends_a_title() {
+        let sequence = JoinSequence {
+            subtitle: Some("Just this".into()),
+            lines: Vec::new(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            packets(&sequence),
+            [
+                JoinPacket::TitleTimes {
+                    fade_in: 10,
+                    stay: 70,
+                    fade_out: 20,
+                },
+                JoinPacket::Subtitle(legacy_text("Just this")),
+                JoinPacket::Title(legacy_text("")),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_sequence_is_the_welcome_line() {
+        assert_eq!(
+            packets(&JoinSequence::default()),
+            [JoinPacket::Message(legacy_text(
+                "&oWelcome to Valence! Build something cool."
+            ))]
+        );
+    }
+
+    #[test]
+    fn empty_sequence_sends_nothing() {
+        let sequence = JoinSequence {
+            lines: Vec::new(),
+            ..Default::default()
+        };
+        assert!(packets(&sequence).is_empty());
+    }
+}
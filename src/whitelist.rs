@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use valence::prelude::*;
+
+use crate::command::{usage, AddCommand, CommandExecution, CommandInfo, Console};
+use crate::config::{Config, WhitelistConfig};
+use crate::kick;
+use crate::lang::Lang;
+use crate::persistence::{Persistence, WriteKind};
+use crate::profiles::Profiles;
+use crate::reload::ConfigReloaded;
+
+const WHITELIST: CommandInfo = CommandInfo {
+    name: "whitelist",
+    aliases: &[],
+    usage: "/whitelist <add|remove> <player> | /whitelist <list|on|off>",
+    description: "Manage who may join the server.",
+    permission: Some("plots.command.whitelist"),
+    console: true,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    /// The player's name when they were added, for listing.
+    pub name: String,
+}
+
+/// The on-disk form of the whitelist.
+#[derive(Serialize, Deserialize, Default)]
+struct WhitelistFile {
+    players: Vec<WhitelistEntry>,
+}
+
+/// Who may join while the whitelist is on.
+#[derive(Default, Debug)]
+pub struct Whitelist {
+    enabled: bool,
+    players: Vec<WhitelistEntry>,
+    /// Shown to players who are turned away, in the default language.
+    rejection: Text,
+    path: PathBuf,
+}
+
+impl Whitelist {
+    /// Reads the whitelisted players, starting with none if the file doesn't
+    /// exist yet.
+    pub fn load(config: &WhitelistConfig) -> anyhow::Result<Self> {
+        let path = &config.file;
+
+        let file: WhitelistFile = if path.exists() {
+            let contents =
+                fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+            toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))?
+        } else {
+            WhitelistFile::default()
+        };
+
+        Ok(Self {
+            enabled: config.enabled,
+            players: file.players,
+            rejection: Text::default(),
+            path: path.clone(),
+        })
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn contains(&self, uuid: Uuid) -> bool {
+        self.players.iter().any(|p| p.uuid == uuid)
+    }
+
+    /// Whether a player may join right now.
+    pub fn allows(&self, uuid: Uuid) -> bool {
+        !self.enabled || self.contains(uuid)
+    }
+
+    pub fn rejection(&self) -> Text {
+        self.rejection.clone()
+    }
+
+    /// Queues the list to be written. Failing to write it is reported
+    /// later, as a [`WriteFailed`](crate::persistence::WriteFailed).
+    fn save(&self, persistence: &Persistence) -> anyhow::Result<()> {
+        let file = WhitelistFile {
+            players: self.players.clone(),
+        };
+        let contents = toml::to_string(&file)?;
+        persistence.write(
+            WriteKind::Whitelist,
+            self.path.clone(),
+            contents.into_bytes(),
+        );
+        Ok(())
+    }
+}
+
+/// A handle to the [`Whitelist`] shared with the login callback.
+#[derive(Resource, Clone, Default)]
+pub struct SharedWhitelist(Arc<RwLock<Whitelist>>);
+
+impl SharedWhitelist {
+    pub fn new(whitelist: Whitelist) -> Self {
+        Self(Arc::new(RwLock::new(whitelist)))
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Whitelist> {
+        self.0.read().unwrap()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Whitelist> {
+        self.0.write().unwrap()
+    }
+}
+
+pub struct WhitelistPlugin;
+
+impl Plugin for WhitelistPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command(WHITELIST)
+            .add_startup_system(render_rejection)
+            .add_system(rerender_rejection)
+            .add_system_to_stage(EventLoop, whitelist_command);
+    }
+}
+
+fn render_rejection(whitelist: Res<SharedWhitelist>, config: Res<Config>, lang: Res<Lang>) {
+    whitelist.write().rejection = lang.text_default(&config.whitelist.message, &[]);
+}
+
+fn rerender_rejection(
+    whitelist: Res<SharedWhitelist>,
+    config: Res<Config>,
+    lang: Res<Lang>,
+    mut events: EventReader<ConfigReloaded>,
+) {
+    if events.iter().count() > 0 {
+        render_rejection(whitelist, config, lang);
+    }
+}
+
+/// Kicks every online player the whitelist doesn't allow, if configured to.
+fn enforce(
+    clients: &mut Query<(Entity, &mut Client)>,
+    whitelist: &Whitelist,
+    config: &Config,
+    lang: &Lang,
+) {
+    if !config.whitelist.enforce {
+        return;
+    }
+
+    for (entity, mut client) in clients.iter_mut() {
+        if !client.is_disconnected() && !whitelist.allows(client.uuid()) {
+            info!("Kicking {} as they aren't whitelisted", client.username());
+            kick(
+                &mut client,
+                lang.text(entity, &config.whitelist.message, &[]),
+            );
+        }
+    }
+}
+
+fn whitelist_command(
+    mut clients: Query<(Entity, &mut Client)>,
+    mut consoles: Query<&mut Console>,
+    whitelist: Res<SharedWhitelist>,
+    mut profiles: ResMut<Profiles>,
+    (mut config, persistence): (ResMut<Config>, Res<Persistence>),
+    lang: Res<Lang>,
+    mut events: EventReader<CommandExecution>,
+) {
+    for event in events.iter().filter(|c| c.is(WHITELIST.name)) {
+        let reply = match event.args.as_slice() {
+            [action, name] if action == "add" => {
+                let Some(player) = profiles.resolve(name, event) else {
+                    continue;
+                };
+                let entry = WhitelistEntry {
+                    uuid: player.uuid,
+                    name: player.name,
+                };
+                add(&whitelist, &persistence, event.sender, entry, &lang)
+            }
+            [action, name] if action == "remove" => {
+                let mut list = whitelist.write();
+                let idx = list
+                    .players
+                    .iter()
+                    .position(|p| p.name.eq_ignore_ascii_case(name));
+
+                match idx {
+                    Some(idx) => {
+                        let entry = list.players.remove(idx);
+                        info!("Removed {} from the whitelist", entry.name);
+                        let saved = list.save(&persistence);
+                        enforce(&mut clients, &list, &config, &lang);
+                        saved_reply(
+                            saved,
+                            lang.tr(event.sender, "whitelist.removed", &[("name", &entry.name)]),
+                            event.sender,
+                            &lang,
+                        )
+                    }
+                    None => lang.tr(event.sender, "whitelist.not_listed", &[("name", name)]),
+                }
+            }
+            [action] if action == "list" => {
+                let list = whitelist.read();
+                if list.players.is_empty() {
+                    lang.tr(event.sender, "whitelist.empty", &[])
+                } else {
+                    let mut names: Vec<_> = list.players.iter().map(|p| p.name.as_str()).collect();
+                    names.sort_unstable_by_key(|name| name.to_ascii_lowercase());
+                    lang.tr(
+                        event.sender,
+                        "whitelist.list",
+                        &[("count", &names.len()), ("players", &names.join(", "))],
+                    )
+                }
+            }
+            [action] if action == "on" || action == "off" => {
+                let enabled = action == "on";
+                let mut list = whitelist.write();
+                list.set_enabled(enabled);
+                if enabled {
+                    enforce(&mut clients, &list, &config, &lang);
+                }
+
+                config.whitelist.enabled = enabled;
+                let key = if enabled {
+                    "whitelist.enabled"
+                } else {
+                    "whitelist.disabled"
+                };
+                saved_reply(
+                    config.save(&persistence),
+                    lang.tr(event.sender, key, &[]),
+                    event.sender,
+                    &lang,
+                )
+            }
+            _ => usage(&lang, event.sender, &WHITELIST),
+        };
+
+        if let Ok((_, mut client)) = clients.get_mut(event.sender) {
+            client.send_message(reply);
+        } else if let Ok(mut console) = consoles.get_mut(event.sender) {
+            console.send_message(reply);
+        }
+    }
+}
+
+fn add(
+    whitelist: &SharedWhitelist,
+    persistence: &Persistence,
+    sender: Entity,
+    entry: WhitelistEntry,
+    lang: &Lang,
+) -> Text {
+    let mut list = whitelist.write();
+
+    if list.contains(entry.uuid) {
+        return lang.tr(sender, "whitelist.already", &[("name", &entry.name)]);
+    }
+
+    info!("Added {} ({}) to the whitelist", entry.name, entry.uuid);
+    let reply = lang.tr(sender, "whitelist.added", &[("name", &entry.name)]);
+    list.players.push(entry);
+    saved_reply(list.save(persistence), reply, sender, lang)
+}
+
+/// The reply to a change, or a warning if it couldn't be saved.
+fn saved_reply(saved: anyhow::Result<()>, reply: Text, sender: Entity, lang: &Lang) -> Text {
+    match saved {
+        Ok(()) => reply,
+        Err(e) => {
+            warn!("Failed to save whitelist changes: {e:#}");
+            lang.tr(sender, "whitelist.unsaved", &[])
+        }
+    }
+}
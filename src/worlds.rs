@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use valence::prelude::*;
+
+use crate::config::Config;
+use crate::WorldName;
+
+/// The worlds made at startup, by name, in the order they're made.
+pub const STARTUP_WORLDS: [&str; 1] = ["world"];
+
+/// The loaded worlds by name, kept in step with their [`WorldName`]s. Look
+/// worlds up here rather than searching the instances.
+#[derive(Resource, Default, Debug)]
+pub struct Worlds {
+    by_name: HashMap<String, Entity>,
+}
+
+impl Worlds {
+    pub fn get(&self, name: &str) -> Option<Entity> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The name of the world an instance is, if it's one of ours.
+    pub fn name_of(&self, instance: Entity) -> Option<&str> {
+        self.by_name
+            .iter()
+            .find(|(_, &entity)| entity == instance)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+pub struct WorldsPlugin;
+
+impl Plugin for WorldsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Worlds>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, track_worlds)
+            .add_system_to_stage(CoreStage::PreUpdate, track_worlds);
+    }
+}
+
+fn track_worlds(
+    mut worlds: ResMut<Worlds>,
+    added: Query<(Entity, &WorldName), Added<WorldName>>,
+    removed: RemovedComponents<WorldName>,
+) {
+    for entity in removed.iter() {
+        worlds.by_name.retain(|_, &mut e| e != entity);
+    }
+    for (entity, name) in &added {
+        worlds.by_name.insert(name.0.clone(), entity);
+    }
+}
+
+/// Players can't join without somewhere to spawn, so a spawn world that
+/// won't exist stops the server before it starts. Checked against the worlds
+/// made at startup, before there's an app to make them in.
+pub fn check_spawn_world(config: &Config) -> anyhow::Result<()> {
+    if !STARTUP_WORLDS.contains(&config.spawn.world.as_str()) {
+        bail!(
+            "spawn.world is {:?}, but the worlds are: {}",
+            config.spawn.world,
+            STARTUP_WORLDS.join(", ")
+        );
+    }
+    Ok(())
+}
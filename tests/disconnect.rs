@@ -0,0 +1,193 @@
+//! Checks that what's kept about a player is gone once they disconnect.
+//! Valence clients can only come from a real connection, so players here
+//! are stand-ins, run through the same schedule the server's clients are.
+
+use std::collections::HashMap;
+
+use plotsirv::disconnect::{
+    AddDisconnectSystem, ClientDisconnected, ClientRegistry, Connection, DisconnectLabel,
+    DisconnectPlugin,
+};
+use valence::prelude::*;
+
+/// Stands in for a client's connection.
+#[derive(Component)]
+struct Player {
+    uuid: Uuid,
+    disconnected: bool,
+}
+
+impl Connection for Player {
+    fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+}
+
+/// Kept by entity, like the clicks waiting for the off hand.
+#[derive(Resource, Default)]
+struct Clicks(HashMap<Entity, BlockPos>);
+
+impl ClientRegistry for Clicks {
+    fn forget(&mut self, client: Entity, _uuid: Uuid) {
+        self.0.remove(&client);
+    }
+
+    fn entries(&self, client: Entity, _uuid: Uuid) -> usize {
+        usize::from(self.0.contains_key(&client))
+    }
+}
+
+/// Kept by UUID, like player data.
+#[derive(Resource, Default)]
+struct Data(HashMap<Uuid, u64>);
+
+impl ClientRegistry for Data {
+    fn forget(&mut self, _client: Entity, uuid: Uuid) {
+        self.0.remove(&uuid);
+    }
+
+    fn entries(&self, _client: Entity, uuid: Uuid) -> usize {
+        usize::from(self.0.contains_key(&uuid))
+    }
+}
+
+/// Between two players, like teleport requests, so either leaving ends it.
+#[derive(Resource, Default)]
+struct Requests(Vec<(Entity, Entity)>);
+
+impl ClientRegistry for Requests {
+    fn forget(&mut self, client: Entity, _uuid: Uuid) {
+        self.0.retain(|&(from, to)| from != client && to != client);
+    }
+
+    fn entries(&self, client: Entity, _uuid: Uuid) -> usize {
+        self.0
+            .iter()
+            .filter(|&&(from, to)| from == client || to == client)
+            .count()
+    }
+}
+
+/// Who a player last messaged, kept on the player, like `/r`.
+#[derive(Component, Default)]
+struct ReplyTo(Option<Entity>);
+
+/// Clears replies to players who've left, noting whether each was still
+/// there to look at.
+fn forget_replies(
+    mut events: EventReader<ClientDisconnected>,
+    mut replies: Query<&mut ReplyTo>,
+    players: Query<&Player>,
+    mut seen: ResMut<SeenLeaving>,
+) {
+    for event in events.iter() {
+        seen.0.push(players.get(event.client).is_ok());
+        for mut reply in &mut replies {
+            if reply.0 == Some(event.client) {
+                reply.0 = None;
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct SeenLeaving(Vec<bool>);
+
+/// Despawns disconnected players, as Valence does clients.
+fn despawn_disconnected(mut commands: Commands, players: Query<(Entity, &Player)>) {
+    for (entity, player) in &players {
+        if player.disconnected {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn server() -> App {
+    let mut app = App::new();
+    app.add_plugin(DisconnectPlugin::<Player>::default())
+        .init_resource::<Clicks>()
+        .init_resource::<Data>()
+        .init_resource::<Requests>()
+        .init_resource::<SeenLeaving>()
+        .add_client_registry::<Clicks>()
+        .add_client_registry::<Data>()
+        .add_client_registry::<Requests>()
+        .add_disconnect_system(forget_replies)
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            despawn_disconnected.label(DisconnectLabel::Despawn),
+        );
+    app
+}
+
+fn connect(app: &mut App, uuid: Uuid) -> Entity {
+    let player = app
+        .world
+        .spawn((
+            Player {
+                uuid,
+                disconnected: false,
+            },
+            ReplyTo::default(),
+        ))
+        .id();
+    app.update();
+    player
+}
+
+/// Everything kept about a player, across every registry.
+fn entries(app: &mut App, player: Entity, uuid: Uuid) -> usize {
+    let world = &mut app.world;
+    world.resource::<Clicks>().entries(player, uuid)
+        + world.resource::<Data>().entries(player, uuid)
+        + world.resource::<Requests>().entries(player, uuid)
+        + world
+            .query::<&ReplyTo>()
+            .iter(world)
+            .filter(|reply| reply.0 == Some(player))
+            .count()
+}
+
+#[test]
+fn disconnecting_leaves_nothing_behind() {
+    let mut app = server();
+    let (alice_uuid, bob_uuid) = (Uuid::from_u128(1), Uuid::from_u128(2));
+    let alice = connect(&mut app, alice_uuid);
+    let bob = connect(&mut app, bob_uuid);
+
+    // Alice clicks, saves, asks to teleport to Bob and messages him, and
+    // Bob messages her back.
+    let world = &mut app.world;
+    world
+        .resource_mut::<Clicks>()
+        .0
+        .insert(alice, BlockPos::new(0, 64, 0));
+    world.resource_mut::<Data>().0.insert(alice_uuid, 60);
+    world.resource_mut::<Data>().0.insert(bob_uuid, 120);
+    world.resource_mut::<Requests>().0.push((alice, bob));
+    world.get_mut::<ReplyTo>(alice).unwrap().0 = Some(bob);
+    world.get_mut::<ReplyTo>(bob).unwrap().0 = Some(alice);
+    app.update();
+    assert_eq!(entries(&mut app, alice, alice_uuid), 4);
+
+    app.world.get_mut::<Player>(alice).unwrap().disconnected = true;
+    app.update();
+
+    assert!(app.world.get_entity(alice).is_none());
+    assert_eq!(entries(&mut app, alice, alice_uuid), 0);
+    // Every disconnect system ran while Alice was still there.
+    assert_eq!(app.world.resource::<SeenLeaving>().0, [true]);
+    // Bob keeps what's his alone.
+    assert_eq!(app.world.resource::<Data>().0.get(&bob_uuid), Some(&120));
+    assert!(app.world.get::<ReplyTo>(bob).unwrap().0.is_none());
+
+    // Whoever joins next, even on Alice's old entity, starts with nothing.
+    let next_uuid = Uuid::from_u128(3);
+    let next = connect(&mut app, next_uuid);
+    assert_eq!(entries(&mut app, next, next_uuid), 0);
+    assert_eq!(app.world.resource::<SeenLeaving>().0, [true]);
+}
@@ -0,0 +1,77 @@
+//! Checks the placement path stays within its allocation budget. Run with
+//! `cargo test --features dhat-heap --test placement_allocations`.
+#![cfg(feature = "dhat-heap")]
+
+use plotsirv::placement::{decide_placement, settle_held_item, Click, Placement};
+use valence::prelude::*;
+use valence_protocol::BlockFace;
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+/// Heap allocations a placement may make, from the click to the item being
+/// used up. Everything it touches is already there, so none.
+const BUDGET: u64 = 0;
+
+const EVENTS: u64 = 1_000;
+
+/// The first hotbar slot of a player inventory.
+const HOTBAR: u16 = 36;
+
+/// Stone at y 64 and air above it.
+fn world(pos: BlockPos) -> Option<BlockState> {
+    Some(if pos.y <= 64 {
+        BlockState::STONE
+    } else {
+        BlockState::AIR
+    })
+}
+
+#[test]
+fn placement_allocations_stay_in_budget() {
+    let _profiler = dhat::Profiler::builder().testing().build();
+
+    let kinds = [
+        BlockKind::OakPlanks,
+        BlockKind::OakStairs,
+        BlockKind::StoneSlab,
+        BlockKind::Furnace,
+    ];
+    // Clicks on the top of the stone place, and clicks into it from the
+    // side are turned down, so both paths are counted.
+    let clicks: Vec<_> = kinds
+        .into_iter()
+        .flat_map(|kind| {
+            [BlockFace::Top, BlockFace::North].map(|face| Click {
+                kind,
+                position: BlockPos::new(0, 64, 0),
+                face,
+                cursor_y: 0.5,
+                yaw: 30.0,
+                pitch: 10.0,
+                feet: DVec3::new(5.5, 65.0, 5.5),
+            })
+        })
+        .collect();
+    let mut inventory = Inventory::new(InventoryKind::Player);
+    let stack = ItemStack::new(ItemKind::OakPlanks, 64, None);
+
+    let before = dhat::HeapStats::get();
+    for i in 0..EVENTS {
+        let click = &clicks[i as usize % clicks.len()];
+        // Keep the stack from running out, so every event has an item.
+        if inventory.slot(HOTBAR).map_or(true, |held| held.count() < 2) {
+            inventory.replace_slot(HOTBAR, Some(stack.clone()));
+        }
+        let placement = decide_placement(click, world, |_| true);
+        settle_held_item(&mut inventory, HOTBAR, &placement, true);
+        std::hint::black_box(matches!(placement, Placement::Place { .. }));
+    }
+    let after = dhat::HeapStats::get();
+
+    let allocated = after.total_blocks - before.total_blocks;
+    dhat::assert!(
+        allocated <= EVENTS * BUDGET,
+        "{allocated} allocations over {EVENTS} placements"
+    );
+}